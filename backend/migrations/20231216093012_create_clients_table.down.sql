@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE tasks
+DROP COLUMN client_id;
+
+DROP TABLE IF EXISTS clients;
@@ -0,0 +1,16 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS clients (
+  id SERIAL PRIMARY KEY,
+  uuid uuid DEFAULT gen_random_uuid() UNIQUE,
+  user_id INT NOT NULL,
+  name TEXT NOT NULL,
+  email TEXT,
+  phone TEXT,
+  address TEXT,
+  -- hourly rate in the smallest currency unit (e.g. cents)
+  default_rate BIGINT,
+  created_on TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE tasks
+ADD COLUMN client_id INT REFERENCES clients (id) ON DELETE SET NULL;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE tasks
+ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
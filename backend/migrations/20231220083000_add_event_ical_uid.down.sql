@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS events_user_id_ical_uid_key;
+ALTER TABLE events DROP COLUMN IF EXISTS ical_uid;
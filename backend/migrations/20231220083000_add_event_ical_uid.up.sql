@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE events ADD COLUMN IF NOT EXISTS ical_uid VARCHAR;
+CREATE UNIQUE INDEX IF NOT EXISTS events_user_id_ical_uid_key ON events (user_id, ical_uid);
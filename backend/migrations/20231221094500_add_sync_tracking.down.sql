@@ -0,0 +1,11 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS events_record_tombstone ON events;
+DROP TRIGGER IF EXISTS tasks_record_tombstone ON tasks;
+DROP TRIGGER IF EXISTS events_track_sync_change ON events;
+DROP TRIGGER IF EXISTS tasks_track_sync_change ON tasks;
+DROP FUNCTION IF EXISTS record_tombstone();
+DROP FUNCTION IF EXISTS track_sync_change();
+DROP TABLE IF EXISTS tombstones;
+ALTER TABLE events DROP COLUMN IF EXISTS field_clock, DROP COLUMN IF EXISTS change_seq;
+ALTER TABLE tasks DROP COLUMN IF EXISTS field_clock, DROP COLUMN IF EXISTS change_seq;
+DROP SEQUENCE IF EXISTS sync_change_seq;
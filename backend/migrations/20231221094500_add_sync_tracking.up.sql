@@ -0,0 +1,74 @@
+-- Add up migration script here
+CREATE SEQUENCE IF NOT EXISTS sync_change_seq;
+
+-- change_seq orders every write for GET /sync, field_clock holds when each
+-- synced field was last written, for per-field last-writer-wins
+ALTER TABLE tasks
+  ADD COLUMN IF NOT EXISTS change_seq BIGINT NOT NULL DEFAULT nextval('sync_change_seq'),
+  ADD COLUMN IF NOT EXISTS field_clock JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE events
+  ADD COLUMN IF NOT EXISTS change_seq BIGINT NOT NULL DEFAULT nextval('sync_change_seq'),
+  ADD COLUMN IF NOT EXISTS field_clock JSONB NOT NULL DEFAULT '{}';
+
+UPDATE tasks SET field_clock = jsonb_build_object(
+  'name', created_on, 'description', created_on, 'tags', created_on
+);
+UPDATE events SET field_clock = jsonb_build_object(
+  'task_id', now(), 'date_began', now(), 'duration', now(), 'notes', now()
+);
+
+CREATE INDEX IF NOT EXISTS tasks_user_id_change_seq_idx ON tasks (user_id, change_seq);
+CREATE INDEX IF NOT EXISTS events_user_id_change_seq_idx ON events (user_id, change_seq);
+
+CREATE TABLE IF NOT EXISTS tombstones (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL,
+  entity VARCHAR NOT NULL,
+  uuid uuid NOT NULL,
+  change_seq BIGINT NOT NULL DEFAULT nextval('sync_change_seq'),
+  deleted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX IF NOT EXISTS tombstones_user_id_change_seq_idx ON tombstones (user_id, change_seq);
+
+-- Takes the synced fields as trigger arguments. Fields a statement changes
+-- without also setting their clock are stamped with the current time, so
+-- edits through the regular routes take part in last-writer-wins too.
+CREATE OR REPLACE FUNCTION track_sync_change() RETURNS trigger AS $$
+DECLARE
+  field TEXT;
+  new_row JSONB := to_jsonb(NEW);
+  old_row JSONB;
+BEGIN
+  IF TG_OP = 'UPDATE' THEN
+    old_row := to_jsonb(OLD);
+    NEW.change_seq := nextval('sync_change_seq');
+  END IF;
+  FOREACH field IN ARRAY TG_ARGV LOOP
+    IF TG_OP = 'INSERT' THEN
+      IF NOT NEW.field_clock ? field THEN
+        NEW.field_clock := NEW.field_clock || jsonb_build_object(field, now());
+      END IF;
+    ELSIF new_row -> field IS DISTINCT FROM old_row -> field
+      AND NEW.field_clock -> field IS NOT DISTINCT FROM OLD.field_clock -> field THEN
+      NEW.field_clock := NEW.field_clock || jsonb_build_object(field, now());
+    END IF;
+  END LOOP;
+  RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION record_tombstone() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO tombstones (user_id, entity, uuid) VALUES (OLD.user_id, TG_ARGV[0], OLD.uuid);
+  RETURN OLD;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER tasks_track_sync_change BEFORE INSERT OR UPDATE ON tasks
+  FOR EACH ROW EXECUTE FUNCTION track_sync_change('name', 'description', 'tags');
+CREATE TRIGGER events_track_sync_change BEFORE INSERT OR UPDATE ON events
+  FOR EACH ROW EXECUTE FUNCTION track_sync_change('task_id', 'date_began', 'duration', 'notes');
+CREATE TRIGGER tasks_record_tombstone AFTER DELETE ON tasks
+  FOR EACH ROW EXECUTE FUNCTION record_tombstone('task');
+CREATE TRIGGER events_record_tombstone AFTER DELETE ON events
+  FOR EACH ROW EXECUTE FUNCTION record_tombstone('event');
@@ -0,0 +1,10 @@
+-- Add down migration script here
+-- The SERIAL defaults are not restored, they only ever handed out ids of
+-- users and tasks that did not exist
+ALTER TABLE users ALTER COLUMN password DROP NOT NULL;
+
+ALTER TABLE sessions DROP CONSTRAINT IF EXISTS sessions_user_id_fkey;
+ALTER TABLE clients DROP CONSTRAINT IF EXISTS clients_user_id_fkey;
+ALTER TABLE events DROP CONSTRAINT IF EXISTS events_task_id_fkey;
+ALTER TABLE events DROP CONSTRAINT IF EXISTS events_user_id_fkey;
+ALTER TABLE tasks DROP CONSTRAINT IF EXISTS tasks_user_id_fkey;
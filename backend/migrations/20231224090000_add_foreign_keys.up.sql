@@ -0,0 +1,74 @@
+-- Add up migration script here
+-- Rows the constraints below would reject are listed first and stop the
+-- migration, instead of failing on whichever constraint comes first. Delete
+-- or fix them and run it again.
+DO $$
+DECLARE
+  orphans TEXT := '';
+  ids TEXT;
+BEGIN
+  SELECT string_agg(id::TEXT, ', ' ORDER BY id) INTO ids
+  FROM tasks WHERE NOT EXISTS (SELECT 1 FROM users WHERE users.id = tasks.user_id);
+  IF ids IS NOT NULL THEN
+    orphans := orphans || E'\n  tasks of missing users: ' || ids;
+  END IF;
+
+  SELECT string_agg(id::TEXT, ', ' ORDER BY id) INTO ids
+  FROM events WHERE NOT EXISTS (SELECT 1 FROM users WHERE users.id = events.user_id);
+  IF ids IS NOT NULL THEN
+    orphans := orphans || E'\n  events of missing users: ' || ids;
+  END IF;
+
+  SELECT string_agg(id::TEXT, ', ' ORDER BY id) INTO ids
+  FROM events WHERE NOT EXISTS (SELECT 1 FROM tasks WHERE tasks.id = events.task_id);
+  IF ids IS NOT NULL THEN
+    orphans := orphans || E'\n  events of missing tasks: ' || ids;
+  END IF;
+
+  SELECT string_agg(id::TEXT, ', ' ORDER BY id) INTO ids
+  FROM clients WHERE NOT EXISTS (SELECT 1 FROM users WHERE users.id = clients.user_id);
+  IF ids IS NOT NULL THEN
+    orphans := orphans || E'\n  clients of missing users: ' || ids;
+  END IF;
+
+  SELECT string_agg(id::TEXT, ', ' ORDER BY id) INTO ids
+  FROM sessions WHERE NOT EXISTS (SELECT 1 FROM users WHERE users.id = sessions.user_id);
+  IF ids IS NOT NULL THEN
+    orphans := orphans || E'\n  sessions of missing users: ' || ids;
+  END IF;
+
+  SELECT string_agg(id::TEXT, ', ' ORDER BY id) INTO ids
+  FROM users WHERE password IS NULL;
+  IF ids IS NOT NULL THEN
+    orphans := orphans || E'\n  users without a password: ' || ids;
+  END IF;
+
+  IF orphans <> '' THEN
+    RAISE EXCEPTION 'rows that would violate the new constraints, by id:%', orphans;
+  END IF;
+END
+$$;
+
+-- The owner columns were declared SERIAL, which gave each one a sequence
+-- that nothing should draw from
+ALTER TABLE tasks ALTER COLUMN user_id DROP DEFAULT;
+ALTER TABLE events ALTER COLUMN user_id DROP DEFAULT;
+ALTER TABLE events ALTER COLUMN task_id DROP DEFAULT;
+DROP SEQUENCE IF EXISTS tasks_user_id_seq;
+DROP SEQUENCE IF EXISTS events_user_id_seq;
+DROP SEQUENCE IF EXISTS events_task_id_seq;
+
+ALTER TABLE tasks
+ADD CONSTRAINT tasks_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+
+ALTER TABLE events
+ADD CONSTRAINT events_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+ADD CONSTRAINT events_task_id_fkey FOREIGN KEY (task_id) REFERENCES tasks (id) ON DELETE CASCADE;
+
+ALTER TABLE clients
+ADD CONSTRAINT clients_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+
+ALTER TABLE sessions
+ADD CONSTRAINT sessions_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+
+ALTER TABLE users ALTER COLUMN password SET NOT NULL;
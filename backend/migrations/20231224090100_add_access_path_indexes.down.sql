@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS clients_user_id_idx;
+DROP INDEX IF EXISTS tasks_client_id_idx;
+DROP INDEX IF EXISTS events_user_id_date_began_idx;
+DROP INDEX IF EXISTS events_task_id_idx;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- tasks.user_id and events.user_id lead the (user_id, change_seq) indexes,
+-- which already serve lookups by user
+CREATE INDEX IF NOT EXISTS events_task_id_idx ON events (task_id);
+CREATE INDEX IF NOT EXISTS events_user_id_date_began_idx ON events (user_id, date_began);
+CREATE INDEX IF NOT EXISTS tasks_client_id_idx ON tasks (client_id);
+CREATE INDEX IF NOT EXISTS clients_user_id_idx ON clients (user_id);
@@ -0,0 +1,9 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS events_search_vector_idx;
+DROP INDEX IF EXISTS tasks_search_vector_idx;
+DROP TRIGGER IF EXISTS events_update_search_vector ON events;
+DROP TRIGGER IF EXISTS tasks_update_search_vector ON tasks;
+DROP FUNCTION IF EXISTS update_event_search_vector();
+DROP FUNCTION IF EXISTS update_task_search_vector();
+ALTER TABLE events DROP COLUMN IF EXISTS search_vector;
+ALTER TABLE tasks DROP COLUMN IF EXISTS search_vector;
@@ -0,0 +1,37 @@
+-- Add up migration script here
+-- What tasks and events can be searched by, kept up to date by triggers.
+-- Task names weigh more than descriptions and notes.
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS search_vector TSVECTOR NOT NULL DEFAULT '';
+ALTER TABLE events ADD COLUMN IF NOT EXISTS search_vector TSVECTOR NOT NULL DEFAULT '';
+
+CREATE OR REPLACE FUNCTION update_task_search_vector() RETURNS trigger AS $$
+BEGIN
+  NEW.search_vector :=
+    setweight(to_tsvector('english', NEW.name), 'A')
+    || setweight(to_tsvector('english', COALESCE(NEW.description, '')), 'B');
+  RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION update_event_search_vector() RETURNS trigger AS $$
+BEGIN
+  NEW.search_vector := setweight(to_tsvector('english', COALESCE(NEW.notes, '')), 'B');
+  RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER tasks_update_search_vector BEFORE INSERT OR UPDATE OF name, description ON tasks
+  FOR EACH ROW EXECUTE FUNCTION update_task_search_vector();
+CREATE TRIGGER events_update_search_vector BEFORE INSERT OR UPDATE OF notes ON events
+  FOR EACH ROW EXECUTE FUNCTION update_event_search_vector();
+
+-- filling in the existing rows is no change for sync clients to pull
+ALTER TABLE tasks DISABLE TRIGGER tasks_track_sync_change;
+ALTER TABLE events DISABLE TRIGGER events_track_sync_change;
+UPDATE tasks SET name = name;
+UPDATE events SET notes = notes;
+ALTER TABLE tasks ENABLE TRIGGER tasks_track_sync_change;
+ALTER TABLE events ENABLE TRIGGER events_track_sync_change;
+
+CREATE INDEX IF NOT EXISTS tasks_search_vector_idx ON tasks USING GIN (search_vector);
+CREATE INDEX IF NOT EXISTS events_search_vector_idx ON events USING GIN (search_vector);
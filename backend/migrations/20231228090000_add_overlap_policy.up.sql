@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- What happens to a new event overlapping the user's others: allow, reject
+-- or trim
+ALTER TABLE users ADD COLUMN IF NOT EXISTS overlap_policy VARCHAR NOT NULL DEFAULT 'allow'
+  CHECK (overlap_policy IN ('allow', 'reject', 'trim'));
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS timers_notify_change ON timers;
+DROP TRIGGER IF EXISTS events_notify_change ON events;
+DROP TRIGGER IF EXISTS tasks_notify_change ON tasks;
+DROP FUNCTION IF EXISTS notify_change();
+DROP TABLE IF EXISTS timers;
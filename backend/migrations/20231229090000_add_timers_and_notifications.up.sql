@@ -0,0 +1,51 @@
+-- Add up migration script here
+-- The timer each user has running, if any
+CREATE TABLE IF NOT EXISTS timers (
+  user_id INT PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+  task_id INT NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+  started TIMESTAMPTZ NOT NULL,
+  notes VARCHAR
+);
+CREATE INDEX IF NOT EXISTS timers_task_id_idx ON timers (task_id);
+
+-- Sends a change on the bandit_changes channel, as JSON with the user, the
+-- type of the change and the ids of what changed, so that every server can
+-- tell the user's clients. The trigger arguments are the types of an
+-- insert, an update and a delete, then the columns to send.
+-- Notifications go out when the transaction commits.
+CREATE OR REPLACE FUNCTION notify_change() RETURNS trigger AS $$
+DECLARE
+  changed JSONB;
+  payload JSONB;
+  field TEXT;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    changed := to_jsonb(OLD);
+  ELSE
+    changed := to_jsonb(NEW);
+  END IF;
+  payload := jsonb_build_object(
+    'user_id', changed -> 'user_id',
+    'type', CASE TG_OP WHEN 'INSERT' THEN TG_ARGV[0] WHEN 'UPDATE' THEN TG_ARGV[1] ELSE TG_ARGV[2] END
+  );
+  FOREACH field IN ARRAY TG_ARGV[3:] LOOP
+    payload := payload || jsonb_build_object(field, changed -> field);
+  END LOOP;
+  PERFORM pg_notify('bandit_changes', payload::TEXT);
+  RETURN NULL;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER tasks_notify_change AFTER INSERT OR UPDATE OF name, description, client_id, tags OR DELETE
+  ON tasks
+  FOR EACH ROW EXECUTE FUNCTION notify_change('task_created', 'task_updated', 'task_deleted', 'id');
+CREATE TRIGGER events_notify_change AFTER INSERT OR UPDATE OF task_id, date_began, duration, notes OR DELETE
+  ON events
+  FOR EACH ROW EXECUTE FUNCTION notify_change(
+    'event_created', 'event_updated', 'event_deleted', 'id', 'task_id'
+  );
+-- timers are only started and stopped
+CREATE TRIGGER timers_notify_change AFTER INSERT OR DELETE ON timers
+  FOR EACH ROW EXECUTE FUNCTION notify_change(
+    'timer_started', '', 'timer_stopped', 'task_id', 'started'
+  );
@@ -0,0 +1,26 @@
+-- Add down migration script here
+CREATE OR REPLACE FUNCTION notify_change() RETURNS trigger AS $$
+DECLARE
+  changed JSONB;
+  payload JSONB;
+  field TEXT;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    changed := to_jsonb(OLD);
+  ELSE
+    changed := to_jsonb(NEW);
+  END IF;
+  payload := jsonb_build_object(
+    'user_id', changed -> 'user_id',
+    'type', CASE TG_OP WHEN 'INSERT' THEN TG_ARGV[0] WHEN 'UPDATE' THEN TG_ARGV[1] ELSE TG_ARGV[2] END
+  );
+  FOREACH field IN ARRAY TG_ARGV[3:] LOOP
+    payload := payload || jsonb_build_object(field, changed -> field);
+  END LOOP;
+  PERFORM pg_notify('bandit_changes', payload::TEXT);
+  RETURN NULL;
+END
+$$ LANGUAGE plpgsql;
+DROP TABLE IF EXISTS webhook_attempts;
+DROP TABLE IF EXISTS webhook_deliveries;
+DROP TABLE IF EXISTS webhooks;
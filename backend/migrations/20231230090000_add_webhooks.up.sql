@@ -0,0 +1,70 @@
+-- Add up migration script here
+-- Where a user wants their changes posted, and which of them
+CREATE TABLE IF NOT EXISTS webhooks (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  url VARCHAR NOT NULL,
+  event_types VARCHAR[] NOT NULL,
+  secret VARCHAR NOT NULL,
+  created_on TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS webhooks_user_id_idx ON webhooks (user_id);
+
+-- The queue of changes to post. `change` is the notification the delivery
+-- was queued for, `payload` the body sent, rendered on the first attempt.
+-- Only pending deliveries have a next attempt.
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+  id BIGSERIAL PRIMARY KEY,
+  webhook_id INT NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+  event_type VARCHAR NOT NULL,
+  change JSONB NOT NULL,
+  payload TEXT,
+  status VARCHAR NOT NULL DEFAULT 'pending'
+    CHECK (status IN ('pending', 'delivered', 'failed')),
+  attempts INT NOT NULL DEFAULT 0,
+  next_attempt TIMESTAMPTZ DEFAULT now(),
+  created_on TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS webhook_deliveries_webhook_id_idx ON webhook_deliveries (webhook_id, id);
+CREATE INDEX IF NOT EXISTS webhook_deliveries_next_attempt_idx ON webhook_deliveries (next_attempt)
+  WHERE next_attempt IS NOT NULL;
+
+-- The log of every try at sending a delivery
+CREATE TABLE IF NOT EXISTS webhook_attempts (
+  id BIGSERIAL PRIMARY KEY,
+  delivery_id BIGINT NOT NULL REFERENCES webhook_deliveries (id) ON DELETE CASCADE,
+  attempted_on TIMESTAMPTZ NOT NULL,
+  status_code INT,
+  error VARCHAR,
+  duration_ms BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS webhook_attempts_delivery_id_idx ON webhook_attempts (delivery_id);
+
+-- Besides notifying, queues a delivery of the change for each of the user's
+-- webhooks that wants it, in the same transaction as the change
+CREATE OR REPLACE FUNCTION notify_change() RETURNS trigger AS $$
+DECLARE
+  changed JSONB;
+  payload JSONB;
+  field TEXT;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    changed := to_jsonb(OLD);
+  ELSE
+    changed := to_jsonb(NEW);
+  END IF;
+  payload := jsonb_build_object(
+    'user_id', changed -> 'user_id',
+    'type', CASE TG_OP WHEN 'INSERT' THEN TG_ARGV[0] WHEN 'UPDATE' THEN TG_ARGV[1] ELSE TG_ARGV[2] END
+  );
+  FOREACH field IN ARRAY TG_ARGV[3:] LOOP
+    payload := payload || jsonb_build_object(field, changed -> field);
+  END LOOP;
+  PERFORM pg_notify('bandit_changes', payload::TEXT);
+  INSERT INTO webhook_deliveries (webhook_id, event_type, change)
+    SELECT id, replace(payload ->> 'type', '_', '.'), payload FROM webhooks
+    WHERE user_id = (payload ->> 'user_id')::INT
+      AND replace(payload ->> 'type', '_', '.') = ANY (event_types);
+  RETURN NULL;
+END
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,14 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS events_record_tombstone;
+DROP TRIGGER IF EXISTS tasks_record_tombstone;
+DROP TRIGGER IF EXISTS events_track_sync_update;
+DROP TRIGGER IF EXISTS events_track_sync_insert;
+DROP TRIGGER IF EXISTS tasks_track_sync_update;
+DROP TRIGGER IF EXISTS tasks_track_sync_insert;
+DROP TABLE IF EXISTS sync_change_seq;
+DROP TABLE IF EXISTS tombstones;
+DROP TABLE IF EXISTS events;
+DROP TABLE IF EXISTS tasks;
+DROP TABLE IF EXISTS clients;
+DROP TABLE IF EXISTS sessions;
+DROP TABLE IF EXISTS users;
@@ -0,0 +1,166 @@
+-- Add up migration script here
+-- The schema of the Postgres migrations up to 20231222090000. uuids are
+-- 16 byte blobs, timestamps are RFC 3339 text in UTC, so they sort as text,
+-- and tags are a JSON array.
+CREATE TABLE IF NOT EXISTS users (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  uuid BLOB NOT NULL UNIQUE,
+  email TEXT NOT NULL UNIQUE,
+  password TEXT,
+  calendar_token TEXT UNIQUE,
+  disabled_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS sessions (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  session_id TEXT NOT NULL UNIQUE,
+  user_id INTEGER NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS clients (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  uuid BLOB NOT NULL UNIQUE,
+  user_id INTEGER NOT NULL,
+  name TEXT NOT NULL,
+  email TEXT,
+  phone TEXT,
+  address TEXT,
+  -- hourly rate in the smallest currency unit (e.g. cents)
+  default_rate INTEGER,
+  created_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS tasks (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  uuid BLOB NOT NULL UNIQUE,
+  user_id INTEGER NOT NULL,
+  name TEXT NOT NULL,
+  description TEXT,
+  created_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+  client_id INTEGER REFERENCES clients (id) ON DELETE SET NULL,
+  tags TEXT NOT NULL DEFAULT '[]',
+  change_seq INTEGER NOT NULL DEFAULT 0,
+  field_clock TEXT NOT NULL DEFAULT '{}'
+);
+
+CREATE TABLE IF NOT EXISTS events (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  uuid BLOB NOT NULL UNIQUE,
+  user_id INTEGER NOT NULL,
+  task_id INTEGER NOT NULL,
+  notes TEXT,
+  date_began TEXT NOT NULL,
+  duration INTEGER NOT NULL,
+  ical_uid TEXT,
+  change_seq INTEGER NOT NULL DEFAULT 0,
+  field_clock TEXT NOT NULL DEFAULT '{}'
+);
+CREATE UNIQUE INDEX IF NOT EXISTS events_user_id_ical_uid_key ON events (user_id, ical_uid);
+
+CREATE TABLE IF NOT EXISTS tombstones (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  user_id INTEGER NOT NULL,
+  entity TEXT NOT NULL,
+  uuid BLOB NOT NULL,
+  change_seq INTEGER NOT NULL,
+  deleted_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+);
+
+CREATE INDEX IF NOT EXISTS tasks_user_id_change_seq_idx ON tasks (user_id, change_seq);
+CREATE INDEX IF NOT EXISTS events_user_id_change_seq_idx ON events (user_id, change_seq);
+CREATE INDEX IF NOT EXISTS tombstones_user_id_change_seq_idx ON tombstones (user_id, change_seq);
+
+-- SQLite has no sequences, this single row counts every write for GET /sync
+CREATE TABLE IF NOT EXISTS sync_change_seq (value INTEGER NOT NULL);
+INSERT INTO sync_change_seq (value) VALUES (0);
+
+-- The triggers of track_sync_change and record_tombstone, spelled out for
+-- each table. Fields an update changes without also setting their clock are
+-- stamped with the current time. Triggers do not fire recursively, so they
+-- may update the row they fired for.
+CREATE TRIGGER IF NOT EXISTS tasks_track_sync_insert AFTER INSERT ON tasks
+BEGIN
+  UPDATE sync_change_seq SET value = value + 1;
+  UPDATE tasks SET
+    change_seq = (SELECT value FROM sync_change_seq),
+    field_clock = json_patch(
+      json_object(
+        'name', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'),
+        'description', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'),
+        'tags', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')
+      ),
+      field_clock
+    )
+  WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS tasks_track_sync_update AFTER UPDATE ON tasks
+BEGIN
+  UPDATE sync_change_seq SET value = value + 1;
+  UPDATE tasks SET change_seq = (SELECT value FROM sync_change_seq) WHERE id = NEW.id;
+  UPDATE tasks
+  SET field_clock = json_set(field_clock, '$.name', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.name IS NOT OLD.name
+    AND json_extract(NEW.field_clock, '$.name') IS json_extract(OLD.field_clock, '$.name');
+  UPDATE tasks
+  SET field_clock = json_set(field_clock, '$.description', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.description IS NOT OLD.description
+    AND json_extract(NEW.field_clock, '$.description') IS json_extract(OLD.field_clock, '$.description');
+  UPDATE tasks
+  SET field_clock = json_set(field_clock, '$.tags', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.tags IS NOT OLD.tags
+    AND json_extract(NEW.field_clock, '$.tags') IS json_extract(OLD.field_clock, '$.tags');
+END;
+
+CREATE TRIGGER IF NOT EXISTS events_track_sync_insert AFTER INSERT ON events
+BEGIN
+  UPDATE sync_change_seq SET value = value + 1;
+  UPDATE events SET
+    change_seq = (SELECT value FROM sync_change_seq),
+    field_clock = json_patch(
+      json_object(
+        'task_id', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'),
+        'date_began', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'),
+        'duration', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'),
+        'notes', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')
+      ),
+      field_clock
+    )
+  WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS events_track_sync_update AFTER UPDATE ON events
+BEGIN
+  UPDATE sync_change_seq SET value = value + 1;
+  UPDATE events SET change_seq = (SELECT value FROM sync_change_seq) WHERE id = NEW.id;
+  UPDATE events
+  SET field_clock = json_set(field_clock, '$.task_id', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.task_id IS NOT OLD.task_id
+    AND json_extract(NEW.field_clock, '$.task_id') IS json_extract(OLD.field_clock, '$.task_id');
+  UPDATE events
+  SET field_clock = json_set(field_clock, '$.date_began', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.date_began IS NOT OLD.date_began
+    AND json_extract(NEW.field_clock, '$.date_began') IS json_extract(OLD.field_clock, '$.date_began');
+  UPDATE events
+  SET field_clock = json_set(field_clock, '$.duration', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.duration IS NOT OLD.duration
+    AND json_extract(NEW.field_clock, '$.duration') IS json_extract(OLD.field_clock, '$.duration');
+  UPDATE events
+  SET field_clock = json_set(field_clock, '$.notes', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.notes IS NOT OLD.notes
+    AND json_extract(NEW.field_clock, '$.notes') IS json_extract(OLD.field_clock, '$.notes');
+END;
+
+CREATE TRIGGER IF NOT EXISTS tasks_record_tombstone AFTER DELETE ON tasks
+BEGIN
+  UPDATE sync_change_seq SET value = value + 1;
+  INSERT INTO tombstones (user_id, entity, uuid, change_seq)
+  VALUES (OLD.user_id, 'task', OLD.uuid, (SELECT value FROM sync_change_seq));
+END;
+
+CREATE TRIGGER IF NOT EXISTS events_record_tombstone AFTER DELETE ON events
+BEGIN
+  UPDATE sync_change_seq SET value = value + 1;
+  INSERT INTO tombstones (user_id, entity, uuid, change_seq)
+  VALUES (OLD.user_id, 'event', OLD.uuid, (SELECT value FROM sync_change_seq));
+END;
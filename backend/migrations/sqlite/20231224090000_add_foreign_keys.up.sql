@@ -0,0 +1,207 @@
+-- Add up migration script here
+-- The constraints and indexes of the Postgres migrations 20231224090000 and
+-- 20231224090100. SQLite cannot add constraints to a table, so the tables
+-- are built again and their rows copied over.
+
+-- Rows the new constraints would reject stop the migration, naming what
+-- they are, e.g. "CHECK constraint failed: events of missing tasks". Delete
+-- or fix them and run it again.
+CREATE TEMP TABLE orphans (
+  tasks_of_missing_users INTEGER CONSTRAINT "tasks of missing users" CHECK (tasks_of_missing_users = 0),
+  events_of_missing_users INTEGER CONSTRAINT "events of missing users" CHECK (events_of_missing_users = 0),
+  events_of_missing_tasks INTEGER CONSTRAINT "events of missing tasks" CHECK (events_of_missing_tasks = 0),
+  clients_of_missing_users INTEGER CONSTRAINT "clients of missing users" CHECK (clients_of_missing_users = 0),
+  sessions_of_missing_users INTEGER CONSTRAINT "sessions of missing users" CHECK (sessions_of_missing_users = 0),
+  users_without_a_password INTEGER CONSTRAINT "users without a password" CHECK (users_without_a_password = 0)
+);
+INSERT INTO orphans VALUES (
+  (SELECT count(*) FROM tasks WHERE user_id NOT IN (SELECT id FROM users)),
+  (SELECT count(*) FROM events WHERE user_id NOT IN (SELECT id FROM users)),
+  (SELECT count(*) FROM events WHERE task_id NOT IN (SELECT id FROM tasks)),
+  (SELECT count(*) FROM clients WHERE user_id NOT IN (SELECT id FROM users)),
+  (SELECT count(*) FROM sessions WHERE user_id NOT IN (SELECT id FROM users)),
+  (SELECT count(*) FROM users WHERE password IS NULL)
+);
+DROP TABLE orphans;
+
+-- Dropping a table drops its row in sqlite_sequence, which keeps ids of
+-- deleted rows from being handed out again
+CREATE TEMP TABLE saved_sequence AS SELECT name, seq FROM sqlite_sequence;
+
+-- The old tables move aside first, so that the new ones do not refer to a
+-- table that is dropped later, which would delete their rows
+ALTER TABLE users RENAME TO users_old;
+ALTER TABLE sessions RENAME TO sessions_old;
+ALTER TABLE clients RENAME TO clients_old;
+ALTER TABLE tasks RENAME TO tasks_old;
+ALTER TABLE events RENAME TO events_old;
+
+CREATE TABLE users (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  uuid BLOB NOT NULL UNIQUE,
+  email TEXT NOT NULL UNIQUE,
+  password TEXT NOT NULL,
+  calendar_token TEXT UNIQUE,
+  disabled_at TEXT
+);
+
+CREATE TABLE sessions (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  session_id TEXT NOT NULL UNIQUE,
+  user_id INTEGER NOT NULL UNIQUE REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE TABLE clients (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  uuid BLOB NOT NULL UNIQUE,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  email TEXT,
+  phone TEXT,
+  address TEXT,
+  -- hourly rate in the smallest currency unit (e.g. cents)
+  default_rate INTEGER,
+  created_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+);
+
+CREATE TABLE tasks (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  uuid BLOB NOT NULL UNIQUE,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  description TEXT,
+  created_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+  client_id INTEGER REFERENCES clients (id) ON DELETE SET NULL,
+  tags TEXT NOT NULL DEFAULT '[]',
+  change_seq INTEGER NOT NULL DEFAULT 0,
+  field_clock TEXT NOT NULL DEFAULT '{}'
+);
+
+CREATE TABLE events (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  uuid BLOB NOT NULL UNIQUE,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  task_id INTEGER NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+  notes TEXT,
+  date_began TEXT NOT NULL,
+  duration INTEGER NOT NULL,
+  ical_uid TEXT,
+  change_seq INTEGER NOT NULL DEFAULT 0,
+  field_clock TEXT NOT NULL DEFAULT '{}'
+);
+
+-- The new tables have no triggers yet, so the copies keep their change_seq
+-- and field_clock
+INSERT INTO users SELECT * FROM users_old;
+INSERT INTO sessions SELECT * FROM sessions_old;
+INSERT INTO clients SELECT * FROM clients_old;
+INSERT INTO tasks SELECT * FROM tasks_old;
+INSERT INTO events SELECT * FROM events_old;
+
+-- Their triggers go with them and do not fire for the drop
+DROP TABLE events_old;
+DROP TABLE tasks_old;
+DROP TABLE clients_old;
+DROP TABLE sessions_old;
+DROP TABLE users_old;
+
+DELETE FROM sqlite_sequence WHERE name IN (SELECT name FROM saved_sequence);
+INSERT INTO sqlite_sequence (name, seq) SELECT name, seq FROM saved_sequence;
+DROP TABLE saved_sequence;
+
+CREATE UNIQUE INDEX events_user_id_ical_uid_key ON events (user_id, ical_uid);
+CREATE INDEX tasks_user_id_change_seq_idx ON tasks (user_id, change_seq);
+CREATE INDEX events_user_id_change_seq_idx ON events (user_id, change_seq);
+-- tasks.user_id and events.user_id lead the (user_id, change_seq) indexes,
+-- which already serve lookups by user
+CREATE INDEX events_task_id_idx ON events (task_id);
+CREATE INDEX events_user_id_date_began_idx ON events (user_id, date_began);
+CREATE INDEX tasks_client_id_idx ON tasks (client_id);
+CREATE INDEX clients_user_id_idx ON clients (user_id);
+
+CREATE TRIGGER tasks_track_sync_insert AFTER INSERT ON tasks
+BEGIN
+  UPDATE sync_change_seq SET value = value + 1;
+  UPDATE tasks SET
+    change_seq = (SELECT value FROM sync_change_seq),
+    field_clock = json_patch(
+      json_object(
+        'name', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'),
+        'description', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'),
+        'tags', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')
+      ),
+      field_clock
+    )
+  WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER tasks_track_sync_update AFTER UPDATE ON tasks
+BEGIN
+  UPDATE sync_change_seq SET value = value + 1;
+  UPDATE tasks SET change_seq = (SELECT value FROM sync_change_seq) WHERE id = NEW.id;
+  UPDATE tasks
+  SET field_clock = json_set(field_clock, '$.name', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.name IS NOT OLD.name
+    AND json_extract(NEW.field_clock, '$.name') IS json_extract(OLD.field_clock, '$.name');
+  UPDATE tasks
+  SET field_clock = json_set(field_clock, '$.description', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.description IS NOT OLD.description
+    AND json_extract(NEW.field_clock, '$.description') IS json_extract(OLD.field_clock, '$.description');
+  UPDATE tasks
+  SET field_clock = json_set(field_clock, '$.tags', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.tags IS NOT OLD.tags
+    AND json_extract(NEW.field_clock, '$.tags') IS json_extract(OLD.field_clock, '$.tags');
+END;
+
+CREATE TRIGGER events_track_sync_insert AFTER INSERT ON events
+BEGIN
+  UPDATE sync_change_seq SET value = value + 1;
+  UPDATE events SET
+    change_seq = (SELECT value FROM sync_change_seq),
+    field_clock = json_patch(
+      json_object(
+        'task_id', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'),
+        'date_began', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'),
+        'duration', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'),
+        'notes', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')
+      ),
+      field_clock
+    )
+  WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER events_track_sync_update AFTER UPDATE ON events
+BEGIN
+  UPDATE sync_change_seq SET value = value + 1;
+  UPDATE events SET change_seq = (SELECT value FROM sync_change_seq) WHERE id = NEW.id;
+  UPDATE events
+  SET field_clock = json_set(field_clock, '$.task_id', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.task_id IS NOT OLD.task_id
+    AND json_extract(NEW.field_clock, '$.task_id') IS json_extract(OLD.field_clock, '$.task_id');
+  UPDATE events
+  SET field_clock = json_set(field_clock, '$.date_began', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.date_began IS NOT OLD.date_began
+    AND json_extract(NEW.field_clock, '$.date_began') IS json_extract(OLD.field_clock, '$.date_began');
+  UPDATE events
+  SET field_clock = json_set(field_clock, '$.duration', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.duration IS NOT OLD.duration
+    AND json_extract(NEW.field_clock, '$.duration') IS json_extract(OLD.field_clock, '$.duration');
+  UPDATE events
+  SET field_clock = json_set(field_clock, '$.notes', strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+  WHERE id = NEW.id AND NEW.notes IS NOT OLD.notes
+    AND json_extract(NEW.field_clock, '$.notes') IS json_extract(OLD.field_clock, '$.notes');
+END;
+
+CREATE TRIGGER tasks_record_tombstone AFTER DELETE ON tasks
+BEGIN
+  UPDATE sync_change_seq SET value = value + 1;
+  INSERT INTO tombstones (user_id, entity, uuid, change_seq)
+  VALUES (OLD.user_id, 'task', OLD.uuid, (SELECT value FROM sync_change_seq));
+END;
+
+CREATE TRIGGER events_record_tombstone AFTER DELETE ON events
+BEGIN
+  UPDATE sync_change_seq SET value = value + 1;
+  INSERT INTO tombstones (user_id, entity, uuid, change_seq)
+  VALUES (OLD.user_id, 'event', OLD.uuid, (SELECT value FROM sync_change_seq));
+END;
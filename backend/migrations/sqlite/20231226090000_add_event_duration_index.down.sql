@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS events_user_id_duration_idx;
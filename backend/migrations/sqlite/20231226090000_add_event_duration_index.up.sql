@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- The longest of a user's events bounds how far before a range the events
+-- reaching into it can begin, which keeps listings on
+-- events_user_id_date_began_idx. This makes looking it up a single probe.
+CREATE INDEX IF NOT EXISTS events_user_id_duration_idx ON events (user_id, duration);
@@ -0,0 +1,9 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS events_search_delete;
+DROP TRIGGER IF EXISTS events_search_update;
+DROP TRIGGER IF EXISTS events_search_insert;
+DROP TRIGGER IF EXISTS tasks_search_delete;
+DROP TRIGGER IF EXISTS tasks_search_update;
+DROP TRIGGER IF EXISTS tasks_search_insert;
+DROP TABLE IF EXISTS events_search;
+DROP TABLE IF EXISTS tasks_search;
@@ -0,0 +1,48 @@
+-- Add up migration script here
+-- What tasks and events can be searched by, in FTS5 tables that index the
+-- rows of tasks and events without a copy of their text. Triggers keep them
+-- up to date.
+CREATE VIRTUAL TABLE tasks_search USING fts5(
+  name, description, content = 'tasks', content_rowid = 'id', tokenize = 'porter unicode61'
+);
+CREATE VIRTUAL TABLE events_search USING fts5(
+  notes, content = 'events', content_rowid = 'id', tokenize = 'porter unicode61'
+);
+INSERT INTO tasks_search (tasks_search) VALUES ('rebuild');
+INSERT INTO events_search (events_search) VALUES ('rebuild');
+
+CREATE TRIGGER tasks_search_insert AFTER INSERT ON tasks
+BEGIN
+  INSERT INTO tasks_search (rowid, name, description)
+  VALUES (NEW.id, NEW.name, NEW.description);
+END;
+
+CREATE TRIGGER tasks_search_update AFTER UPDATE OF name, description ON tasks
+BEGIN
+  INSERT INTO tasks_search (tasks_search, rowid, name, description)
+  VALUES ('delete', OLD.id, OLD.name, OLD.description);
+  INSERT INTO tasks_search (rowid, name, description)
+  VALUES (NEW.id, NEW.name, NEW.description);
+END;
+
+CREATE TRIGGER tasks_search_delete AFTER DELETE ON tasks
+BEGIN
+  INSERT INTO tasks_search (tasks_search, rowid, name, description)
+  VALUES ('delete', OLD.id, OLD.name, OLD.description);
+END;
+
+CREATE TRIGGER events_search_insert AFTER INSERT ON events
+BEGIN
+  INSERT INTO events_search (rowid, notes) VALUES (NEW.id, NEW.notes);
+END;
+
+CREATE TRIGGER events_search_update AFTER UPDATE OF notes ON events
+BEGIN
+  INSERT INTO events_search (events_search, rowid, notes) VALUES ('delete', OLD.id, OLD.notes);
+  INSERT INTO events_search (rowid, notes) VALUES (NEW.id, NEW.notes);
+END;
+
+CREATE TRIGGER events_search_delete AFTER DELETE ON events
+BEGIN
+  INSERT INTO events_search (events_search, rowid, notes) VALUES ('delete', OLD.id, OLD.notes);
+END;
@@ -0,0 +1,12 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS timers_notify_delete;
+DROP TRIGGER IF EXISTS timers_notify_insert;
+DROP TRIGGER IF EXISTS events_notify_delete;
+DROP TRIGGER IF EXISTS events_notify_update;
+DROP TRIGGER IF EXISTS events_notify_insert;
+DROP TRIGGER IF EXISTS tasks_notify_delete;
+DROP TRIGGER IF EXISTS tasks_notify_update;
+DROP TRIGGER IF EXISTS tasks_notify_insert;
+DROP TRIGGER IF EXISTS notifications_keep_latest;
+DROP TABLE IF EXISTS notifications;
+DROP TABLE IF EXISTS timers;
@@ -0,0 +1,54 @@
+-- Add up migration script here
+-- The timer each user has running, if any
+CREATE TABLE timers (
+  user_id INTEGER PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+  task_id INTEGER NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+  started TEXT NOT NULL,
+  notes TEXT
+);
+CREATE INDEX timers_task_id_idx ON timers (task_id);
+
+-- The changes Postgres sends on its bandit_changes channel. SQLite cannot
+-- notify other connections, so triggers write them here for the server to
+-- poll, and only the latest are kept. Updates count if they set a column
+-- clients show, not the bookkeeping of the sync triggers.
+CREATE TABLE notifications (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  payload TEXT NOT NULL
+);
+CREATE TRIGGER notifications_keep_latest AFTER INSERT ON notifications BEGIN
+  DELETE FROM notifications WHERE id <= NEW.id - 10000;
+END;
+
+CREATE TRIGGER tasks_notify_insert AFTER INSERT ON tasks BEGIN
+  INSERT INTO notifications (payload)
+  VALUES (json_object('user_id', NEW.user_id, 'type', 'task_created', 'id', NEW.id));
+END;
+CREATE TRIGGER tasks_notify_update AFTER UPDATE OF name, description, client_id, tags ON tasks BEGIN
+  INSERT INTO notifications (payload)
+  VALUES (json_object('user_id', NEW.user_id, 'type', 'task_updated', 'id', NEW.id));
+END;
+CREATE TRIGGER tasks_notify_delete AFTER DELETE ON tasks BEGIN
+  INSERT INTO notifications (payload)
+  VALUES (json_object('user_id', OLD.user_id, 'type', 'task_deleted', 'id', OLD.id));
+END;
+CREATE TRIGGER events_notify_insert AFTER INSERT ON events BEGIN
+  INSERT INTO notifications (payload)
+  VALUES (json_object('user_id', NEW.user_id, 'type', 'event_created', 'id', NEW.id, 'task_id', NEW.task_id));
+END;
+CREATE TRIGGER events_notify_update AFTER UPDATE OF task_id, date_began, duration, notes ON events BEGIN
+  INSERT INTO notifications (payload)
+  VALUES (json_object('user_id', NEW.user_id, 'type', 'event_updated', 'id', NEW.id, 'task_id', NEW.task_id));
+END;
+CREATE TRIGGER events_notify_delete AFTER DELETE ON events BEGIN
+  INSERT INTO notifications (payload)
+  VALUES (json_object('user_id', OLD.user_id, 'type', 'event_deleted', 'id', OLD.id, 'task_id', OLD.task_id));
+END;
+CREATE TRIGGER timers_notify_insert AFTER INSERT ON timers BEGIN
+  INSERT INTO notifications (payload)
+  VALUES (json_object('user_id', NEW.user_id, 'type', 'timer_started', 'task_id', NEW.task_id, 'started', NEW.started));
+END;
+CREATE TRIGGER timers_notify_delete AFTER DELETE ON timers BEGIN
+  INSERT INTO notifications (payload)
+  VALUES (json_object('user_id', OLD.user_id, 'type', 'timer_stopped', 'task_id', OLD.task_id));
+END;
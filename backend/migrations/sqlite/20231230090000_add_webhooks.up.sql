@@ -0,0 +1,54 @@
+-- Add up migration script here
+-- Where a user wants their changes posted, and which of them, a JSON array
+CREATE TABLE webhooks (
+  id INTEGER PRIMARY KEY,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  url TEXT NOT NULL,
+  event_types TEXT NOT NULL,
+  secret TEXT NOT NULL,
+  created_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+);
+CREATE INDEX webhooks_user_id_idx ON webhooks (user_id);
+
+-- The queue of changes to post. `change` is the notification the delivery
+-- was queued for, `payload` the body sent, rendered on the first attempt.
+-- Only pending deliveries have a next attempt.
+CREATE TABLE webhook_deliveries (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  webhook_id INTEGER NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+  event_type TEXT NOT NULL,
+  change TEXT NOT NULL,
+  payload TEXT,
+  status TEXT NOT NULL DEFAULT 'pending'
+    CHECK (status IN ('pending', 'delivered', 'failed')),
+  attempts INTEGER NOT NULL DEFAULT 0,
+  next_attempt TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+  created_on TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+);
+CREATE INDEX webhook_deliveries_webhook_id_idx ON webhook_deliveries (webhook_id, id);
+CREATE INDEX webhook_deliveries_next_attempt_idx ON webhook_deliveries (next_attempt)
+  WHERE next_attempt IS NOT NULL;
+
+-- The log of every try at sending a delivery
+CREATE TABLE webhook_attempts (
+  id INTEGER PRIMARY KEY,
+  delivery_id INTEGER NOT NULL REFERENCES webhook_deliveries (id) ON DELETE CASCADE,
+  attempted_on TEXT NOT NULL,
+  status_code INTEGER,
+  error TEXT,
+  duration_ms INTEGER NOT NULL
+);
+CREATE INDEX webhook_attempts_delivery_id_idx ON webhook_attempts (delivery_id);
+
+-- Queues a delivery of each change for each of the user's webhooks that
+-- wants it, in the same transaction as the change
+CREATE TRIGGER notifications_queue_webhooks AFTER INSERT ON notifications BEGIN
+  INSERT INTO webhook_deliveries (webhook_id, event_type, change)
+  SELECT webhooks.id, replace(json_extract(NEW.payload, '$.type'), '_', '.'), NEW.payload
+  FROM webhooks
+  WHERE webhooks.user_id = json_extract(NEW.payload, '$.user_id')
+    AND EXISTS (
+      SELECT 1 FROM json_each(webhooks.event_types)
+      WHERE json_each.value = replace(json_extract(NEW.payload, '$.type'), '_', '.')
+    );
+END;
@@ -0,0 +1,423 @@
+//! Operator commands of the backend binary, e.g. `backend user create`.
+//!
+//! They run against the same database as the server, through `Store`, so a
+//! command behaves exactly like the matching API call would.
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    pin::pin,
+    sync::Arc,
+};
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use clap::{Subcommand, ValueEnum};
+use futures::StreamExt;
+use rand::{distributions::Alphanumeric, seq::SliceRandom, Rng};
+use serde::Serialize;
+
+use crate::{
+    export::{csv_records, EventColumn, ExportColumn},
+    models::{
+        Client, DurationFormat, EventExportQuery, ImportDedupe, ImportRow, LoginDetails,
+        TaskWithTaskEvents, UserEmail, UserId,
+    },
+    store::Store,
+    validation::{FieldErrors, Validate},
+};
+
+type AdminResult = Result<(), Box<dyn Error>>;
+
+/// Length of the passwords generated for new accounts
+const GENERATED_PASSWORD_LENGTH: usize = 20;
+
+/// Tasks of seeded accounts: name, client and tags
+const SEED_TASKS: [(&str, Option<&str>, &[&str]); 6] = [
+    ("Website redesign", Some("Acme"), &["design"]),
+    ("API integration", Some("Acme"), &["dev"]),
+    ("Support", Some("Globex"), &["support"]),
+    ("Bookkeeping", None, &["admin"]),
+    ("Blog post", None, &["writing"]),
+    ("Reading", None, &[]),
+];
+
+/// How far back seeded events go
+const SEED_DAYS: i64 = 90;
+
+#[derive(Subcommand)]
+pub enum AdminCommand {
+    /// Apply, revert or list the database migrations
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Manage accounts
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Manage login sessions
+    #[command(subcommand)]
+    Sessions(SessionsCommand),
+    /// Write all tasks, events and clients of a user to stdout or a file
+    Export {
+        /// Email of the account
+        #[arg(long)]
+        user: String,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Fill an account with random tasks and events, for development
+    Seed {
+        /// Number of events to create
+        #[arg(long = "fake-data", value_name = "N")]
+        fake_data: usize,
+        /// Email of the account, created when it does not exist
+        #[arg(long, default_value = "demo@example.com")]
+        user: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// Apply all pending migrations
+    Up,
+    /// Revert the latest migrations
+    Down {
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+    /// List the migrations and whether they are applied
+    Status,
+}
+
+#[derive(Subcommand)]
+pub enum UserCommand {
+    /// Create an account, printing a generated password unless one is given
+    Create {
+        email: String,
+        /// Read the password from the first line of stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Set a new password and end the user's session
+    ResetPassword {
+        email: String,
+        /// Read the password from the first line of stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Keep the user from logging in, without deleting their data
+    Disable { email: String },
+}
+
+#[derive(Subcommand)]
+pub enum SessionsCommand {
+    /// Log out everybody, or a single user
+    Purge {
+        /// Email of the account
+        #[arg(long)]
+        user: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// Tasks with their events, and clients
+    Json,
+    /// One row per event, as `GET /events/export.csv`
+    Csv,
+}
+
+/// Everything a user owns, as written by `export --format json`
+#[derive(Serialize)]
+struct UserExport {
+    email: UserEmail,
+    exported_at: DateTime<Utc>,
+    tasks: Vec<TaskWithTaskEvents>,
+    clients: Vec<Client>,
+}
+
+pub async fn run(command: AdminCommand, store: Arc<dyn Store>) -> AdminResult {
+    match command {
+        AdminCommand::Migrate(command) => migrate(command, store).await,
+        AdminCommand::User(command) => user(command, store).await,
+        AdminCommand::Sessions(SessionsCommand::Purge { user }) => {
+            match user {
+                Some(email) => {
+                    let user_id = find_user(&*store, &email).await?;
+                    store.delete_user_session(user_id).await?;
+                    println!("Logged out {}", email);
+                }
+                None => {
+                    let purged = store.purge_sessions().await?;
+                    println!("Ended {} sessions", purged);
+                }
+            }
+            Ok(())
+        }
+        AdminCommand::Export {
+            user,
+            format,
+            output,
+        } => export(store, &user, format, output).await,
+        AdminCommand::Seed { fake_data, user } => seed(store, &user, fake_data).await,
+    }
+}
+
+async fn migrate(command: MigrateCommand, store: Arc<dyn Store>) -> AdminResult {
+    match command {
+        MigrateCommand::Up => {
+            store.run_migrations().await?;
+            println!("Database is up to date");
+        }
+        MigrateCommand::Down { steps } => {
+            let mut applied = store.applied_migrations().await?;
+            applied.sort_unstable();
+            // undo reverts every migration newer than the target
+            let target = applied.iter().rev().nth(steps).copied().unwrap_or_default();
+            store.undo_migrations(target).await?;
+            let reverted = applied.iter().filter(|version| **version > target).count();
+            println!("Reverted {} migrations", reverted);
+        }
+        MigrateCommand::Status => {
+            let applied = store.applied_migrations().await?;
+            for migration in store
+                .migrator()
+                .iter()
+                .filter(|migration| migration.migration_type.is_up_migration())
+            {
+                let state = if applied.contains(&migration.version) {
+                    "applied"
+                } else {
+                    "pending"
+                };
+                println!(
+                    "{:<8} {} {}",
+                    state, migration.version, migration.description
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn user(command: UserCommand, store: Arc<dyn Store>) -> AdminResult {
+    match command {
+        UserCommand::Create {
+            email,
+            password_stdin,
+        } => {
+            let password = password(password_stdin)?;
+            let details = LoginDetails {
+                email: UserEmail(email.clone()),
+                password: password.clone().into_string(),
+            };
+            check(&details)?;
+            store.register_account(details).await?;
+            println!("Created {}", email);
+            if let Some(generated) = password.generated() {
+                println!("Password: {}", generated);
+            }
+        }
+        UserCommand::ResetPassword {
+            email,
+            password_stdin,
+        } => {
+            let password = password(password_stdin)?;
+            check(&LoginDetails {
+                email: UserEmail(email.clone()),
+                password: password.clone().into_string(),
+            })?;
+            store
+                .set_password(UserEmail(email.clone()), password.clone().into_string())
+                .await
+                .map_err(|e| not_found(e, &email))?;
+            println!("Reset the password of {}", email);
+            if let Some(generated) = password.generated() {
+                println!("Password: {}", generated);
+            }
+        }
+        UserCommand::Disable { email } => {
+            store
+                .disable_user(UserEmail(email.clone()))
+                .await
+                .map_err(|e| not_found(e, &email))?;
+            println!("Disabled {}", email);
+        }
+    }
+    Ok(())
+}
+
+async fn export(
+    store: Arc<dyn Store>,
+    email: &str,
+    format: ExportFormat,
+    output: Option<PathBuf>,
+) -> AdminResult {
+    let user_id = find_user(&*store, email).await?;
+    let mut output: Box<dyn Write> = match &output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    match format {
+        ExportFormat::Json => {
+            let export = UserExport {
+                email: UserEmail(email.to_string()),
+                exported_at: Utc::now(),
+                tasks: store.get_user_tasks_with_events(user_id.clone()).await?,
+                clients: store.get_clients_by_user(user_id).await?,
+            };
+            serde_json::to_writer_pretty(&mut output, &export)?;
+            writeln!(output)?;
+        }
+        ExportFormat::Csv => {
+            let query = EventExportQuery {
+                from: None,
+                to: None,
+                tz: "UTC".to_string(),
+                columns: None,
+                duration_format: DurationFormat::Seconds,
+            };
+            let rows = store.stream_events_for_export(user_id, query);
+            let mut records = pin!(csv_records(
+                EventColumn::ALL.to_vec(),
+                DurationFormat::Seconds,
+                rows
+            ));
+            while let Some(record) = records.next().await {
+                output.write_all(&record?)?;
+            }
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
+async fn seed(store: Arc<dyn Store>, email: &str, events: usize) -> AdminResult {
+    let user_id = match store.get_user_id(UserEmail(email.to_string())).await {
+        Ok(user_id) => user_id,
+        Err(sqlx::Error::RowNotFound) => {
+            let password = generate_password();
+            store
+                .register_account(LoginDetails {
+                    email: UserEmail(email.to_string()),
+                    password: password.clone(),
+                })
+                .await?;
+            println!("Created {} with password {}", email, password);
+            store.get_user_id(UserEmail(email.to_string())).await?
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let report = store
+        .import_events(user_id, fake_events(events), ImportDedupe::None, false)
+        .await?;
+    println!(
+        "Created {} events, {} tasks and {} clients for {}",
+        report.imported,
+        report.created_tasks.len(),
+        report.created_clients.len(),
+        email
+    );
+    if !report.errors.is_empty() {
+        println!(
+            "Left out {} events that overlap others, as the account's policy asks",
+            report.errors.len()
+        );
+    }
+    Ok(())
+}
+
+/// Events on working hours of the last `SEED_DAYS` days, in steps of quarter
+/// hours like people tend to book them
+fn fake_events(count: usize) -> Vec<ImportRow> {
+    let mut rng = rand::thread_rng();
+    let today = Utc::now().duration_trunc(Duration::days(1)).unwrap();
+    (0..count)
+        .map(|index| {
+            let (task, client, tags) = SEED_TASKS.choose(&mut rng).unwrap();
+            let date_began = today - Duration::days(rng.gen_range(0..SEED_DAYS))
+                + Duration::minutes(rng.gen_range(8 * 4..18 * 4) * 15);
+            ImportRow {
+                row: index + 1,
+                uuid: None,
+                task_name: task.to_string(),
+                client_name: client.map(str::to_string),
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                date_began,
+                duration: rng.gen_range(1..=16) * 15 * 60,
+                notes: rng.gen_bool(0.3).then(|| "Seeded".to_string()),
+                ical_uid: None,
+            }
+        })
+        .collect()
+}
+
+async fn find_user(store: &dyn Store, email: &str) -> Result<UserId, Box<dyn Error>> {
+    store
+        .get_user_id(UserEmail(email.to_string()))
+        .await
+        .map_err(|e| not_found(e, email))
+}
+
+/// Applies the rules of the API, so accounts can log in from the frontend
+fn check(details: &LoginDetails) -> Result<(), Box<dyn Error>> {
+    let mut errors = FieldErrors::default();
+    details.validate(&mut errors);
+    if !errors.is_empty() {
+        return Err(errors.to_string().into());
+    }
+    Ok(())
+}
+
+fn not_found(e: sqlx::Error, email: &str) -> Box<dyn Error> {
+    match e {
+        sqlx::Error::RowNotFound => format!("No user with email {}", email).into(),
+        e => e.into(),
+    }
+}
+
+/// A password given on stdin, or a generated one to show the operator
+#[derive(Clone)]
+enum Password {
+    Given(String),
+    Generated(String),
+}
+
+impl Password {
+    fn into_string(self) -> String {
+        match self {
+            Password::Given(password) | Password::Generated(password) => password,
+        }
+    }
+
+    fn generated(&self) -> Option<&str> {
+        match self {
+            Password::Generated(password) => Some(password),
+            Password::Given(_) => None,
+        }
+    }
+}
+
+fn password(from_stdin: bool) -> Result<Password, Box<dyn Error>> {
+    if !from_stdin {
+        return Ok(Password::Generated(generate_password()));
+    }
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    let password = line.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err("The password must not be empty".into());
+    }
+    Ok(Password::Given(password.to_string()))
+}
+
+fn generate_password() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(GENERATED_PASSWORD_LENGTH)
+        .map(char::from)
+        .collect()
+}
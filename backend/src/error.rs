@@ -0,0 +1,216 @@
+//! The error type of all routes.
+//!
+//! Errors are sent as an `ApiError` body. Database errors are classified by
+//! the constraint they violate, whichever backend raised them; their text
+//! stays in the server log, since it names tables and constraints.
+
+use axum::{
+    extract::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::{header::HeaderName, HeaderValue, StatusCode};
+use serde_json::Value;
+use sqlx::error::ErrorKind;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::models::ApiError;
+
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Request ids sent by clients longer than this are replaced
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+#[derive(Debug)]
+pub enum AppError {
+    /// 400, the request is malformed, e.g. an unknown time zone
+    BadRequest(String),
+    /// 401, no valid session
+    Unauthorized,
+    /// 404, the resource does not exist or belongs to another user
+    NotFound(String),
+    /// 409, the request clashes with existing data; `details` may hold what
+    /// it clashes with
+    Conflict {
+        message: String,
+        details: Option<Value>,
+    },
+    /// 422, the request is well formed but its values are not acceptable;
+    /// `details` maps the fields to what is wrong with them
+    Unprocessable {
+        message: String,
+        details: Option<Value>,
+    },
+    /// classified by `database_status`
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        AppError::Database(e)
+    }
+}
+
+impl AppError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::Unprocessable { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Database(e) => database_status(e),
+        }
+    }
+
+    /// The status and body of the response to the error
+    pub fn into_api_error(self) -> (StatusCode, ApiError) {
+        let status = self.status();
+        let (message, details) = match self {
+            AppError::BadRequest(message) | AppError::NotFound(message) => (message, None),
+            AppError::Unauthorized => ("Unauthorized".to_string(), None),
+            AppError::Conflict { message, details }
+            | AppError::Unprocessable { message, details } => (message, details),
+            AppError::Database(e) => {
+                if status.is_server_error() {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                } else {
+                    tracing::event!(tracing::Level::WARN, "{:?}", e);
+                }
+                let message = match status {
+                    StatusCode::NOT_FOUND => "Not found",
+                    StatusCode::CONFLICT => "This conflicts with existing data",
+                    StatusCode::UNPROCESSABLE_ENTITY => {
+                        "A value is invalid or refers to something that does not exist"
+                    }
+                    _ => "Internal server error",
+                };
+                (message.to_string(), None)
+            }
+        };
+        let body = ApiError {
+            code: code(status).to_string(),
+            message,
+            details,
+            request_id: REQUEST_ID.try_with(|id| id.clone()).ok(),
+        };
+        (status, body)
+    }
+}
+
+/// Maps the errors a client can cause to 4xx, anything else is on us
+fn database_status(e: &sqlx::Error) -> StatusCode {
+    match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        sqlx::Error::Database(e) => match e.kind() {
+            ErrorKind::UniqueViolation => StatusCode::CONFLICT,
+            ErrorKind::ForeignKeyViolation
+            | ErrorKind::NotNullViolation
+            | ErrorKind::CheckViolation => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => match e.code().as_deref() {
+                // Postgres: exclusion_violation
+                Some("23P01") => StatusCode::CONFLICT,
+                // Postgres: string too long, numeric out of range, bad text or
+                // datetime
+                Some("22001") | Some("22003") | Some("22P02") | Some("22007") | Some("22008") => {
+                    StatusCode::UNPROCESSABLE_ENTITY
+                }
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+        },
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "bad_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::UNPROCESSABLE_ENTITY => "unprocessable",
+        _ => "internal_error",
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, body) = self.into_api_error();
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Tags each request with an id, taken from the `x-request-id` header or
+/// generated, which is echoed in the response, the log and error bodies
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let span = tracing::info_span!(
+        "request",
+        id = %id,
+        method = %request.method(),
+        path = %request.uri().path()
+    );
+    let mut response = REQUEST_ID
+        .scope(id.clone(), next.run(request))
+        .instrument(span)
+        .await;
+    if let Ok(id) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(X_REQUEST_ID.clone(), id);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn render(error: AppError) -> (StatusCode, ApiError) {
+        let response = REQUEST_ID
+            .scope("req-1".to_string(), async { error.into_response() })
+            .await;
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn renders_the_stable_body() {
+        let (status, body) = render(AppError::NotFound("Unknown calendar".to_string())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            ApiError {
+                code: "not_found".to_string(),
+                message: "Unknown calendar".to_string(),
+                details: None,
+                request_id: Some("req-1".to_string()),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn hides_database_errors() {
+        let (status, body) = render(sqlx::Error::RowNotFound.into()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body.message, "Not found");
+
+        let (status, body) = render(sqlx::Error::PoolTimedOut.into()).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body.code, "internal_error");
+        assert_eq!(body.message, "Internal server error");
+    }
+}
@@ -0,0 +1,230 @@
+use axum::{
+    body::{Body, Bytes},
+    response::Response,
+};
+use futures::{stream, Stream, StreamExt};
+use http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+
+use crate::models::{DurationFormat, EventExportRow, ReportBucket};
+
+/// A column of a CSV export, selectable by name in the `columns` query parameter
+pub trait ExportColumn: Copy + Send + Sync + 'static {
+    type Row;
+    /// Every column in the order used when no `columns` are requested
+    const ALL: &'static [Self];
+
+    fn name(self) -> &'static str;
+
+    fn value(self, row: &Self::Row, duration_format: DurationFormat) -> String;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventColumn {
+    Id,
+    Uuid,
+    TaskId,
+    Task,
+    Client,
+    Tags,
+    Date,
+    Start,
+    End,
+    Duration,
+    Notes,
+}
+
+impl ExportColumn for EventColumn {
+    type Row = EventExportRow;
+    const ALL: &'static [Self] = &[
+        EventColumn::Id,
+        EventColumn::Uuid,
+        EventColumn::TaskId,
+        EventColumn::Task,
+        EventColumn::Client,
+        EventColumn::Tags,
+        EventColumn::Date,
+        EventColumn::Start,
+        EventColumn::End,
+        EventColumn::Duration,
+        EventColumn::Notes,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            EventColumn::Id => "id",
+            EventColumn::Uuid => "uuid",
+            EventColumn::TaskId => "task_id",
+            EventColumn::Task => "task",
+            EventColumn::Client => "client",
+            EventColumn::Tags => "tags",
+            EventColumn::Date => "date",
+            EventColumn::Start => "start",
+            EventColumn::End => "end",
+            EventColumn::Duration => "duration",
+            EventColumn::Notes => "notes",
+        }
+    }
+
+    fn value(self, row: &EventExportRow, duration_format: DurationFormat) -> String {
+        match self {
+            EventColumn::Id => row.id.0.to_string(),
+            EventColumn::Uuid => row.uuid.to_string(),
+            EventColumn::TaskId => row.task_id.0.to_string(),
+            EventColumn::Task => row.task_name.clone(),
+            EventColumn::Client => row.client_name.clone().unwrap_or_default(),
+            EventColumn::Tags => row.tags.join(";"),
+            EventColumn::Date => row.date.clone(),
+            EventColumn::Start => row.start_time.clone(),
+            EventColumn::End => row.end_time.clone(),
+            EventColumn::Duration => format_duration(row.duration, duration_format),
+            EventColumn::Notes => row.notes.clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportColumn {
+    Key,
+    Id,
+    Start,
+    Duration,
+}
+
+impl ExportColumn for ReportColumn {
+    type Row = ReportBucket;
+    const ALL: &'static [Self] = &[
+        ReportColumn::Key,
+        ReportColumn::Id,
+        ReportColumn::Start,
+        ReportColumn::Duration,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            ReportColumn::Key => "key",
+            ReportColumn::Id => "id",
+            ReportColumn::Start => "start",
+            ReportColumn::Duration => "duration",
+        }
+    }
+
+    fn value(self, row: &ReportBucket, duration_format: DurationFormat) -> String {
+        match self {
+            ReportColumn::Key => row.key.clone(),
+            ReportColumn::Id => row.id.map(|id| id.to_string()).unwrap_or_default(),
+            ReportColumn::Start => row
+                .start
+                .map(|start| start.to_rfc3339())
+                .unwrap_or_default(),
+            ReportColumn::Duration => format_duration(row.total_duration, duration_format),
+        }
+    }
+}
+
+/// Parses a comma separated list of column names, keeping the requested order
+pub fn parse_columns<C: ExportColumn>(columns: Option<&str>) -> Result<Vec<C>, String> {
+    let Some(columns) = columns.filter(|columns| !columns.trim().is_empty()) else {
+        return Ok(C::ALL.to_vec());
+    };
+    columns
+        .split(',')
+        .map(|name| {
+            let name = name.trim();
+            C::ALL
+                .iter()
+                .find(|column| column.name() == name)
+                .copied()
+                .ok_or_else(|| {
+                    let known: Vec<&str> = C::ALL.iter().map(|column| column.name()).collect();
+                    format!(
+                        "Unknown column `{}`, expected one of: {}",
+                        name,
+                        known.join(", ")
+                    )
+                })
+        })
+        .collect()
+}
+
+pub fn format_duration(seconds: i64, duration_format: DurationFormat) -> String {
+    match duration_format {
+        DurationFormat::Seconds => seconds.to_string(),
+        DurationFormat::Hours => format!("{:.2}", seconds as f64 / 3600.0),
+        DurationFormat::Hhmm => {
+            let sign = if seconds < 0 { "-" } else { "" };
+            let minutes = seconds.abs() / 60;
+            format!("{}{}:{:02}", sign, minutes / 60, minutes % 60)
+        }
+    }
+}
+
+/// Prefixes cells a spreadsheet would evaluate as a formula with `'`, so an
+/// exported task named `=HYPERLINK(...)` opens as text
+fn escape_formula(value: String) -> String {
+    if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value
+    }
+}
+
+fn csv_record<I, T>(fields: I) -> Bytes
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(fields)
+        .expect("writing to a Vec cannot fail");
+    Bytes::from(writer.into_inner().expect("writing to a Vec cannot fail"))
+}
+
+/// Builds a chunked `text/csv` response which writes each row as it arrives
+/// from the database
+pub fn csv_response<C, S>(
+    filename: &str,
+    columns: Vec<C>,
+    duration_format: DurationFormat,
+    rows: S,
+) -> Response
+where
+    C: ExportColumn,
+    S: Stream<Item = Result<C::Row, sqlx::Error>> + Send + 'static,
+{
+    Response::builder()
+        .header(CONTENT_TYPE, "text/csv; charset=utf-8")
+        .header(
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(Body::from_stream(csv_records(
+            columns,
+            duration_format,
+            rows,
+        )))
+        .unwrap()
+}
+
+/// The header and then one CSV record per row
+pub fn csv_records<C, S>(
+    columns: Vec<C>,
+    duration_format: DurationFormat,
+    rows: S,
+) -> impl Stream<Item = Result<Bytes, sqlx::Error>>
+where
+    C: ExportColumn,
+    S: Stream<Item = Result<C::Row, sqlx::Error>>,
+{
+    let header = csv_record(columns.iter().map(|column| column.name()));
+    let records = rows.map(move |row| {
+        row.map(|row| {
+            csv_record(
+                columns
+                    .iter()
+                    .map(|column| escape_formula(column.value(&row, duration_format))),
+            )
+        })
+    });
+    stream::once(async move { Ok(header) }).chain(records)
+}
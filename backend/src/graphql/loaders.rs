@@ -0,0 +1,115 @@
+//! Batched fetching of the user's data. Every key asked for while a query
+//! resolves one level is loaded with one call to the store.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use async_graphql::dataloader::Loader;
+
+use crate::{
+    error::AppError,
+    models::{Client, ClientId, TaskEvent, TaskId, TaskWithTaskEvents, UserId},
+    store::Store,
+};
+
+pub struct Loaders {
+    store: Arc<dyn Store>,
+    user_id: UserId,
+}
+
+impl Loaders {
+    pub fn new(store: Arc<dyn Store>, user_id: UserId) -> Loaders {
+        Loaders { store, user_id }
+    }
+}
+
+/// The events of a task, the latest first, and only the latest `last` of
+/// them if it is set
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TaskEvents {
+    pub task_id: TaskId,
+    pub last: Option<i64>,
+}
+
+/// Tasks with their totals but without their events
+impl Loader<TaskId> for Loaders {
+    type Value = TaskWithTaskEvents;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[TaskId]) -> Result<HashMap<TaskId, Self::Value>, Self::Error> {
+        let tasks = self
+            .store
+            .get_tasks_by_ids(self.user_id.clone(), keys.to_vec())
+            .await
+            .map_err(AppError::from)?;
+        Ok(tasks
+            .into_iter()
+            .map(|task| (task.task.id.clone(), task))
+            .collect())
+    }
+}
+
+impl Loader<TaskEvents> for Loaders {
+    type Value = Vec<TaskEvent>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[TaskEvents],
+    ) -> Result<HashMap<TaskEvents, Self::Value>, Self::Error> {
+        // one call for each `last` asked for, which is usually just one
+        let mut by_last: BTreeMap<Option<i64>, Vec<TaskId>> = BTreeMap::new();
+        for key in keys {
+            by_last
+                .entry(key.last)
+                .or_default()
+                .push(key.task_id.clone());
+        }
+        let mut loaded = HashMap::new();
+        for (last, task_ids) in by_last {
+            for task_id in &task_ids {
+                loaded.insert(
+                    TaskEvents {
+                        task_id: task_id.clone(),
+                        last,
+                    },
+                    vec![],
+                );
+            }
+            let events = self
+                .store
+                .get_events_of_tasks(self.user_id.clone(), task_ids, last)
+                .await
+                .map_err(AppError::from)?;
+            for event in events {
+                let key = TaskEvents {
+                    task_id: event.task_id.clone(),
+                    last,
+                };
+                loaded.entry(key).or_insert_with(Vec::new).push(event);
+            }
+        }
+        Ok(loaded)
+    }
+}
+
+/// Clients are few, so all of the user's are fetched
+impl Loader<ClientId> for Loaders {
+    type Value = Client;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[ClientId]) -> Result<HashMap<ClientId, Self::Value>, Self::Error> {
+        let clients = self
+            .store
+            .get_clients_by_user(self.user_id.clone())
+            .await
+            .map_err(AppError::from)?;
+        Ok(clients
+            .into_iter()
+            .filter(|client| keys.contains(&client.id))
+            .map(|client| (client.id.clone(), client))
+            .collect())
+    }
+}
@@ -0,0 +1,57 @@
+//! The GraphQL API at `/graphql`, for clients that want other shapes than
+//! the REST routes give, such as tasks with only their latest events.
+//!
+//! Queries and mutations go through the same handlers as the REST routes, so
+//! both check and store things the same way. Tasks, events and clients
+//! reached from many places in one response are fetched through
+//! `DataLoader`s, which gather the ids asked for and get them from the store
+//! at once. Queries nesting deeper than `GRAPHQL_MAX_DEPTH`, or costing more
+//! than `GRAPHQL_MAX_COMPLEXITY`, are refused before they run.
+
+use async_graphql::{
+    dataloader::DataLoader, EmptySubscription, ErrorExtensionValues, Request, Schema, Value,
+};
+
+use crate::{config::GraphqlConfig, error::AppError, models::UserId, AppState};
+
+mod loaders;
+mod schema;
+
+pub use schema::{MutationRoot, QueryRoot};
+
+pub type BanditSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+pub fn schema(config: &GraphqlConfig) -> BanditSchema {
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .limit_depth(config.max_depth)
+        .limit_complexity(config.max_complexity)
+        .finish()
+}
+
+/// A request with what its resolvers need: the app, the user and loaders of
+/// that user's data, which only batch and keep nothing between requests
+pub fn prepare(request: Request, state: &AppState, user_id: UserId) -> Request {
+    let loaders = loaders::Loaders::new(state.store.clone(), user_id.clone());
+    request
+        .data(state.clone())
+        .data(user_id)
+        .data(DataLoader::new(loaders, tokio::spawn))
+}
+
+/// Errors carry the `code` and `details` of the REST API's `ApiError` in
+/// their extensions
+impl From<AppError> for async_graphql::Error {
+    fn from(e: AppError) -> Self {
+        let (_, body) = e.into_api_error();
+        let mut extensions = ErrorExtensionValues::default();
+        extensions.set("code", body.code);
+        if let Some(details) = body.details {
+            extensions.set("details", Value::from_json(details).unwrap_or_default());
+        }
+        async_graphql::Error {
+            message: body.message,
+            source: None,
+            extensions: Some(extensions),
+        }
+    }
+}
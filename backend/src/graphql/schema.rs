@@ -0,0 +1,663 @@
+//! The types, queries and mutations of the GraphQL API. Ids are the same
+//! integers as in the REST API, and durations are in seconds.
+
+use async_graphql::{
+    dataloader::DataLoader, Context, Enum, InputObject, Object, Result, SimpleObject,
+};
+use axum::{extract::State, Extension};
+use chrono::{DateTime, NaiveDate, Utc};
+use uuid::Uuid;
+
+use super::loaders::{Loaders, TaskEvents};
+use crate::{
+    error::AppError,
+    extract::{Json, Path, Query},
+    models::{self, default_timezone, ClientId, IncludeEvents, TaskEventId, TaskId, UserId},
+    routes::{self, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE},
+    validation::Valid,
+    AppState,
+};
+
+/// How many times a list of up to `limit` items counts in the complexity of
+/// a query
+fn list_cost(limit: Option<i64>) -> usize {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize
+}
+
+fn state<'a>(ctx: &Context<'a>) -> &'a AppState {
+    ctx.data_unchecked::<AppState>()
+}
+
+fn user_id(ctx: &Context<'_>) -> UserId {
+    ctx.data_unchecked::<UserId>().clone()
+}
+
+fn loader<'a>(ctx: &Context<'a>) -> &'a DataLoader<Loaders> {
+    ctx.data_unchecked::<DataLoader<Loaders>>()
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "models::TaskSort")]
+enum TaskSort {
+    UpdatedOn,
+    CreatedOn,
+    Name,
+    TotalDuration,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "models::ReportGroupBy")]
+enum ReportGroupBy {
+    Day,
+    Week,
+    Month,
+    Task,
+    Tag,
+    Project,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "models::OverlapPolicy")]
+enum OverlapPolicy {
+    Allow,
+    Reject,
+    Trim,
+}
+
+/// The user the session belongs to
+struct User;
+
+#[Object]
+impl User {
+    async fn id(&self, ctx: &Context<'_>) -> i32 {
+        user_id(ctx).0
+    }
+
+    async fn email(&self, ctx: &Context<'_>) -> Result<String> {
+        let email = state(ctx).store.get_user_email(user_id(ctx)).await;
+        Ok(email.map_err(AppError::from)?.0)
+    }
+
+    async fn settings(&self, ctx: &Context<'_>) -> Result<Settings> {
+        let Json(settings) =
+            routes::users::get_user_settings(State(state(ctx).clone()), Extension(user_id(ctx)))
+                .await?;
+        Ok(settings.into())
+    }
+
+    /// The running timer, if there is one
+    async fn timer(&self, ctx: &Context<'_>) -> Result<Option<Timer>> {
+        let Json(timer) =
+            routes::timer::get_timer(State(state(ctx).clone()), Extension(user_id(ctx))).await?;
+        Ok(timer.map(Timer))
+    }
+
+    async fn clients(&self, ctx: &Context<'_>) -> Result<Vec<Client>> {
+        let Json(clients) =
+            routes::clients::get_user_clients(State(state(ctx).clone()), Extension(user_id(ctx)))
+                .await?;
+        Ok(clients.into_iter().map(Client).collect())
+    }
+}
+
+#[derive(SimpleObject)]
+struct Settings {
+    overlap_policy: OverlapPolicy,
+}
+
+impl From<models::UserSettings> for Settings {
+    fn from(settings: models::UserSettings) -> Self {
+        Settings {
+            overlap_policy: settings.overlap_policy.into(),
+        }
+    }
+}
+
+/// A task with its totals, its events are loaded when asked for
+struct Task(models::TaskWithTaskEvents);
+
+#[Object]
+impl Task {
+    async fn id(&self) -> i32 {
+        self.0.task.id.0
+    }
+
+    async fn uuid(&self) -> Uuid {
+        self.0.task.uuid
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.task.name
+    }
+
+    async fn description(&self) -> &str {
+        &self.0.task.description
+    }
+
+    async fn tags(&self) -> &[String] {
+        &self.0.task.tags
+    }
+
+    async fn created_on(&self) -> DateTime<Utc> {
+        self.0.task.created_on
+    }
+
+    /// When its latest event began, or when it was created if it has none
+    async fn updated_on(&self) -> DateTime<Utc> {
+        self.0.updated_on
+    }
+
+    /// Of all of its events
+    async fn total_duration(&self) -> i64 {
+        self.0.total_duration
+    }
+
+    async fn client(&self, ctx: &Context<'_>) -> Result<Option<Client>> {
+        let Some(client_id) = self.0.task.client_id.clone() else {
+            return Ok(None);
+        };
+        Ok(loader(ctx).load_one(client_id).await?.map(Client))
+    }
+
+    /// The latest `last` events, the latest first. Without `last`, as many as
+    /// a page may have.
+    #[graphql(complexity = "list_cost(last.or(Some(MAX_PAGE_SIZE))) * child_complexity")]
+    async fn events(&self, ctx: &Context<'_>, last: Option<i64>) -> Result<Vec<Event>> {
+        // the cost of the query counted no more than this
+        let last = last.unwrap_or(MAX_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&last) {
+            return Err(format!("`last` must be between 1 and {}", MAX_PAGE_SIZE).into());
+        }
+        let key = TaskEvents {
+            task_id: self.0.task.id.clone(),
+            last: Some(last),
+        };
+        let events = loader(ctx).load_one(key).await?.unwrap_or_default();
+        Ok(events.into_iter().map(Event::from).collect())
+    }
+}
+
+struct Event {
+    event: models::TaskEvent,
+    duration_in_range: Option<i64>,
+}
+
+impl From<models::TaskEvent> for Event {
+    fn from(event: models::TaskEvent) -> Self {
+        Event {
+            event,
+            duration_in_range: None,
+        }
+    }
+}
+
+impl From<models::ListedEvent> for Event {
+    fn from(listed: models::ListedEvent) -> Self {
+        Event {
+            event: listed.event,
+            duration_in_range: listed.duration_in_range,
+        }
+    }
+}
+
+#[Object]
+impl Event {
+    async fn id(&self) -> i32 {
+        self.event.id.0
+    }
+
+    async fn uuid(&self) -> Uuid {
+        self.event.uuid
+    }
+
+    async fn task(&self, ctx: &Context<'_>) -> Result<Option<Task>> {
+        Ok(loader(ctx)
+            .load_one(self.event.task_id.clone())
+            .await?
+            .map(Task))
+    }
+
+    async fn date_began(&self) -> DateTime<Utc> {
+        self.event.date_began
+    }
+
+    async fn duration(&self) -> i64 {
+        self.event.duration
+    }
+
+    async fn notes(&self) -> Option<&str> {
+        self.event.notes.as_deref()
+    }
+
+    /// With `clip`, the part of the duration within the range listed
+    async fn duration_in_range(&self) -> Option<i64> {
+        self.duration_in_range
+    }
+}
+
+struct Client(models::Client);
+
+#[Object]
+impl Client {
+    async fn id(&self) -> i32 {
+        self.0.id.0
+    }
+
+    async fn uuid(&self) -> Uuid {
+        self.0.uuid
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn email(&self) -> Option<&str> {
+        self.0.email.as_deref()
+    }
+
+    async fn phone(&self) -> Option<&str> {
+        self.0.phone.as_deref()
+    }
+
+    async fn address(&self) -> Option<&str> {
+        self.0.address.as_deref()
+    }
+
+    /// Hourly, in the smallest unit of the currency
+    async fn default_rate(&self) -> Option<i64> {
+        self.0.default_rate
+    }
+
+    async fn created_on(&self) -> DateTime<Utc> {
+        self.0.created_on
+    }
+}
+
+struct Timer(models::Timer);
+
+#[Object]
+impl Timer {
+    async fn task(&self, ctx: &Context<'_>) -> Result<Option<Task>> {
+        Ok(loader(ctx)
+            .load_one(self.0.task_id.clone())
+            .await?
+            .map(Task))
+    }
+
+    async fn started(&self) -> DateTime<Utc> {
+        self.0.started
+    }
+
+    async fn notes(&self) -> Option<&str> {
+        self.0.notes.as_deref()
+    }
+}
+
+/// `next_cursor` is `null` on the last page
+#[derive(SimpleObject)]
+struct TaskPage {
+    tasks: Vec<Task>,
+    next_cursor: Option<String>,
+}
+
+/// `next_cursor` is `null` on the last page
+#[derive(SimpleObject)]
+struct EventPage {
+    events: Vec<Event>,
+    next_cursor: Option<String>,
+}
+
+struct Report(models::ReportSummary);
+
+#[Object]
+impl Report {
+    async fn from(&self) -> NaiveDate {
+        self.0.from
+    }
+
+    async fn to(&self) -> NaiveDate {
+        self.0.to
+    }
+
+    async fn group_by(&self) -> ReportGroupBy {
+        self.0.group_by.into()
+    }
+
+    async fn tz(&self) -> &str {
+        &self.0.tz
+    }
+
+    async fn buckets(&self) -> Vec<ReportBucket> {
+        self.0.buckets.iter().cloned().map(ReportBucket).collect()
+    }
+
+    async fn total_duration(&self) -> i64 {
+        self.0.total_duration
+    }
+}
+
+struct ReportBucket(models::ReportBucket);
+
+#[Object]
+impl ReportBucket {
+    /// The local date the bucket starts on, or the task, tag or client name
+    async fn key(&self) -> &str {
+        &self.0.key
+    }
+
+    /// The task or client id when grouping by task or project
+    async fn id(&self) -> Option<i32> {
+        self.0.id
+    }
+
+    /// The instant a bucket of a day, week or month starts at
+    async fn start(&self) -> Option<DateTime<Utc>> {
+        self.0.start
+    }
+
+    async fn total_duration(&self) -> i64 {
+        self.0.total_duration
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn me(&self) -> User {
+        User
+    }
+
+    /// A page of the user's tasks, see `GET /tasks`
+    #[graphql(complexity = "list_cost(limit) * child_complexity")]
+    #[allow(clippy::too_many_arguments)]
+    async fn tasks(
+        &self,
+        ctx: &Context<'_>,
+        sort: Option<TaskSort>,
+        q: Option<String>,
+        updated_from: Option<NaiveDate>,
+        updated_to: Option<NaiveDate>,
+        tz: Option<String>,
+        cursor: Option<String>,
+        limit: Option<i64>,
+    ) -> Result<TaskPage> {
+        let query = models::TaskListQuery {
+            sort: sort.map(Into::into).unwrap_or_default(),
+            q,
+            updated_from,
+            updated_to,
+            tz: tz.unwrap_or_else(default_timezone),
+            // they are loaded when asked for
+            include_events: IncludeEvents::None,
+            cursor,
+            limit,
+        };
+        let Json(page) = routes::tasks::list_tasks(
+            State(state(ctx).clone()),
+            Extension(user_id(ctx)),
+            Query(query),
+        )
+        .await?;
+        Ok(TaskPage {
+            tasks: page.tasks.into_iter().map(Task).collect(),
+            next_cursor: page.next_cursor,
+        })
+    }
+
+    async fn task(&self, ctx: &Context<'_>, id: i32) -> Result<Option<Task>> {
+        Ok(loader(ctx).load_one(TaskId(id)).await?.map(Task))
+    }
+
+    /// A page of the user's events overlapping a range, see `GET /events`
+    #[graphql(complexity = "list_cost(limit) * child_complexity")]
+    #[allow(clippy::too_many_arguments)]
+    async fn events(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        tz: Option<String>,
+        task_id: Option<i32>,
+        tag: Option<String>,
+        #[graphql(default)] clip: bool,
+        cursor: Option<String>,
+        limit: Option<i64>,
+    ) -> Result<EventPage> {
+        let query = models::EventListQuery {
+            from,
+            to,
+            tz: tz.unwrap_or_else(default_timezone),
+            task_id: task_id.map(TaskId),
+            tag,
+            clip,
+            cursor,
+            limit,
+        };
+        let Json(page) = routes::events::list_events(
+            State(state(ctx).clone()),
+            Extension(user_id(ctx)),
+            Query(query),
+        )
+        .await?;
+        Ok(EventPage {
+            events: page.events.into_iter().map(Event::from).collect(),
+            next_cursor: page.next_cursor,
+        })
+    }
+
+    async fn event(&self, ctx: &Context<'_>, id: i32) -> Result<Option<Event>> {
+        let event = routes::events::get_event(
+            State(state(ctx).clone()),
+            Extension(user_id(ctx)),
+            Path(TaskEventId(id)),
+        )
+        .await;
+        match event {
+            Ok(Json(event)) => Ok(Some(event.into())),
+            Err(AppError::Database(sqlx::Error::RowNotFound)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Totals of the user's time between two dates, see `GET /reports/summary`
+    async fn report(
+        &self,
+        ctx: &Context<'_>,
+        from: NaiveDate,
+        to: NaiveDate,
+        group_by: Option<ReportGroupBy>,
+        tz: Option<String>,
+    ) -> Result<Report> {
+        let query = models::ReportQuery {
+            from,
+            to,
+            group_by: group_by.map(Into::into).unwrap_or_default(),
+            tz: tz.unwrap_or_else(default_timezone),
+        };
+        let Json(summary) = routes::reports::get_report_summary(
+            State(state(ctx).clone()),
+            Extension(user_id(ctx)),
+            Query(query),
+        )
+        .await?;
+        Ok(Report(summary))
+    }
+}
+
+#[derive(InputObject)]
+struct TaskInput {
+    name: String,
+    description: Option<String>,
+    client_id: Option<i32>,
+    #[graphql(default)]
+    tags: Vec<String>,
+}
+
+impl TaskInput {
+    fn into_new_task(self, user_id: UserId) -> models::NewTask {
+        models::NewTask {
+            user_id,
+            name: self.name,
+            description: self.description,
+            client_id: self.client_id.map(ClientId),
+            tags: self.tags,
+        }
+    }
+}
+
+#[derive(InputObject)]
+struct EventInput {
+    task_id: i32,
+    date_began: DateTime<Utc>,
+    duration: i64,
+    notes: Option<String>,
+}
+
+#[derive(InputObject)]
+struct ClientInput {
+    name: String,
+    email: Option<String>,
+    phone: Option<String>,
+    address: Option<String>,
+    default_rate: Option<i64>,
+}
+
+impl ClientInput {
+    fn into_new_client(self, user_id: UserId) -> models::NewClient {
+        models::NewClient {
+            user_id,
+            name: self.name,
+            email: self.email,
+            phone: self.phone,
+            address: self.address,
+            default_rate: self.default_rate,
+        }
+    }
+}
+
+/// A task as it is now, looked up without the loaders so that the totals
+/// are those after the mutation
+async fn current_task(ctx: &Context<'_>, task_id: TaskId) -> Result<Task> {
+    let mut tasks = state(ctx)
+        .store
+        .get_tasks_by_ids(user_id(ctx), vec![task_id])
+        .await
+        .map_err(AppError::from)?;
+    let task = tasks.pop().ok_or(sqlx::Error::RowNotFound);
+    Ok(Task(task.map_err(AppError::from)?))
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    async fn add_task(&self, ctx: &Context<'_>, input: TaskInput) -> Result<Task> {
+        let new_task = Valid::check(input.into_new_task(user_id(ctx)))?;
+        let Json(task) =
+            routes::tasks::add_task(State(state(ctx).clone()), Extension(user_id(ctx)), new_task)
+                .await?;
+        current_task(ctx, task.id).await
+    }
+
+    async fn update_task(&self, ctx: &Context<'_>, id: i32, input: TaskInput) -> Result<Task> {
+        let new_task = Valid::check(input.into_new_task(user_id(ctx)))?;
+        let Json(task) = routes::tasks::update_task(
+            State(state(ctx).clone()),
+            Extension(user_id(ctx)),
+            Path(TaskId(id)),
+            new_task,
+        )
+        .await?;
+        current_task(ctx, task.id).await
+    }
+
+    /// Under the user's overlap policy, see `POST /events`
+    async fn add_event(&self, ctx: &Context<'_>, input: EventInput) -> Result<Event> {
+        let new_event = Valid::check(models::NewTaskEvent {
+            user_id: user_id(ctx),
+            task_id: TaskId(input.task_id),
+            date_began: input.date_began,
+            duration: input.duration,
+            notes: input.notes,
+        })?;
+        let Json(event) = routes::events::add_event(
+            State(state(ctx).clone()),
+            Extension(user_id(ctx)),
+            new_event,
+        )
+        .await?;
+        Ok(event.into())
+    }
+
+    async fn add_client(&self, ctx: &Context<'_>, input: ClientInput) -> Result<Client> {
+        let new_client = Valid::check(input.into_new_client(user_id(ctx)))?;
+        let Json(client) = routes::clients::add_client(
+            State(state(ctx).clone()),
+            Extension(user_id(ctx)),
+            new_client,
+        )
+        .await?;
+        Ok(Client(client))
+    }
+
+    async fn update_client(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+        input: ClientInput,
+    ) -> Result<Client> {
+        let new_client = Valid::check(input.into_new_client(user_id(ctx)))?;
+        let Json(client) = routes::clients::update_client(
+            State(state(ctx).clone()),
+            Extension(user_id(ctx)),
+            Path(ClientId(id)),
+            new_client,
+        )
+        .await?;
+        Ok(Client(client))
+    }
+
+    async fn start_timer(
+        &self,
+        ctx: &Context<'_>,
+        task_id: i32,
+        notes: Option<String>,
+    ) -> Result<Timer> {
+        let start = Valid::check(models::StartTimer {
+            task_id: TaskId(task_id),
+            notes,
+        })?;
+        let Json(timer) =
+            routes::timer::start_timer(State(state(ctx).clone()), Extension(user_id(ctx)), start)
+                .await?;
+        Ok(Timer(timer))
+    }
+
+    /// Saves the time since the timer started as an event, see
+    /// `POST /timer/stop`
+    async fn stop_timer(&self, ctx: &Context<'_>, notes: Option<String>) -> Result<Event> {
+        let stop = Valid::check(models::StopTimer { notes })?;
+        let Json(event) =
+            routes::timer::stop_timer(State(state(ctx).clone()), Extension(user_id(ctx)), stop)
+                .await?;
+        Ok(event.into())
+    }
+
+    async fn update_settings(
+        &self,
+        ctx: &Context<'_>,
+        overlap_policy: OverlapPolicy,
+    ) -> Result<Settings> {
+        let settings = models::UserSettings {
+            overlap_policy: overlap_policy.into(),
+        };
+        let Json(settings) = routes::users::update_user_settings(
+            State(state(ctx).clone()),
+            Extension(user_id(ctx)),
+            Json(settings),
+        )
+        .await?;
+        Ok(settings.into())
+    }
+}
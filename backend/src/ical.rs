@@ -0,0 +1,416 @@
+//! Rendering of tracked time as an RFC 5545 iCalendar feed, and parsing of
+//! the calendar files users import
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::models::CalendarEvent;
+
+const PRODID: &str = "-//Time Bandit//Time Bandit//EN";
+
+/// Content lines longer than this many octets are folded
+const MAX_LINE_OCTETS: usize = 75;
+
+pub fn render_calendar(events: &[CalendarEvent], now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODID),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        "X-WR-CALNAME:Time Bandit".to_string(),
+    ];
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}@time-bandit", event.uuid));
+        lines.push(format!("DTSTAMP:{}", format_datetime(now)));
+        lines.push(format!("DTSTART:{}", format_datetime(event.date_began)));
+        lines.push(format!("DURATION:{}", format_duration(event.duration)));
+        lines.push(format!("SUMMARY:{}", escape_text(&event.task_name)));
+        if let Some(notes) = event.notes.as_deref().filter(|notes| !notes.is_empty()) {
+            lines.push(format!("DESCRIPTION:{}", escape_text(notes)));
+        }
+        lines.push("TRANSP:TRANSPARENT".to_string());
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold_line(line) + "\r\n").collect()
+}
+
+/// A UTC date-time such as `20231201T090000Z`
+pub fn format_datetime(datetime: DateTime<Utc>) -> String {
+    datetime.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// A duration in seconds as a `dur-time`, such as `PT1H30M`
+pub fn format_duration(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    let mut duration = "PT".to_string();
+    if hours > 0 {
+        duration += &format!("{}H", hours);
+    }
+    if minutes > 0 {
+        duration += &format!("{}M", minutes);
+    }
+    if seconds > 0 || duration == "PT" {
+        duration += &format!("{}S", seconds);
+    }
+    duration
+}
+
+pub fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Splits a content line into lines of at most 75 octets, continued with a
+/// leading space, without breaking up multi-byte characters
+pub fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut octets = 0;
+    for character in line.chars() {
+        if octets + character.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            // the leading space counts towards the next line
+            octets = 1;
+        }
+        folded.push(character);
+        octets += character.len_utf8();
+    }
+    folded
+}
+
+/// A component of a parsed calendar file, such as a VCALENDAR or VEVENT
+#[derive(Debug, Default)]
+pub struct Component {
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub components: Vec<Component>,
+}
+
+#[derive(Debug)]
+pub struct Property {
+    pub name: String,
+    /// parameter names are upper case, quotes are removed from the values
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+impl Component {
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+    }
+
+    pub fn all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Property> {
+        self.properties
+            .iter()
+            .filter(move |property| property.name == name)
+    }
+
+    /// The unescaped value of a text property
+    pub fn text(&self, name: &str) -> Option<String> {
+        self.property(name)
+            .map(|property| unescape_text(&property.value))
+    }
+}
+
+impl Property {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The start or end of an event
+#[derive(Debug, PartialEq, Eq)]
+pub enum Moment {
+    Date(NaiveDate),
+    DateTime(DateTime<Utc>),
+}
+
+/// Parses the top level components of a calendar file, usually a single
+/// VCALENDAR
+pub fn parse_calendar(data: &str) -> Result<Vec<Component>, String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in data.trim_start_matches('\u{feff}').lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continued), Some(last)) => last.push_str(continued),
+            _ if line.is_empty() => {}
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut open: Vec<Component> = Vec::new();
+    let mut components = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let property = parse_content_line(line)
+            .ok_or_else(|| format!("Invalid content line {}: {}", index + 1, line))?;
+        match property.name.as_str() {
+            "BEGIN" => open.push(Component {
+                name: property.value.to_ascii_uppercase(),
+                ..Component::default()
+            }),
+            "END" => {
+                let component = open
+                    .pop()
+                    .filter(|component| component.name.eq_ignore_ascii_case(&property.value))
+                    .ok_or_else(|| format!("Unexpected END:{}", property.value))?;
+                match open.last_mut() {
+                    Some(parent) => parent.components.push(component),
+                    None => components.push(component),
+                }
+            }
+            _ => match open.last_mut() {
+                Some(component) => component.properties.push(property),
+                None => return Err(format!("Property outside of a component: {}", line)),
+            },
+        }
+    }
+    match open.last() {
+        Some(component) => Err(format!("Missing END:{}", component.name)),
+        None if components.is_empty() => Err("Not an iCalendar file".to_string()),
+        None => Ok(components),
+    }
+}
+
+/// Splits `NAME;PARAM=value;PARAM="quoted":value`, where the parameter values
+/// may contain colons and semicolons inside quotes
+fn parse_content_line(line: &str) -> Option<Property> {
+    let mut in_quotes = false;
+    let mut value_start = None;
+    let mut segments = Vec::new();
+    let mut segment_start = 0;
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                segments.push(&line[segment_start..index]);
+                segment_start = index + 1;
+            }
+            ':' if !in_quotes => {
+                segments.push(&line[segment_start..index]);
+                value_start = Some(index + 1);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let value = line[value_start?..].to_string();
+    let name = segments.first()?.trim().to_ascii_uppercase();
+    if name.is_empty() {
+        return None;
+    }
+    let params = segments[1..]
+        .iter()
+        .filter_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            Some((name.to_ascii_uppercase(), value.replace('"', "")))
+        })
+        .collect();
+    Some(Property {
+        name,
+        params,
+        value,
+    })
+}
+
+pub fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut characters = text.chars();
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            unescaped.push(character);
+            continue;
+        }
+        match characters.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Reads a DATE or DATE-TIME property. Times are in UTC if they end in `Z`,
+/// in the zone named by the TZID parameter, or floating in `tz`.
+pub fn parse_moment(property: &Property, tz: Tz) -> Result<Moment, String> {
+    let value = property.value.trim();
+    if property.param("VALUE") == Some("DATE") || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .map(Moment::Date)
+            .map_err(|_| format!("`{}` is not a valid date", value));
+    }
+    if let Some(utc) = value.strip_suffix('Z') {
+        return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .map(|naive| Moment::DateTime(naive.and_utc()))
+            .map_err(|_| format!("`{}` is not a valid date and time", value));
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .map_err(|_| format!("`{}` is not a valid date and time", value))?;
+    let tz = match property.param("TZID") {
+        Some(tzid) => tzid
+            .trim_start_matches('/')
+            .parse::<Tz>()
+            .map_err(|_| format!("Unknown time zone: {}", tzid))?,
+        None => tz,
+    };
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .map(|datetime| Moment::DateTime(datetime.with_timezone(&Utc)))
+        .ok_or_else(|| format!("`{}` does not exist in {}", value, tz))
+}
+
+/// The inverse of `format_duration`, also accepting days and weeks.
+/// Durations chrono cannot hold are invalid.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("`{}` is not a valid duration", value);
+    let (sign, unsigned) = match value.trim().strip_prefix('-') {
+        Some(unsigned) => (-1, unsigned),
+        None => (1, value.trim().trim_start_matches('+')),
+    };
+    let designators = unsigned.strip_prefix('P').ok_or_else(invalid)?;
+    let mut seconds: i64 = 0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for character in designators.chars() {
+        let unit = match character {
+            '0'..='9' => {
+                number.push(character);
+                continue;
+            }
+            'T' if !in_time && number.is_empty() => {
+                in_time = true;
+                continue;
+            }
+            'W' if !in_time => 7 * 24 * 3600,
+            'D' if !in_time => 24 * 3600,
+            'H' if in_time => 3600,
+            'M' if in_time => 60,
+            'S' if in_time => 1,
+            _ => return Err(invalid()),
+        };
+        let amount: i64 = number.parse().map_err(|_| invalid())?;
+        seconds = amount
+            .checked_mul(unit)
+            .and_then(|amount| seconds.checked_add(amount))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() || designators.is_empty() || designators.ends_with('T') {
+        return Err(invalid());
+    }
+    Duration::try_seconds(sign * seconds).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(5400), "PT1H30M");
+        assert_eq!(format_duration(3605), "PT1H5S");
+        assert_eq!(format_duration(0), "PT0S");
+    }
+
+    #[test]
+    fn escapes_and_folds_text() {
+        assert_eq!(escape_text("a, b; c\\d\ne"), "a\\, b\\; c\\\\d\\ne");
+
+        let folded = fold_line(&format!("DESCRIPTION:{}", "é".repeat(60)));
+        for line in folded.split("\r\n") {
+            assert!(line.len() <= MAX_LINE_OCTETS);
+        }
+        assert_eq!(
+            folded.replace("\r\n ", ""),
+            format!("DESCRIPTION:{}", "é".repeat(60))
+        );
+    }
+
+    #[test]
+    fn renders_events() {
+        let event = CalendarEvent {
+            uuid: Uuid::nil(),
+            task_name: "Website, homepage".to_string(),
+            date_began: Utc.with_ymd_and_hms(2023, 12, 1, 9, 0, 0).unwrap(),
+            duration: 5400,
+            notes: Some("layout".to_string()),
+        };
+        let now = Utc.with_ymd_and_hms(2023, 12, 2, 0, 0, 0).unwrap();
+        let calendar = render_calendar(&[event], now);
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(calendar.contains("\r\nUID:00000000-0000-0000-0000-000000000000@time-bandit\r\n"));
+        assert!(calendar.contains("\r\nDTSTART:20231201T090000Z\r\nDURATION:PT1H30M\r\n"));
+        assert!(calendar.contains("\r\nSUMMARY:Website\\, homepage\r\nDESCRIPTION:layout\r\n"));
+        assert!(calendar.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    fn parses_what_it_renders() {
+        let event = CalendarEvent {
+            uuid: Uuid::nil(),
+            task_name: "Standup; daily".to_string(),
+            date_began: Utc.with_ymd_and_hms(2023, 12, 1, 9, 0, 0).unwrap(),
+            duration: 900,
+            notes: Some("a".repeat(100)),
+        };
+        let calendar = render_calendar(&[event], Utc::now());
+        let components = parse_calendar(&calendar).unwrap();
+        let vevent = &components[0].components[0];
+        assert_eq!(vevent.name, "VEVENT");
+        assert_eq!(vevent.text("SUMMARY").unwrap(), "Standup; daily");
+        assert_eq!(vevent.text("DESCRIPTION").unwrap(), "a".repeat(100));
+        assert_eq!(
+            parse_moment(vevent.property("DTSTART").unwrap(), chrono_tz::UTC),
+            Ok(Moment::DateTime(
+                Utc.with_ymd_and_hms(2023, 12, 1, 9, 0, 0).unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn parses_parameters_and_time_zones() {
+        let property =
+            parse_content_line(r#"DTSTART;TZID="Europe/Berlin";X-NOTE="a:b;c":20231201T100000"#)
+                .unwrap();
+        assert_eq!(property.param("X-NOTE"), Some("a:b;c"));
+        assert_eq!(
+            parse_moment(&property, chrono_tz::UTC),
+            Ok(Moment::DateTime(
+                Utc.with_ymd_and_hms(2023, 12, 1, 9, 0, 0).unwrap()
+            ))
+        );
+
+        let all_day = parse_content_line("DTSTART;VALUE=DATE:20231201").unwrap();
+        assert_eq!(
+            parse_moment(&all_day, chrono_tz::UTC),
+            Ok(Moment::Date(NaiveDate::from_ymd_opt(2023, 12, 1).unwrap()))
+        );
+        assert!(parse_calendar("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nEND:VCALENDAR\r\n").is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("PT1H30M"), Ok(Duration::seconds(5400)));
+        assert_eq!(parse_duration("P1DT2H"), Ok(Duration::seconds(93600)));
+        assert_eq!(parse_duration("-PT15M"), Ok(Duration::seconds(-900)));
+        assert_eq!(parse_duration("P1W"), Ok(Duration::days(7)));
+        assert!(parse_duration("PT").is_err());
+        assert!(parse_duration("1H").is_err());
+        assert!(parse_duration("PT9999999999999999S").is_err());
+        assert!(parse_duration("P9999999999999999W").is_err());
+        assert!(parse_duration("P99999999999999DT99999999999999H").is_err());
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use uuid::Uuid;
+
+use crate::{
+    models::{
+        DurationFormat, ImportFormat, ImportMapping, ImportRequest, ImportRow, ImportRowError,
+        NewClient, NewTask, NewTaskEvent, TaskId, UserId,
+    },
+    validation::FieldErrors,
+};
+
+/// A source record, keyed by column name
+pub type Record = HashMap<String, String>;
+
+#[derive(Debug)]
+pub struct ParsedImport {
+    pub total_rows: usize,
+    pub rows: Vec<ImportRow>,
+    pub errors: Vec<ImportRowError>,
+}
+
+impl ParsedImport {
+    /// Moves the rows whose task, client or event would break the rules of
+    /// `POST /tasks`, `POST /clients` or `POST /events` over to the errors
+    pub fn check_rows(&mut self) {
+        let rows = std::mem::take(&mut self.rows);
+        for row in rows {
+            let errors = row_errors(&row);
+            if errors.is_empty() {
+                self.rows.push(row);
+            } else {
+                self.errors.extend(errors);
+            }
+        }
+        self.errors.sort_by_key(|error| error.row);
+    }
+}
+
+fn row_errors(row: &ImportRow) -> Vec<ImportRowError> {
+    let task = NewTask {
+        user_id: UserId(0),
+        name: row.task_name.clone(),
+        description: None,
+        client_id: None,
+        tags: row.tags.clone(),
+    };
+    let event = NewTaskEvent {
+        user_id: UserId(0),
+        task_id: TaskId(0),
+        date_began: row.date_began,
+        duration: row.duration,
+        notes: row.notes.clone(),
+    };
+    let client = row.client_name.as_ref().map(|name| NewClient {
+        user_id: UserId(0),
+        name: name.clone(),
+        email: None,
+        phone: None,
+        address: None,
+        default_rate: None,
+    });
+    let checks = [
+        ("task", FieldErrors::of(&task)),
+        (
+            "client",
+            client.as_ref().map(FieldErrors::of).unwrap_or_default(),
+        ),
+        ("event", FieldErrors::of(&event)),
+    ];
+    let mut errors = vec![];
+    for (entity, problems) in &checks {
+        for (field, message) in problems.iter() {
+            // the names are the task and the client of the row
+            let field = match (*entity, field) {
+                ("task" | "client", "name") => entity,
+                _ => field,
+            };
+            errors.push(ImportRowError {
+                row: row.row,
+                field: field.to_string(),
+                message: message.to_string(),
+            });
+        }
+    }
+    errors
+}
+
+/// Parses and validates the records of an import. Problems with the file as a
+/// whole are returned as an error, problems with single rows are collected
+/// next to the rows that are valid.
+pub fn parse_import(request: &ImportRequest) -> Result<ParsedImport, String> {
+    let tz: Tz = request
+        .tz
+        .parse()
+        .map_err(|_| format!("Unknown time zone: {}", request.tz))?;
+    let records = match request.format {
+        ImportFormat::Csv => read_csv(&request.data)?,
+        ImportFormat::Json => read_json(&request.data)?,
+    };
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        match parse_record(
+            index + 1,
+            record,
+            &request.mapping,
+            request.duration_format,
+            tz,
+        ) {
+            Ok(row) => rows.push(row),
+            Err(mut row_errors) => errors.append(&mut row_errors),
+        }
+    }
+    Ok(ParsedImport {
+        total_rows: records.len(),
+        rows,
+        errors,
+    })
+}
+
+pub fn read_csv(data: &str) -> Result<Vec<Record>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(data.trim_start_matches('\u{feff}').as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Invalid CSV header: {}", e))?
+        .clone();
+    reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| format!("Invalid CSV: {}", e))?;
+            Ok(headers
+                .iter()
+                .zip(record.iter())
+                .map(|(header, value)| (header.to_string(), value.to_string()))
+                .collect())
+        })
+        .collect()
+}
+
+fn read_json(data: &str) -> Result<Vec<Record>, String> {
+    let objects: Vec<serde_json::Map<String, serde_json::Value>> =
+        serde_json::from_str(data).map_err(|e| format!("Invalid JSON: {}", e))?;
+    Ok(objects
+        .into_iter()
+        .map(|object| {
+            object
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    let value = match value {
+                        serde_json::Value::Null => return None,
+                        serde_json::Value::String(value) => value,
+                        value => value.to_string(),
+                    };
+                    Some((key, value))
+                })
+                .collect()
+        })
+        .collect())
+}
+
+fn parse_record(
+    row: usize,
+    record: &Record,
+    mapping: &ImportMapping,
+    duration_format: DurationFormat,
+    tz: Tz,
+) -> Result<ImportRow, Vec<ImportRowError>> {
+    let mut errors = Vec::new();
+    let mut error = |field: &str, message: String| {
+        errors.push(ImportRowError {
+            row,
+            field: field.to_string(),
+            message,
+        })
+    };
+    let value = |column: &str| {
+        record
+            .get(column)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+
+    let task_name = value(&mapping.task).map(str::to_string);
+    if task_name.is_none() {
+        error("task", format!("missing value in `{}`", mapping.task));
+    }
+
+    let date_began = match value(&mapping.date_began) {
+        Some(date_began) => parse_datetime(date_began, tz)
+            .map_err(|message| error("date_began", message))
+            .ok(),
+        None => {
+            error(
+                "date_began",
+                format!("missing value in `{}`", mapping.date_began),
+            );
+            None
+        }
+    };
+
+    let duration = match value(&mapping.duration) {
+        Some(duration) => match parse_duration(duration, duration_format) {
+            Ok(duration) if duration < 0 => {
+                error("duration", "must not be negative".to_string());
+                None
+            }
+            Ok(duration) => Some(duration),
+            Err(message) => {
+                error("duration", message);
+                None
+            }
+        },
+        None => {
+            error(
+                "duration",
+                format!("missing value in `{}`", mapping.duration),
+            );
+            None
+        }
+    };
+
+    let uuid = match mapping.uuid.as_deref().and_then(value) {
+        Some(uuid) => match Uuid::parse_str(uuid) {
+            Ok(uuid) => Some(uuid),
+            Err(_) => {
+                error("uuid", format!("`{}` is not a valid uuid", uuid));
+                None
+            }
+        },
+        None => None,
+    };
+
+    let notes = mapping.notes.as_deref().and_then(value).map(str::to_string);
+
+    match (task_name, date_began, duration) {
+        (Some(task_name), Some(date_began), Some(duration)) if errors.is_empty() => Ok(ImportRow {
+            row,
+            uuid,
+            task_name,
+            client_name: None,
+            tags: vec![],
+            date_began,
+            duration,
+            notes,
+            ical_uid: None,
+        }),
+        _ => Err(errors),
+    }
+}
+
+/// Accepts RFC 3339 timestamps, or local date times in the given time zone
+pub fn parse_datetime(value: &str, tz: Tz) -> Result<DateTime<Utc>, String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+    const FORMATS: [&str; 4] = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ];
+    let naive = FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .ok_or_else(|| format!("`{}` is not a recognised date and time", value))?;
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .map(|datetime| datetime.with_timezone(&Utc))
+        .ok_or_else(|| format!("`{}` does not exist in {}", value, tz))
+}
+
+/// The inverse of `export::format_duration`, returning whole seconds.
+/// Durations too long to count in seconds are invalid.
+pub fn parse_duration(value: &str, duration_format: DurationFormat) -> Result<i64, String> {
+    let invalid = || format!("`{}` is not a valid duration", value);
+    match duration_format {
+        DurationFormat::Seconds => value.parse::<i64>().map_err(|_| invalid()),
+        DurationFormat::Hours => value
+            .parse::<f64>()
+            .ok()
+            .map(|hours| (hours * 3600.0).round())
+            .filter(|seconds| seconds.is_finite() && seconds.abs() < i64::MAX as f64)
+            .map(|seconds| seconds as i64)
+            .ok_or_else(invalid),
+        DurationFormat::Hhmm => {
+            let parts = value
+                .split(':')
+                .map(|part| part.parse::<i64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid())?;
+            let seconds = match parts.as_slice() {
+                [hours, minutes] if (0..60).contains(minutes) => hours
+                    .checked_mul(3600)
+                    .and_then(|seconds| seconds.checked_add(minutes * 60)),
+                [hours, minutes, seconds]
+                    if (0..60).contains(minutes) && (0..60).contains(seconds) =>
+                {
+                    hours
+                        .checked_mul(3600)
+                        .and_then(|total| total.checked_add(minutes * 60 + seconds))
+                }
+                _ => None,
+            };
+            seconds.ok_or_else(invalid)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+
+    fn request(format: &str, data: &str) -> ImportRequest {
+        serde_json::from_value(json!({
+            "format": format,
+            "data": data,
+            "duration_format": "hh:mm",
+            "tz": "Europe/Berlin",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("5400", DurationFormat::Seconds), Ok(5400));
+        assert_eq!(parse_duration("1.5", DurationFormat::Hours), Ok(5400));
+        assert_eq!(parse_duration("1:30", DurationFormat::Hhmm), Ok(5400));
+        assert_eq!(parse_duration("1:30:15", DurationFormat::Hhmm), Ok(5415));
+        for (value, format) in [
+            ("1.5", DurationFormat::Seconds),
+            ("an hour", DurationFormat::Hours),
+            ("inf", DurationFormat::Hours),
+            ("1e300", DurationFormat::Hours),
+            ("1:60", DurationFormat::Hhmm),
+            ("1:30:60", DurationFormat::Hhmm),
+            ("1", DurationFormat::Hhmm),
+            // too many seconds for an i64
+            ("9999999999999999:00", DurationFormat::Hhmm),
+            ("2562047788015215:59:59", DurationFormat::Hhmm),
+        ] {
+            assert!(parse_duration(value, format).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn parses_datetimes_in_the_time_zone() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let expected = Utc.with_ymd_and_hms(2023, 12, 1, 8, 0, 0).unwrap();
+        assert_eq!(parse_datetime("2023-12-01T08:00:00Z", berlin), Ok(expected));
+        assert_eq!(parse_datetime("2023-12-01 09:00:00", berlin), Ok(expected));
+        assert_eq!(parse_datetime("2023-12-01T09:00", berlin), Ok(expected));
+        assert!(parse_datetime("01/12/2023", berlin).is_err());
+        // skipped when the clocks went forward
+        assert!(parse_datetime("2023-03-26 02:30", berlin).is_err());
+    }
+
+    #[test]
+    fn parses_csv_and_reports_bad_rows() {
+        let data = "task,date_began,duration,notes\n\
+            Writing,2023-12-01 09:00,1:30,chapter one\n\
+            ,2023-12-01 11:00,0:30,\n\
+            Reading,yesterday,9999999999999999:00,\n";
+        let parsed = parse_import(&request("csv", data)).unwrap();
+        assert_eq!(parsed.total_rows, 3);
+        assert_eq!(parsed.rows.len(), 1);
+        let row = &parsed.rows[0];
+        assert_eq!(row.row, 1);
+        assert_eq!(row.task_name, "Writing");
+        assert_eq!(row.duration, 5400);
+        assert_eq!(row.notes.as_deref(), Some("chapter one"));
+        assert_eq!(
+            row.date_began,
+            Utc.with_ymd_and_hms(2023, 12, 1, 8, 0, 0).unwrap()
+        );
+        let errors: Vec<(usize, &str)> = parsed
+            .errors
+            .iter()
+            .map(|error| (error.row, error.field.as_str()))
+            .collect();
+        assert_eq!(errors, [(2, "task"), (3, "date_began"), (3, "duration")]);
+    }
+
+    #[test]
+    fn checks_rows_by_the_rules_of_the_api() {
+        let data = format!(
+            "task,date_began,duration,notes\n\
+             Writing,2023-12-01 09:00,1:30,\n\
+             {},2023-12-01 11:00,0:30,\n\
+             Reading,2099-12-01 11:00,0:30,{}\n",
+            "x".repeat(201),
+            "x".repeat(5001),
+        );
+        let mut parsed = parse_import(&request("csv", &data)).unwrap();
+        parsed.check_rows();
+        assert_eq!(parsed.rows.len(), 1);
+        assert_eq!(parsed.rows[0].task_name, "Writing");
+        let errors: Vec<(usize, &str)> = parsed
+            .errors
+            .iter()
+            .map(|error| (error.row, error.field.as_str()))
+            .collect();
+        assert_eq!(errors, [(2, "task"), (3, "date_began"), (3, "notes")]);
+    }
+
+    #[test]
+    fn parses_json_and_rejects_bad_files() {
+        let data = r#"[{"task": "Writing", "date_began": "2023-12-01T08:00:00Z",
+                        "duration": "0:45", "uuid": "not a uuid"},
+                       {"task": "Reading", "date_began": "2023-12-01T10:00:00Z",
+                        "duration": "-1:00"}]"#;
+        let parsed = parse_import(&request("json", data)).unwrap();
+        assert!(parsed.rows.is_empty());
+        assert_eq!(parsed.errors[0].field, "uuid");
+        assert_eq!(parsed.errors[1].message, "must not be negative");
+
+        assert!(parse_import(&request("json", "{}")).is_err());
+        let mut unknown_zone = request("csv", "task\n");
+        unknown_zone.tz = "Mars/Olympus".to_string();
+        assert!(parse_import(&unknown_zone).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use chrono_tz::Tz;
+
+use super::{
+    collect_entries, field, parse_local, require_columns, split_tags, ExternalEntry, Importer,
+};
+use crate::{
+    import::{parse_duration, read_csv, ParsedImport},
+    models::DurationFormat,
+};
+
+/// Clockify's detailed report exported as CSV.
+/// Clockify tasks are categories within a project, so they become tags.
+pub struct ClockifyImporter;
+
+impl Importer for ClockifyImporter {
+    fn source(&self) -> &'static str {
+        "clockify"
+    }
+
+    fn parse(&self, data: &str, tz: Tz) -> Result<ParsedImport, String> {
+        let records = read_csv(data)?;
+        require_columns(
+            &records,
+            "Clockify",
+            &[
+                "Project",
+                "Description",
+                "Start Date",
+                "Start Time",
+                "Duration (h)",
+            ],
+        )?;
+        let entries = records
+            .iter()
+            .map(|record| {
+                let mut tags = split_tags(field(record, "Tags"));
+                tags.extend(field(record, "Task"));
+                ExternalEntry {
+                    project: field(record, "Project"),
+                    description: field(record, "Description"),
+                    client: field(record, "Client"),
+                    tags,
+                    date_began: field(record, "Start Date")
+                        .map(|date| parse_local(&date, field(record, "Start Time").as_deref(), tz)),
+                    duration: field(record, "Duration (h)")
+                        .map(|duration| parse_duration(&duration, DurationFormat::Hhmm)),
+                }
+            })
+            .collect();
+        Ok(collect_entries(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    #[test]
+    fn parses_the_detailed_csv_report() {
+        let data = include_str!("../../tests/fixtures/clockify_detailed.csv");
+        let parsed = ClockifyImporter
+            .parse(data, "America/New_York".parse().unwrap())
+            .unwrap();
+        assert_eq!(parsed.total_rows, 3);
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+
+        let first = &parsed.rows[0];
+        assert_eq!(first.task_name, "Website");
+        assert_eq!(first.client_name.as_deref(), Some("Acme"));
+        assert_eq!(first.tags, vec!["design", "frontend", "Design"]);
+        assert_eq!(
+            first.date_began,
+            Utc.with_ymd_and_hms(2023, 12, 1, 14, 0, 0).unwrap()
+        );
+        assert_eq!(first.duration, 5400);
+
+        assert_eq!(
+            parsed.rows[1].date_began,
+            Utc.with_ymd_and_hms(2023, 12, 1, 18, 15, 0).unwrap()
+        );
+        assert_eq!(parsed.rows[2].task_name, "Reading");
+    }
+
+    #[test]
+    fn reports_invalid_rows() {
+        let data = "Project,Description,Start Date,Start Time,Duration (h)\n\
+                    Website,ok,12/01/2023,09:00:00 AM,00:10:00\n\
+                    Website,bad,31/31/2023,09:00:00 AM,ten minutes\n";
+        let parsed = ClockifyImporter.parse(data, chrono_tz::UTC).unwrap();
+        assert_eq!(parsed.rows.len(), 1);
+        let fields: Vec<&str> = parsed.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["date_began", "duration"]);
+        assert!(parsed.errors.iter().all(|e| e.row == 2));
+    }
+}
@@ -0,0 +1,65 @@
+use chrono_tz::Tz;
+
+use super::{collect_entries, field, parse_local, require_columns, ExternalEntry, Importer};
+use crate::{
+    import::{parse_duration, read_csv, ParsedImport},
+    models::DurationFormat,
+};
+
+/// Harvest's detailed time report exported as CSV.
+/// Harvest only records the day of an entry, so it starts at local midnight,
+/// and Harvest tasks are categories within a project, so they become tags.
+pub struct HarvestImporter;
+
+impl Importer for HarvestImporter {
+    fn source(&self) -> &'static str {
+        "harvest"
+    }
+
+    fn parse(&self, data: &str, tz: Tz) -> Result<ParsedImport, String> {
+        let records = read_csv(data)?;
+        require_columns(&records, "Harvest", &["Date", "Project", "Notes", "Hours"])?;
+        let entries = records
+            .iter()
+            .map(|record| ExternalEntry {
+                project: field(record, "Project"),
+                description: field(record, "Notes"),
+                client: field(record, "Client"),
+                tags: field(record, "Task").into_iter().collect(),
+                date_began: field(record, "Date").map(|date| parse_local(&date, None, tz)),
+                duration: field(record, "Hours")
+                    .map(|hours| parse_duration(&hours, DurationFormat::Hours)),
+            })
+            .collect();
+        Ok(collect_entries(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    #[test]
+    fn parses_the_detailed_time_report() {
+        let data = include_str!("../../tests/fixtures/harvest_detailed.csv");
+        let parsed = HarvestImporter.parse(data, chrono_tz::UTC).unwrap();
+        assert_eq!(parsed.total_rows, 3);
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+
+        let first = &parsed.rows[0];
+        assert_eq!(first.task_name, "Website");
+        assert_eq!(first.client_name.as_deref(), Some("Acme"));
+        assert_eq!(first.tags, vec!["Design"]);
+        assert_eq!(first.notes.as_deref(), Some("Homepage layout"));
+        assert_eq!(
+            first.date_began,
+            Utc.with_ymd_and_hms(2023, 12, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(first.duration, 5400);
+
+        assert_eq!(parsed.rows[1].duration, 1800);
+        assert_eq!(parsed.rows[2].notes, None);
+    }
+}
@@ -0,0 +1,313 @@
+//! Calendar files, where the user's rules decide the task of each event
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use regex::Regex;
+
+use crate::{
+    ical::{parse_calendar, parse_duration, parse_moment, Component, Moment},
+    import::ParsedImport,
+    models::{IcsRule, IcsSkipped, ImportRow, ImportRowError},
+};
+
+pub struct ParsedCalendar {
+    pub import: ParsedImport,
+    pub skipped: IcsSkipped,
+}
+
+struct CompiledRule<'a> {
+    rule: &'a IcsRule,
+    summary: Option<Regex>,
+}
+
+impl CompiledRule<'_> {
+    fn matches(&self, event: &Component, calendar_name: Option<&str>) -> bool {
+        let summary = event.text("SUMMARY").unwrap_or_default();
+        self.summary
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(&summary))
+            && self.rule.organizer.as_deref().is_none_or(|organizer| {
+                event.property("ORGANIZER").is_some_and(|property| {
+                    email(&property.value).eq_ignore_ascii_case(organizer)
+                        || property
+                            .param("CN")
+                            .is_some_and(|name| name.eq_ignore_ascii_case(organizer))
+                })
+            })
+            && self.rule.calendar.as_deref().is_none_or(|calendar| {
+                calendar_name.is_some_and(|name| name.eq_ignore_ascii_case(calendar))
+            })
+    }
+}
+
+/// Reads the events of every calendar in the file. Recurring events are not
+/// expanded: a series counts as its first occurrence, and changed occurrences
+/// exported as their own VEVENT are imported separately.
+pub fn parse_ics(
+    data: &str,
+    rules: &[IcsRule],
+    attendee: &str,
+    tz: Tz,
+) -> Result<ParsedCalendar, String> {
+    let rules = compile_rules(rules)?;
+    let calendars = parse_calendar(data)?;
+
+    let mut total_rows = 0;
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    let mut skipped = IcsSkipped::default();
+    for calendar in calendars.iter().filter(|c| c.name == "VCALENDAR") {
+        let calendar_name = calendar.text("X-WR-CALNAME");
+        for event in calendar.components.iter().filter(|c| c.name == "VEVENT") {
+            total_rows += 1;
+            let row = total_rows;
+            let error = |field: &str, message: String| ImportRowError {
+                row,
+                field: field.to_string(),
+                message,
+            };
+
+            if event
+                .property("STATUS")
+                .is_some_and(|status| status.value.eq_ignore_ascii_case("CANCELLED"))
+            {
+                skipped.cancelled += 1;
+                continue;
+            }
+            if is_declined(event, attendee) {
+                skipped.declined += 1;
+                continue;
+            }
+            let date_began = match event
+                .property("DTSTART")
+                .map(|start| parse_moment(start, tz))
+            {
+                Some(Ok(Moment::DateTime(date_began))) => date_began,
+                Some(Ok(Moment::Date(_))) => {
+                    skipped.all_day += 1;
+                    continue;
+                }
+                Some(Err(message)) => {
+                    errors.push(error("DTSTART", message));
+                    continue;
+                }
+                None => {
+                    errors.push(error("DTSTART", "missing start".to_string()));
+                    continue;
+                }
+            };
+            let Some(rule) = rules
+                .iter()
+                .find(|rule| rule.matches(event, calendar_name.as_deref()))
+            else {
+                skipped.unmatched += 1;
+                continue;
+            };
+
+            let Some(uid) = event.property("UID").map(|uid| uid.value.trim()) else {
+                errors.push(error("UID", "missing UID".to_string()));
+                continue;
+            };
+            // changed occurrences of a series share the UID of the series
+            let ical_uid = match event.property("RECURRENCE-ID") {
+                Some(recurrence_id) => format!("{}#{}", uid, recurrence_id.value.trim()),
+                None => uid.to_string(),
+            };
+            match event_duration(event, date_began, tz) {
+                Ok(duration) => rows.push(ImportRow {
+                    row,
+                    uuid: None,
+                    task_name: rule.rule.task.trim().to_string(),
+                    client_name: None,
+                    tags: rule.rule.tags.clone(),
+                    date_began,
+                    duration,
+                    notes: event.text("SUMMARY"),
+                    ical_uid: Some(ical_uid),
+                }),
+                Err((field, message)) => errors.push(error(field, message)),
+            }
+        }
+    }
+    Ok(ParsedCalendar {
+        import: ParsedImport {
+            total_rows,
+            rows,
+            errors,
+        },
+        skipped,
+    })
+}
+
+fn compile_rules(rules: &[IcsRule]) -> Result<Vec<CompiledRule<'_>>, String> {
+    rules
+        .iter()
+        .enumerate()
+        .map(|(index, rule)| {
+            if rule.task.trim().is_empty() {
+                return Err(format!("Rule {}: task must not be empty", index + 1));
+            }
+            let summary =
+                match &rule.summary {
+                    Some(pattern) => Some(Regex::new(pattern).map_err(|e| {
+                        format!("Rule {}: invalid summary pattern: {}", index + 1, e)
+                    })?),
+                    None => None,
+                };
+            Ok(CompiledRule { rule, summary })
+        })
+        .collect()
+}
+
+/// Whether the attendee turned down the invitation
+fn is_declined(event: &Component, attendee: &str) -> bool {
+    event.all("ATTENDEE").any(|property| {
+        email(&property.value).eq_ignore_ascii_case(attendee)
+            && property
+                .param("PARTSTAT")
+                .is_some_and(|status| status.eq_ignore_ascii_case("DECLINED"))
+    })
+}
+
+/// The address of a `mailto:` calendar user
+fn email(value: &str) -> &str {
+    let value = value.trim();
+    match value.get(..7) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => &value[7..],
+        _ => value,
+    }
+}
+
+/// The length of an event in seconds, from DTEND or DURATION. Events with
+/// neither take no time.
+fn event_duration(
+    event: &Component,
+    date_began: DateTime<Utc>,
+    tz: Tz,
+) -> Result<i64, (&'static str, String)> {
+    let duration = if let Some(end) = event.property("DTEND") {
+        match parse_moment(end, tz) {
+            Ok(Moment::DateTime(end)) => end - date_began,
+            Ok(Moment::Date(_)) => {
+                return Err(("DTEND", "must be a date and time".to_string()));
+            }
+            Err(message) => return Err(("DTEND", message)),
+        }
+    } else if let Some(duration) = event.property("DURATION") {
+        parse_duration(&duration.value).map_err(|message| ("DURATION", message))?
+    } else {
+        chrono::Duration::zero()
+    };
+    if duration < chrono::Duration::zero() {
+        return Err(("DTEND", "the event ends before it starts".to_string()));
+    }
+    Ok(duration.num_seconds())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const CALENDAR: &str = include_str!("../../tests/fixtures/work_calendar.ics");
+
+    fn rule(summary: Option<&str>, organizer: Option<&str>, task: &str) -> IcsRule {
+        IcsRule {
+            summary: summary.map(str::to_string),
+            organizer: organizer.map(str::to_string),
+            calendar: None,
+            task: task.to_string(),
+            tags: vec![],
+        }
+    }
+
+    #[test]
+    fn maps_events_to_tasks_by_rules() {
+        let rules = [
+            rule(Some("(?i)standup"), None, "Meetings"),
+            rule(None, Some("Client Contact"), "Acme"),
+        ];
+        let parsed = parse_ics(CALENDAR, &rules, "me@example.com", chrono_tz::UTC).unwrap();
+        assert!(
+            parsed.import.errors.is_empty(),
+            "{:?}",
+            parsed.import.errors
+        );
+        assert_eq!(parsed.import.total_rows, 7);
+        assert_eq!(parsed.skipped.declined, 1);
+        assert_eq!(parsed.skipped.cancelled, 1);
+        assert_eq!(parsed.skipped.all_day, 1);
+        assert_eq!(parsed.skipped.unmatched, 1);
+
+        let rows = &parsed.import.rows;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].task_name, "Meetings");
+        assert_eq!(rows[0].ical_uid.as_deref(), Some("standup-1@example.com"));
+        // 10:00 in Berlin
+        assert_eq!(
+            rows[0].date_began,
+            Utc.with_ymd_and_hms(2023, 12, 4, 9, 0, 0).unwrap()
+        );
+        assert_eq!(rows[0].duration, 900);
+        assert_eq!(
+            rows[1].ical_uid.as_deref(),
+            Some("standup-1@example.com#20231205T100000")
+        );
+        assert_eq!(rows[2].task_name, "Acme");
+        assert_eq!(rows[2].notes.as_deref(), Some("Kick-off, phase 2"));
+        assert_eq!(rows[2].duration, 5400);
+    }
+
+    #[test]
+    fn reports_durations_out_of_range() {
+        let calendar = "BEGIN:VCALENDAR\r\n\
+            BEGIN:VEVENT\r\n\
+            UID:long@example.com\r\n\
+            DTSTART:20231204T090000Z\r\n\
+            DURATION:PT9999999999999999S\r\n\
+            SUMMARY:Forever\r\n\
+            END:VEVENT\r\n\
+            END:VCALENDAR\r\n";
+        let rules = [rule(None, None, "Meetings")];
+        let parsed = parse_ics(calendar, &rules, "me@example.com", chrono_tz::UTC).unwrap();
+        assert!(parsed.import.rows.is_empty());
+        assert_eq!(parsed.import.errors.len(), 1);
+        assert_eq!(parsed.import.errors[0].field, "DURATION");
+    }
+
+    #[test]
+    fn matches_calendar_names() {
+        let rules = [IcsRule {
+            calendar: Some("work".to_string()),
+            ..rule(None, None, "Work")
+        }];
+        let parsed = parse_ics(CALENDAR, &rules, "me@example.com", chrono_tz::UTC).unwrap();
+        assert_eq!(parsed.import.rows.len(), 4);
+
+        let rules = [IcsRule {
+            calendar: Some("Home".to_string()),
+            ..rule(None, None, "Home")
+        }];
+        let parsed = parse_ics(CALENDAR, &rules, "me@example.com", chrono_tz::UTC).unwrap();
+        assert_eq!(parsed.skipped.unmatched, 4);
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let err = parse_ics(
+            CALENDAR,
+            &[rule(Some("(standup"), None, "Meetings")],
+            "",
+            chrono_tz::UTC,
+        )
+        .err()
+        .unwrap();
+        assert!(
+            err.starts_with("Rule 1: invalid summary pattern"),
+            "{}",
+            err
+        );
+        assert!(parse_ics(CALENDAR, &[rule(None, None, " ")], "", chrono_tz::UTC).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! Importers for the export files of other time trackers.
+//!
+//! Each importer turns a file into the same `ImportRow`s as `POST /import`,
+//! so they all go through `Store::import_events`. To support a new format,
+//! implement `Importer` and add it to `IMPORTERS`.
+//!
+//! Calendar files need mapping rules on top of the file, so `ics` is not an
+//! `Importer` and has its own route.
+
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::{
+    import::{ParsedImport, Record},
+    models::{ImportRow, ImportRowError},
+};
+
+mod clockify;
+mod harvest;
+pub mod ics;
+mod toggl;
+
+pub trait Importer: Send + Sync {
+    /// The name used in `POST /import/:source`
+    fn source(&self) -> &'static str;
+
+    /// Parses an export file, with times without an offset read in `tz`
+    fn parse(&self, data: &str, tz: Tz) -> Result<ParsedImport, String>;
+}
+
+static IMPORTERS: &[&dyn Importer] = &[
+    &toggl::TogglImporter,
+    &clockify::ClockifyImporter,
+    &harvest::HarvestImporter,
+];
+
+pub fn importer_for(source: &str) -> Option<&'static dyn Importer> {
+    IMPORTERS
+        .iter()
+        .find(|importer| importer.source() == source)
+        .copied()
+}
+
+pub fn sources() -> Vec<&'static str> {
+    IMPORTERS.iter().map(|importer| importer.source()).collect()
+}
+
+/// A time entry as found in an export file, before validation
+#[derive(Debug, Default)]
+struct ExternalEntry {
+    project: Option<String>,
+    description: Option<String>,
+    client: Option<String>,
+    tags: Vec<String>,
+    date_began: Option<Result<DateTime<Utc>, String>>,
+    duration: Option<Result<i64, String>>,
+}
+
+/// Validates the entries of an export; the project becomes the task and the
+/// description the notes of the event
+fn collect_entries(entries: Vec<ExternalEntry>) -> ParsedImport {
+    let total_rows = entries.len();
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (index, entry) in entries.into_iter().enumerate() {
+        let row = index + 1;
+        let mut error = |field: &str, message: String| {
+            errors.push(ImportRowError {
+                row,
+                field: field.to_string(),
+                message,
+            })
+        };
+        let date_began = match entry.date_began {
+            Some(Ok(date_began)) => Some(date_began),
+            Some(Err(message)) => {
+                error("date_began", message);
+                None
+            }
+            None => {
+                error("date_began", "missing start".to_string());
+                None
+            }
+        };
+        let duration = match entry.duration {
+            Some(Ok(duration)) if duration < 0 => {
+                error("duration", "must not be negative".to_string());
+                None
+            }
+            Some(Ok(duration)) => Some(duration),
+            Some(Err(message)) => {
+                error("duration", message);
+                None
+            }
+            None => {
+                error("duration", "missing duration".to_string());
+                None
+            }
+        };
+        if let (Some(date_began), Some(duration)) = (date_began, duration) {
+            rows.push(ImportRow {
+                row,
+                uuid: None,
+                task_name: entry
+                    .project
+                    .clone()
+                    .or_else(|| entry.description.clone())
+                    .unwrap_or_else(|| "No project".to_string()),
+                client_name: entry.client,
+                tags: entry.tags,
+                date_began,
+                duration,
+                notes: entry.description,
+                ical_uid: None,
+            });
+        }
+    }
+    ParsedImport {
+        total_rows,
+        rows,
+        errors,
+    }
+}
+
+/// Fails with a readable error when the file is not the expected export
+fn require_columns(records: &[Record], source: &str, columns: &[&str]) -> Result<(), String> {
+    let Some(record) = records.first() else {
+        return Ok(());
+    };
+    match columns.iter().find(|column| !record.contains_key(**column)) {
+        Some(column) => Err(format!(
+            "Not a {} export: missing column `{}`",
+            source, column
+        )),
+        None => Ok(()),
+    }
+}
+
+fn field(record: &Record, column: &str) -> Option<String> {
+    record
+        .get(column)
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+fn split_tags(tags: Option<String>) -> Vec<String> {
+    tags.map(|tags| {
+        tags.split(',')
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Reads a local date and optional time in the formats the trackers use
+fn parse_local(date: &str, time: Option<&str>, tz: Tz) -> Result<DateTime<Utc>, String> {
+    const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%m/%d/%Y", "%d.%m.%Y"];
+    const TIME_FORMATS: [&str; 4] = ["%H:%M:%S", "%H:%M", "%I:%M:%S %p", "%I:%M %p"];
+    let date = DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date, format).ok())
+        .ok_or_else(|| format!("`{}` is not a recognised date", date))?;
+    let time = match time {
+        Some(time) => TIME_FORMATS
+            .iter()
+            .find_map(|format| NaiveTime::parse_from_str(time, format).ok())
+            .ok_or_else(|| format!("`{}` is not a recognised time", time))?,
+        None => NaiveTime::MIN,
+    };
+    let local = date.and_time(time);
+    tz.from_local_datetime(&local)
+        .earliest()
+        .map(|datetime| datetime.with_timezone(&Utc))
+        .ok_or_else(|| format!("`{}` does not exist in {}", local, tz))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_importers_by_source() {
+        assert_eq!(sources(), vec!["toggl", "clockify", "harvest"]);
+        assert!(importer_for("harvest").is_some());
+        assert!(importer_for("rescuetime").is_none());
+    }
+
+    #[test]
+    fn parses_local_dates_in_the_given_time_zone() {
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        let expected = Utc.with_ymd_and_hms(2023, 12, 1, 8, 0, 0).unwrap();
+        assert_eq!(
+            parse_local("2023-12-01", Some("09:00:00"), tz),
+            Ok(expected)
+        );
+        assert_eq!(
+            parse_local("12/01/2023", Some("09:00 AM"), tz),
+            Ok(expected)
+        );
+        assert!(parse_local("2023-13-01", None, tz).is_err());
+    }
+
+    #[test]
+    fn rejects_files_of_another_tracker() {
+        let harvest = include_str!("../../tests/fixtures/harvest_detailed.csv");
+        let err = importer_for("toggl")
+            .unwrap()
+            .parse(harvest, chrono_tz::UTC)
+            .unwrap_err();
+        assert!(err.contains("Toggl Track"), "{}", err);
+    }
+}
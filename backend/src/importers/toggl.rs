@@ -0,0 +1,193 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use super::{
+    collect_entries, field, parse_local, require_columns, split_tags, ExternalEntry, Importer,
+};
+use crate::{
+    import::{parse_duration, read_csv, ParsedImport},
+    models::DurationFormat,
+};
+
+/// Toggl Track's detailed report, exported either as CSV or as JSON
+pub struct TogglImporter;
+
+impl Importer for TogglImporter {
+    fn source(&self) -> &'static str {
+        "toggl"
+    }
+
+    fn parse(&self, data: &str, tz: Tz) -> Result<ParsedImport, String> {
+        if data.trim_start().starts_with(['[', '{']) {
+            parse_json(data)
+        } else {
+            parse_csv(data, tz)
+        }
+    }
+}
+
+fn parse_csv(data: &str, tz: Tz) -> Result<ParsedImport, String> {
+    let records = read_csv(data)?;
+    require_columns(
+        &records,
+        "Toggl Track",
+        &[
+            "Project",
+            "Description",
+            "Start date",
+            "Start time",
+            "Duration",
+        ],
+    )?;
+    let entries = records
+        .iter()
+        .map(|record| ExternalEntry {
+            project: field(record, "Project"),
+            description: field(record, "Description"),
+            client: field(record, "Client"),
+            tags: split_tags(field(record, "Tags")),
+            date_began: field(record, "Start date")
+                .map(|date| parse_local(&date, field(record, "Start time").as_deref(), tz)),
+            duration: field(record, "Duration")
+                .map(|duration| parse_duration(&duration, DurationFormat::Hhmm)),
+        })
+        .collect();
+    Ok(collect_entries(entries))
+}
+
+/// The detailed report's JSON wraps the entries in `data`,
+/// while the time entries API returns them as a bare array
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TogglJson {
+    Report { data: Vec<TogglJsonEntry> },
+    Entries(Vec<TogglJsonEntry>),
+}
+
+#[derive(Deserialize)]
+struct TogglJsonEntry {
+    description: Option<String>,
+    #[serde(alias = "project_name")]
+    project: Option<String>,
+    #[serde(alias = "client_name")]
+    client: Option<String>,
+    tags: Option<Vec<String>>,
+    start: String,
+    #[serde(alias = "stop")]
+    end: Option<String>,
+    /// milliseconds, in the detailed report
+    dur: Option<i64>,
+    /// seconds, negative while the entry is running, in the time entries API
+    duration: Option<i64>,
+}
+
+fn parse_json(data: &str) -> Result<ParsedImport, String> {
+    let entries = match serde_json::from_str(data)
+        .map_err(|e| format!("Not a Toggl Track JSON export: {}", e))?
+    {
+        TogglJson::Report { data } => data,
+        TogglJson::Entries(entries) => entries,
+    };
+    let entries = entries
+        .into_iter()
+        .map(|entry| {
+            let date_began = parse_timestamp(&entry.start);
+            let duration = match (entry.dur, entry.duration, &entry.end) {
+                (Some(dur), _, _) => Ok(dur / 1000),
+                (None, Some(duration), _) if duration < 0 => {
+                    Err("the entry is still running".to_string())
+                }
+                (None, Some(duration), _) => Ok(duration),
+                (None, None, Some(end)) => match (&date_began, parse_timestamp(end)) {
+                    (Ok(start), Ok(end)) => Ok((end - *start).num_seconds()),
+                    (Err(e), _) => Err(e.clone()),
+                    (_, Err(e)) => Err(e),
+                },
+                (None, None, None) => Err("missing duration".to_string()),
+            };
+            ExternalEntry {
+                project: entry.project,
+                description: entry
+                    .description
+                    .filter(|description| !description.is_empty()),
+                client: entry.client,
+                tags: entry.tags.unwrap_or_default(),
+                date_began: Some(date_began),
+                duration: Some(duration),
+            }
+        })
+        .collect();
+    Ok(collect_entries(entries))
+}
+
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|datetime| datetime.with_timezone(&Utc))
+        .map_err(|_| format!("`{}` is not a valid timestamp", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn parses_the_detailed_csv_report() {
+        let data = include_str!("../../tests/fixtures/toggl_detailed.csv");
+        let parsed = TogglImporter
+            .parse(data, "Europe/Berlin".parse().unwrap())
+            .unwrap();
+        assert_eq!(parsed.total_rows, 3);
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+
+        let first = &parsed.rows[0];
+        assert_eq!(first.task_name, "Website");
+        assert_eq!(first.client_name.as_deref(), Some("Acme"));
+        assert_eq!(first.tags, vec!["design", "frontend"]);
+        assert_eq!(first.notes.as_deref(), Some("Homepage layout"));
+        assert_eq!(
+            first.date_began,
+            Utc.with_ymd_and_hms(2023, 12, 1, 8, 0, 0).unwrap()
+        );
+        assert_eq!(first.duration, 5400);
+
+        assert_eq!(parsed.rows[1].notes.as_deref(), Some("Fix nav, again"));
+
+        // entries without a project are filed under their description
+        let last = &parsed.rows[2];
+        assert_eq!(last.task_name, "Reading");
+        assert_eq!(last.client_name, None);
+        assert!(last.tags.is_empty());
+    }
+
+    #[test]
+    fn parses_the_detailed_json_report() {
+        let data = include_str!("../../tests/fixtures/toggl_detailed.json");
+        let parsed = TogglImporter.parse(data, chrono_tz::UTC).unwrap();
+        assert_eq!(parsed.total_rows, 2);
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        assert_eq!(parsed.rows[0].task_name, "Website");
+        assert_eq!(parsed.rows[0].duration, 5400);
+        assert_eq!(
+            parsed.rows[0].date_began,
+            Utc.with_ymd_and_hms(2023, 12, 1, 8, 0, 0).unwrap()
+        );
+        assert_eq!(parsed.rows[1].task_name, "Reading");
+        assert_eq!(parsed.rows[1].duration, 3600);
+    }
+
+    #[test]
+    fn reports_running_time_entries() {
+        let data = r#"[
+            {"description": "Done", "project_name": "Website", "start": "2023-12-01T09:00:00Z", "duration": 60},
+            {"description": "Running", "start": "2023-12-01T10:00:00Z", "duration": -1701421200}
+        ]"#;
+        let parsed = TogglImporter.parse(data, chrono_tz::UTC).unwrap();
+        assert_eq!(parsed.rows.len(), 1);
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].row, 2);
+        assert_eq!(parsed.errors[0].field, "duration");
+    }
+}
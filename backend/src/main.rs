@@ -1,73 +1,76 @@
-use std::net::SocketAddr;
-
-use http::{
-    header::{
-        ACCEPT, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION,
-        CONTENT_TYPE, COOKIE, ORIGIN,
-    },
-    HeaderValue,
+use http::header::{
+    ACCEPT, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION,
+    CONTENT_TYPE, COOKIE, ORIGIN,
 };
 use routes::{
-    auth::{auth_middleware, get_session},
-    events::add_event,
-    tasks::{add_task, get_one_task_with_events, get_user_tasks_with_events, update_task},
+    auth::auth_middleware,
+    docs::{get_docs, get_openapi},
+    health::{get_health, get_metrics, get_readiness},
 };
-use sqlx::{PgPool, Pool, Postgres};
-use tower_http::cors::CorsLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use tracing::info;
 
+use std::sync::Arc;
+
 use axum::{
-    async_trait,
-    extract::{FromRef, FromRequestParts},
-    http::{request::Parts, Method, StatusCode},
+    extract::FromRef,
+    http::Method,
     middleware::{self},
-    routing::{get, post},
+    routing::get,
     Router,
 };
 use axum_extra::extract::cookie::Key;
+use clap::{Parser, Subcommand};
 
 use crate::models::LoginDetails;
-use crate::routes::users::{login, register_user};
 use dotenv::dotenv;
 
+mod admin;
 mod config;
-mod models;
+mod error;
+mod export;
+mod extract;
+mod graphql;
+mod ical;
+mod import;
+mod importers;
+mod metrics;
+mod openapi;
 mod routes;
 mod store;
+mod sync;
+#[cfg(test)]
+mod tests;
+mod validation;
+mod webhooks;
 
-#[derive(Clone)]
-struct AppState {
-    store: store::Store,
-    key: Key,
-}
-
-// this has to be implemented to share the store
-#[async_trait]
-impl<S> FromRequestParts<S> for store::Store
-where
-    PgPool: FromRef<S>,
-    S: Send + Sync,
-{
-    type Rejection = (StatusCode, String);
+// the API types live in their own crate, shared with the CLI
+use time_bandit_models as models;
 
-    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let pool = PgPool::from_ref(state);
-
-        Ok(Self { connection: pool })
-    }
+/// The Time Bandit API server, and the commands to administer its database
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-// this allows the Pool to be shared with AppState
-impl FromRef<AppState> for Pool<Postgres> {
-    fn from_ref(state: &AppState) -> Self {
-        state.store.connection.clone()
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// Run the API server, the default
+    Serve,
+    #[command(flatten)]
+    Admin(admin::AdminCommand),
 }
-// this implementation allows the store to be shared with AppState
-impl FromRef<AppState> for store::Store {
-    fn from_ref(state: &AppState) -> Self {
-        state.store.clone()
-    }
+
+#[derive(Clone)]
+struct AppState {
+    store: Arc<dyn store::Store>,
+    key: Key,
+    cookie: config::CookieConfig,
+    docs: config::DocsConfig,
+    graphql: graphql::BanditSchema,
+    metrics: Arc<metrics::Metrics>,
 }
 
 impl FromRef<AppState> for Key {
@@ -78,27 +81,56 @@ impl FromRef<AppState> for Key {
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt().init();
+    let cli = Cli::parse();
     dotenv().ok();
-    let config = config::Config::init();
-    let store = store::Store::new(&config.database_url)
+    let config = match config::Config::init() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            config.log.init_tracing(std::io::stdout);
+            serve(config).await;
+        }
+        Command::Admin(command) => {
+            // keep stdout for the output of the command, e.g. an export
+            config.log.init_tracing(std::io::stderr);
+            let store = store::connect(&config.database_url, &config.pool)
+                .await
+                .expect("Cannot connect to database");
+            if let Err(e) = admin::run(command, store).await {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+async fn serve(config: config::Config) {
+    let store = store::connect(&config.database_url, &config.pool)
         .await
         .expect("Cannot connect to database");
-    sqlx::migrate!()
-        .run(&store.clone().connection)
+    store.run_migrations().await.expect("Cannot run migrations");
+    webhooks::spawn(store.clone(), config.webhooks.clone());
+    let app = router(store, &config).await;
+    let listener = tokio::net::TcpListener::bind(config.bind_address)
         .await
-        .expect("Cannot run migrations");
-    let app = router(store).await;
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        .unwrap();
+    info!("Listening on {}", config.bind_address);
     axum::serve(listener, app).await.unwrap();
-    info!("Listening on {addr}");
 }
 
-async fn router(store: store::Store) -> Router {
+async fn router(store: Arc<dyn store::Store>, config: &config::Config) -> Router {
     let state = AppState {
         store,
-        key: Key::generate(),
+        key: config.cookie.key.clone().unwrap_or_else(Key::generate),
+        cookie: config.cookie.clone(),
+        docs: config.docs.clone(),
+        graphql: graphql::schema(&config.graphql),
+        metrics: Arc::default(),
     };
     let cors = CorsLayer::new()
         .allow_headers([
@@ -110,31 +142,34 @@ async fn router(store: store::Store) -> Router {
             ACCESS_CONTROL_ALLOW_CREDENTIALS,
             CONTENT_TYPE,
         ])
+        .expose_headers([error::X_REQUEST_ID.clone()])
         .allow_credentials(true)
         .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-        .allow_origin("http://localhost:3000".parse::<HeaderValue>().unwrap());
-    Router::new()
-        .route("/tasks", post(add_task).get(get_user_tasks_with_events))
-        .route(
-            "/tasks/:task_id",
-            get(get_one_task_with_events).put(update_task),
-        )
-        .route("/events", post(add_event))
-        .route_layer(middleware::from_fn_with_state(
+        .allow_origin(AllowOrigin::list(config.allowed_origins.clone()));
+    let mut api = Router::new();
+    for route in routes::session_routes() {
+        api = api.route(route.path, route.handler);
+    }
+    api = api.route_layer(middleware::from_fn_with_state(
+        state.clone(),
+        auth_middleware,
+    ));
+    for route in routes::public_routes() {
+        api = api.route(route.path, route.handler);
+    }
+    // not part of the API, so not in its document
+    api.route("/openapi.json", get(get_openapi))
+        .route("/docs", get(get_docs))
+        .route("/healthz", get(get_health))
+        .route("/readyz", get(get_readiness))
+        .route("/metrics", get(get_metrics))
+        .route("/", get(|| async { "Time Bandit" }))
+        .fallback(routes::unknown_path)
+        .layer(middleware::from_fn_with_state(
             state.clone(),
-            auth_middleware,
+            metrics::track,
         ))
-        .route("/auth", get(get_session))
-        .route("/users/register", post(register_user))
-        .route("/users/login", post(login))
-        .route("/", get(|| async { "Time Bandit" }))
         .with_state(state)
         .layer(cors)
-}
-
-pub fn internal_error<E>(err: E) -> (StatusCode, String)
-where
-    E: std::error::Error,
-{
-    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+        .layer(middleware::from_fn(error::request_id))
 }
@@ -0,0 +1,166 @@
+//! The Prometheus metrics served at `/metrics`.
+//!
+//! Every request is counted and timed by `track`, labelled with the route it
+//! matched rather than its path, so ids in paths don't each make a series of
+//! their own. The database pool, the sessions and the running timers are
+//! read from the store whenever the metrics are scraped.
+
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use prometheus_client::{
+    encoding::{text::encode, EncodeLabelSet},
+    metrics::{counter::Counter, family::Family, gauge::Gauge, histogram::Histogram},
+    registry::Registry,
+};
+
+use crate::{
+    store::{Activity, PoolStatus},
+    AppState,
+};
+
+/// The content type of the OpenMetrics text format, which Prometheus reads
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// The upper bounds of the buckets of request durations, in seconds
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The route of requests that matched none
+const UNMATCHED: &str = "unmatched";
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RequestLabels {
+    method: String,
+    route: String,
+    status: u16,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RouteLabels {
+    method: String,
+    route: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ConnectionLabels {
+    state: &'static str,
+}
+
+pub struct Metrics {
+    registry: Registry,
+    requests: Family<RequestLabels, Counter>,
+    durations: Family<RouteLabels, Histogram, fn() -> Histogram>,
+    connections: Family<ConnectionLabels, Gauge>,
+    max_connections: Gauge,
+    sessions: Gauge,
+    running_timers: Gauge,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let mut registry = Registry::with_prefix("bandit");
+        let requests = Family::<RequestLabels, Counter>::default();
+        registry.register(
+            "http_requests",
+            "Requests answered, by route and status",
+            requests.clone(),
+        );
+        let durations: Family<RouteLabels, Histogram, fn() -> Histogram> =
+            Family::new_with_constructor(|| Histogram::new(DURATION_BUCKETS));
+        registry.register(
+            "http_request_duration_seconds",
+            "How long requests took to answer, by route",
+            durations.clone(),
+        );
+        let connections = Family::<ConnectionLabels, Gauge>::default();
+        registry.register(
+            "db_pool_connections",
+            "Open connections of the database pool, by whether they are idle or in use",
+            connections.clone(),
+        );
+        let max_connections = Gauge::default();
+        registry.register(
+            "db_pool_max_connections",
+            "The most connections the database pool opens",
+            max_connections.clone(),
+        );
+        let sessions = Gauge::default();
+        registry.register("sessions", "Users logged in", sessions.clone());
+        let running_timers = Gauge::default();
+        registry.register(
+            "running_timers",
+            "Timers started and not yet stopped",
+            running_timers.clone(),
+        );
+        Metrics {
+            registry,
+            requests,
+            durations,
+            connections,
+            max_connections,
+            sessions,
+            running_timers,
+        }
+    }
+}
+
+impl Metrics {
+    /// The metrics in the OpenMetrics text format, with the pool and the
+    /// activity as they are now
+    pub fn encode(&self, pool: PoolStatus, activity: Activity) -> String {
+        let idle = pool.idle as i64;
+        self.connections
+            .get_or_create(&ConnectionLabels { state: "idle" })
+            .set(idle);
+        self.connections
+            .get_or_create(&ConnectionLabels { state: "in_use" })
+            .set(i64::from(pool.size) - idle);
+        self.max_connections.set(pool.max_size.into());
+        self.sessions.set(activity.sessions);
+        self.running_timers.set(activity.running_timers);
+        let mut text = String::new();
+        encode(&mut text, &self.registry).expect("writing to a String cannot fail");
+        text
+    }
+
+    fn observe(&self, method: String, route: String, status: u16, seconds: f64) {
+        self.durations
+            .get_or_create(&RouteLabels {
+                method: method.clone(),
+                route: route.clone(),
+            })
+            .observe(seconds);
+        self.requests
+            .get_or_create(&RequestLabels {
+                method,
+                route,
+                status,
+            })
+            .inc();
+    }
+}
+
+/// Counts and times the request, until its response starts
+pub async fn track(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(UNMATCHED, MatchedPath::as_str)
+        .to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    state.metrics.observe(
+        method,
+        route,
+        response.status().as_u16(),
+        started.elapsed().as_secs_f64(),
+    );
+    response
+}
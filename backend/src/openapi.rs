@@ -0,0 +1,253 @@
+//! The OpenAPI document of the API, served at `/openapi.json` and shown at
+//! `/docs`.
+//!
+//! It is generated from the `#[utoipa::path]` attribute of each handler and
+//! the schemas of the `models` types. A handler routed in `router()` has to
+//! be listed in `paths` here too, which a test checks.
+
+use utoipa::{
+    openapi::{
+        security::{ApiKey, ApiKeyValue, SecurityScheme},
+        ContentBuilder, OpenApi as Document, Ref, RefOr, ResponseBuilder,
+    },
+    Modify, OpenApi,
+};
+
+use crate::{
+    models::{ApiError, TaskSort},
+    routes,
+};
+
+/// Names the session cookie in `security`
+const SESSION: &str = "session";
+
+/// The response every operation may fail with
+const ERROR_RESPONSE: &str = "Error";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Time Bandit",
+        description = "Tracks the time spent on tasks. Requests are authenticated by the \
+            session cookie that `POST /users/login` sets, unless they say otherwise."
+    ),
+    paths(
+        routes::tasks::add_task,
+        routes::tasks::list_tasks,
+        routes::tasks::get_one_task_with_events,
+        routes::tasks::update_task,
+        routes::events::add_event,
+        routes::events::list_events,
+        routes::events::export_events_csv,
+        routes::events::get_event_overlaps,
+        routes::events::get_event,
+        routes::clients::add_client,
+        routes::clients::get_user_clients,
+        routes::clients::get_client,
+        routes::clients::update_client,
+        routes::clients::delete_client,
+        routes::clients::get_client_summary,
+        routes::import::import_events,
+        routes::import::import_ics,
+        routes::import::import_from_source,
+        routes::calendar::regenerate_calendar_token,
+        routes::calendar::get_calendar_feed,
+        routes::reports::get_report_summary,
+        routes::reports::get_report_summary_csv,
+        routes::search::search,
+        routes::sync::get_sync_changes,
+        routes::sync::push_sync_changes,
+        routes::timer::get_timer,
+        routes::timer::start_timer,
+        routes::timer::stop_timer,
+        routes::stream::stream,
+        routes::graphql::graphql,
+        routes::webhooks::add_webhook,
+        routes::webhooks::get_webhooks,
+        routes::webhooks::delete_webhook,
+        routes::webhooks::get_webhook_deliveries,
+        routes::webhooks::redeliver_webhook,
+        routes::users::get_user_settings,
+        routes::users::update_user_settings,
+        routes::users::register_user,
+        routes::users::login,
+        routes::auth::get_session,
+    ),
+    // schemas only referred to by query parameters aren't picked up
+    components(schemas(ApiError, TaskSort)),
+    security(("session" = [])),
+    modifiers(&ErrorResponses)
+)]
+struct ApiDoc;
+
+/// Adds the `ApiError` body of failed requests to every operation, as the
+/// `default` response
+struct ErrorResponses;
+
+impl Modify for ErrorResponses {
+    fn modify(&self, openapi: &mut Document) {
+        let error = ResponseBuilder::new()
+            .description("The request failed, see `code`")
+            .content(
+                "application/json",
+                ContentBuilder::new()
+                    .schema(Some(Ref::from_schema_name("ApiError")))
+                    .build(),
+            )
+            .build();
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .responses
+            .insert(ERROR_RESPONSE.to_string(), RefOr::T(error));
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [
+                &mut item.get,
+                &mut item.put,
+                &mut item.post,
+                &mut item.delete,
+            ];
+            for operation in operations.into_iter().flatten() {
+                operation.responses.responses.insert(
+                    "default".to_string(),
+                    RefOr::Ref(Ref::from_response_name(ERROR_RESPONSE)),
+                );
+            }
+        }
+    }
+}
+
+/// The document, with the session cookie under the name it is configured
+/// with
+pub fn document(cookie_name: &str) -> Document {
+    let mut document = ApiDoc::openapi();
+    // there is none in Cargo.toml, and an empty one is invalid
+    document.info.license = None;
+    document
+        .components
+        .get_or_insert_with(Default::default)
+        .add_security_scheme(
+            SESSION,
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(cookie_name))),
+        );
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, sync::Arc};
+
+    use axum::body::{to_bytes, Body};
+    use http::{Method, Request, StatusCode};
+    use regex::Regex;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{config::Config, router, routes, store::MemoryStore};
+
+    /// The operations `router()` routes as `METHOD /path`, with their
+    /// parameters written as OpenAPI's `{param}`
+    fn routed() -> BTreeSet<String> {
+        let routes = routes::session_routes()
+            .into_iter()
+            .chain(routes::public_routes());
+        routes
+            .map(|route| {
+                let path: Vec<String> = route
+                    .path
+                    .split('/')
+                    .map(|part| match part.strip_prefix(':') {
+                        Some(param) => format!("{{{}}}", param),
+                        None => part.to_string(),
+                    })
+                    .collect();
+                format!("{} {}", route.method, path.join("/"))
+            })
+            .collect()
+    }
+
+    /// The operations of the document as `METHOD /path`
+    fn documented() -> BTreeSet<String> {
+        let document = document("session");
+        let mut operations = BTreeSet::new();
+        for (path, item) in document.paths.paths {
+            let methods = [
+                ("GET", item.get),
+                ("PUT", item.put),
+                ("POST", item.post),
+                ("DELETE", item.delete),
+            ];
+            for (method, operation) in methods {
+                if operation.is_some() {
+                    operations.insert(format!("{} {}", method, path));
+                }
+            }
+        }
+        operations
+    }
+
+    #[test]
+    fn documents_every_route() {
+        let routed = routed();
+        let documented = documented();
+        assert!(
+            routed == documented,
+            "routed but not documented: {:?}\ndocumented but not routed: {:?}",
+            routed.difference(&documented).collect::<Vec<_>>(),
+            documented.difference(&routed).collect::<Vec<_>>(),
+        );
+    }
+
+    /// The router has to match every operation: neither a 405 nor the 404
+    /// of an unknown path
+    #[tokio::test]
+    async fn routes_every_documented_operation() {
+        let router = router(Arc::new(MemoryStore::default()), &Config::for_tests()).await;
+        let param = Regex::new(r"\{\w+\}").unwrap();
+        for operation in documented() {
+            let (method, path) = operation.split_once(' ').unwrap();
+            let request = Request::builder()
+                .method(Method::from_bytes(method.as_bytes()).unwrap())
+                .uri(param.replace_all(path, "1").as_ref())
+                .body(Body::empty())
+                .unwrap();
+            let response = router.clone().oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+            assert_ne!(status, StatusCode::METHOD_NOT_ALLOWED, "{}", operation);
+            assert!(
+                status != StatusCode::NOT_FOUND || body["message"] != routes::UNKNOWN_PATH,
+                "{} is not routed",
+                operation
+            );
+        }
+    }
+
+    #[test]
+    fn defines_every_referenced_schema() {
+        let json = serde_json::to_string(&document("session")).unwrap();
+        let schemas = &document("session").components.unwrap().schemas;
+        let reference = Regex::new(r"#/components/schemas/(\w+)").unwrap();
+        for found in reference.captures_iter(&json) {
+            assert!(
+                schemas.contains_key(&found[1]),
+                "{} is undefined",
+                &found[1]
+            );
+        }
+    }
+
+    #[test]
+    fn names_the_configured_cookie() {
+        let json = serde_json::to_value(document("custom_cookie")).unwrap();
+        assert_eq!(
+            json["components"]["securitySchemes"]["session"]["name"],
+            "custom_cookie"
+        );
+        assert_eq!(
+            json["paths"]["/tasks"]["get"]["responses"]["default"]["$ref"],
+            "#/components/responses/Error"
+        );
+    }
+}
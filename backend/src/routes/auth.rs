@@ -1,35 +1,38 @@
 use axum::{
     extract::{Request, State},
     middleware::Next,
-    response::{IntoResponse, Response},
-    Json,
+    response::Response,
 };
 use axum_extra::extract::PrivateCookieJar;
-use http::StatusCode;
-use sqlx::postgres::PgRow;
-use sqlx::Row;
 use tracing::info;
 
-use crate::{internal_error, models::UserId, AppState};
+use crate::{error::AppError, extract::Json, models::UserId, AppState};
 
 /// A simple endpoint to check if the cookie session is valid
 /// This is used in a <Session/> wrapper in the frontend
+#[utoipa::path(
+    get,
+    path = "/auth",
+    tag = "auth",
+    responses(
+        (status = 200, description = "the user the session cookie belongs to", body = UserId),
+    ),
+)]
 pub async fn get_session(
     State(state): State<AppState>,
     jar: PrivateCookieJar,
-) -> Result<Json<UserId>, (StatusCode, String)> {
+) -> Result<Json<UserId>, AppError> {
     let Some(cookie) = jar
-        .get("time_bandit_auth_token_v1")
+        .get(&state.cookie.name)
         .map(|cookie| cookie.value().to_owned())
     else {
-        return Err((StatusCode::UNAUTHORIZED, "Unauthorized".to_string()));
+        return Err(AppError::Unauthorized);
     };
-    let res = sqlx::query("SELECT * FROM sessions WHERE session_id = $1")
-        .bind(cookie)
-        .map(|row: PgRow| UserId(row.get("user_id")))
-        .fetch_one(&state.store.connection)
-        .await
-        .map_err(internal_error)?;
+    let res = state
+        .store
+        .get_session_user(cookie)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
     info!("{:?}", res);
     Ok(Json(res))
 }
@@ -41,32 +44,26 @@ pub async fn auth_middleware(
     mut request: Request,
     // this allows us to call the request
     next: Next,
-) -> Result<(PrivateCookieJar, Response), Response> {
+) -> Result<(PrivateCookieJar, Response), AppError> {
     let Some(cookie) = jar
-        .get("time_bandit_auth_token_v1")
+        .get(&state.cookie.name)
         .map(|cookie| cookie.value().to_owned())
     else {
-        let res = (StatusCode::UNAUTHORIZED).into_response();
-        info!("{:?}", res);
-        return Err(res);
+        info!("No session cookie");
+        return Err(AppError::Unauthorized);
     };
-    let find_session = sqlx::query("SELECT * FROM sessions WHERE session_id = $1")
-        .bind(cookie)
-        .map(|row: PgRow| UserId(row.get("user_id")))
-        .fetch_one(&state.store.connection)
-        .await;
+    let find_session = state.store.get_session_user(cookie).await?;
 
     match find_session {
-        Ok(res) => {
+        Some(res) => {
             // send the extension to the next request
             info!("{:?}", res);
             request.extensions_mut().insert(res);
             Ok((jar, next.run(request).await))
         }
-        Err(_) => {
-            let res = StatusCode::UNAUTHORIZED.into_response();
-            info!("{:?}", res);
-            return Err(res);
+        None => {
+            info!("Unknown session");
+            Err(AppError::Unauthorized)
         }
     }
 }
@@ -0,0 +1,65 @@
+use axum::{extract::State, response::IntoResponse, Extension};
+use chrono::Utc;
+use http::header::CONTENT_TYPE;
+use tracing::info;
+
+use crate::{
+    error::AppError,
+    extract::{Json, Path, Query},
+    ical::render_calendar,
+    models::{CalendarQuery, CalendarToken, UserId},
+    AppState,
+};
+
+/// The user's tracked time as an iCalendar feed. The secret token in the
+/// path stands in for the session cookie, so calendar apps can subscribe.
+#[utoipa::path(
+    get,
+    path = "/calendar/{token}",
+    tag = "calendar",
+    params(
+        ("token" = String, Path, description = "the calendar token, `.ics` may be appended"),
+        CalendarQuery,
+    ),
+    security(()),
+    responses((status = 200, body = String, content_type = "text/calendar")),
+)]
+pub async fn get_calendar_feed(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    Query(filter): Query<CalendarQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let token = token.strip_suffix(".ics").unwrap_or(&token);
+    let user_id = state
+        .store
+        .get_user_by_calendar_token(token)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("Unknown calendar".to_string()),
+            e => e.into(),
+        })?;
+    let events = state.store.get_calendar_events(user_id, filter).await?;
+    Ok((
+        [(CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        render_calendar(&events, Utc::now()),
+    ))
+}
+
+/// Creates a new secret URL for the calendar feed, revoking the old one
+#[utoipa::path(
+    post,
+    path = "/calendar/token",
+    tag = "calendar",
+    responses((status = 200, body = CalendarToken)),
+)]
+pub async fn regenerate_calendar_token(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+) -> Result<Json<CalendarToken>, AppError> {
+    let token = state.store.regenerate_calendar_token(user_id).await?;
+    info!("Regenerated calendar token");
+    Ok(Json(CalendarToken {
+        path: format!("/calendar/{}.ics", token),
+        token,
+    }))
+}
@@ -0,0 +1,118 @@
+use axum::{extract::State, Extension};
+use tracing::info;
+
+use crate::{
+    error::AppError,
+    extract::{Json, Path},
+    models::{Client, ClientId, ClientSummary, NewClient, UserId},
+    validation::Valid,
+    AppState,
+};
+
+#[utoipa::path(
+    post,
+    path = "/clients",
+    tag = "clients",
+    request_body = NewClient,
+    responses((status = 200, body = Client)),
+)]
+pub async fn add_client(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Valid(new_client): Valid<NewClient>,
+) -> Result<Json<Client>, AppError> {
+    let new_client = NewClient {
+        user_id,
+        ..new_client
+    };
+    let res = state.store.add_client(new_client).await?;
+    info!("{:?}", res);
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clients",
+    tag = "clients",
+    responses((status = 200, body = Vec<Client>)),
+)]
+pub async fn get_user_clients(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+) -> Result<Json<Vec<Client>>, AppError> {
+    let res = state.store.get_clients_by_user(user_id).await?;
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clients/{client_id}",
+    tag = "clients",
+    params(("client_id" = ClientId, Path)),
+    responses((status = 200, body = Client)),
+)]
+pub async fn get_client(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Path(client_id): Path<ClientId>,
+) -> Result<Json<Client>, AppError> {
+    let res = state.store.get_client(client_id, user_id).await?;
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    put,
+    path = "/clients/{client_id}",
+    tag = "clients",
+    params(("client_id" = ClientId, Path)),
+    request_body = NewClient,
+    responses((status = 200, body = Client)),
+)]
+pub async fn update_client(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Path(client_id): Path<ClientId>,
+    Valid(new_client): Valid<NewClient>,
+) -> Result<Json<Client>, AppError> {
+    let new_client = NewClient {
+        user_id,
+        ..new_client
+    };
+    let res = state.store.update_client(new_client, client_id).await?;
+    info!("{:?}", res);
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/clients/{client_id}",
+    tag = "clients",
+    params(("client_id" = ClientId, Path)),
+    responses((status = 200, body = ClientId)),
+)]
+pub async fn delete_client(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Path(client_id): Path<ClientId>,
+) -> Result<Json<ClientId>, AppError> {
+    let res = state.store.delete_client(client_id, user_id).await?;
+    info!("{:?}", res);
+    Ok(Json(res))
+}
+
+/// Total time spent on a client's tasks, broken down per task and per month
+#[utoipa::path(
+    get,
+    path = "/clients/{client_id}/summary",
+    tag = "clients",
+    params(("client_id" = ClientId, Path)),
+    responses((status = 200, body = ClientSummary)),
+)]
+pub async fn get_client_summary(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Path(client_id): Path<ClientId>,
+) -> Result<Json<ClientSummary>, AppError> {
+    let res = state.store.get_client_summary(client_id, user_id).await?;
+    Ok(Json(res))
+}
@@ -0,0 +1,66 @@
+use axum::{extract::State, response::Html, Json};
+use utoipa::openapi::OpenApi;
+use utoipa_scalar::Scalar;
+
+use crate::{config::DocsConfig, openapi::document, AppState};
+
+/// The OpenAPI document of the API
+pub async fn get_openapi(State(state): State<AppState>) -> Json<OpenApi> {
+    Json(document(&state.cookie.name))
+}
+
+/// A page to browse the OpenAPI document and try out requests
+pub async fn get_docs(State(state): State<AppState>) -> Html<String> {
+    Html(
+        Scalar::new(document(&state.cookie.name))
+            .custom_html(page(&state.docs))
+            .to_html(),
+    )
+}
+
+/// Scalar's own page, loading the script from where the config says and
+/// checking it against its hash if there is one. `$spec` is replaced with
+/// the document.
+fn page(docs: &DocsConfig) -> String {
+    let integrity = match &docs.script_integrity {
+        Some(hash) => format!(r#" integrity="{}" crossorigin="anonymous""#, hash),
+        None => String::new(),
+    };
+    format!(
+        r#"<!doctype html>
+<html>
+<head>
+    <title>Time Bandit</title>
+    <meta charset="utf-8"/>
+    <meta name="viewport" content="width=device-width, initial-scale=1"/>
+</head>
+<body>
+<script id="api-reference" type="application/json">
+    $spec
+</script>
+<script src="{}"{}></script>
+</body>
+</html>
+"#,
+        docs.script_url, integrity
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn loads_the_script_from_the_config() {
+        let mut docs = Config::for_tests().docs;
+        assert!(page(&docs).contains(
+            r#"<script src="https://cdn.jsdelivr.net/npm/@scalar/api-reference@1.25.0"></script>"#
+        ));
+        docs.script_url = "/assets/scalar.js".to_string();
+        docs.script_integrity = Some("sha384-abc".to_string());
+        assert!(page(&docs).contains(
+            r#"<script src="/assets/scalar.js" integrity="sha384-abc" crossorigin="anonymous"></script>"#
+        ));
+    }
+}
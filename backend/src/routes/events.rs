@@ -1,18 +1,36 @@
-use axum::{extract::State, Extension, Json};
-use http::StatusCode;
+use axum::{extract::State, response::Response, Extension};
+use serde_json::json;
 use tracing::info;
 
 use crate::{
-    internal_error,
-    models::{NewTaskEvent, TaskEvent, UserId},
+    error::AppError,
+    export::{csv_response, parse_columns, EventColumn},
+    extract::{Json, Path, Query},
+    models::{
+        EventExportQuery, EventListQuery, EventOverlapQuery, EventOverlaps, EventPage, ListedEvent,
+        NewTaskEvent, TaskEvent, TaskEventId, UserId,
+    },
+    routes::{
+        decode_cursor, encode_cursor, page_limit,
+        reports::{check_date_range, check_timezone},
+    },
+    store::{EventAdded, EventCursor},
+    validation::Valid,
     AppState,
 };
 
+#[utoipa::path(
+    post,
+    path = "/events",
+    tag = "events",
+    request_body = NewTaskEvent,
+    responses((status = 200, body = TaskEvent)),
+)]
 pub async fn add_event(
     State(state): State<AppState>,
     Extension(user_id): Extension<UserId>,
-    Json(new_event): Json<NewTaskEvent>,
-) -> Result<Json<TaskEvent>, (StatusCode, String)> {
+    Valid(new_event): Valid<NewTaskEvent>,
+) -> Result<Json<TaskEvent>, AppError> {
     let new_event = NewTaskEvent {
         user_id,
         task_id: new_event.task_id,
@@ -20,11 +38,126 @@ pub async fn add_event(
         duration: new_event.duration,
         notes: new_event.notes,
     };
-    let res = state
+    let added = state
         .store
         .add_event(new_event)
         .await
-        .map_err(internal_error)?;
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("Unknown task".to_string()),
+            e => e.into(),
+        })?;
+    let res = added_event(added)?;
     info!("{:?}", res);
     Ok(Json(res))
 }
+
+/// The event that was added, or a conflict listing the events it overlaps
+pub fn added_event(added: EventAdded) -> Result<TaskEvent, AppError> {
+    match added {
+        EventAdded::Added(event) => Ok(event),
+        EventAdded::Overlapping(events) => Err(AppError::Conflict {
+            message: "The event overlaps others".to_string(),
+            details: Some(json!({ "overlapping": events })),
+        }),
+    }
+}
+
+/// A page of the user's events by the time they began, with a cursor to the
+/// next one if there is more
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "events",
+    params(EventListQuery),
+    responses((status = 200, body = EventPage)),
+)]
+pub async fn list_events(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Query(query): Query<EventListQuery>,
+) -> Result<Json<EventPage>, AppError> {
+    let limit = page_limit(query.limit)?;
+    check_date_range(query.from, query.to)?;
+    check_timezone(&*state.store, &query.tz).await?;
+    let after = query
+        .cursor
+        .as_deref()
+        .map(decode_cursor::<EventCursor>)
+        .transpose()?;
+
+    // one more than asked for tells whether there is a next page
+    let mut events = state
+        .store
+        .list_events(user_id, query, after, limit + 1)
+        .await?;
+    let next_cursor = if events.len() as i64 > limit {
+        events.truncate(limit as usize);
+        events
+            .last()
+            .map(|event| encode_cursor(&EventCursor::after(event)))
+    } else {
+        None
+    };
+    Ok(Json(EventPage {
+        events,
+        next_cursor,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/events/{event_id}",
+    tag = "events",
+    params(("event_id" = TaskEventId, Path)),
+    responses((status = 200, body = ListedEvent)),
+)]
+pub async fn get_event(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Path(event_id): Path<TaskEventId>,
+) -> Result<Json<ListedEvent>, AppError> {
+    let event = state.store.get_event(event_id, user_id).await?;
+    Ok(Json(event))
+}
+
+/// Pairs of the user's events that overlap, optionally limited to those
+/// overlapping within a date range
+#[utoipa::path(
+    get,
+    path = "/events/overlaps",
+    tag = "events",
+    params(EventOverlapQuery),
+    responses((status = 200, body = EventOverlaps)),
+)]
+pub async fn get_event_overlaps(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Query(query): Query<EventOverlapQuery>,
+) -> Result<Json<EventOverlaps>, AppError> {
+    check_date_range(query.from, query.to)?;
+    check_timezone(&*state.store, &query.tz).await?;
+    let overlaps = state.store.find_overlaps(user_id, query).await?;
+    Ok(Json(EventOverlaps { overlaps }))
+}
+
+/// Streams the user's events as a CSV file, optionally limited to a date range
+#[utoipa::path(
+    get,
+    path = "/events/export.csv",
+    tag = "events",
+    params(EventExportQuery),
+    responses((status = 200, body = String, content_type = "text/csv")),
+)]
+pub async fn export_events_csv(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Query(export): Query<EventExportQuery>,
+) -> Result<Response, AppError> {
+    let columns =
+        parse_columns::<EventColumn>(export.columns.as_deref()).map_err(AppError::BadRequest)?;
+    check_date_range(export.from, export.to)?;
+    check_timezone(&*state.store, &export.tz).await?;
+    let duration_format = export.duration_format;
+    let rows = state.store.stream_events_for_export(user_id, export);
+    Ok(csv_response("events.csv", columns, duration_format, rows))
+}
@@ -0,0 +1,24 @@
+use axum::{extract::State, Extension};
+
+use crate::{extract::Json, graphql::prepare, models::UserId, AppState};
+
+/// Runs a GraphQL query or mutation for the user of the session. Errors are
+/// in `errors` of the response, which is always a 200.
+#[utoipa::path(
+    post,
+    path = "/graphql",
+    tag = "graphql",
+    request_body(
+        content = Object,
+        description = "`query`, and `variables` and `operationName` if it has any"
+    ),
+    responses((status = 200, body = Object, description = "`data`, and `errors` if any")),
+)]
+pub async fn graphql(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let request = prepare(request, &state, user_id);
+    Json(state.graphql.execute(request).await)
+}
@@ -0,0 +1,46 @@
+use axum::{extract::State, response::IntoResponse};
+use http::{header::CONTENT_TYPE, StatusCode};
+
+use crate::{error::AppError, metrics, AppState};
+
+/// The process is up, whatever the state of the database
+pub async fn get_health() -> &'static str {
+    "OK"
+}
+
+/// The database can be reached and has every migration of this build
+/// applied, so requests can be served. Only reads from the database.
+pub async fn get_readiness(State(state): State<AppState>) -> (StatusCode, String) {
+    let applied = match state.store.probe_migrations().await {
+        Ok(applied) => applied,
+        Err(e) => {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "The database cannot be reached".to_string(),
+            );
+        }
+    };
+    let pending = state
+        .store
+        .migrator()
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .filter(|migration| !applied.contains(&migration.version))
+        .count();
+    if pending > 0 {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("{} migrations are pending", pending),
+        );
+    }
+    (StatusCode::OK, "Ready".to_string())
+}
+
+/// The metrics of the requests, the database pool, the sessions and the
+/// timers, for Prometheus
+pub async fn get_metrics(State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    let activity = state.store.get_activity().await?;
+    let text = state.metrics.encode(state.store.pool_status(), activity);
+    Ok(([(CONTENT_TYPE, metrics::CONTENT_TYPE)], text))
+}
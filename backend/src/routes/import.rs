@@ -0,0 +1,148 @@
+use axum::{extract::State, Extension};
+use chrono_tz::Tz;
+use http::StatusCode;
+use tracing::info;
+
+use crate::{
+    error::AppError,
+    extract::{Json, Path},
+    import::{parse_import, ParsedImport},
+    importers::{ics::parse_ics, importer_for, sources},
+    models::{
+        ExternalImportRequest, IcsImportReport, IcsImportRequest, ImportDedupe, ImportReport,
+        ImportRequest, UserId,
+    },
+    AppState,
+};
+
+/// Bulk imports historical events from a CSV or JSON file.
+/// Nothing is written if any row is invalid; a dry run only reports what would happen.
+/// Rows the user's overlap policy leaves out are reported as errors, the others are written.
+#[utoipa::path(
+    post,
+    path = "/import",
+    tag = "import",
+    request_body = ImportRequest,
+    responses(
+        (status = 200, body = ImportReport),
+        (status = 422, description = "nothing was written", body = ImportReport),
+    ),
+)]
+pub async fn import_events(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Json(request): Json<ImportRequest>,
+) -> Result<(StatusCode, Json<ImportReport>), AppError> {
+    let parsed = parse_import(&request).map_err(AppError::BadRequest)?;
+    run_import(state, user_id, parsed, request.dedupe, request.dry_run).await
+}
+
+/// Imports the export file of another time tracker, such as `toggl`,
+/// `clockify` or `harvest`, with the same rules as `import_events`
+#[utoipa::path(
+    post,
+    path = "/import/{source}",
+    tag = "import",
+    params(("source" = String, Path, description = "`toggl`, `clockify` or `harvest`")),
+    request_body = ExternalImportRequest,
+    responses(
+        (status = 200, body = ImportReport),
+        (status = 422, description = "nothing was written", body = ImportReport),
+    ),
+)]
+pub async fn import_from_source(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Path(source): Path<String>,
+    Json(request): Json<ExternalImportRequest>,
+) -> Result<(StatusCode, Json<ImportReport>), AppError> {
+    let Some(importer) = importer_for(&source) else {
+        return Err(AppError::NotFound(format!(
+            "Unknown import source `{}`, expected one of: {}",
+            source,
+            sources().join(", ")
+        )));
+    };
+    let tz = parse_tz(&request.tz)?;
+    let parsed = importer
+        .parse(&request.data, tz)
+        .map_err(AppError::BadRequest)?;
+    run_import(state, user_id, parsed, request.dedupe, request.dry_run).await
+}
+
+/// Imports the events of an .ics file, assigning them to tasks by the first
+/// matching rule. Declined, cancelled, all-day and unmatched events are
+/// skipped, and re-importing a file skips the events already imported.
+#[utoipa::path(
+    post,
+    path = "/import/ics",
+    tag = "import",
+    request_body = IcsImportRequest,
+    responses(
+        (status = 200, body = IcsImportReport),
+        (status = 422, description = "nothing was written", body = IcsImportReport),
+    ),
+)]
+pub async fn import_ics(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Json(request): Json<IcsImportRequest>,
+) -> Result<(StatusCode, Json<IcsImportReport>), AppError> {
+    let tz = parse_tz(&request.tz)?;
+    let attendee = match request.attendee {
+        Some(attendee) => attendee,
+        None => state.store.get_user_email(user_id.clone()).await?.0,
+    };
+    let parsed =
+        parse_ics(&request.data, &request.rules, &attendee, tz).map_err(AppError::BadRequest)?;
+    // calendar events are recognised by their UID alone
+    let (status, Json(report)) = run_import(
+        state,
+        user_id,
+        parsed.import,
+        ImportDedupe::None,
+        request.dry_run,
+    )
+    .await?;
+    Ok((
+        status,
+        Json(IcsImportReport {
+            report,
+            skipped: parsed.skipped,
+        }),
+    ))
+}
+
+fn parse_tz(tz: &str) -> Result<Tz, AppError> {
+    tz.parse()
+        .map_err(|_| AppError::BadRequest(format!("Unknown time zone: {}", tz)))
+}
+
+async fn run_import(
+    state: AppState,
+    user_id: UserId,
+    mut parsed: ParsedImport,
+    dedupe: ImportDedupe,
+    dry_run: bool,
+) -> Result<(StatusCode, Json<ImportReport>), AppError> {
+    parsed.check_rows();
+    if !parsed.errors.is_empty() && !dry_run {
+        let report = ImportReport {
+            total_rows: parsed.total_rows,
+            errors: parsed.errors,
+            ..ImportReport::default()
+        };
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(report)));
+    }
+
+    let mut report = state
+        .store
+        .import_events(user_id, parsed.rows, dedupe, dry_run)
+        .await?;
+    report.total_rows = parsed.total_rows;
+    // the rows the overlap policy left out, next to the invalid ones of a dry run
+    report.errors.extend(parsed.errors);
+    report.errors.sort_by_key(|error| error.row);
+    info!("{:?}", report);
+    Ok((StatusCode::OK, Json(report)))
+}
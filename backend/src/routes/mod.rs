@@ -1,4 +1,183 @@
+use axum::{
+    extract::DefaultBodyLimit,
+    handler::Handler,
+    routing::{delete, get, post, put, MethodRouter},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use http::Method;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{error::AppError, AppState};
+
 pub mod auth;
+pub mod calendar;
+pub mod clients;
+pub mod docs;
 pub mod events;
+pub mod graphql;
+pub mod health;
+pub mod import;
+pub mod reports;
+pub mod search;
+pub mod stream;
+pub mod sync;
 pub mod tasks;
+pub mod timer;
 pub mod users;
+pub mod webhooks;
+
+/// Items in a page of a listing unless `limit` asks for another number up to
+/// the most
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+/// Imports carry whole spreadsheets, so they may be larger than other requests
+const IMPORT_BODY_LIMIT: usize = 20 * 1024 * 1024;
+
+/// An operation of the API, which `router()` routes and the OpenAPI document
+/// has to describe
+pub struct ApiRoute {
+    /// only read by the test comparing the routes with the document
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub method: Method,
+    /// in axum's syntax, with `:param`
+    pub path: &'static str,
+    pub handler: MethodRouter<AppState>,
+}
+
+impl ApiRoute {
+    fn new<H, T>(method: Method, path: &'static str, handler: H) -> ApiRoute
+    where
+        H: Handler<T, AppState>,
+        T: 'static,
+    {
+        let handler = match method {
+            Method::GET => get(handler),
+            Method::POST => post(handler),
+            Method::PUT => put(handler),
+            Method::DELETE => delete(handler),
+            _ => unreachable!("the API has no {} routes", method),
+        };
+        ApiRoute {
+            method,
+            path,
+            handler,
+        }
+    }
+
+    fn body_limit(self, limit: usize) -> ApiRoute {
+        ApiRoute {
+            handler: self.handler.layer(DefaultBodyLimit::max(limit)),
+            ..self
+        }
+    }
+}
+
+/// The operations for the user of the session
+pub fn session_routes() -> Vec<ApiRoute> {
+    use Method as M;
+    vec![
+        ApiRoute::new(M::POST, "/tasks", tasks::add_task),
+        ApiRoute::new(M::GET, "/tasks", tasks::list_tasks),
+        ApiRoute::new(M::GET, "/tasks/:task_id", tasks::get_one_task_with_events),
+        ApiRoute::new(M::PUT, "/tasks/:task_id", tasks::update_task),
+        ApiRoute::new(M::POST, "/events", events::add_event),
+        ApiRoute::new(M::GET, "/events", events::list_events),
+        ApiRoute::new(M::GET, "/events/export.csv", events::export_events_csv),
+        ApiRoute::new(M::GET, "/events/overlaps", events::get_event_overlaps),
+        ApiRoute::new(M::GET, "/events/:event_id", events::get_event),
+        ApiRoute::new(M::POST, "/clients", clients::add_client),
+        ApiRoute::new(M::GET, "/clients", clients::get_user_clients),
+        ApiRoute::new(M::GET, "/clients/:client_id", clients::get_client),
+        ApiRoute::new(M::PUT, "/clients/:client_id", clients::update_client),
+        ApiRoute::new(M::DELETE, "/clients/:client_id", clients::delete_client),
+        ApiRoute::new(
+            M::GET,
+            "/clients/:client_id/summary",
+            clients::get_client_summary,
+        ),
+        ApiRoute::new(M::POST, "/import", import::import_events).body_limit(IMPORT_BODY_LIMIT),
+        ApiRoute::new(M::POST, "/import/ics", import::import_ics).body_limit(IMPORT_BODY_LIMIT),
+        ApiRoute::new(M::POST, "/import/:source", import::import_from_source)
+            .body_limit(IMPORT_BODY_LIMIT),
+        ApiRoute::new(
+            M::POST,
+            "/calendar/token",
+            calendar::regenerate_calendar_token,
+        ),
+        ApiRoute::new(M::GET, "/reports/summary", reports::get_report_summary),
+        ApiRoute::new(
+            M::GET,
+            "/reports/summary.csv",
+            reports::get_report_summary_csv,
+        ),
+        ApiRoute::new(M::GET, "/search", search::search),
+        ApiRoute::new(M::GET, "/sync", sync::get_sync_changes),
+        ApiRoute::new(M::POST, "/sync", sync::push_sync_changes),
+        ApiRoute::new(M::GET, "/timer", timer::get_timer),
+        ApiRoute::new(M::POST, "/timer/start", timer::start_timer),
+        ApiRoute::new(M::POST, "/timer/stop", timer::stop_timer),
+        ApiRoute::new(M::GET, "/stream", stream::stream),
+        ApiRoute::new(M::POST, "/graphql", graphql::graphql),
+        ApiRoute::new(M::POST, "/webhooks", webhooks::add_webhook),
+        ApiRoute::new(M::GET, "/webhooks", webhooks::get_webhooks),
+        ApiRoute::new(M::DELETE, "/webhooks/:webhook_id", webhooks::delete_webhook),
+        ApiRoute::new(
+            M::GET,
+            "/webhooks/:webhook_id/deliveries",
+            webhooks::get_webhook_deliveries,
+        ),
+        ApiRoute::new(
+            M::POST,
+            "/webhooks/:webhook_id/deliveries/:delivery_id/redeliver",
+            webhooks::redeliver_webhook,
+        ),
+        ApiRoute::new(M::GET, "/users/settings", users::get_user_settings),
+        ApiRoute::new(M::PUT, "/users/settings", users::update_user_settings),
+    ]
+}
+
+/// The operations anyone may call
+pub fn public_routes() -> Vec<ApiRoute> {
+    use Method as M;
+    vec![
+        ApiRoute::new(M::GET, "/auth", auth::get_session),
+        // authenticated by the secret token in the path
+        ApiRoute::new(M::GET, "/calendar/:token", calendar::get_calendar_feed),
+        ApiRoute::new(M::POST, "/users/register", users::register_user),
+        ApiRoute::new(M::POST, "/users/login", users::login),
+    ]
+}
+
+/// The message of the 404 of a path that no route matches
+pub const UNKNOWN_PATH: &str = "Unknown path";
+
+/// Answers requests no route matches, like any other 404
+pub async fn unknown_path() -> AppError {
+    AppError::NotFound(UNKNOWN_PATH.to_string())
+}
+
+fn page_limit(limit: Option<i64>) -> Result<i64, AppError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::BadRequest(format!(
+            "`limit` must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+    Ok(limit)
+}
+
+/// Cursors are opaque to clients, the JSON of the store's cursor in URL safe
+/// base64
+fn encode_cursor<T: Serialize>(cursor: &T) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(cursor).unwrap())
+}
+
+fn decode_cursor<T: DeserializeOwned>(cursor: &str) -> Result<T, AppError> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or_else(|| AppError::BadRequest("Invalid cursor".to_string()))
+}
@@ -0,0 +1,120 @@
+use axum::{extract::State, response::Response, Extension};
+use chrono::{Datelike, NaiveDate};
+
+use crate::{
+    error::AppError,
+    export::{csv_response, parse_columns, ReportColumn},
+    extract::{Json, Query},
+    models::{
+        DurationFormat, ReportExportQuery, ReportGroupBy, ReportQuery, ReportSummary, UserId,
+    },
+    store::Store,
+    AppState,
+};
+
+/// The most days, weeks or months a report is bucketed into
+pub const MAX_REPORT_BUCKETS: i64 = 1000;
+
+/// Totals of the user's tracked time between two dates, grouped by
+/// day, week, month, task, tag or project
+#[utoipa::path(
+    get,
+    path = "/reports/summary",
+    tag = "reports",
+    params(ReportQuery),
+    responses((status = 200, body = ReportSummary)),
+)]
+pub async fn get_report_summary(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Query(report): Query<ReportQuery>,
+) -> Result<Json<ReportSummary>, AppError> {
+    check_report_range(&report)?;
+    check_timezone(&*state.store, &report.tz).await?;
+    let res = state.store.get_report_summary(user_id, report).await?;
+    Ok(Json(res))
+}
+
+/// The same totals as `get_report_summary`, streamed as a CSV file
+#[utoipa::path(
+    get,
+    path = "/reports/summary.csv",
+    tag = "reports",
+    params(
+        ReportQuery,
+        ("columns" = Option<String>, Query, description = "comma separated, defaults to all"),
+        ("duration_format" = Option<DurationFormat>, Query),
+    ),
+    responses((status = 200, body = String, content_type = "text/csv")),
+)]
+pub async fn get_report_summary_csv(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Query(export): Query<ReportExportQuery>,
+) -> Result<Response, AppError> {
+    let columns =
+        parse_columns::<ReportColumn>(export.columns.as_deref()).map_err(AppError::BadRequest)?;
+    check_report_range(&export.report)?;
+    check_timezone(&*state.store, &export.report.tz).await?;
+    let rows = state.store.stream_report_summary(user_id, export.report);
+    Ok(csv_response(
+        "summary.csv",
+        columns,
+        export.duration_format,
+        rows,
+    ))
+}
+
+pub fn check_date_range(from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<(), AppError> {
+    // beyond these the instants of the dates are out of reach in some time
+    // zones
+    if [from, to]
+        .into_iter()
+        .flatten()
+        .any(|date| !(1..=9999).contains(&date.year()))
+    {
+        return Err(AppError::BadRequest(
+            "Dates must be between 0001-01-01 and 9999-12-31".to_string(),
+        ));
+    }
+    match (from, to) {
+        (Some(from), Some(to)) if from > to => Err(AppError::BadRequest(
+            "`from` must not be after `to`".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Time based reports have a bucket for every day, week or month of their
+/// range, even an empty one, so the range is limited
+fn check_report_range(report: &ReportQuery) -> Result<(), AppError> {
+    check_date_range(Some(report.from), Some(report.to))?;
+    let (from, to) = (report.from, report.to);
+    let buckets = match report.group_by {
+        ReportGroupBy::Day => (to - from).num_days() + 1,
+        ReportGroupBy::Week => {
+            let monday = i64::from(from.weekday().num_days_from_monday());
+            ((to - from).num_days() + monday) / 7 + 1
+        }
+        ReportGroupBy::Month => {
+            i64::from(to.year() - from.year()) * 12 + i64::from(to.month())
+                - i64::from(from.month())
+                + 1
+        }
+        ReportGroupBy::Task | ReportGroupBy::Tag | ReportGroupBy::Project => return Ok(()),
+    };
+    if buckets > MAX_REPORT_BUCKETS {
+        return Err(AppError::BadRequest(format!(
+            "A report has at most {} days, weeks or months, group it by a longer period",
+            MAX_REPORT_BUCKETS
+        )));
+    }
+    Ok(())
+}
+
+pub async fn check_timezone(store: &dyn Store, tz: &str) -> Result<(), AppError> {
+    if !store.timezone_exists(tz).await? {
+        return Err(AppError::BadRequest(format!("Unknown time zone: {}", tz)));
+    }
+    Ok(())
+}
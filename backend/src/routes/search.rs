@@ -0,0 +1,35 @@
+use axum::{extract::State, Extension};
+
+use crate::{
+    error::AppError,
+    extract::{Json, Query},
+    models::{SearchQuery, SearchResults, UserId},
+    routes::{
+        page_limit,
+        reports::{check_date_range, check_timezone},
+    },
+    AppState,
+};
+
+/// The user's tasks and events matching a search, the best matches first
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "search",
+    params(SearchQuery),
+    responses((status = 200, body = SearchResults)),
+)]
+pub async fn search(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResults>, AppError> {
+    if query.q.trim().is_empty() {
+        return Err(AppError::BadRequest("`q` must not be empty".to_string()));
+    }
+    let limit = page_limit(query.limit)?;
+    check_date_range(query.from, query.to)?;
+    check_timezone(&*state.store, &query.tz).await?;
+    let hits = state.store.search(user_id, query, limit).await?;
+    Ok(Json(SearchResults { hits }))
+}
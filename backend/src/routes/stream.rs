@@ -0,0 +1,60 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+    Extension,
+};
+use futures::{stream, Stream};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    error::AppError,
+    models::{Notification, UserId},
+    AppState,
+};
+
+/// Server-sent events of the changes to the user's data, each named after
+/// the `type` of its `Notification`. A `lagged` event, with the number of
+/// notifications missed, tells the client to fetch what it shows again.
+#[utoipa::path(
+    get,
+    path = "/stream",
+    tag = "stream",
+    responses(
+        (
+            status = 200,
+            description = "a server-sent event for each notification",
+            body = Notification,
+            content_type = "text/event-stream"
+        ),
+    ),
+)]
+pub async fn stream(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let receiver = state.store.subscribe().await?;
+    let events = stream::unfold(receiver, move |mut receiver| {
+        let user_id = user_id.clone();
+        async move {
+            loop {
+                let event = match receiver.recv().await {
+                    Ok(received) if received.user_id == user_id => {
+                        let notification = received.notification;
+                        Event::default()
+                            .event(notification.kind())
+                            .data(serde_json::to_string(&notification).unwrap())
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        Event::default().event("lagged").data(missed.to_string())
+                    }
+                    Err(RecvError::Closed) => return None,
+                };
+                return Some((Ok(event), receiver));
+            }
+        }
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,50 @@
+use axum::{extract::State, Extension};
+use tracing::info;
+
+use crate::{
+    error::AppError,
+    extract::{Json, Query},
+    models::{SyncChanges, SyncPush, SyncQuery, SyncReport, UserId},
+    AppState,
+};
+
+/// The tasks, events and deletions after the cursor in `since`, along with
+/// the cursor to pass next time
+#[utoipa::path(
+    get,
+    path = "/sync",
+    tag = "sync",
+    params(SyncQuery),
+    responses((status = 200, body = SyncChanges)),
+)]
+pub async fn get_sync_changes(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Query(query): Query<SyncQuery>,
+) -> Result<Json<SyncChanges>, AppError> {
+    let res = state.store.get_sync_changes(user_id, query.since).await?;
+    Ok(Json(res))
+}
+
+/// Applies changes made on a client, keyed by the uuids the client generated.
+/// Fields the server wrote more recently are kept and listed as conflicts.
+#[utoipa::path(
+    post,
+    path = "/sync",
+    tag = "sync",
+    request_body = SyncPush,
+    responses((status = 200, body = SyncReport)),
+)]
+pub async fn push_sync_changes(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Json(push): Json<SyncPush>,
+) -> Result<Json<SyncReport>, AppError> {
+    let res = state.store.apply_sync(user_id, push).await?;
+    info!(
+        "Synced {} changes with {} conflicts",
+        res.applied,
+        res.conflicts.len()
+    );
+    Ok(Json(res))
+}
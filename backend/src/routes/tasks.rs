@@ -1,71 +1,158 @@
-use axum::{
-    extract::{Path, State},
-    Extension, Json,
-};
-use http::StatusCode;
+use axum::{extract::State, Extension};
 use tracing::info;
 
 use crate::{
-    internal_error,
-    models::{NewTask, Task, TaskId, TaskWithTaskEvents, UserId},
+    error::AppError,
+    extract::{Json, Path, Query},
+    models::{
+        ClientId, NewTask, Task, TaskId, TaskListQuery, TaskPage, TaskWithTaskEvents, UserId,
+    },
+    routes::{decode_cursor, encode_cursor, page_limit, reports::check_timezone},
+    store::TaskCursor,
+    validation::{FieldErrors, Valid},
     AppState,
 };
 
+#[utoipa::path(
+    post,
+    path = "/tasks",
+    tag = "tasks",
+    request_body = NewTask,
+    responses((status = 200, body = Task)),
+)]
 pub async fn add_task(
     State(state): State<AppState>,
     // this extension is given by auth and extracted here
     Extension(user_id): Extension<UserId>,
-    Json(new_task): Json<NewTask>,
-) -> Result<Json<Task>, (StatusCode, String)> {
+    Valid(new_task): Valid<NewTask>,
+) -> Result<Json<Task>, AppError> {
+    // a task can only be assigned to one of the user's own clients
+    if let Some(client_id) = new_task.client_id.clone() {
+        check_client(&state, client_id, user_id.clone()).await?;
+    }
     let new_task = NewTask {
         user_id,
         name: new_task.name,
         description: new_task.description,
+        client_id: new_task.client_id,
+        tags: new_task.tags,
     };
-    let res = state
-        .store
-        .add_task(new_task)
-        .await
-        .map_err(internal_error)?;
+    let res = state.store.add_task(new_task).await?;
     info!("{:?}", res);
     Ok(Json(res))
 }
 
+#[utoipa::path(
+    put,
+    path = "/tasks/{task_id}",
+    tag = "tasks",
+    params(("task_id" = TaskId, Path)),
+    request_body = NewTask,
+    responses((status = 200, body = Task)),
+)]
 pub async fn update_task(
     State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
     Path(task_id): Path<TaskId>,
-    Json(new_task_data): Json<NewTask>,
-) -> Result<Json<Task>, (StatusCode, String)> {
-    let res = state
-        .store
-        .update_task(new_task_data, task_id)
-        .await
-        .map_err(internal_error)?;
+    Valid(new_task_data): Valid<NewTask>,
+) -> Result<Json<Task>, AppError> {
+    if let Some(client_id) = new_task_data.client_id.clone() {
+        check_client(&state, client_id, user_id.clone()).await?;
+    }
+    // only the user's own task is updated, whoever the body names
+    let new_task_data = NewTask {
+        user_id,
+        ..new_task_data
+    };
+    let res = state.store.update_task(new_task_data, task_id).await?;
     info!("{:?}", res);
     Ok(Json(res))
 }
 
-pub async fn get_user_tasks_with_events(
+/// A page of the user's tasks, with a cursor to the next one if there is more
+#[utoipa::path(
+    get,
+    path = "/tasks",
+    tag = "tasks",
+    params(TaskListQuery),
+    responses((status = 200, body = TaskPage)),
+)]
+pub async fn list_tasks(
     State(state): State<AppState>,
     Extension(user_id): Extension<UserId>,
-) -> Result<Json<Vec<TaskWithTaskEvents>>, (StatusCode, String)> {
-    let res = state
+    Query(query): Query<TaskListQuery>,
+) -> Result<Json<TaskPage>, AppError> {
+    let limit = page_limit(query.limit)?;
+    if let (Some(from), Some(to)) = (query.updated_from, query.updated_to) {
+        if from > to {
+            return Err(AppError::BadRequest(
+                "`updated_from` must not be after `updated_to`".to_string(),
+            ));
+        }
+    }
+    check_timezone(&*state.store, &query.tz).await?;
+    let after = query
+        .cursor
+        .as_deref()
+        .map(decode_cursor::<TaskCursor>)
+        .transpose()?;
+    if after
+        .as_ref()
+        .is_some_and(|after| after.sort() != query.sort)
+    {
+        return Err(AppError::BadRequest(
+            "The cursor is from a listing with another sort".to_string(),
+        ));
+    }
+
+    let sort = query.sort;
+    // one more than asked for tells whether there is a next page
+    let mut tasks = state
         .store
-        .get_user_tasks_with_events(user_id)
-        .await
-        .map_err(internal_error)?;
-    Ok(Json(res))
+        .list_tasks(user_id, query, after, limit + 1)
+        .await?;
+    let next_cursor = if tasks.len() as i64 > limit {
+        tasks.truncate(limit as usize);
+        tasks
+            .last()
+            .map(|task| encode_cursor(&TaskCursor::after(sort, task)))
+    } else {
+        None
+    };
+    Ok(Json(TaskPage { tasks, next_cursor }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/tasks/{task_id}",
+    tag = "tasks",
+    params(("task_id" = TaskId, Path)),
+    responses((status = 200, body = TaskWithTaskEvents)),
+)]
 pub async fn get_one_task_with_events(
     State(state): State<AppState>,
     Extension(user_id): Extension<UserId>,
     Path(task_id): Path<TaskId>,
-) -> Result<Json<TaskWithTaskEvents>, (StatusCode, String)> {
+) -> Result<Json<TaskWithTaskEvents>, AppError> {
     let task = state
         .store
-        .get_task_with_events_by_task_id(task_id)
-        .await
-        .map_err(internal_error)?;
+        .get_task_with_events_by_task_id(task_id, user_id)
+        .await?;
     Ok(Json(task))
 }
+
+async fn check_client(
+    state: &AppState,
+    client_id: ClientId,
+    user_id: UserId,
+) -> Result<(), AppError> {
+    match state.store.get_client(client_id, user_id).await {
+        Ok(_) => Ok(()),
+        Err(sqlx::Error::RowNotFound) => {
+            let mut errors = FieldErrors::default();
+            errors.add("client_id", "is not one of your clients");
+            Err(errors.into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
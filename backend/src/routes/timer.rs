@@ -0,0 +1,80 @@
+use axum::{extract::State, Extension};
+use tracing::info;
+
+use crate::{
+    error::AppError,
+    extract::Json,
+    models::{StartTimer, StopTimer, TaskEvent, Timer, UserId},
+    routes::events::added_event,
+    validation::Valid,
+    AppState,
+};
+
+/// The user's running timer, `null` if there is none
+#[utoipa::path(
+    get,
+    path = "/timer",
+    tag = "timer",
+    responses((status = 200, body = Option<Timer>)),
+)]
+pub async fn get_timer(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+) -> Result<Json<Option<Timer>>, AppError> {
+    Ok(Json(state.store.get_timer(user_id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/timer/start",
+    tag = "timer",
+    request_body = StartTimer,
+    responses((status = 200, body = Timer)),
+)]
+pub async fn start_timer(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Valid(start): Valid<StartTimer>,
+) -> Result<Json<Timer>, AppError> {
+    let timer = state
+        .store
+        .start_timer(user_id, start)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("Unknown task".to_string()),
+            sqlx::Error::Database(e) if e.is_unique_violation() => AppError::Conflict {
+                message: "A timer is running already".to_string(),
+                details: None,
+            },
+            e => e.into(),
+        })?;
+    info!("{:?}", timer);
+    Ok(Json(timer))
+}
+
+/// Saves the time since the timer started as an event. If the user's overlap
+/// policy rejects it, the timer keeps running.
+#[utoipa::path(
+    post,
+    path = "/timer/stop",
+    tag = "timer",
+    request_body = StopTimer,
+    responses((status = 200, body = TaskEvent)),
+)]
+pub async fn stop_timer(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Valid(stop): Valid<StopTimer>,
+) -> Result<Json<TaskEvent>, AppError> {
+    let added = state
+        .store
+        .stop_timer(user_id, stop)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("No timer is running".to_string()),
+            e => e.into(),
+        })?;
+    let event = added_event(added)?;
+    info!("{:?}", event);
+    Ok(Json(event))
+}
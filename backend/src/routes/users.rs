@@ -1,49 +1,106 @@
-use axum::{debug_handler, extract::State, Json};
-use axum_extra::extract::{
-    cookie::{Cookie, SameSite},
-    PrivateCookieJar,
-};
+use axum::{debug_handler, extract::State, Extension};
+use axum_extra::extract::{cookie::Cookie, PrivateCookieJar};
 use http::StatusCode;
 use tracing::info;
 
-use crate::{internal_error, models::LoginDetails, AppState};
+use crate::{
+    error::AppError,
+    extract::Json,
+    models::{LoginDetails, UserId, UserSettings},
+    validation::Valid,
+    AppState,
+};
 
+#[utoipa::path(
+    post,
+    path = "/users/register",
+    tag = "users",
+    request_body = LoginDetails,
+    security(()),
+    responses((status = 200, description = "a confirmation message", body = String)),
+)]
 pub async fn register_user(
     State(state): State<AppState>,
-    Json(new_user): Json<LoginDetails>,
-) -> Result<Json<String>, (StatusCode, String)> {
+    Valid(new_user): Valid<LoginDetails>,
+) -> Result<Json<String>, AppError> {
     let res = state
         .store
         .register_account(new_user)
         .await
-        .map_err(internal_error)?;
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => AppError::Conflict {
+                message: "An account with this email already exists".to_string(),
+                details: None,
+            },
+            e => e.into(),
+        })?;
     info!("{:?}", res);
     Ok(Json(res))
 }
 
 #[debug_handler(state = AppState)]
+#[utoipa::path(
+    post,
+    path = "/users/login",
+    tag = "users",
+    request_body = LoginDetails,
+    security(()),
+    responses((status = 200, description = "the session cookie is set")),
+)]
 pub async fn login(
     jar: PrivateCookieJar,
     State(state): State<AppState>,
     Json(login): Json<LoginDetails>,
-) -> Result<(PrivateCookieJar, StatusCode), StatusCode> {
-    let user = match state.store.clone().get_account(login.email).await {
-        Ok(user) => Ok(user),
-        Err(_) => Err(StatusCode::BAD_REQUEST),
+) -> Result<(PrivateCookieJar, StatusCode), AppError> {
+    let invalid = || AppError::BadRequest("Wrong email or password".to_string());
+    let user = match state.store.get_account(login.email).await {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => return Err(invalid()),
+        Err(e) => return Err(e.into()),
+    };
+    let session_id = match state.store.create_session(user, login.password).await {
+        Ok(session_id) => session_id,
+        Err(sqlx::Error::RowNotFound) => return Err(invalid()),
+        Err(e) => return Err(e.into()),
     };
-    match user {
-        Ok(user) => match state.store.create_session(user, login.password).await {
-            Ok(session_id) => {
-                let cookie = Cookie::build(("time_bandit_auth_token_v1", session_id.0))
-                    .secure(true)
-                    .same_site(SameSite::Strict)
-                    .http_only(true)
-                    .path("/")
-                    .build();
-                Ok((jar.add(cookie), StatusCode::OK))
-            }
-            Err(_) => Err(StatusCode::BAD_REQUEST),
-        },
-        Err(e) => Err(e),
+    let settings = &state.cookie;
+    let mut cookie = Cookie::build((settings.name.clone(), session_id.0))
+        .secure(settings.secure)
+        .same_site(settings.same_site)
+        .http_only(true)
+        .path("/");
+    if let Some(max_age) = settings.max_age {
+        cookie = cookie.max_age(max_age);
     }
+    Ok((jar.add(cookie.build()), StatusCode::OK))
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/settings",
+    tag = "users",
+    responses((status = 200, body = UserSettings)),
+)]
+pub async fn get_user_settings(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+) -> Result<Json<UserSettings>, AppError> {
+    Ok(Json(state.store.get_user_settings(user_id).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/settings",
+    tag = "users",
+    request_body = UserSettings,
+    responses((status = 200, body = UserSettings)),
+)]
+pub async fn update_user_settings(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Json(settings): Json<UserSettings>,
+) -> Result<Json<UserSettings>, AppError> {
+    let settings = state.store.update_user_settings(user_id, settings).await?;
+    info!("{:?}", settings);
+    Ok(Json(settings))
 }
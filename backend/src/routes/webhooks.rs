@@ -0,0 +1,123 @@
+use axum::{extract::State, Extension};
+use tracing::info;
+
+use crate::{
+    error::AppError,
+    extract::{Json, Path, Query},
+    models::{
+        NewWebhook, UserId, Webhook, WebhookDelivery, WebhookDeliveryId, WebhookDeliveryPage,
+        WebhookDeliveryQuery, WebhookId,
+    },
+    routes::{decode_cursor, encode_cursor, page_limit},
+    validation::Valid,
+    AppState,
+};
+
+/// Registers a webhook, its secret is only ever shown to its user
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    tag = "webhooks",
+    request_body = NewWebhook,
+    responses((status = 200, body = Webhook)),
+)]
+pub async fn add_webhook(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Valid(new_webhook): Valid<NewWebhook>,
+) -> Result<Json<Webhook>, AppError> {
+    let webhook = state.store.add_webhook(user_id, new_webhook).await?;
+    info!("{:?} {}", webhook.id, webhook.url);
+    Ok(Json(webhook))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    tag = "webhooks",
+    responses((status = 200, body = Vec<Webhook>)),
+)]
+pub async fn get_webhooks(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+) -> Result<Json<Vec<Webhook>>, AppError> {
+    Ok(Json(state.store.get_webhooks(user_id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{webhook_id}",
+    tag = "webhooks",
+    params(("webhook_id" = WebhookId, Path)),
+    responses((status = 200, body = WebhookId)),
+)]
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Path(webhook_id): Path<WebhookId>,
+) -> Result<Json<WebhookId>, AppError> {
+    let res = state.store.delete_webhook(webhook_id, user_id).await?;
+    info!("{:?}", res);
+    Ok(Json(res))
+}
+
+/// A page of a webhook's deliveries with the log of each, the latest first
+#[utoipa::path(
+    get,
+    path = "/webhooks/{webhook_id}/deliveries",
+    tag = "webhooks",
+    params(("webhook_id" = WebhookId, Path), WebhookDeliveryQuery),
+    responses((status = 200, body = WebhookDeliveryPage)),
+)]
+pub async fn get_webhook_deliveries(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Path(webhook_id): Path<WebhookId>,
+    Query(query): Query<WebhookDeliveryQuery>,
+) -> Result<Json<WebhookDeliveryPage>, AppError> {
+    let limit = page_limit(query.limit)?;
+    let before = query
+        .cursor
+        .as_deref()
+        .map(decode_cursor::<WebhookDeliveryId>)
+        .transpose()?;
+
+    // one more than asked for tells whether there is a next page
+    let mut deliveries = state
+        .store
+        .get_webhook_deliveries(webhook_id, user_id, before, limit + 1)
+        .await?;
+    let next_cursor = if deliveries.len() as i64 > limit {
+        deliveries.truncate(limit as usize);
+        deliveries
+            .last()
+            .map(|delivery| encode_cursor(&delivery.id))
+    } else {
+        None
+    };
+    Ok(Json(WebhookDeliveryPage {
+        deliveries,
+        next_cursor,
+    }))
+}
+
+/// Sends a delivery again as soon as possible, whatever became of it
+#[utoipa::path(
+    post,
+    path = "/webhooks/{webhook_id}/deliveries/{delivery_id}/redeliver",
+    tag = "webhooks",
+    params(("webhook_id" = WebhookId, Path), ("delivery_id" = WebhookDeliveryId, Path)),
+    responses((status = 200, body = WebhookDelivery)),
+)]
+pub async fn redeliver_webhook(
+    State(state): State<AppState>,
+    Extension(user_id): Extension<UserId>,
+    Path((webhook_id, delivery_id)): Path<(WebhookId, WebhookDeliveryId)>,
+) -> Result<Json<WebhookDelivery>, AppError> {
+    let delivery = state
+        .store
+        .redeliver_webhook(delivery_id, webhook_id, user_id)
+        .await?;
+    info!("redeliver {:?}", delivery.id);
+    Ok(Json(delivery))
+}
@@ -0,0 +1,2285 @@
+//! A backend that keeps everything in memory, for tests that exercise the
+//! routes without a database.
+//!
+//! It follows the SQL backends closely, including what their triggers do:
+//! every write takes the next change sequence number, fields written without
+//! a clock are stamped with the current time, deletions leave tombstones and
+//! changes are notified once the data is unlocked.
+
+use std::{
+    borrow::Cow,
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error as StdError,
+    fmt, mem,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use axum::async_trait;
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use sqlx::{
+    error::{DatabaseError, ErrorKind},
+    migrate::{MigrateError, Migrator},
+    Error,
+};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
+
+use super::{
+    apply_overlap_policy, check_password, clip_events, event_end, hash_password, highlight,
+    new_calendar_token, new_session_id, new_webhook_secret,
+    notifications::Notifier,
+    overlap_duration, parse_payload,
+    reports::{date_range, export_times, parse_timezone, time_buckets},
+    timer_event, Activity, DueDelivery, EventAdded, EventCursor, PoolStatus, Store, TaskCursor,
+    UserNotification, EVENT_SYNC_COLUMNS, EXPORT_CHANNEL_SIZE, MATCH_END, MATCH_START,
+    OVERLAPS_OTHERS, TASK_SYNC_COLUMNS, UUID_TAKEN,
+};
+use crate::{
+    models::{
+        CalendarEvent, CalendarQuery, Client, ClientId, ClientMonthSummary, ClientSummary,
+        ClientTaskSummary, DeliveryStatus, EventExportQuery, EventExportRow, EventListQuery,
+        EventOverlap, EventOverlapQuery, FieldClock, ImportDedupe, ImportReport, ImportRow,
+        ImportRowError, IncludeEvents, ListedEvent, NewClient, NewTask, NewTaskEvent, NewWebhook,
+        Notification, OverlapPolicy, ReportBucket, ReportGroupBy, ReportQuery, ReportSummary,
+        SearchHit, SearchHitKind, SearchQuery, SessionId, StartTimer, StopTimer, SyncChanges,
+        SyncConflict, SyncEntity, SyncError, SyncEvent, SyncPush, SyncReport, SyncTask, Task,
+        TaskEvent, TaskEventId, TaskId, TaskListQuery, TaskWithTaskEvents, Timer, Tombstone, User,
+        UserEmail, UserId, UserSettings, Webhook, WebhookAttempt, WebhookDelivery,
+        WebhookDeliveryId, WebhookEvent, WebhookId,
+    },
+    sync::{check_event, check_task, deleted_conflict, new_clock, FieldMerge},
+    LoginDetails,
+};
+
+/// There is no schema to migrate
+static MIGRATOR: Migrator = Migrator {
+    migrations: Cow::Borrowed(&[]),
+    ignore_missing: false,
+    locking: false,
+};
+
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<Data>,
+    notifier: Arc<Notifier>,
+}
+
+/// The locked data, which sends the notifications of the changes made
+/// through it when it is unlocked
+struct DataGuard<'a> {
+    data: MutexGuard<'a, Data>,
+    notifier: &'a Notifier,
+}
+
+impl Deref for DataGuard<'_> {
+    type Target = Data;
+
+    fn deref(&self) -> &Data {
+        &self.data
+    }
+}
+
+impl DerefMut for DataGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Data {
+        &mut self.data
+    }
+}
+
+impl Drop for DataGuard<'_> {
+    fn drop(&mut self) {
+        for notification in mem::take(&mut self.data.notifications) {
+            self.notifier.send(notification);
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Data {
+    users: Vec<UserRow>,
+    /// session ids by user, a user has at most one session
+    sessions: HashMap<i32, String>,
+    clients: Vec<Client>,
+    tasks: Vec<TaskRow>,
+    events: Vec<EventRow>,
+    tombstones: Vec<(i32, Tombstone, i64)>,
+    /// running timers by user
+    timers: HashMap<i32, Timer>,
+    /// of the changes since the data was locked
+    notifications: Vec<UserNotification>,
+    webhooks: Vec<(UserId, Webhook)>,
+    deliveries: Vec<DeliveryRow>,
+    change_seq: i64,
+    last_id: i32,
+}
+
+#[derive(Clone)]
+struct UserRow {
+    user: User,
+    calendar_token: Option<String>,
+    disabled: bool,
+    overlap_policy: OverlapPolicy,
+}
+
+#[derive(Clone)]
+struct TaskRow {
+    task: Task,
+    change_seq: i64,
+    field_clock: FieldClock,
+}
+
+#[derive(Clone)]
+struct EventRow {
+    event: TaskEvent,
+    ical_uid: Option<String>,
+    change_seq: i64,
+    field_clock: FieldClock,
+}
+
+/// A delivery as the SQL backends keep it: the change it is for and the
+/// payload as it was sent, rather than its JSON
+#[derive(Clone)]
+struct DeliveryRow {
+    delivery: WebhookDelivery,
+    change: UserNotification,
+    payload: Option<String>,
+}
+
+impl DeliveryRow {
+    fn delivery(&self) -> Result<WebhookDelivery, Error> {
+        Ok(WebhookDelivery {
+            payload: parse_payload(self.payload.clone())?,
+            ..self.delivery.clone()
+        })
+    }
+}
+
+/// What the SQL backends report when a constraint is violated, so callers
+/// can tell them apart the same way
+#[derive(Debug)]
+struct ConstraintViolation {
+    message: String,
+    foreign_key: bool,
+}
+
+impl ConstraintViolation {
+    fn unique(constraint: &str) -> Error {
+        Error::Database(Box::new(ConstraintViolation {
+            message: format!(
+                "duplicate key value violates unique constraint \"{}\"",
+                constraint
+            ),
+            foreign_key: false,
+        }))
+    }
+
+    fn foreign_key(table: &str, constraint: &str) -> Error {
+        Error::Database(Box::new(ConstraintViolation {
+            message: format!(
+                "insert or update on table \"{}\" violates foreign key constraint \"{}\"",
+                table, constraint
+            ),
+            foreign_key: true,
+        }))
+    }
+}
+
+impl fmt::Display for ConstraintViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl StdError for ConstraintViolation {}
+
+impl DatabaseError for ConstraintViolation {
+    fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn as_error(&self) -> &(dyn StdError + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn StdError + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn StdError + Send + Sync + 'static> {
+        self
+    }
+
+    fn kind(&self) -> ErrorKind {
+        if self.foreign_key {
+            ErrorKind::ForeignKeyViolation
+        } else {
+            ErrorKind::UniqueViolation
+        }
+    }
+}
+
+impl MemoryStore {
+    fn data(&self) -> DataGuard<'_> {
+        DataGuard {
+            data: self.data.lock().unwrap(),
+            notifier: &self.notifier,
+        }
+    }
+
+    /// Computed from a single look at the data, which the stream uses too
+    fn report_summary(&self, user_id: UserId, report: ReportQuery) -> Result<ReportSummary, Error> {
+        let tz = parse_timezone(&report.tz)?;
+        let (Some(range_start), Some(range_end)) =
+            date_range(Some(report.from), Some(report.to), tz)
+        else {
+            unreachable!("both dates are given");
+        };
+        let data = self.data();
+        let in_range = |row: &&EventRow| {
+            row.event.user_id == user_id
+                && row.event.date_began >= range_start
+                && row.event.date_began < range_end
+        };
+        let task_duration = |task: &Task| -> i64 {
+            data.events
+                .iter()
+                .filter(in_range)
+                .filter(|row| row.event.task_id == task.id)
+                .map(|row| row.event.duration)
+                .sum()
+        };
+        let user_tasks = || {
+            data.tasks
+                .iter()
+                .map(|row| &row.task)
+                .filter(|task| task.user_id == user_id)
+        };
+
+        let buckets = match report.group_by {
+            ReportGroupBy::Day | ReportGroupBy::Week | ReportGroupBy::Month => {
+                let events = data
+                    .events
+                    .iter()
+                    .filter(in_range)
+                    .map(|row| (row.event.date_began, row.event.duration));
+                time_buckets(report.group_by, report.from, report.to, tz, events)
+            }
+            ReportGroupBy::Task => {
+                let mut buckets: Vec<ReportBucket> = user_tasks()
+                    .map(|task| ReportBucket {
+                        key: task.name.clone(),
+                        id: Some(task.id.0),
+                        start: None,
+                        total_duration: task_duration(task),
+                    })
+                    .collect();
+                sort_buckets(&mut buckets);
+                buckets
+            }
+            ReportGroupBy::Tag => {
+                let mut totals: HashMap<String, i64> = HashMap::new();
+                for task in user_tasks() {
+                    let duration = task_duration(task);
+                    for tag in &task.tags {
+                        *totals.entry(tag.clone()).or_default() += duration;
+                    }
+                }
+                let mut buckets: Vec<ReportBucket> = totals
+                    .into_iter()
+                    .map(|(key, total_duration)| ReportBucket {
+                        key,
+                        id: None,
+                        start: None,
+                        total_duration,
+                    })
+                    .collect();
+                sort_buckets(&mut buckets);
+                buckets
+            }
+            ReportGroupBy::Project => {
+                let mut totals: HashMap<Option<i32>, i64> = HashMap::new();
+                for task in user_tasks() {
+                    let client_id = task.client_id.as_ref().map(|client_id| client_id.0);
+                    *totals.entry(client_id).or_default() += task_duration(task);
+                }
+                let mut buckets: Vec<ReportBucket> = totals
+                    .into_iter()
+                    .map(|(client_id, total_duration)| ReportBucket {
+                        key: client_id
+                            .and_then(|client_id| {
+                                data.clients.iter().find(|client| client.id.0 == client_id)
+                            })
+                            .map(|client| client.name.clone())
+                            .unwrap_or_else(|| "No client".to_string()),
+                        id: client_id,
+                        start: None,
+                        total_duration,
+                    })
+                    .collect();
+                sort_buckets(&mut buckets);
+                buckets
+            }
+        };
+
+        let total_duration = match report.group_by {
+            // a task can carry several tags, so the buckets may overlap
+            ReportGroupBy::Tag => data
+                .events
+                .iter()
+                .filter(in_range)
+                .map(|row| row.event.duration)
+                .sum(),
+            _ => buckets.iter().map(|bucket| bucket.total_duration).sum(),
+        };
+
+        Ok(ReportSummary {
+            from: report.from,
+            to: report.to,
+            group_by: report.group_by,
+            tz: report.tz,
+            buckets,
+            total_duration,
+        })
+    }
+}
+
+impl Data {
+    fn next_id(&mut self) -> i32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn next_change_seq(&mut self) -> i64 {
+        self.change_seq += 1;
+        self.change_seq
+    }
+
+    /// Notifies a change and, like the triggers, queues a delivery of it
+    /// for each of the user's webhooks that wants it
+    fn notify(&mut self, user_id: &UserId, notification: Notification) {
+        let event_type = WebhookEvent::from(&notification);
+        let change = UserNotification {
+            user_id: user_id.clone(),
+            notification,
+        };
+        let webhook_ids: Vec<WebhookId> = self
+            .webhooks
+            .iter()
+            .filter(|(owner, webhook)| {
+                owner == user_id && webhook.event_types.contains(&event_type)
+            })
+            .map(|(_, webhook)| webhook.id.clone())
+            .collect();
+        for webhook_id in webhook_ids {
+            let now = Utc::now();
+            let delivery = WebhookDelivery {
+                id: WebhookDeliveryId(self.next_id().into()),
+                webhook_id,
+                event_type,
+                payload: None,
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                next_attempt: Some(now),
+                created_on: now,
+                log: vec![],
+            };
+            self.deliveries.push(DeliveryRow {
+                delivery,
+                change: change.clone(),
+                payload: None,
+            });
+        }
+        self.notifications.push(change);
+    }
+
+    fn webhook(&self, webhook_id: &WebhookId, user_id: &UserId) -> Result<&Webhook, Error> {
+        self.webhooks
+            .iter()
+            .find(|(owner, webhook)| owner == user_id && webhook.id == *webhook_id)
+            .map(|(_, webhook)| webhook)
+            .ok_or(Error::RowNotFound)
+    }
+
+    fn user(&self, email: &UserEmail) -> Result<&UserRow, Error> {
+        self.users
+            .iter()
+            .find(|row| row.user.email == *email)
+            .ok_or(Error::RowNotFound)
+    }
+
+    fn user_mut(&mut self, email: &UserEmail) -> Result<&mut UserRow, Error> {
+        self.users
+            .iter_mut()
+            .find(|row| row.user.email == *email)
+            .ok_or(Error::RowNotFound)
+    }
+
+    fn user_by_id(&mut self, user_id: &UserId) -> Result<&mut UserRow, Error> {
+        self.users
+            .iter_mut()
+            .find(|row| row.user.id == *user_id)
+            .ok_or(Error::RowNotFound)
+    }
+
+    fn listed_event(&self, event: &TaskEvent) -> Result<ListedEvent, Error> {
+        Ok(ListedEvent {
+            event: event.clone(),
+            task_name: self.task(&event.task_id)?.task.name.clone(),
+            duration_in_range: None,
+        })
+    }
+
+    fn task(&self, task_id: &TaskId) -> Result<&TaskRow, Error> {
+        self.tasks
+            .iter()
+            .find(|row| row.task.id == *task_id)
+            .ok_or(Error::RowNotFound)
+    }
+
+    fn client(&self, client_id: &ClientId, user_id: &UserId) -> Result<&Client, Error> {
+        self.clients
+            .iter()
+            .find(|client| client.id == *client_id && client.user_id == *user_id)
+            .ok_or(Error::RowNotFound)
+    }
+
+    fn insert_task(&mut self, new_task: NewTask, uuid: Uuid, clock: FieldClock) -> Task {
+        let now = Utc::now();
+        let task = Task {
+            id: TaskId(self.next_id()),
+            uuid,
+            user_id: new_task.user_id,
+            name: new_task.name,
+            description: new_task.description.unwrap_or_default(),
+            client_id: new_task.client_id,
+            tags: new_task.tags,
+            created_on: now,
+        };
+        let mut field_clock = new_clock(&TASK_SYNC_COLUMNS, now);
+        field_clock.extend(clock);
+        let change_seq = self.next_change_seq();
+        self.tasks.push(TaskRow {
+            task: task.clone(),
+            change_seq,
+            field_clock,
+        });
+        self.notify(
+            &task.user_id,
+            Notification::TaskCreated {
+                id: task.id.clone(),
+            },
+        );
+        task
+    }
+
+    fn insert_event(
+        &mut self,
+        new_event: NewTaskEvent,
+        uuid: Uuid,
+        ical_uid: Option<String>,
+        clock: FieldClock,
+    ) -> Result<TaskEvent, Error> {
+        if self.events.iter().any(|row| row.event.uuid == uuid) {
+            return Err(ConstraintViolation::unique("events_uuid_key"));
+        }
+        if !self
+            .users
+            .iter()
+            .any(|row| row.user.id == new_event.user_id)
+        {
+            return Err(ConstraintViolation::foreign_key(
+                "events",
+                "events_user_id_fkey",
+            ));
+        }
+        if !self
+            .tasks
+            .iter()
+            .any(|row| row.task.id == new_event.task_id)
+        {
+            return Err(ConstraintViolation::foreign_key(
+                "events",
+                "events_task_id_fkey",
+            ));
+        }
+        let event = TaskEvent {
+            id: TaskEventId(self.next_id()),
+            uuid,
+            user_id: new_event.user_id,
+            task_id: new_event.task_id,
+            date_began: new_event.date_began,
+            duration: new_event.duration,
+            notes: new_event.notes,
+        };
+        let mut field_clock = new_clock(&EVENT_SYNC_COLUMNS, Utc::now());
+        field_clock.extend(clock);
+        let change_seq = self.next_change_seq();
+        self.events.push(EventRow {
+            event: event.clone(),
+            ical_uid,
+            change_seq,
+            field_clock,
+        });
+        self.notify(
+            &event.user_id,
+            Notification::EventCreated {
+                id: event.id.clone(),
+                task_id: event.task_id.clone(),
+            },
+        );
+        Ok(event)
+    }
+
+    fn add_event_under_policy(&mut self, new_event: NewTaskEvent) -> Result<EventAdded, Error> {
+        let policy = self.user_by_id(&new_event.user_id)?.overlap_policy;
+        // the event can only be of one of the user's own tasks
+        if self.task(&new_event.task_id)?.task.user_id != new_event.user_id {
+            return Err(Error::RowNotFound);
+        }
+        match self.fit_event(policy, new_event)? {
+            Ok(new_event) => Ok(EventAdded::Added(self.insert_event(
+                new_event,
+                Uuid::new_v4(),
+                None,
+                FieldClock::new(),
+            )?)),
+            Err(overlapping) => Ok(EventAdded::Overlapping(overlapping)),
+        }
+    }
+
+    /// Applies the overlap policy to a new event: the event to add, trimmed
+    /// under `trim`, or the events that leave no room for it
+    fn fit_event(
+        &self,
+        policy: OverlapPolicy,
+        new_event: NewTaskEvent,
+    ) -> Result<Result<NewTaskEvent, Vec<ListedEvent>>, Error> {
+        let end = event_end(new_event.date_began, new_event.duration);
+        let mut overlapping = vec![];
+        if policy != OverlapPolicy::Allow && new_event.duration > 0 {
+            for row in &self.events {
+                let event = &row.event;
+                if event.user_id == new_event.user_id
+                    && event.duration > 0
+                    && event.date_began < end
+                    && event_end(event.date_began, event.duration) > new_event.date_began
+                {
+                    overlapping.push(self.listed_event(event)?);
+                }
+            }
+            overlapping.sort_by_key(|listed| (listed.event.date_began, listed.event.id.0));
+        }
+        Ok(apply_overlap_policy(policy, new_event, &overlapping).ok_or(overlapping))
+    }
+
+    /// Updates a task like the SQL backends' triggers: the fields that
+    /// changed without a new clock in `written` are stamped with now
+    fn update_task(&mut self, index: usize, task: Task, written: &FieldClock) {
+        let change_seq = self.next_change_seq();
+        let row = &mut self.tasks[index];
+        let changed = [
+            ("name", row.task.name != task.name),
+            ("description", row.task.description != task.description),
+            ("tags", row.task.tags != task.tags),
+        ];
+        stamp(&mut row.field_clock, &changed, written);
+        let notification = Notification::TaskUpdated {
+            id: task.id.clone(),
+        };
+        let user_id = task.user_id.clone();
+        row.task = task;
+        row.change_seq = change_seq;
+        self.notify(&user_id, notification);
+    }
+
+    fn update_event(&mut self, index: usize, event: TaskEvent, written: &FieldClock) {
+        let change_seq = self.next_change_seq();
+        let row = &mut self.events[index];
+        let changed = [
+            ("task_id", row.event.task_id != event.task_id),
+            ("date_began", row.event.date_began != event.date_began),
+            ("duration", row.event.duration != event.duration),
+            ("notes", row.event.notes != event.notes),
+        ];
+        stamp(&mut row.field_clock, &changed, written);
+        let notification = Notification::EventUpdated {
+            id: event.id.clone(),
+            task_id: event.task_id.clone(),
+        };
+        let user_id = event.user_id.clone();
+        row.event = event;
+        row.change_seq = change_seq;
+        self.notify(&user_id, notification);
+    }
+
+    /// Also stops a timer running on the task, like the foreign key does
+    fn delete_task(&mut self, index: usize) {
+        let row = self.tasks.remove(index);
+        let user_id = row.task.user_id;
+        if self
+            .timers
+            .get(&user_id.0)
+            .is_some_and(|timer| timer.task_id == row.task.id)
+        {
+            self.stop_timer(&user_id);
+        }
+        self.record_tombstone(user_id.0, SyncEntity::Task, row.task.uuid);
+        self.notify(&user_id, Notification::TaskDeleted { id: row.task.id });
+    }
+
+    fn delete_event(&mut self, index: usize) {
+        let row = self.events.remove(index);
+        let event = row.event;
+        self.record_tombstone(event.user_id.0, SyncEntity::Event, event.uuid);
+        self.notify(
+            &event.user_id,
+            Notification::EventDeleted {
+                id: event.id,
+                task_id: event.task_id,
+            },
+        );
+    }
+
+    fn stop_timer(&mut self, user_id: &UserId) -> Option<Timer> {
+        let timer = self.timers.remove(&user_id.0)?;
+        self.notify(
+            user_id,
+            Notification::TimerStopped {
+                task_id: timer.task_id.clone(),
+            },
+        );
+        Some(timer)
+    }
+
+    fn record_tombstone(&mut self, user_id: i32, entity: SyncEntity, uuid: Uuid) {
+        let change_seq = self.next_change_seq();
+        let tombstone = Tombstone {
+            entity,
+            uuid,
+            deleted_at: Utc::now(),
+        };
+        self.tombstones.push((user_id, tombstone, change_seq));
+    }
+
+    fn task_with_events(&self, row: &TaskRow) -> TaskWithTaskEvents {
+        let events: Vec<TaskEvent> = self
+            .events
+            .iter()
+            .filter(|event| event.event.task_id == row.task.id)
+            .map(|event| event.event.clone())
+            .collect();
+        TaskWithTaskEvents {
+            task: row.task.clone(),
+            total_duration: events.iter().map(|event| event.duration).sum(),
+            updated_on: events
+                .iter()
+                .map(|event| event.date_began)
+                .max()
+                .unwrap_or(row.task.created_on),
+            events,
+        }
+    }
+
+    /// When the user's record was deleted, if that was after `modified_at`
+    fn deleted_since(
+        &self,
+        user_id: &UserId,
+        entity: SyncEntity,
+        uuid: Uuid,
+        modified_at: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        self.tombstones
+            .iter()
+            .filter(|(owner, tombstone, _)| {
+                *owner == user_id.0 && tombstone.entity == entity && tombstone.uuid == uuid
+            })
+            .map(|(_, tombstone, _)| tombstone.deleted_at)
+            .max()
+            .filter(|deleted_at| *deleted_at > modified_at)
+    }
+}
+
+fn stamp(clock: &mut FieldClock, changed: &[(&str, bool)], written: &FieldClock) {
+    let now = Utc::now();
+    for (column, changed) in changed {
+        match written.get(*column) {
+            Some(modified_at) => {
+                clock.insert(column.to_string(), *modified_at);
+            }
+            None if *changed => {
+                clock.insert(column.to_string(), now);
+            }
+            None => {}
+        }
+    }
+}
+
+/// The order of two positions in a task listing of the same sort
+fn compare_positions(a: &TaskCursor, b: &TaskCursor) -> Ordering {
+    match (a, b) {
+        (TaskCursor::UpdatedOn(a, a_id), TaskCursor::UpdatedOn(b, b_id))
+        | (TaskCursor::CreatedOn(a, a_id), TaskCursor::CreatedOn(b, b_id)) => {
+            (b, b_id.0).cmp(&(a, a_id.0))
+        }
+        (TaskCursor::TotalDuration(a, a_id), TaskCursor::TotalDuration(b, b_id)) => {
+            (b, b_id.0).cmp(&(a, a_id.0))
+        }
+        (TaskCursor::Name(a, a_id), TaskCursor::Name(b, b_id)) => (a, a_id.0).cmp(&(b, b_id.0)),
+        _ => unreachable!("positions of different sorts"),
+    }
+}
+
+/// The words a search looks for in the memory store and those it must not
+/// find. It matches whole words only, without stemming, phrases or `or`.
+fn search_words(q: &str) -> (Vec<String>, Vec<String>) {
+    let words = |negated: bool| {
+        let mut words: Vec<String> = q
+            .split_whitespace()
+            .filter(|word| word.starts_with('-') == negated && !word.eq_ignore_ascii_case("or"))
+            .flat_map(|word| word.split(|c: char| !c.is_alphanumeric()))
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        words.sort();
+        words.dedup();
+        words
+    };
+    (words(false), words(true))
+}
+
+/// How many of the words of `text` are among `wanted` and the text with them
+/// marked, if all of `wanted` and none of `unwanted` are there
+fn mark_words(text: &str, wanted: &[String], unwanted: &[String]) -> Option<(f64, String)> {
+    let mut marked = String::with_capacity(text.len());
+    let mut found = HashSet::new();
+    let mut matches = 0;
+    for piece in text.split_inclusive(|c: char| !c.is_alphanumeric()) {
+        let word = piece.trim_end_matches(|c: char| !c.is_alphanumeric());
+        let lower = word.to_lowercase();
+        if unwanted.contains(&lower) {
+            return None;
+        }
+        if wanted.contains(&lower) {
+            matches += 1;
+            found.insert(lower);
+            marked.push(MATCH_START);
+            marked.push_str(word);
+            marked.push(MATCH_END);
+        } else {
+            marked.push_str(word);
+        }
+        marked.push_str(&piece[word.len()..]);
+    }
+    (found.len() == wanted.len()).then(|| (f64::from(matches), marked))
+}
+
+/// Sorts report buckets like the SQL backends: largest first, then by key
+fn sort_buckets(buckets: &mut [ReportBucket]) {
+    buckets.sort_by(|a, b| {
+        b.total_duration
+            .cmp(&a.total_duration)
+            .then_with(|| a.key.cmp(&b.key))
+    });
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    fn migrator(&self) -> &'static Migrator {
+        &MIGRATOR
+    }
+
+    async fn run_migrations(&self) -> Result<(), MigrateError> {
+        Ok(())
+    }
+
+    async fn undo_migrations(&self, _target: i64) -> Result<(), MigrateError> {
+        Ok(())
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<i64>, MigrateError> {
+        Ok(vec![])
+    }
+
+    async fn probe_migrations(&self) -> Result<Vec<i64>, Error> {
+        Ok(vec![])
+    }
+
+    fn pool_status(&self) -> PoolStatus {
+        PoolStatus::default()
+    }
+
+    async fn register_account(&self, new_user: LoginDetails) -> Result<String, Error> {
+        let password = hash_password(new_user.password);
+        let mut data = self.data();
+        if data.user(&new_user.email).is_ok() {
+            return Err(ConstraintViolation::unique("users_email_key"));
+        }
+        let id = UserId(data.next_id());
+        data.users.push(UserRow {
+            user: User {
+                id,
+                uuid: Uuid::new_v4(),
+                email: new_user.email,
+                password,
+            },
+            calendar_token: None,
+            disabled: false,
+            overlap_policy: OverlapPolicy::default(),
+        });
+        Ok("Created account!".to_string())
+    }
+
+    async fn get_account(&self, email: UserEmail) -> Result<User, Error> {
+        self.data()
+            .user(&email)
+            .ok()
+            .filter(|row| !row.disabled)
+            .map(|row| row.user.clone())
+            .ok_or(Error::RowNotFound)
+    }
+
+    async fn get_user_email(&self, user_id: UserId) -> Result<UserEmail, Error> {
+        self.data()
+            .users
+            .iter()
+            .find(|row| row.user.id == user_id)
+            .map(|row| row.user.email.clone())
+            .ok_or(Error::RowNotFound)
+    }
+
+    async fn get_user_settings(&self, user_id: UserId) -> Result<UserSettings, Error> {
+        Ok(UserSettings {
+            overlap_policy: self.data().user_by_id(&user_id)?.overlap_policy,
+        })
+    }
+
+    async fn update_user_settings(
+        &self,
+        user_id: UserId,
+        settings: UserSettings,
+    ) -> Result<UserSettings, Error> {
+        self.data().user_by_id(&user_id)?.overlap_policy = settings.overlap_policy;
+        Ok(settings)
+    }
+
+    async fn get_user_id(&self, email: UserEmail) -> Result<UserId, Error> {
+        Ok(self.data().user(&email)?.user.id.clone())
+    }
+
+    async fn set_password(&self, email: UserEmail, password: String) -> Result<UserId, Error> {
+        let password = hash_password(password);
+        let mut data = self.data();
+        let row = data.user_mut(&email)?;
+        row.user.password = password;
+        let user_id = row.user.id.clone();
+        data.sessions.remove(&user_id.0);
+        Ok(user_id)
+    }
+
+    async fn disable_user(&self, email: UserEmail) -> Result<UserId, Error> {
+        let mut data = self.data();
+        let row = data.user_mut(&email)?;
+        row.disabled = true;
+        let user_id = row.user.id.clone();
+        data.sessions.remove(&user_id.0);
+        Ok(user_id)
+    }
+
+    async fn purge_sessions(&self) -> Result<u64, Error> {
+        let mut data = self.data();
+        let purged = data.sessions.len() as u64;
+        data.sessions.clear();
+        Ok(purged)
+    }
+
+    async fn delete_user_session(&self, user_id: UserId) -> Result<UserId, Error> {
+        self.data().sessions.remove(&user_id.0);
+        Ok(user_id)
+    }
+
+    async fn create_session(&self, user: User, password: String) -> Result<SessionId, Error> {
+        check_password(&user, password)?;
+        let session_id = new_session_id();
+        self.data().sessions.insert(user.id.0, session_id.clone());
+        Ok(SessionId(session_id))
+    }
+
+    async fn get_session_user(&self, session_id: String) -> Result<Option<UserId>, Error> {
+        Ok(self
+            .data()
+            .sessions
+            .iter()
+            .find(|(_, id)| **id == session_id)
+            .map(|(user_id, _)| UserId(*user_id)))
+    }
+
+    async fn get_activity(&self) -> Result<Activity, Error> {
+        let data = self.data();
+        Ok(Activity {
+            sessions: data.sessions.len() as i64,
+            running_timers: data.timers.len() as i64,
+        })
+    }
+
+    async fn add_task(&self, new_task: NewTask) -> Result<Task, Error> {
+        Ok(self
+            .data()
+            .insert_task(new_task, Uuid::new_v4(), FieldClock::new()))
+    }
+
+    async fn update_task(&self, new_task: NewTask, task_id: TaskId) -> Result<Task, Error> {
+        let mut data = self.data();
+        let index = data
+            .tasks
+            .iter()
+            .position(|row| row.task.id == task_id && row.task.user_id == new_task.user_id)
+            .ok_or(Error::RowNotFound)?;
+        let task = Task {
+            name: new_task.name,
+            description: new_task.description.unwrap_or_default(),
+            client_id: new_task.client_id,
+            tags: new_task.tags,
+            ..data.tasks[index].task.clone()
+        };
+        data.update_task(index, task.clone(), &FieldClock::new());
+        Ok(task)
+    }
+
+    async fn add_event(&self, new_event: NewTaskEvent) -> Result<EventAdded, Error> {
+        self.data().add_event_under_policy(new_event)
+    }
+
+    async fn find_overlaps(
+        &self,
+        user_id: UserId,
+        query: EventOverlapQuery,
+    ) -> Result<Vec<EventOverlap>, Error> {
+        let (from, to) = date_range(query.from, query.to, parse_timezone(&query.tz)?);
+        let data = self.data();
+        let mut events: Vec<&TaskEvent> = data
+            .events
+            .iter()
+            .map(|row| &row.event)
+            .filter(|event| event.user_id == user_id && event.duration > 0)
+            .collect();
+        events.sort_by_key(|event| (event.date_began, event.id.0));
+        let mut overlaps = vec![];
+        for (index, first) in events.iter().enumerate() {
+            let first_end = event_end(first.date_began, first.duration);
+            for second in &events[index + 1..] {
+                if second.date_began >= first_end {
+                    break;
+                }
+                let second_end = event_end(second.date_began, second.duration);
+                if from.is_some_and(|from| first_end.min(second_end) <= from)
+                    || to.is_some_and(|to| second.date_began >= to)
+                {
+                    continue;
+                }
+                overlaps.push(EventOverlap {
+                    first: data.listed_event(first)?,
+                    second: data.listed_event(second)?,
+                    duration: overlap_duration(first, second),
+                });
+            }
+        }
+        overlaps.sort_by_key(|overlap| {
+            (
+                overlap.second.event.date_began,
+                overlap.second.event.id.0,
+                overlap.first.event.date_began,
+                overlap.first.event.id.0,
+            )
+        });
+        Ok(overlaps)
+    }
+
+    async fn get_timer(&self, user_id: UserId) -> Result<Option<Timer>, Error> {
+        Ok(self.data().timers.get(&user_id.0).cloned())
+    }
+
+    async fn start_timer(&self, user_id: UserId, start: StartTimer) -> Result<Timer, Error> {
+        let mut data = self.data();
+        if data.task(&start.task_id)?.task.user_id != user_id {
+            return Err(Error::RowNotFound);
+        }
+        if data.timers.contains_key(&user_id.0) {
+            return Err(ConstraintViolation::unique("timers_pkey"));
+        }
+        let timer = Timer {
+            task_id: start.task_id,
+            started: Utc::now(),
+            notes: start.notes,
+        };
+        data.timers.insert(user_id.0, timer.clone());
+        data.notify(
+            &user_id,
+            Notification::TimerStarted {
+                task_id: timer.task_id.clone(),
+                started: timer.started,
+            },
+        );
+        Ok(timer)
+    }
+
+    async fn stop_timer(&self, user_id: UserId, stop: StopTimer) -> Result<EventAdded, Error> {
+        let mut data = self.data();
+        let timer = data
+            .timers
+            .get(&user_id.0)
+            .cloned()
+            .ok_or(Error::RowNotFound)?;
+        let added = data.add_event_under_policy(timer_event(user_id.clone(), timer, stop))?;
+        if let EventAdded::Added(_) = added {
+            data.stop_timer(&user_id);
+        }
+        Ok(added)
+    }
+
+    async fn subscribe(&self) -> Result<broadcast::Receiver<UserNotification>, Error> {
+        // the store sends the notifications itself, there is nothing to start
+        self.notifier.subscribe(|_| async { Ok(()) }).await
+    }
+
+    async fn add_webhook(
+        &self,
+        user_id: UserId,
+        new_webhook: NewWebhook,
+    ) -> Result<Webhook, Error> {
+        let mut data = self.data();
+        let webhook = Webhook {
+            id: WebhookId(data.next_id()),
+            url: new_webhook.url,
+            event_types: new_webhook.event_types,
+            secret: new_webhook_secret(),
+            created_on: Utc::now(),
+        };
+        data.webhooks.push((user_id, webhook.clone()));
+        Ok(webhook)
+    }
+
+    async fn get_webhooks(&self, user_id: UserId) -> Result<Vec<Webhook>, Error> {
+        Ok(self
+            .data()
+            .webhooks
+            .iter()
+            .filter(|(owner, _)| *owner == user_id)
+            .map(|(_, webhook)| webhook.clone())
+            .collect())
+    }
+
+    async fn delete_webhook(
+        &self,
+        webhook_id: WebhookId,
+        user_id: UserId,
+    ) -> Result<WebhookId, Error> {
+        let mut data = self.data();
+        data.webhook(&webhook_id, &user_id)?;
+        data.webhooks
+            .retain(|(_, webhook)| webhook.id != webhook_id);
+        data.deliveries
+            .retain(|row| row.delivery.webhook_id != webhook_id);
+        Ok(webhook_id)
+    }
+
+    async fn get_webhook_deliveries(
+        &self,
+        webhook_id: WebhookId,
+        user_id: UserId,
+        before: Option<WebhookDeliveryId>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        let data = self.data();
+        data.webhook(&webhook_id, &user_id)?;
+        data.deliveries
+            .iter()
+            .rev()
+            .filter(|row| row.delivery.webhook_id == webhook_id)
+            .filter(|row| {
+                before
+                    .as_ref()
+                    .is_none_or(|before| row.delivery.id.0 < before.0)
+            })
+            .take(limit as usize)
+            .map(DeliveryRow::delivery)
+            .collect()
+    }
+
+    async fn redeliver_webhook(
+        &self,
+        delivery_id: WebhookDeliveryId,
+        webhook_id: WebhookId,
+        user_id: UserId,
+    ) -> Result<WebhookDelivery, Error> {
+        let mut data = self.data();
+        data.webhook(&webhook_id, &user_id)?;
+        let row = data
+            .deliveries
+            .iter_mut()
+            .find(|row| row.delivery.id == delivery_id && row.delivery.webhook_id == webhook_id)
+            .ok_or(Error::RowNotFound)?;
+        row.delivery.status = DeliveryStatus::Pending;
+        row.delivery.attempts = 0;
+        row.delivery.next_attempt = Some(Utc::now());
+        row.delivery()
+    }
+
+    async fn claim_webhook_deliveries(
+        &self,
+        limit: i64,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<DueDelivery>, Error> {
+        let mut data = self.data();
+        let now = Utc::now();
+        let mut due: Vec<usize> = data
+            .deliveries
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row.delivery.next_attempt.is_some_and(|next| next <= now))
+            .map(|(index, _)| index)
+            .collect();
+        due.sort_by_key(|index| {
+            let delivery = &data.deliveries[*index].delivery;
+            (delivery.next_attempt, delivery.id.0)
+        });
+        due.truncate(limit as usize);
+        let mut claimed = vec![];
+        for index in due {
+            let row = &data.deliveries[index];
+            let webhook = data
+                .webhooks
+                .iter()
+                .map(|(_, webhook)| webhook)
+                .find(|webhook| webhook.id == row.delivery.webhook_id)
+                .ok_or(Error::RowNotFound)?;
+            claimed.push(DueDelivery {
+                id: row.delivery.id.clone(),
+                url: webhook.url.clone(),
+                secret: webhook.secret.clone(),
+                event_type: row.delivery.event_type,
+                change: row.change.clone(),
+                payload: row.payload.clone(),
+                attempts: row.delivery.attempts,
+                created_on: row.delivery.created_on,
+            });
+            data.deliveries[index].delivery.next_attempt = Some(until);
+        }
+        claimed.sort_by_key(|delivery| delivery.id.0);
+        Ok(claimed)
+    }
+
+    async fn record_webhook_attempt(
+        &self,
+        delivery_id: WebhookDeliveryId,
+        payload: String,
+        attempt: WebhookAttempt,
+        status: DeliveryStatus,
+        next_attempt: Option<DateTime<Utc>>,
+    ) -> Result<(), Error> {
+        let mut data = self.data();
+        // the webhook may have been deleted while the delivery was on its way
+        let Some(row) = data
+            .deliveries
+            .iter_mut()
+            .find(|row| row.delivery.id == delivery_id)
+        else {
+            return Ok(());
+        };
+        row.payload = Some(payload);
+        row.delivery.status = status;
+        row.delivery.attempts += 1;
+        row.delivery.next_attempt = next_attempt;
+        row.delivery.log.insert(0, attempt);
+        Ok(())
+    }
+
+    async fn get_tasks_by_user(&self, user_id: UserId) -> Result<Vec<Task>, Error> {
+        Ok(self
+            .data()
+            .tasks
+            .iter()
+            .filter(|row| row.task.user_id == user_id)
+            .map(|row| row.task.clone())
+            .collect())
+    }
+
+    async fn get_event(
+        &self,
+        event_id: TaskEventId,
+        user_id: UserId,
+    ) -> Result<ListedEvent, Error> {
+        let data = self.data();
+        let row = data
+            .events
+            .iter()
+            .find(|row| row.event.id == event_id && row.event.user_id == user_id)
+            .ok_or(Error::RowNotFound)?;
+        data.listed_event(&row.event)
+    }
+
+    async fn list_events(
+        &self,
+        user_id: UserId,
+        query: EventListQuery,
+        after: Option<EventCursor>,
+        limit: i64,
+    ) -> Result<Vec<ListedEvent>, Error> {
+        let (from, to) = date_range(query.from, query.to, parse_timezone(&query.tz)?);
+        let data = self.data();
+        let mut events = vec![];
+        for row in &data.events {
+            let event = &row.event;
+            let ended = event_end(event.date_began, event.duration);
+            if event.user_id != user_id
+                || query
+                    .task_id
+                    .as_ref()
+                    .is_some_and(|task_id| event.task_id != *task_id)
+                || from.is_some_and(|from| event.date_began < from && ended <= from)
+                || to.is_some_and(|to| event.date_began >= to)
+                || after.as_ref().is_some_and(|EventCursor(began, id)| {
+                    (event.date_began, event.id.0) <= (*began, id.0)
+                })
+            {
+                continue;
+            }
+            let task = &data.task(&event.task_id)?.task;
+            if query
+                .tag
+                .as_ref()
+                .is_some_and(|tag| !task.tags.contains(tag))
+            {
+                continue;
+            }
+            events.push(ListedEvent {
+                event: event.clone(),
+                task_name: task.name.clone(),
+                duration_in_range: None,
+            });
+        }
+        events.sort_by_key(|listed| (listed.event.date_began, listed.event.id.0));
+        events.truncate(limit.try_into().unwrap_or(0));
+        if query.clip {
+            clip_events(&mut events, from, to);
+        }
+        Ok(events)
+    }
+
+    async fn search(
+        &self,
+        user_id: UserId,
+        query: SearchQuery,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, Error> {
+        let (wanted, unwanted) = search_words(&query.q);
+        if wanted.is_empty() {
+            return Ok(vec![]);
+        }
+        let (from, to) = date_range(query.from, query.to, parse_timezone(&query.tz)?);
+        let in_range = |date_began: DateTime<Utc>| {
+            from.is_none_or(|from| date_began >= from) && to.is_none_or(|to| date_began < to)
+        };
+        let data = self.data();
+        let mut hits = vec![];
+        for row in &data.tasks {
+            let task = &row.task;
+            if task.user_id != user_id
+                || query
+                    .task_id
+                    .as_ref()
+                    .is_some_and(|task_id| task.id != *task_id)
+                || ((from.is_some() || to.is_some())
+                    && !data
+                        .events
+                        .iter()
+                        .any(|row| row.event.task_id == task.id && in_range(row.event.date_began)))
+            {
+                continue;
+            }
+            let text = [task.name.as_str(), task.description.as_str()].join("\n");
+            if let Some((rank, marked)) = mark_words(text.trim_end(), &wanted, &unwanted) {
+                hits.push(SearchHit {
+                    kind: SearchHitKind::Task,
+                    task_id: task.id.clone(),
+                    task_name: task.name.clone(),
+                    event_id: None,
+                    date_began: None,
+                    rank,
+                    snippet: highlight(&marked),
+                });
+            }
+        }
+        for row in &data.events {
+            let event = &row.event;
+            if event.user_id != user_id
+                || query
+                    .task_id
+                    .as_ref()
+                    .is_some_and(|task_id| event.task_id != *task_id)
+                || !in_range(event.date_began)
+            {
+                continue;
+            }
+            let notes = event.notes.as_deref().unwrap_or("");
+            if let Some((rank, marked)) = mark_words(notes, &wanted, &unwanted) {
+                hits.push(SearchHit {
+                    kind: SearchHitKind::Event,
+                    task_id: event.task_id.clone(),
+                    task_name: data.task(&event.task_id)?.task.name.clone(),
+                    event_id: Some(event.id.clone()),
+                    date_began: Some(event.date_began),
+                    rank,
+                    snippet: highlight(&marked),
+                });
+            }
+        }
+        hits.sort_by(|a, b| b.rank.total_cmp(&a.rank));
+        hits.truncate(limit.try_into().unwrap_or(0));
+        Ok(hits)
+    }
+
+    async fn get_task_by_id(&self, task_id: TaskId) -> Result<Task, Error> {
+        Ok(self.data().task(&task_id)?.task.clone())
+    }
+
+    async fn get_task_with_events_by_task_id(
+        &self,
+        task_id: TaskId,
+        user_id: UserId,
+    ) -> Result<TaskWithTaskEvents, Error> {
+        let data = self.data();
+        let row = data.task(&task_id)?;
+        if row.task.user_id != user_id {
+            return Err(Error::RowNotFound);
+        }
+        Ok(data.task_with_events(row))
+    }
+
+    async fn get_user_tasks_with_events(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<TaskWithTaskEvents>, Error> {
+        let data = self.data();
+        Ok(data
+            .tasks
+            .iter()
+            .filter(|row| row.task.user_id == user_id)
+            .map(|row| data.task_with_events(row))
+            .collect())
+    }
+
+    async fn list_tasks(
+        &self,
+        user_id: UserId,
+        query: TaskListQuery,
+        after: Option<TaskCursor>,
+        limit: i64,
+    ) -> Result<Vec<TaskWithTaskEvents>, Error> {
+        let (updated_from, updated_to) = date_range(
+            query.updated_from,
+            query.updated_to,
+            parse_timezone(&query.tz)?,
+        );
+        let needle = query.q.map(|q| q.to_lowercase());
+        let data = self.data();
+        let mut tasks: Vec<TaskWithTaskEvents> = data
+            .tasks
+            .iter()
+            .filter(|row| row.task.user_id == user_id)
+            .filter(|row| {
+                needle
+                    .as_ref()
+                    .is_none_or(|needle| row.task.name.to_lowercase().contains(needle))
+            })
+            .map(|row| data.task_with_events(row))
+            .filter(|task| {
+                updated_from.is_none_or(|from| task.updated_on >= from)
+                    && updated_to.is_none_or(|to| task.updated_on < to)
+            })
+            .filter(|task| {
+                after.as_ref().is_none_or(|after| {
+                    compare_positions(&TaskCursor::after(query.sort, task), after)
+                        == Ordering::Greater
+                })
+            })
+            .collect();
+        tasks.sort_by(|a, b| {
+            compare_positions(
+                &TaskCursor::after(query.sort, a),
+                &TaskCursor::after(query.sort, b),
+            )
+        });
+        tasks.truncate(limit.try_into().unwrap_or(0));
+        for task in &mut tasks {
+            task.events
+                .sort_by_key(|event| Reverse((event.date_began, event.id.0)));
+            match query.include_events {
+                IncludeEvents::All => {}
+                IncludeEvents::None => task.events.clear(),
+                IncludeEvents::Recent(count) => task.events.truncate(count as usize),
+            }
+        }
+        Ok(tasks)
+    }
+
+    async fn get_tasks_by_ids(
+        &self,
+        user_id: UserId,
+        task_ids: Vec<TaskId>,
+    ) -> Result<Vec<TaskWithTaskEvents>, Error> {
+        let data = self.data();
+        let mut tasks: Vec<TaskWithTaskEvents> = data
+            .tasks
+            .iter()
+            .filter(|row| row.task.user_id == user_id && task_ids.contains(&row.task.id))
+            .map(|row| TaskWithTaskEvents {
+                events: vec![],
+                ..data.task_with_events(row)
+            })
+            .collect();
+        tasks.sort_by_key(|task| task.task.id.0);
+        Ok(tasks)
+    }
+
+    async fn get_events_of_tasks(
+        &self,
+        user_id: UserId,
+        task_ids: Vec<TaskId>,
+        per_task: Option<i64>,
+    ) -> Result<Vec<TaskEvent>, Error> {
+        let mut events: Vec<TaskEvent> = self
+            .data()
+            .events
+            .iter()
+            .filter(|row| row.event.user_id == user_id && task_ids.contains(&row.event.task_id))
+            .map(|row| row.event.clone())
+            .collect();
+        events.sort_by_key(|event| (event.task_id.0, Reverse((event.date_began, event.id.0))));
+        let mut taken: HashMap<i32, i64> = HashMap::new();
+        events.retain(|event| {
+            let count = taken.entry(event.task_id.0).or_default();
+            *count += 1;
+            per_task.is_none_or(|per_task| *count <= per_task)
+        });
+        Ok(events)
+    }
+
+    async fn add_client(&self, new_client: NewClient) -> Result<Client, Error> {
+        let mut data = self.data();
+        let client = Client {
+            id: ClientId(data.next_id()),
+            uuid: Uuid::new_v4(),
+            user_id: new_client.user_id,
+            name: new_client.name,
+            email: new_client.email,
+            phone: new_client.phone,
+            address: new_client.address,
+            default_rate: new_client.default_rate,
+            created_on: Utc::now(),
+        };
+        data.clients.push(client.clone());
+        Ok(client)
+    }
+
+    async fn get_clients_by_user(&self, user_id: UserId) -> Result<Vec<Client>, Error> {
+        let mut clients: Vec<Client> = self
+            .data()
+            .clients
+            .iter()
+            .filter(|client| client.user_id == user_id)
+            .cloned()
+            .collect();
+        clients.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(clients)
+    }
+
+    async fn get_client(&self, client_id: ClientId, user_id: UserId) -> Result<Client, Error> {
+        Ok(self.data().client(&client_id, &user_id)?.clone())
+    }
+
+    async fn update_client(
+        &self,
+        new_client: NewClient,
+        client_id: ClientId,
+    ) -> Result<Client, Error> {
+        let mut data = self.data();
+        let client = data
+            .clients
+            .iter_mut()
+            .find(|client| client.id == client_id && client.user_id == new_client.user_id)
+            .ok_or(Error::RowNotFound)?;
+        client.name = new_client.name;
+        client.email = new_client.email;
+        client.phone = new_client.phone;
+        client.address = new_client.address;
+        client.default_rate = new_client.default_rate;
+        Ok(client.clone())
+    }
+
+    async fn delete_client(&self, client_id: ClientId, user_id: UserId) -> Result<ClientId, Error> {
+        let mut data = self.data();
+        data.client(&client_id, &user_id)?;
+        data.clients.retain(|client| client.id != client_id);
+        let orphans: Vec<usize> = data
+            .tasks
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row.task.client_id.as_ref() == Some(&client_id))
+            .map(|(index, _)| index)
+            .collect();
+        for index in orphans {
+            let task = Task {
+                client_id: None,
+                ..data.tasks[index].task.clone()
+            };
+            data.update_task(index, task, &FieldClock::new());
+        }
+        Ok(client_id)
+    }
+
+    async fn get_client_summary(
+        &self,
+        client_id: ClientId,
+        user_id: UserId,
+    ) -> Result<ClientSummary, Error> {
+        let data = self.data();
+        let client = data.client(&client_id, &user_id)?.clone();
+        let tasks: Vec<&TaskRow> = data
+            .tasks
+            .iter()
+            .filter(|row| {
+                row.task.client_id == Some(client_id.clone()) && row.task.user_id == user_id
+            })
+            .collect();
+
+        let mut task_summaries = Vec::new();
+        let mut months: BTreeMap<DateTime<Utc>, i64> = BTreeMap::new();
+        for row in tasks {
+            let mut total_duration = 0;
+            for event in data
+                .events
+                .iter()
+                .filter(|event| event.event.task_id == row.task.id)
+            {
+                total_duration += event.event.duration;
+                let began = event.event.date_began;
+                let month = Utc
+                    .with_ymd_and_hms(began.year(), began.month(), 1, 0, 0, 0)
+                    .unwrap();
+                *months.entry(month).or_default() += event.event.duration;
+            }
+            task_summaries.push(ClientTaskSummary {
+                task_id: row.task.id.clone(),
+                name: row.task.name.clone(),
+                total_duration,
+            });
+        }
+        task_summaries.sort_by(|a, b| {
+            b.total_duration
+                .cmp(&a.total_duration)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        let total_duration = task_summaries.iter().map(|task| task.total_duration).sum();
+        // durations are in seconds and the rate is per hour
+        let billable_amount = client.default_rate.map(|rate| total_duration * rate / 3600);
+
+        Ok(ClientSummary {
+            client,
+            tasks: task_summaries,
+            months: months
+                .into_iter()
+                .map(|(month, total_duration)| ClientMonthSummary {
+                    month,
+                    total_duration,
+                })
+                .collect(),
+            total_duration,
+            billable_amount,
+        })
+    }
+
+    async fn timezone_exists(&self, tz: &str) -> Result<bool, Error> {
+        Ok(parse_timezone(tz).is_ok())
+    }
+
+    async fn get_report_summary(
+        &self,
+        user_id: UserId,
+        report: ReportQuery,
+    ) -> Result<ReportSummary, Error> {
+        self.report_summary(user_id, report)
+    }
+
+    fn stream_events_for_export(
+        &self,
+        user_id: UserId,
+        export: EventExportQuery,
+    ) -> ReceiverStream<Result<EventExportRow, Error>> {
+        let rows = parse_timezone(&export.tz).map(|tz| {
+            let (range_start, range_end) = date_range(export.from, export.to, tz);
+            let data = self.data();
+            let mut events: Vec<&EventRow> = data
+                .events
+                .iter()
+                .filter(|row| {
+                    row.event.user_id == user_id
+                        && range_start.is_none_or(|start| row.event.date_began >= start)
+                        && range_end.is_none_or(|end| row.event.date_began < end)
+                })
+                .collect();
+            events.sort_by_key(|row| row.event.date_began);
+            events
+                .into_iter()
+                .filter_map(|row| {
+                    let task = &data.task(&row.event.task_id).ok()?.task;
+                    let client_name = task
+                        .client_id
+                        .as_ref()
+                        .and_then(|client_id| data.client(client_id, &task.user_id).ok())
+                        .map(|client| client.name.clone());
+                    let (date, start_time, end_time) =
+                        export_times(row.event.date_began, row.event.duration, tz);
+                    Some(EventExportRow {
+                        id: row.event.id.clone(),
+                        uuid: row.event.uuid,
+                        task_id: task.id.clone(),
+                        task_name: task.name.clone(),
+                        client_name,
+                        tags: task.tags.clone(),
+                        date,
+                        start_time,
+                        end_time,
+                        duration: row.event.duration,
+                        notes: row.event.notes.clone(),
+                    })
+                })
+                .collect::<Vec<_>>()
+        });
+        let (sender, receiver) = mpsc::channel(EXPORT_CHANNEL_SIZE);
+        tokio::spawn(async move {
+            match rows {
+                Ok(rows) => {
+                    for row in rows {
+                        if sender.send(Ok(row)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                }
+            }
+        });
+        ReceiverStream::new(receiver)
+    }
+
+    fn stream_report_summary(
+        &self,
+        user_id: UserId,
+        report: ReportQuery,
+    ) -> ReceiverStream<Result<ReportBucket, Error>> {
+        let (sender, receiver) = mpsc::channel(EXPORT_CHANNEL_SIZE);
+        let summary = self.report_summary(user_id, report);
+        tokio::spawn(async move {
+            match summary {
+                Ok(summary) => {
+                    for bucket in summary.buckets {
+                        if sender.send(Ok(bucket)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                }
+            }
+        });
+        ReceiverStream::new(receiver)
+    }
+
+    async fn import_events(
+        &self,
+        user_id: UserId,
+        rows: Vec<ImportRow>,
+        dedupe: ImportDedupe,
+        dry_run: bool,
+    ) -> Result<ImportReport, Error> {
+        let mut data = self.data();
+        // changes go to a copy, which replaces the data once all rows went in
+        let mut draft = data.clone();
+        let policy = draft.user_by_id(&user_id)?.overlap_policy;
+
+        let mut tasks: HashMap<String, TaskId> = draft
+            .tasks
+            .iter()
+            .filter(|row| row.task.user_id == user_id)
+            .map(|row| (row.task.name.clone(), row.task.id.clone()))
+            .collect();
+        let mut clients: HashMap<String, ClientId> = draft
+            .clients
+            .iter()
+            .filter(|client| client.user_id == user_id)
+            .map(|client| (client.name.clone(), client.id.clone()))
+            .collect();
+
+        let mut seen_uuids: HashSet<Uuid> = HashSet::new();
+        let mut seen_events: HashSet<(TaskId, DateTime<Utc>, i64)> = HashSet::new();
+        match dedupe {
+            ImportDedupe::Uuid => {
+                seen_uuids = draft.events.iter().map(|row| row.event.uuid).collect();
+            }
+            ImportDedupe::TaskDateDuration => {
+                seen_events = draft
+                    .events
+                    .iter()
+                    .filter(|row| row.event.user_id == user_id)
+                    .map(|row| {
+                        (
+                            row.event.task_id.clone(),
+                            row.event.date_began,
+                            row.event.duration,
+                        )
+                    })
+                    .collect();
+            }
+            ImportDedupe::None => {}
+        }
+        let mut seen_ical_uids: HashSet<String> = draft
+            .events
+            .iter()
+            .filter(|row| row.event.user_id == user_id)
+            .filter_map(|row| row.ical_uid.clone())
+            .collect();
+
+        let mut report = ImportReport {
+            dry_run,
+            total_rows: rows.len(),
+            ..ImportReport::default()
+        };
+        for row in rows {
+            let task_id = match tasks.get(&row.task_name) {
+                Some(task_id) => task_id.clone(),
+                None => {
+                    let client_id = match &row.client_name {
+                        Some(client_name) => match clients.get(client_name) {
+                            Some(client_id) => Some(client_id.clone()),
+                            None => {
+                                let client_id = ClientId(draft.next_id());
+                                draft.clients.push(Client {
+                                    id: client_id.clone(),
+                                    uuid: Uuid::new_v4(),
+                                    user_id: user_id.clone(),
+                                    name: client_name.clone(),
+                                    email: None,
+                                    phone: None,
+                                    address: None,
+                                    default_rate: None,
+                                    created_on: Utc::now(),
+                                });
+                                report.created_clients.push(client_name.clone());
+                                clients.insert(client_name.clone(), client_id.clone());
+                                Some(client_id)
+                            }
+                        },
+                        None => None,
+                    };
+                    let task = draft.insert_task(
+                        NewTask {
+                            user_id: user_id.clone(),
+                            name: row.task_name.clone(),
+                            description: None,
+                            client_id,
+                            tags: vec![],
+                        },
+                        Uuid::new_v4(),
+                        FieldClock::new(),
+                    );
+                    report.created_tasks.push(task.name.clone());
+                    tasks.insert(task.name, task.id.clone());
+                    task.id
+                }
+            };
+
+            let index = draft
+                .tasks
+                .iter()
+                .position(|task| task.task.id == task_id)
+                .unwrap();
+            let mut task = draft.tasks[index].task.clone();
+            for tag in &row.tags {
+                if !task.tags.contains(tag) {
+                    task.tags.push(tag.clone());
+                }
+            }
+            if task.tags != draft.tasks[index].task.tags {
+                draft.update_task(index, task, &FieldClock::new());
+            }
+
+            let duplicate = row
+                .ical_uid
+                .as_ref()
+                .is_some_and(|ical_uid| !seen_ical_uids.insert(ical_uid.clone()))
+                || match dedupe {
+                    ImportDedupe::Uuid => row.uuid.is_some_and(|uuid| !seen_uuids.insert(uuid)),
+                    ImportDedupe::TaskDateDuration => {
+                        !seen_events.insert((task_id.clone(), row.date_began, row.duration))
+                    }
+                    ImportDedupe::None => false,
+                };
+            if duplicate {
+                report.skipped_duplicates += 1;
+                continue;
+            }
+
+            let new_event = NewTaskEvent {
+                user_id: user_id.clone(),
+                task_id,
+                date_began: row.date_began,
+                duration: row.duration,
+                notes: row.notes,
+            };
+            let Ok(new_event) = draft.fit_event(policy, new_event)? else {
+                report.errors.push(ImportRowError {
+                    row: row.row,
+                    field: "date_began".to_string(),
+                    message: OVERLAPS_OTHERS.to_string(),
+                });
+                continue;
+            };
+            draft.insert_event(
+                new_event,
+                row.uuid.unwrap_or_else(Uuid::new_v4),
+                row.ical_uid,
+                FieldClock::new(),
+            )?;
+            report.imported += 1;
+        }
+
+        if !dry_run {
+            *data = draft;
+        }
+        Ok(report)
+    }
+
+    async fn regenerate_calendar_token(&self, user_id: UserId) -> Result<String, Error> {
+        let mut data = self.data();
+        let row = data
+            .users
+            .iter_mut()
+            .find(|row| row.user.id == user_id)
+            .ok_or(Error::RowNotFound)?;
+        let token = new_calendar_token();
+        row.calendar_token = Some(token.clone());
+        Ok(token)
+    }
+
+    async fn get_user_by_calendar_token(&self, token: &str) -> Result<UserId, Error> {
+        self.data()
+            .users
+            .iter()
+            .find(|row| row.calendar_token.as_deref() == Some(token) && !row.disabled)
+            .map(|row| row.user.id.clone())
+            .ok_or(Error::RowNotFound)
+    }
+
+    async fn get_calendar_events(
+        &self,
+        user_id: UserId,
+        filter: CalendarQuery,
+    ) -> Result<Vec<CalendarEvent>, Error> {
+        let data = self.data();
+        let mut events: Vec<CalendarEvent> = data
+            .events
+            .iter()
+            .filter(|row| row.event.user_id == user_id)
+            .filter(|row| {
+                filter
+                    .task_id
+                    .as_ref()
+                    .is_none_or(|task_id| row.event.task_id == *task_id)
+            })
+            .filter_map(|row| {
+                let task = &data.task(&row.event.task_id).ok()?.task;
+                if let Some(tag) = &filter.tag {
+                    if !task.tags.contains(tag) {
+                        return None;
+                    }
+                }
+                Some(CalendarEvent {
+                    uuid: row.event.uuid,
+                    task_name: task.name.clone(),
+                    date_began: row.event.date_began,
+                    duration: row.event.duration,
+                    notes: row.event.notes.clone(),
+                })
+            })
+            .collect();
+        events.sort_by_key(|event| event.date_began);
+        Ok(events)
+    }
+
+    async fn get_sync_changes(&self, user_id: UserId, since: i64) -> Result<SyncChanges, Error> {
+        let data = self.data();
+
+        let mut tasks: Vec<&TaskRow> = data
+            .tasks
+            .iter()
+            .filter(|row| row.task.user_id == user_id && row.change_seq > since)
+            .collect();
+        tasks.sort_by_key(|row| row.change_seq);
+
+        let mut events: Vec<(&EventRow, Uuid)> = data
+            .events
+            .iter()
+            .filter(|row| row.event.user_id == user_id && row.change_seq > since)
+            .filter_map(|row| Some((row, data.task(&row.event.task_id).ok()?.task.uuid)))
+            .collect();
+        events.sort_by_key(|(row, _)| row.change_seq);
+
+        let mut tombstones: Vec<&(i32, Tombstone, i64)> = data
+            .tombstones
+            .iter()
+            .filter(|(owner, _, change_seq)| *owner == user_id.0 && *change_seq > since)
+            .collect();
+        tombstones.sort_by_key(|(_, _, change_seq)| *change_seq);
+
+        let cursor = tasks
+            .iter()
+            .map(|row| row.change_seq)
+            .chain(events.iter().map(|(row, _)| row.change_seq))
+            .chain(tombstones.iter().map(|(_, _, change_seq)| *change_seq))
+            .fold(since, i64::max);
+        Ok(SyncChanges {
+            cursor,
+            tasks: tasks
+                .into_iter()
+                .map(|row| SyncTask {
+                    uuid: row.task.uuid,
+                    name: row.task.name.clone(),
+                    description: row.task.description.clone(),
+                    tags: row.task.tags.clone(),
+                    modified_at: row.field_clock.clone(),
+                })
+                .collect(),
+            events: events
+                .into_iter()
+                .map(|(row, task_uuid)| {
+                    let mut modified_at = row.field_clock.clone();
+                    // clients know the task by its uuid, not by the task_id column
+                    if let Some(task_modified_at) = modified_at.remove("task_id") {
+                        modified_at.insert("task_uuid".to_string(), task_modified_at);
+                    }
+                    SyncEvent {
+                        uuid: row.event.uuid,
+                        task_uuid,
+                        date_began: row.event.date_began,
+                        duration: row.event.duration,
+                        notes: row.event.notes.clone(),
+                        modified_at,
+                    }
+                })
+                .collect(),
+            tombstones: tombstones
+                .into_iter()
+                .map(|(_, tombstone, _)| tombstone.clone())
+                .collect(),
+        })
+    }
+
+    async fn apply_sync(&self, user_id: UserId, push: SyncPush) -> Result<SyncReport, Error> {
+        let mut data = self.data();
+        let policy = data.user_by_id(&user_id)?.overlap_policy;
+        let mut report = SyncReport::default();
+
+        for change in push.tasks {
+            let error = |message: &str| SyncError {
+                entity: SyncEntity::Task,
+                uuid: change.uuid,
+                message: message.to_string(),
+            };
+            match data
+                .tasks
+                .iter()
+                .position(|row| row.task.uuid == change.uuid)
+            {
+                Some(index) if data.tasks[index].task.user_id != user_id => {
+                    report.errors.push(error(UUID_TAKEN));
+                }
+                Some(index) => {
+                    let row = data.tasks[index].clone();
+                    let mut merge = FieldMerge::new(
+                        SyncEntity::Task,
+                        change.uuid,
+                        change.modified_at,
+                        row.field_clock,
+                    );
+                    let task = Task {
+                        name: merge.field("name", "name", row.task.name.clone(), change.name),
+                        description: merge.field(
+                            "description",
+                            "description",
+                            row.task.description.clone(),
+                            change.description,
+                        ),
+                        tags: merge.field("tags", "tags", row.task.tags.clone(), change.tags),
+                        ..row.task
+                    };
+                    report.conflicts.append(&mut merge.conflicts);
+                    if merge.written.is_empty() {
+                        continue;
+                    }
+                    if let Err(message) = check_task(&task.name, &task.description, &task.tags) {
+                        report.errors.push(error(&message));
+                        continue;
+                    }
+                    data.update_task(index, task, &merge.written);
+                    report.applied += 1;
+                }
+                None => {
+                    if let Some(deleted_at) = data.deleted_since(
+                        &user_id,
+                        SyncEntity::Task,
+                        change.uuid,
+                        change.modified_at,
+                    ) {
+                        report.conflicts.push(deleted_conflict(
+                            SyncEntity::Task,
+                            change.uuid,
+                            deleted_at,
+                            change.modified_at,
+                        ));
+                        continue;
+                    }
+                    let Some(name) = change.name else {
+                        report.errors.push(error("a new task needs a name"));
+                        continue;
+                    };
+                    let description = change.description.unwrap_or_default();
+                    let tags = change.tags.unwrap_or_default();
+                    if let Err(message) = check_task(&name, &description, &tags) {
+                        report.errors.push(error(&message));
+                        continue;
+                    }
+                    data.insert_task(
+                        NewTask {
+                            user_id: user_id.clone(),
+                            name,
+                            description: Some(description),
+                            client_id: None,
+                            tags,
+                        },
+                        change.uuid,
+                        new_clock(&TASK_SYNC_COLUMNS, change.modified_at),
+                    );
+                    report.applied += 1;
+                }
+            }
+        }
+
+        for change in push.events {
+            let error = |message: &str| SyncError {
+                entity: SyncEntity::Event,
+                uuid: change.uuid,
+                message: message.to_string(),
+            };
+            let task_id = match change.task_uuid {
+                Some(task_uuid) => match data
+                    .tasks
+                    .iter()
+                    .find(|row| row.task.uuid == task_uuid && row.task.user_id == user_id)
+                {
+                    Some(row) => Some(row.task.id.clone()),
+                    None => {
+                        report.errors.push(error("unknown task_uuid"));
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            match data
+                .events
+                .iter()
+                .position(|row| row.event.uuid == change.uuid)
+            {
+                Some(index) if data.events[index].event.user_id != user_id => {
+                    report.errors.push(error(UUID_TAKEN));
+                }
+                Some(index) => {
+                    let row = data.events[index].clone();
+                    let task_uuid = data.task(&row.event.task_id).ok().map(|row| row.task.uuid);
+                    let mut merge = FieldMerge::new(
+                        SyncEntity::Event,
+                        change.uuid,
+                        change.modified_at,
+                        row.field_clock,
+                    );
+                    merge.field(
+                        "task_uuid",
+                        "task_id",
+                        task_uuid,
+                        change.task_uuid.map(Some),
+                    );
+                    let event = TaskEvent {
+                        task_id: match task_id {
+                            Some(task_id) if merge.wrote("task_id") => task_id,
+                            _ => row.event.task_id.clone(),
+                        },
+                        date_began: merge.field(
+                            "date_began",
+                            "date_began",
+                            row.event.date_began,
+                            change.date_began,
+                        ),
+                        duration: merge.field(
+                            "duration",
+                            "duration",
+                            row.event.duration,
+                            change.duration,
+                        ),
+                        notes: merge.field("notes", "notes", row.event.notes.clone(), change.notes),
+                        ..row.event
+                    };
+                    report.conflicts.append(&mut merge.conflicts);
+                    if merge.written.is_empty() {
+                        continue;
+                    }
+                    if let Err(message) =
+                        check_event(event.date_began, event.duration, event.notes.as_deref())
+                    {
+                        report.errors.push(error(&message));
+                        continue;
+                    }
+                    data.update_event(index, event, &merge.written);
+                    report.applied += 1;
+                }
+                None => {
+                    if let Some(deleted_at) = data.deleted_since(
+                        &user_id,
+                        SyncEntity::Event,
+                        change.uuid,
+                        change.modified_at,
+                    ) {
+                        report.conflicts.push(deleted_conflict(
+                            SyncEntity::Event,
+                            change.uuid,
+                            deleted_at,
+                            change.modified_at,
+                        ));
+                        continue;
+                    }
+                    let (Some(task_id), Some(date_began), Some(duration)) =
+                        (task_id, change.date_began, change.duration)
+                    else {
+                        report.errors.push(error(
+                            "a new event needs a task_uuid, date_began and duration",
+                        ));
+                        continue;
+                    };
+                    let notes = change.notes.flatten();
+                    if let Err(message) = check_event(date_began, duration, notes.as_deref()) {
+                        report.errors.push(error(&message));
+                        continue;
+                    }
+                    let new_event = NewTaskEvent {
+                        user_id: user_id.clone(),
+                        task_id,
+                        date_began,
+                        duration,
+                        notes,
+                    };
+                    let Ok(new_event) = data.fit_event(policy, new_event)? else {
+                        report.errors.push(error(OVERLAPS_OTHERS));
+                        continue;
+                    };
+                    data.insert_event(
+                        new_event,
+                        change.uuid,
+                        None,
+                        new_clock(&EVENT_SYNC_COLUMNS, change.modified_at),
+                    )?;
+                    report.applied += 1;
+                }
+            }
+        }
+
+        for deletion in push.deletions {
+            let found = match deletion.entity {
+                SyncEntity::Task => data
+                    .tasks
+                    .iter()
+                    .find(|row| row.task.uuid == deletion.uuid)
+                    .map(|row| (row.task.user_id.clone(), row.field_clock.clone())),
+                SyncEntity::Event => data
+                    .events
+                    .iter()
+                    .find(|row| row.event.uuid == deletion.uuid)
+                    .map(|row| (row.event.user_id.clone(), row.field_clock.clone())),
+            };
+            // deleting twice is fine
+            let Some((owner, clock)) = found else {
+                continue;
+            };
+            if owner != user_id {
+                report.errors.push(SyncError {
+                    entity: deletion.entity,
+                    uuid: deletion.uuid,
+                    message: UUID_TAKEN.to_string(),
+                });
+                continue;
+            }
+            // an edit made after the deletion keeps the record alive
+            if let Some(&modified_at) = clock
+                .values()
+                .max()
+                .filter(|modified_at| **modified_at > deletion.deleted_at)
+            {
+                report.conflicts.push(SyncConflict {
+                    entity: deletion.entity,
+                    uuid: deletion.uuid,
+                    field: "deleted".to_string(),
+                    server_value: false.into(),
+                    client_value: true.into(),
+                    server_modified_at: modified_at,
+                    client_modified_at: deletion.deleted_at,
+                });
+                continue;
+            }
+            match deletion.entity {
+                SyncEntity::Task => {
+                    let index = data
+                        .tasks
+                        .iter()
+                        .position(|row| row.task.uuid == deletion.uuid)
+                        .unwrap();
+                    let task_id = data.tasks[index].task.id.clone();
+                    while let Some(event) = data
+                        .events
+                        .iter()
+                        .position(|row| row.event.task_id == task_id)
+                    {
+                        data.delete_event(event);
+                    }
+                    data.delete_task(index);
+                }
+                SyncEntity::Event => {
+                    let index = data
+                        .events
+                        .iter()
+                        .position(|row| row.event.uuid == deletion.uuid)
+                        .unwrap();
+                    data.delete_event(index);
+                }
+            }
+            // the deletion happened on the client
+            if let Some((_, tombstone, _)) = data.tombstones.last_mut() {
+                tombstone.deleted_at = deletion.deleted_at;
+            }
+            report.applied += 1;
+        }
+
+        Ok(report)
+    }
+}
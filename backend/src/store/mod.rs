@@ -0,0 +1,707 @@
+//! Persistence of accounts, tasks, events and clients.
+//!
+//! Routes only see the `Store` trait. `connect` picks the backend from the
+//! scheme of `DATABASE_URL`: Postgres for `postgres://` and `postgresql://`,
+//! SQLite for `sqlite:`, which suits a single person running their own copy.
+//! Each backend brings its own migrations.
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    migrate::{Migrate, MigrateError, Migrator},
+    Database, Error, Pool,
+};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::info;
+
+use crate::{
+    config::PoolConfig,
+    models::{
+        CalendarEvent, CalendarQuery, Client, ClientId, ClientSummary, DeliveryStatus,
+        EventExportQuery, EventExportRow, EventListQuery, EventOverlap, EventOverlapQuery,
+        ImportDedupe, ImportReport, ImportRow, ListedEvent, NewClient, NewTask, NewTaskEvent,
+        NewWebhook, OverlapPolicy, ReportBucket, ReportQuery, ReportSummary, SearchHit,
+        SearchQuery, SessionId, StartTimer, StopTimer, SyncChanges, SyncPush, SyncReport, Task,
+        TaskEvent, TaskEventId, TaskId, TaskListQuery, TaskSort, TaskWithTaskEvents, Timer, User,
+        UserEmail, UserId, UserSettings, Webhook, WebhookAttempt, WebhookDelivery,
+        WebhookDeliveryId, WebhookEvent, WebhookId,
+    },
+    LoginDetails,
+};
+
+#[cfg(test)]
+mod memory;
+mod notifications;
+mod postgres;
+mod reports;
+mod sqlite;
+#[cfg(test)]
+mod tests;
+
+#[cfg(test)]
+pub use memory::MemoryStore;
+pub use notifications::UserNotification;
+pub use postgres::PgStore;
+pub use sqlite::SqliteStore;
+
+/// How many rows of an export may be buffered ahead of the HTTP response
+const EXPORT_CHANNEL_SIZE: usize = 64;
+
+/// The columns of tasks and events that clients sync, each with its own clock
+const TASK_SYNC_COLUMNS: [&str; 3] = ["name", "description", "tags"];
+const EVENT_SYNC_COLUMNS: [&str; 4] = ["task_id", "date_began", "duration", "notes"];
+
+const UUID_TAKEN: &str = "the uuid is already in use";
+/// Why an imported or synced event was left out under the `reject` policy,
+/// or under `trim` when none of its time is free
+const OVERLAPS_OTHERS: &str = "the event overlaps others";
+
+/// What SQLite and the memory store put around the matches in search
+/// snippets, characters that do not turn up in text people write
+const MATCH_START: char = '\u{1}';
+const MATCH_END: char = '\u{2}';
+
+/// Where a page of tasks ends: the sort value and id of its last task, which
+/// breaks ties
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskCursor {
+    UpdatedOn(DateTime<Utc>, TaskId),
+    CreatedOn(DateTime<Utc>, TaskId),
+    Name(String, TaskId),
+    TotalDuration(i64, TaskId),
+}
+
+impl TaskCursor {
+    pub fn after(sort: TaskSort, task: &TaskWithTaskEvents) -> TaskCursor {
+        let id = task.task.id.clone();
+        match sort {
+            TaskSort::UpdatedOn => TaskCursor::UpdatedOn(task.updated_on, id),
+            TaskSort::CreatedOn => TaskCursor::CreatedOn(task.task.created_on, id),
+            TaskSort::Name => TaskCursor::Name(task.task.name.clone(), id),
+            TaskSort::TotalDuration => TaskCursor::TotalDuration(task.total_duration, id),
+        }
+    }
+
+    pub fn sort(&self) -> TaskSort {
+        match self {
+            TaskCursor::UpdatedOn(..) => TaskSort::UpdatedOn,
+            TaskCursor::CreatedOn(..) => TaskSort::CreatedOn,
+            TaskCursor::Name(..) => TaskSort::Name,
+            TaskCursor::TotalDuration(..) => TaskSort::TotalDuration,
+        }
+    }
+}
+
+/// Where a page of events ends: when its last event began and its id
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EventCursor(pub DateTime<Utc>, pub TaskEventId);
+
+impl EventCursor {
+    pub fn after(event: &ListedEvent) -> EventCursor {
+        EventCursor(event.event.date_began, event.event.id.clone())
+    }
+}
+
+/// A delivery that is due, with what it takes to send it
+#[derive(Debug, Clone)]
+pub struct DueDelivery {
+    pub id: WebhookDeliveryId,
+    pub url: String,
+    pub secret: String,
+    pub event_type: WebhookEvent,
+    /// the change it was queued for, as the triggers describe it
+    pub change: UserNotification,
+    /// the body sent before, if it was tried already
+    pub payload: Option<String>,
+    pub attempts: i32,
+    pub created_on: DateTime<Utc>,
+}
+
+/// The connections of a store's pool
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PoolStatus {
+    /// open connections, idle or in use
+    pub size: u32,
+    pub idle: usize,
+    pub max_size: u32,
+}
+
+/// What is going on across all users
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Activity {
+    pub sessions: i64,
+    pub running_timers: i64,
+}
+
+/// What became of an event added under its user's overlap policy
+#[derive(Debug)]
+pub enum EventAdded {
+    /// the event as stored, trimmed if the policy says so
+    Added(TaskEvent),
+    /// nothing was added, as the event overlaps these and the policy rejects
+    /// that, or as trimming would leave nothing of it
+    Overlapping(Vec<ListedEvent>),
+}
+
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// The migrations of this backend's schema
+    fn migrator(&self) -> &'static Migrator;
+
+    async fn run_migrations(&self) -> Result<(), MigrateError>;
+
+    /// Reverts every applied migration newer than `target`
+    async fn undo_migrations(&self, target: i64) -> Result<(), MigrateError>;
+
+    /// Versions of the migrations applied to the database
+    async fn applied_migrations(&self) -> Result<Vec<i64>, MigrateError>;
+
+    /// Like `applied_migrations`, but only reads, without creating the table
+    /// of migrations if there is none, so it can be polled by the readiness
+    /// check. Fails if the database cannot be reached.
+    async fn probe_migrations(&self) -> Result<Vec<i64>, Error>;
+
+    /// The connections of the database pool, for monitoring
+    fn pool_status(&self) -> PoolStatus;
+
+    async fn register_account(&self, new_user: LoginDetails) -> Result<String, Error>;
+
+    async fn get_account(&self, email: UserEmail) -> Result<User, Error>;
+
+    async fn get_user_email(&self, user_id: UserId) -> Result<UserEmail, Error>;
+
+    async fn get_user_settings(&self, user_id: UserId) -> Result<UserSettings, Error>;
+
+    async fn update_user_settings(
+        &self,
+        user_id: UserId,
+        settings: UserSettings,
+    ) -> Result<UserSettings, Error>;
+
+    /// Finds any account by email, including disabled ones
+    async fn get_user_id(&self, email: UserEmail) -> Result<UserId, Error>;
+
+    /// Replaces the user's password and ends their session
+    async fn set_password(&self, email: UserEmail, password: String) -> Result<UserId, Error>;
+
+    /// Keeps the user from logging in, ends their session and turns off
+    /// their calendar feed. Their data is kept.
+    async fn disable_user(&self, email: UserEmail) -> Result<UserId, Error>;
+
+    /// Logs everybody out, returning the number of sessions ended
+    async fn purge_sessions(&self) -> Result<u64, Error>;
+
+    async fn delete_user_session(&self, user_id: UserId) -> Result<UserId, Error>;
+
+    /// Checks the password and starts a session, replacing any earlier one
+    async fn create_session(&self, user: User, password: String) -> Result<SessionId, Error>;
+
+    /// The user a session cookie belongs to
+    async fn get_session_user(&self, session_id: String) -> Result<Option<UserId>, Error>;
+
+    /// The sessions and the running timers of all users
+    async fn get_activity(&self) -> Result<Activity, Error>;
+
+    async fn add_task(&self, new_task: NewTask) -> Result<Task, Error>;
+
+    async fn update_task(&self, new_task: NewTask, task_id: TaskId) -> Result<Task, Error>;
+
+    /// Adds an event under its user's overlap policy. Events of the user are
+    /// added one at a time, so two of them cannot slip past each other. A task
+    /// of another user is not found.
+    async fn add_event(&self, new_event: NewTaskEvent) -> Result<EventAdded, Error>;
+
+    /// Every pair of the user's events covering the same time, where that
+    /// time overlaps the range of `query`
+    async fn find_overlaps(
+        &self,
+        user_id: UserId,
+        query: EventOverlapQuery,
+    ) -> Result<Vec<EventOverlap>, Error>;
+
+    // currently supplanted by get_one_task_with_events
+    #[allow(dead_code)]
+    async fn get_tasks_by_user(&self, user_id: UserId) -> Result<Vec<Task>, Error>;
+
+    /// One of the user's events with the name of its task
+    async fn get_event(&self, event_id: TaskEventId, user_id: UserId)
+        -> Result<ListedEvent, Error>;
+
+    /// Up to `limit` of the user's events overlapping the range of `query`,
+    /// by the time they began, starting after `after`
+    async fn list_events(
+        &self,
+        user_id: UserId,
+        query: EventListQuery,
+        after: Option<EventCursor>,
+        limit: i64,
+    ) -> Result<Vec<ListedEvent>, Error>;
+
+    /// Up to `limit` of the user's tasks and events matching a search, the
+    /// best matches first
+    async fn search(
+        &self,
+        user_id: UserId,
+        query: SearchQuery,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, Error>;
+
+    /// A task of any user, for callers that know whose it is
+    async fn get_task_by_id(&self, task_id: TaskId) -> Result<Task, Error>;
+
+    async fn get_task_with_events_by_task_id(
+        &self,
+        task_id: TaskId,
+        user_id: UserId,
+    ) -> Result<TaskWithTaskEvents, Error>;
+
+    async fn get_user_tasks_with_events(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<TaskWithTaskEvents>, Error>;
+
+    /// Up to `limit` of the user's tasks in the order of `query.sort`,
+    /// starting after `after`. Totals always count every event, whichever
+    /// of them are included.
+    async fn list_tasks(
+        &self,
+        user_id: UserId,
+        query: TaskListQuery,
+        after: Option<TaskCursor>,
+        limit: i64,
+    ) -> Result<Vec<TaskWithTaskEvents>, Error>;
+
+    /// The user's tasks among `task_ids` with their totals, but without
+    /// their events
+    async fn get_tasks_by_ids(
+        &self,
+        user_id: UserId,
+        task_ids: Vec<TaskId>,
+    ) -> Result<Vec<TaskWithTaskEvents>, Error>;
+
+    /// The events of the user's tasks among `task_ids` by task, the latest
+    /// first, and only the latest `per_task` of each if it is set
+    async fn get_events_of_tasks(
+        &self,
+        user_id: UserId,
+        task_ids: Vec<TaskId>,
+        per_task: Option<i64>,
+    ) -> Result<Vec<TaskEvent>, Error>;
+
+    async fn add_client(&self, new_client: NewClient) -> Result<Client, Error>;
+
+    async fn get_clients_by_user(&self, user_id: UserId) -> Result<Vec<Client>, Error>;
+
+    async fn get_client(&self, client_id: ClientId, user_id: UserId) -> Result<Client, Error>;
+
+    async fn update_client(
+        &self,
+        new_client: NewClient,
+        client_id: ClientId,
+    ) -> Result<Client, Error>;
+
+    /// Tasks belonging to the client are kept and simply lose their client
+    async fn delete_client(&self, client_id: ClientId, user_id: UserId) -> Result<ClientId, Error>;
+
+    /// Aggregates the durations of the events of all of the client's tasks,
+    /// both per task and per calendar month (UTC)
+    async fn get_client_summary(
+        &self,
+        client_id: ClientId,
+        user_id: UserId,
+    ) -> Result<ClientSummary, Error>;
+
+    async fn timezone_exists(&self, tz: &str) -> Result<bool, Error>;
+
+    /// Sums the user's event durations between two local dates, grouped into
+    /// time buckets or by task, tag or project. Buckets without any events
+    /// are included with a total of zero.
+    async fn get_report_summary(
+        &self,
+        user_id: UserId,
+        report: ReportQuery,
+    ) -> Result<ReportSummary, Error>;
+
+    /// Streams the user's events together with their task and client, with
+    /// times converted to the export's time zone. Rows are handed over through
+    /// a bounded channel as they are read, so a large export is never held in
+    /// memory as a whole.
+    fn stream_events_for_export(
+        &self,
+        user_id: UserId,
+        export: EventExportQuery,
+    ) -> ReceiverStream<Result<EventExportRow, Error>>;
+
+    /// Streams the buckets of `get_report_summary` for exporting
+    fn stream_report_summary(
+        &self,
+        user_id: UserId,
+        report: ReportQuery,
+    ) -> ReceiverStream<Result<ReportBucket, Error>>;
+
+    /// Inserts the rows of an import in a single transaction, creating
+    /// missing tasks and clients by name, adding new tags to the tasks and
+    /// skipping duplicates. Events go through the user's overlap policy like
+    /// those of `add_event`, and the rows it leaves out are reported as
+    /// errors. A dry run goes through the same inserts and rolls them back.
+    async fn import_events(
+        &self,
+        user_id: UserId,
+        rows: Vec<ImportRow>,
+        dedupe: ImportDedupe,
+        dry_run: bool,
+    ) -> Result<ImportReport, Error>;
+
+    /// Replaces the secret token of the user's calendar feed, which
+    /// invalidates any previously shared feed URL
+    async fn regenerate_calendar_token(&self, user_id: UserId) -> Result<String, Error>;
+
+    async fn get_user_by_calendar_token(&self, token: &str) -> Result<UserId, Error>;
+
+    async fn get_calendar_events(
+        &self,
+        user_id: UserId,
+        filter: CalendarQuery,
+    ) -> Result<Vec<CalendarEvent>, Error>;
+
+    /// Everything that changed for the user after the cursor `since`
+    async fn get_sync_changes(&self, user_id: UserId, since: i64) -> Result<SyncChanges, Error>;
+
+    /// Applies a batch of client changes in one transaction: tasks first, so
+    /// new events can reference new tasks, then events, then deletions. New
+    /// events go through the user's overlap policy like those of `add_event`.
+    async fn apply_sync(&self, user_id: UserId, push: SyncPush) -> Result<SyncReport, Error>;
+
+    async fn get_timer(&self, user_id: UserId) -> Result<Option<Timer>, Error>;
+
+    /// Starts a timer on one of the user's tasks, a unique violation if one
+    /// is running already
+    async fn start_timer(&self, user_id: UserId, start: StartTimer) -> Result<Timer, Error>;
+
+    /// Stops the user's timer and adds the time since it started as an event
+    /// under their overlap policy. A timer whose event is not added keeps
+    /// running.
+    async fn stop_timer(&self, user_id: UserId, stop: StopTimer) -> Result<EventAdded, Error>;
+
+    /// The changes to every user's data as they are committed, through this
+    /// store or any other on the same database
+    async fn subscribe(&self) -> Result<broadcast::Receiver<UserNotification>, Error>;
+
+    /// Registers a webhook with a new secret. Changes made from then on are
+    /// queued for it, in the same transaction as the change.
+    async fn add_webhook(&self, user_id: UserId, new_webhook: NewWebhook)
+        -> Result<Webhook, Error>;
+
+    async fn get_webhooks(&self, user_id: UserId) -> Result<Vec<Webhook>, Error>;
+
+    /// Deletes the webhook together with its deliveries
+    async fn delete_webhook(
+        &self,
+        webhook_id: WebhookId,
+        user_id: UserId,
+    ) -> Result<WebhookId, Error>;
+
+    /// Up to `limit` deliveries of one of the user's webhooks with their
+    /// logs, the latest first, starting before `before`
+    async fn get_webhook_deliveries(
+        &self,
+        webhook_id: WebhookId,
+        user_id: UserId,
+        before: Option<WebhookDeliveryId>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, Error>;
+
+    /// Makes a delivery of one of the user's webhooks due right away, with
+    /// all of its attempts ahead of it again. The payload and the log are
+    /// kept.
+    async fn redeliver_webhook(
+        &self,
+        delivery_id: WebhookDeliveryId,
+        webhook_id: WebhookId,
+        user_id: UserId,
+    ) -> Result<WebhookDelivery, Error>;
+
+    /// Takes up to `limit` of the deliveries that are due, the oldest first,
+    /// and holds them until `until` by moving their next attempt there, so
+    /// no other server takes them in the meantime
+    async fn claim_webhook_deliveries(
+        &self,
+        limit: i64,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<DueDelivery>, Error>;
+
+    /// Logs an attempt at a delivery and keeps the payload that was sent.
+    /// A delivery still pending is tried again at `next_attempt`.
+    async fn record_webhook_attempt(
+        &self,
+        delivery_id: WebhookDeliveryId,
+        payload: String,
+        attempt: WebhookAttempt,
+        status: DeliveryStatus,
+        next_attempt: Option<DateTime<Utc>>,
+    ) -> Result<(), Error>;
+}
+
+/// Connects to the database of `db_url`, with the backend its scheme names
+pub async fn connect(db_url: &str, pool: &PoolConfig) -> Result<Arc<dyn Store>, Error> {
+    if db_url.starts_with("sqlite:") {
+        Ok(Arc::new(SqliteStore::new(db_url, pool).await?))
+    } else if db_url.starts_with("postgres://") || db_url.starts_with("postgresql://") {
+        Ok(Arc::new(PgStore::new(db_url, pool).await?))
+    } else {
+        Err(Error::Configuration(
+            "DATABASE_URL must start with postgres://, postgresql:// or sqlite:".into(),
+        ))
+    }
+}
+
+fn hash_password(password: String) -> String {
+    bcrypt::hash(password, 10).unwrap()
+}
+
+/// A wrong password is reported like an unknown account
+fn check_password(user: &User, password: String) -> Result<(), Error> {
+    info!("Create session");
+    if !bcrypt::verify(password, &user.password).unwrap_or_default() {
+        info!("Unauthorized");
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+fn new_session_id() -> String {
+    rand::random::<u64>().to_string()
+}
+
+fn new_calendar_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+fn new_webhook_secret() -> String {
+    format!(
+        "{:032x}{:032x}",
+        rand::random::<u128>(),
+        rand::random::<u128>()
+    )
+}
+
+fn parse_webhook_event(event: &str) -> Result<WebhookEvent, Error> {
+    event.parse().map_err(|e: String| Error::Decode(e.into()))
+}
+
+fn parse_delivery_status(status: &str) -> Result<DeliveryStatus, Error> {
+    status.parse().map_err(|e: String| Error::Decode(e.into()))
+}
+
+/// The `ORDER BY` of a task listing and the condition for the tasks after a
+/// cursor, whose id is bound to `$7`. `key` is the SQL of a task's sort value
+/// and `cursor_key` that of the cursor's.
+fn task_order(sort: TaskSort, key: &str, cursor_key: &str) -> (String, String) {
+    let (direction, comparison) = match sort {
+        TaskSort::Name => ("ASC", ">"),
+        TaskSort::UpdatedOn | TaskSort::CreatedOn | TaskSort::TotalDuration => ("DESC", "<"),
+    };
+    (
+        format!("{} {}, id {}", key, direction, direction),
+        format!("({}, id) {} ({}, $7)", key, comparison, cursor_key),
+    )
+}
+
+/// Gives each task the events that belong to it, in the order they come in
+fn attach_events(tasks: &mut [TaskWithTaskEvents], events: Vec<TaskEvent>) {
+    let mut by_task: HashMap<i32, Vec<TaskEvent>> = HashMap::new();
+    for event in events {
+        by_task.entry(event.task_id.0).or_default().push(event);
+    }
+    for task in tasks {
+        task.events = by_task.remove(&task.task.id.0).unwrap_or_default();
+    }
+}
+
+/// Gives each delivery the attempts at it, in the order they come in
+fn attach_attempts(
+    deliveries: &mut [WebhookDelivery],
+    attempts: Vec<(WebhookDeliveryId, WebhookAttempt)>,
+) {
+    let mut by_delivery: HashMap<i64, Vec<WebhookAttempt>> = HashMap::new();
+    for (delivery_id, attempt) in attempts {
+        by_delivery.entry(delivery_id.0).or_default().push(attempt);
+    }
+    for delivery in deliveries {
+        delivery.log = by_delivery.remove(&delivery.id.0).unwrap_or_default();
+    }
+}
+
+/// The JSON of a payload as it was sent
+fn parse_payload(payload: Option<String>) -> Result<Option<serde_json::Value>, Error> {
+    payload
+        .map(|payload| serde_json::from_str(&payload))
+        .transpose()
+        .map_err(|e| Error::Decode(e.into()))
+}
+
+fn parse_overlap_policy(policy: &str) -> Result<OverlapPolicy, Error> {
+    policy.parse().map_err(|e: String| Error::Decode(e.into()))
+}
+
+/// Applies an overlap policy to a new event, given the events it overlaps:
+/// the event to add, if any
+fn apply_overlap_policy(
+    policy: OverlapPolicy,
+    new_event: NewTaskEvent,
+    overlapping: &[ListedEvent],
+) -> Option<NewTaskEvent> {
+    if overlapping.is_empty() {
+        return Some(new_event);
+    }
+    match policy {
+        OverlapPolicy::Allow => Some(new_event),
+        OverlapPolicy::Reject => None,
+        OverlapPolicy::Trim => {
+            let (start, end) = longest_free_stretch(&new_event, overlapping)?;
+            let duration = (end - start).num_seconds();
+            (duration > 0).then_some(NewTaskEvent {
+                date_began: start,
+                duration,
+                ..new_event
+            })
+        }
+    }
+}
+
+/// When an event ends. Durations are at most `validation::MAX_DURATION`,
+/// but one stored before that rule ends at the latest time chrono knows
+/// rather than overflowing.
+fn event_end(date_began: DateTime<Utc>, duration: i64) -> DateTime<Utc> {
+    Duration::try_seconds(duration)
+        .and_then(|duration| date_began.checked_add_signed(duration))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+/// The longest stretch of a new event that none of the events it overlaps
+/// cover, the earliest of equally long ones
+fn longest_free_stretch(
+    new_event: &NewTaskEvent,
+    overlapping: &[ListedEvent],
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let end = event_end(new_event.date_began, new_event.duration);
+    let mut covered: Vec<(DateTime<Utc>, DateTime<Utc>)> = overlapping
+        .iter()
+        .map(|listed| {
+            let began = listed.event.date_began;
+            (began, event_end(began, listed.event.duration))
+        })
+        .collect();
+    covered.sort();
+    let mut stretches = vec![];
+    let mut free_from = new_event.date_began;
+    for (began, ended) in covered {
+        if began > free_from {
+            stretches.push((free_from, began.min(end)));
+        }
+        free_from = free_from.max(ended);
+    }
+    if free_from < end {
+        stretches.push((free_from, end));
+    }
+    stretches
+        .into_iter()
+        .filter(|(start, end)| start < end)
+        .fold(None, |longest, stretch| match longest {
+            Some((start, end)) if end - start >= stretch.1 - stretch.0 => Some((start, end)),
+            _ => Some(stretch),
+        })
+}
+
+/// The event a stopped timer leaves, lasting until now
+fn timer_event(user_id: UserId, timer: Timer, stop: StopTimer) -> NewTaskEvent {
+    NewTaskEvent {
+        user_id,
+        task_id: timer.task_id,
+        date_began: timer.started,
+        duration: (Utc::now() - timer.started).num_seconds().max(0),
+        notes: stop.notes.or(timer.notes),
+    }
+}
+
+/// The seconds two events both cover
+fn overlap_duration(first: &TaskEvent, second: &TaskEvent) -> i64 {
+    let end = |event: &TaskEvent| event_end(event.date_began, event.duration);
+    (end(first).min(end(second)) - first.date_began.max(second.date_began))
+        .num_seconds()
+        .max(0)
+}
+
+/// Fills in how many seconds of each event fall between `from` and `to`,
+/// either of which may be open
+fn clip_events(events: &mut [ListedEvent], from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) {
+    for listed in events {
+        let began = listed.event.date_began;
+        let ended = event_end(began, listed.event.duration);
+        let start = from.map_or(began, |from| began.max(from));
+        let end = to.map_or(ended, |to| ended.min(to));
+        listed.duration_in_range = Some((end - start).num_seconds().max(0));
+    }
+}
+
+/// The HTML of a search snippet: its text escaped and the matches, between
+/// `MATCH_START` and `MATCH_END`, in `<mark>`
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for character in snippet.chars() {
+        match character {
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            character => html.push(character),
+        }
+    }
+    html
+}
+
+fn pool_status<DB: Database>(pool: &Pool<DB>) -> PoolStatus {
+    PoolStatus {
+        size: pool.size(),
+        idle: pool.num_idle(),
+        max_size: pool.options().get_max_connections(),
+    }
+}
+
+async fn applied_versions<C: Migrate + ?Sized>(
+    connection: &mut C,
+) -> Result<Vec<i64>, MigrateError> {
+    connection.ensure_migrations_table().await?;
+    Ok(connection
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect())
+}
+
+/// Sends the rows of a query stream over to the receiving end of an export,
+/// stopping at the first error or once the receiver has gone away
+async fn forward_rows<T>(
+    rows: &mut (impl Stream<Item = Result<T, Error>> + Unpin),
+    sender: mpsc::Sender<Result<T, Error>>,
+) {
+    while let Some(row) = rows.next().await {
+        let failed = row.is_err();
+        if let Err(e) = &row {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+        }
+        if sender.send(row).await.is_err() || failed {
+            break;
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Changes to users' data as they are committed, for `GET /stream`.
+//!
+//! Triggers in the database describe each change. Postgres sends them on the
+//! `bandit_changes` channel, which reaches every server on the database;
+//! SQLite writes them to a table that is polled. The memory store sends them
+//! itself.
+
+use std::{
+    future::Future,
+    sync::{Arc, Weak},
+};
+
+use serde::Deserialize;
+use sqlx::Error;
+use tokio::sync::{broadcast, OnceCell};
+
+use crate::models::{Notification, UserId};
+
+/// The Postgres channel the triggers notify on
+pub const CHANNEL: &str = "bandit_changes";
+
+/// How far a subscriber may fall behind before it misses notifications
+const BUFFER: usize = 1024;
+
+/// A notification and the user whose clients it is for, as the triggers
+/// write it
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct UserNotification {
+    pub user_id: UserId,
+    #[serde(flatten)]
+    pub notification: Notification,
+}
+
+/// Hands out subscriptions to the notifications of a database. What brings
+/// them in is started by the first subscription, and only holds on to the
+/// notifier weakly, so that it can stop once the store is gone.
+#[derive(Debug)]
+pub struct Notifier {
+    sender: broadcast::Sender<UserNotification>,
+    started: OnceCell<()>,
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Notifier {
+            sender: broadcast::channel(BUFFER).0,
+            started: OnceCell::new(),
+        }
+    }
+}
+
+impl Notifier {
+    pub async fn subscribe<F, S>(
+        self: &Arc<Self>,
+        start: S,
+    ) -> Result<broadcast::Receiver<UserNotification>, Error>
+    where
+        S: FnOnce(Weak<Notifier>) -> F,
+        F: Future<Output = Result<(), Error>>,
+    {
+        self.started
+            .get_or_try_init(|| start(Arc::downgrade(self)))
+            .await?;
+        Ok(self.sender.subscribe())
+    }
+
+    pub fn send(&self, notification: UserNotification) {
+        // no one listening is fine
+        let _ = self.sender.send(notification);
+    }
+
+    /// Parses a notification as the triggers write it and passes it on
+    pub fn send_payload(&self, payload: &str) {
+        match serde_json::from_str(payload) {
+            Ok(notification) => self.send(notification),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "notification {}: {}", payload, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,2629 @@
+//! The Postgres backend, the one the server was built on. Reports and
+//! exports are computed in the database, time zones included.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Weak},
+};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{
+    migrate::{MigrateError, Migrator},
+    postgres::{PgConnection, PgListener, PgPool, PgPoolOptions, PgRow},
+    types::Json,
+    Error, Executor, Postgres, Row,
+};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::info;
+use uuid::Uuid;
+
+use super::{
+    applied_versions, apply_overlap_policy, attach_attempts, attach_events, check_password,
+    clip_events, forward_rows, hash_password, new_calendar_token, new_session_id,
+    new_webhook_secret,
+    notifications::{Notifier, CHANNEL},
+    overlap_duration, parse_delivery_status, parse_overlap_policy, parse_payload,
+    parse_webhook_event, pool_status,
+    reports::{date_range, parse_timezone},
+    task_order, timer_event, Activity, DueDelivery, EventAdded, EventCursor, PoolStatus, Store,
+    TaskCursor, UserNotification, EVENT_SYNC_COLUMNS, EXPORT_CHANNEL_SIZE, OVERLAPS_OTHERS,
+    TASK_SYNC_COLUMNS, UUID_TAKEN,
+};
+use crate::{
+    config::PoolConfig,
+    models::{
+        CalendarEvent, CalendarQuery, Client, ClientId, ClientMonthSummary, ClientSummary,
+        ClientTaskSummary, DeliveryStatus, EventExportQuery, EventExportRow, EventListQuery,
+        EventOverlap, EventOverlapQuery, FieldClock, ImportDedupe, ImportReport, ImportRow,
+        ImportRowError, IncludeEvents, ListedEvent, NewClient, NewTask, NewTaskEvent, NewWebhook,
+        OverlapPolicy, ReportBucket, ReportGroupBy, ReportQuery, ReportSummary, SearchHit,
+        SearchHitKind, SearchQuery, SessionId, StartTimer, StopTimer, SyncChanges, SyncConflict,
+        SyncEntity, SyncError, SyncEvent, SyncPush, SyncReport, SyncTask, Task, TaskEvent,
+        TaskEventId, TaskId, TaskListQuery, TaskWithTaskEvents, Timer, Tombstone, User, UserEmail,
+        UserId, UserSettings, Webhook, WebhookAttempt, WebhookDelivery, WebhookDeliveryId,
+        WebhookEvent, WebhookId,
+    },
+    sync::{check_event, check_task, deleted_conflict, new_clock, FieldMerge},
+    LoginDetails,
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// How long to wait before listening again after an error
+const LISTEN_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// The columns of a webhook delivery, but for its log
+const WEBHOOK_DELIVERY_COLUMNS: &str =
+    "id, webhook_id, event_type, payload, status, attempts, next_attempt, created_on";
+
+/// Events with the names of their tasks, for `get_event` and `list_events`
+const LISTED_EVENTS_QUERY: &str = "
+    SELECT
+        e.id, e.uuid, e.user_id, e.task_id, e.notes, e.date_began, e.duration,
+        t.name AS task_name
+    FROM events e
+    JOIN tasks t ON t.id = e.task_id";
+
+#[derive(Clone, Debug)]
+pub struct PgStore {
+    pub connection: PgPool,
+    notifier: Arc<Notifier>,
+}
+
+impl PgStore {
+    pub async fn new(db_url: &str, pool: &PoolConfig) -> Result<Self, sqlx::Error> {
+        let db_pool = match PgPoolOptions::new()
+            .max_connections(pool.max_connections)
+            .min_connections(pool.min_connections)
+            .acquire_timeout(pool.acquire_timeout)
+            .idle_timeout(pool.idle_timeout)
+            .connect(db_url)
+            .await
+        {
+            Ok(pool) => pool,
+            Err(e) => panic!("Couldn't establish a DB connection: {}", e),
+        };
+        Ok(PgStore {
+            connection: db_pool,
+            notifier: Arc::default(),
+        })
+    }
+}
+
+#[async_trait]
+impl Store for PgStore {
+    fn migrator(&self) -> &'static Migrator {
+        &MIGRATOR
+    }
+
+    async fn run_migrations(&self) -> Result<(), MigrateError> {
+        MIGRATOR.run(&self.connection).await
+    }
+
+    async fn undo_migrations(&self, target: i64) -> Result<(), MigrateError> {
+        MIGRATOR.undo(&self.connection, target).await
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<i64>, MigrateError> {
+        applied_versions(&mut *self.connection.acquire().await?).await
+    }
+
+    async fn probe_migrations(&self) -> Result<Vec<i64>, Error> {
+        let migrated =
+            sqlx::query("SELECT to_regclass('_sqlx_migrations') IS NOT NULL AS migrated")
+                .map(|row: PgRow| row.get::<bool, _>("migrated"))
+                .fetch_one(&self.connection)
+                .await?;
+        if !migrated {
+            return Ok(vec![]);
+        }
+        sqlx::query("SELECT version FROM _sqlx_migrations WHERE success ORDER BY version")
+            .map(|row: PgRow| row.get("version"))
+            .fetch_all(&self.connection)
+            .await
+    }
+
+    fn pool_status(&self) -> PoolStatus {
+        pool_status(&self.connection)
+    }
+
+    async fn register_account(&self, new_user: LoginDetails) -> Result<String, Error> {
+        let new_user = LoginDetails {
+            email: new_user.email,
+            password: hash_password(new_user.password),
+        };
+        match sqlx::query(
+            "INSERT INTO users (email, password)
+            VALUES ($1, $2)",
+        )
+        .bind(new_user.email.0)
+        .bind(new_user.password)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok("Created account!".to_string()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_account(&self, email: UserEmail) -> Result<User, Error> {
+        match sqlx::query(
+            "SELECT id, uuid, email, password FROM users WHERE email = $1 AND disabled_at IS NULL",
+        )
+        .bind(email.0)
+        .map(|row: PgRow| User {
+            id: UserId(row.get("id")),
+            uuid: row.get("uuid"),
+            email: UserEmail(row.get("email")),
+            password: row.get("password"),
+        })
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(user) => Ok(user),
+            Err(e) => {
+                info!("{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_user_email(&self, user_id: UserId) -> Result<UserEmail, Error> {
+        match sqlx::query("SELECT email FROM users WHERE id = $1")
+            .bind(user_id.0)
+            .map(|row: PgRow| UserEmail(row.get("email")))
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(email) => Ok(email),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_user_settings(&self, user_id: UserId) -> Result<UserSettings, Error> {
+        let policy = sqlx::query("SELECT overlap_policy FROM users WHERE id = $1")
+            .bind(user_id.0)
+            .map(|row: PgRow| row.get::<String, _>("overlap_policy"))
+            .fetch_one(&self.connection)
+            .await?;
+        Ok(UserSettings {
+            overlap_policy: parse_overlap_policy(&policy)?,
+        })
+    }
+
+    async fn update_user_settings(
+        &self,
+        user_id: UserId,
+        settings: UserSettings,
+    ) -> Result<UserSettings, Error> {
+        sqlx::query("UPDATE users SET overlap_policy = $2 WHERE id = $1")
+            .bind(user_id.0)
+            .bind(settings.overlap_policy.as_str())
+            .execute(&self.connection)
+            .await?;
+        self.get_user_settings(user_id).await
+    }
+
+    async fn get_user_id(&self, email: UserEmail) -> Result<UserId, Error> {
+        sqlx::query("SELECT id FROM users WHERE email = $1")
+            .bind(email.0)
+            .map(|row: PgRow| UserId(row.get("id")))
+            .fetch_one(&self.connection)
+            .await
+    }
+
+    async fn set_password(&self, email: UserEmail, password: String) -> Result<UserId, Error> {
+        let mut tx = self.connection.begin().await?;
+        let user_id = sqlx::query("UPDATE users SET password = $1 WHERE email = $2 RETURNING id")
+            .bind(hash_password(password))
+            .bind(email.0)
+            .map(|row: PgRow| UserId(row.get("id")))
+            .fetch_one(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+            .bind(user_id.0)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(user_id)
+    }
+
+    async fn disable_user(&self, email: UserEmail) -> Result<UserId, Error> {
+        let mut tx = self.connection.begin().await?;
+        let user_id = sqlx::query(
+            "UPDATE users SET disabled_at = COALESCE(disabled_at, NOW()) WHERE email = $1
+            RETURNING id",
+        )
+        .bind(email.0)
+        .map(|row: PgRow| UserId(row.get("id")))
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+            .bind(user_id.0)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(user_id)
+    }
+
+    async fn purge_sessions(&self) -> Result<u64, Error> {
+        match sqlx::query("DELETE FROM sessions")
+            .execute(&self.connection)
+            .await
+        {
+            Ok(res) => Ok(res.rows_affected()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn delete_user_session(&self, user_id: UserId) -> Result<UserId, Error> {
+        match sqlx::query(
+            "DELETE FROM sessions
+               WHERE user_id = $1 
+            ",
+        )
+        .bind(user_id.0)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(user_id),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn create_session(&self, user: User, password: String) -> Result<SessionId, Error> {
+        check_password(&user, password)?;
+        let session_id = new_session_id();
+
+        match sqlx::query(
+            "
+            INSERT INTO sessions (session_id, user_id)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id)
+            DO UPDATE SET session_id = EXCLUDED.session_id
+            RETURNING session_id
+            ",
+        )
+        .bind(&session_id)
+        .bind(user.id.0)
+        .map(|row: PgRow| SessionId(row.get("session_id")))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(session_id) => Ok(session_id),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_session_user(&self, session_id: String) -> Result<Option<UserId>, Error> {
+        sqlx::query("SELECT user_id FROM sessions WHERE session_id = $1")
+            .bind(session_id)
+            .map(|row: PgRow| UserId(row.get("user_id")))
+            .fetch_optional(&self.connection)
+            .await
+    }
+
+    async fn get_activity(&self) -> Result<Activity, Error> {
+        sqlx::query(
+            "SELECT (SELECT COUNT(*) FROM sessions) AS sessions,
+                (SELECT COUNT(*) FROM timers) AS running_timers",
+        )
+        .map(|row: PgRow| Activity {
+            sessions: row.get("sessions"),
+            running_timers: row.get("running_timers"),
+        })
+        .fetch_one(&self.connection)
+        .await
+    }
+
+    async fn add_task(&self, new_task: NewTask) -> Result<Task, Error> {
+        match insert_task(&self.connection, new_task).await {
+            Ok(task) => Ok(task),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn update_task(&self, new_task: NewTask, task_id: TaskId) -> Result<Task, Error> {
+        match sqlx::query(
+            "UPDATE tasks SET name = $1, description = $2, client_id = $5, tags = $6 WHERE id = $3 AND user_id = $4 RETURNING 
+             id, uuid, user_id, name, description, client_id, tags, created_on",
+        )
+        .bind(new_task.name)
+        .bind(new_task.description)
+        .bind(task_id.0)
+        .bind(new_task.user_id.0)
+        .bind(new_task.client_id.map(|client_id| client_id.0))
+        .bind(new_task.tags)
+        .map(|row: PgRow| Task {
+            id: TaskId(row.get("id")),
+            uuid: row.get("uuid"),
+            user_id: UserId(row.get("user_id")),
+            name: row.get("name"),
+            description: row.get("description"),
+            client_id: row.get::<Option<i32>, _>("client_id").map(ClientId),
+            tags: row.get("tags"),
+            created_on: row.get("created_on"),
+        })
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(task) => Ok(task),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn add_event(&self, new_event: NewTaskEvent) -> Result<EventAdded, Error> {
+        let mut tx = self.connection.begin().await?;
+        let added = add_event_under_policy(&mut tx, new_event).await?;
+        if let EventAdded::Added(_) = added {
+            tx.commit().await?;
+        }
+        Ok(added)
+    }
+
+    async fn find_overlaps(
+        &self,
+        user_id: UserId,
+        query: EventOverlapQuery,
+    ) -> Result<Vec<EventOverlap>, Error> {
+        let (from, to) = date_range(query.from, query.to, parse_timezone(&query.tz)?);
+        // b begins no earlier than a, so they overlap if b begins before a
+        // ends; the overlap is from the start of b to whichever ends first
+        sqlx::query(
+            "
+            SELECT
+                a.id AS a_id, a.uuid AS a_uuid, a.user_id AS a_user_id, a.task_id AS a_task_id,
+                a.notes AS a_notes, a.date_began AS a_date_began, a.duration AS a_duration,
+                ta.name AS a_task_name,
+                b.id AS b_id, b.uuid AS b_uuid, b.user_id AS b_user_id, b.task_id AS b_task_id,
+                b.notes AS b_notes, b.date_began AS b_date_began, b.duration AS b_duration,
+                tb.name AS b_task_name
+            FROM events a
+            JOIN tasks ta ON ta.id = a.task_id
+            JOIN events b ON b.user_id = a.user_id
+                AND (b.date_began, b.id) > (a.date_began, a.id)
+                AND b.date_began < a.date_began + a.duration * INTERVAL '1 second'
+            JOIN tasks tb ON tb.id = b.task_id
+            WHERE a.user_id = $1
+                AND a.duration > 0
+                AND b.duration > 0
+                AND ($2::TIMESTAMPTZ IS NULL OR LEAST(
+                    a.date_began + a.duration * INTERVAL '1 second',
+                    b.date_began + b.duration * INTERVAL '1 second'
+                ) > $2)
+                AND ($3::TIMESTAMPTZ IS NULL OR b.date_began < $3)
+            ORDER BY b.date_began, b.id, a.date_began, a.id
+            ",
+        )
+        .bind(user_id.0)
+        .bind(from)
+        .bind(to)
+        .map(|row: PgRow| {
+            let first = prefixed_listed_event(&row, "a_");
+            let second = prefixed_listed_event(&row, "b_");
+            EventOverlap {
+                duration: overlap_duration(&first.event, &second.event),
+                first,
+                second,
+            }
+        })
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn get_timer(&self, user_id: UserId) -> Result<Option<Timer>, Error> {
+        sqlx::query("SELECT task_id, started, notes FROM timers WHERE user_id = $1")
+            .bind(user_id.0)
+            .map(timer_from_row)
+            .fetch_optional(&self.connection)
+            .await
+    }
+
+    async fn start_timer(&self, user_id: UserId, start: StartTimer) -> Result<Timer, Error> {
+        sqlx::query(
+            "INSERT INTO timers (user_id, task_id, started, notes)
+            SELECT user_id, id, $3, $4 FROM tasks WHERE id = $2 AND user_id = $1
+            RETURNING task_id, started, notes",
+        )
+        .bind(user_id.0)
+        .bind(start.task_id.0)
+        .bind(Utc::now())
+        .bind(start.notes)
+        .map(timer_from_row)
+        .fetch_one(&self.connection)
+        .await
+    }
+
+    async fn stop_timer(&self, user_id: UserId, stop: StopTimer) -> Result<EventAdded, Error> {
+        let mut tx = self.connection.begin().await?;
+        let timer =
+            sqlx::query("DELETE FROM timers WHERE user_id = $1 RETURNING task_id, started, notes")
+                .bind(user_id.0)
+                .map(timer_from_row)
+                .fetch_one(&mut *tx)
+                .await?;
+        let new_event = timer_event(user_id, timer, stop);
+        let added = add_event_under_policy(&mut tx, new_event).await?;
+        if let EventAdded::Added(_) = added {
+            tx.commit().await?;
+        }
+        Ok(added)
+    }
+
+    async fn subscribe(&self) -> Result<broadcast::Receiver<UserNotification>, Error> {
+        self.notifier
+            .subscribe(|notifier| async {
+                // the listener keeps one connection of the pool to itself
+                let mut listener = PgListener::connect_with(&self.connection).await?;
+                listener.listen(CHANNEL).await?;
+                tokio::spawn(forward_notifications(listener, notifier));
+                Ok(())
+            })
+            .await
+    }
+
+    async fn add_webhook(
+        &self,
+        user_id: UserId,
+        new_webhook: NewWebhook,
+    ) -> Result<Webhook, Error> {
+        let event_types: Vec<&str> = new_webhook
+            .event_types
+            .iter()
+            .map(WebhookEvent::as_str)
+            .collect();
+        sqlx::query(
+            "
+            INSERT INTO webhooks (user_id, url, event_types, secret)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, url, event_types, secret, created_on
+            ",
+        )
+        .bind(user_id.0)
+        .bind(new_webhook.url)
+        .bind(event_types)
+        .bind(new_webhook_secret())
+        .try_map(webhook_from_row)
+        .fetch_one(&self.connection)
+        .await
+    }
+
+    async fn get_webhooks(&self, user_id: UserId) -> Result<Vec<Webhook>, Error> {
+        sqlx::query(
+            "
+            SELECT id, url, event_types, secret, created_on
+            FROM webhooks
+            WHERE user_id = $1
+            ORDER BY id
+            ",
+        )
+        .bind(user_id.0)
+        .try_map(webhook_from_row)
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn delete_webhook(
+        &self,
+        webhook_id: WebhookId,
+        user_id: UserId,
+    ) -> Result<WebhookId, Error> {
+        sqlx::query("DELETE FROM webhooks WHERE id = $1 AND user_id = $2 RETURNING id")
+            .bind(webhook_id.0)
+            .bind(user_id.0)
+            .map(|row: PgRow| WebhookId(row.get("id")))
+            .fetch_one(&self.connection)
+            .await
+    }
+
+    async fn get_webhook_deliveries(
+        &self,
+        webhook_id: WebhookId,
+        user_id: UserId,
+        before: Option<WebhookDeliveryId>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        sqlx::query("SELECT id FROM webhooks WHERE id = $1 AND user_id = $2")
+            .bind(webhook_id.0)
+            .bind(user_id.0)
+            .fetch_one(&self.connection)
+            .await?;
+        let mut deliveries = sqlx::query(&format!(
+            "
+            SELECT {}
+            FROM webhook_deliveries
+            WHERE webhook_id = $1 AND ($2::BIGINT IS NULL OR id < $2)
+            ORDER BY id DESC
+            LIMIT $3
+            ",
+            WEBHOOK_DELIVERY_COLUMNS
+        ))
+        .bind(webhook_id.0)
+        .bind(before.map(|before| before.0))
+        .bind(limit)
+        .try_map(delivery_from_row)
+        .fetch_all(&self.connection)
+        .await?;
+        attach_delivery_log(&self.connection, &mut deliveries).await?;
+        Ok(deliveries)
+    }
+
+    async fn redeliver_webhook(
+        &self,
+        delivery_id: WebhookDeliveryId,
+        webhook_id: WebhookId,
+        user_id: UserId,
+    ) -> Result<WebhookDelivery, Error> {
+        let delivery = sqlx::query(&format!(
+            "
+            UPDATE webhook_deliveries
+            SET status = 'pending', attempts = 0, next_attempt = $4
+            WHERE id = $1 AND webhook_id = $2
+                AND webhook_id IN (SELECT id FROM webhooks WHERE user_id = $3)
+            RETURNING {}
+            ",
+            WEBHOOK_DELIVERY_COLUMNS
+        ))
+        .bind(delivery_id.0)
+        .bind(webhook_id.0)
+        .bind(user_id.0)
+        .bind(Utc::now())
+        .try_map(delivery_from_row)
+        .fetch_one(&self.connection)
+        .await?;
+        let mut deliveries = [delivery];
+        attach_delivery_log(&self.connection, &mut deliveries).await?;
+        let [delivery] = deliveries;
+        Ok(delivery)
+    }
+
+    async fn claim_webhook_deliveries(
+        &self,
+        limit: i64,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<DueDelivery>, Error> {
+        // the lock skips over deliveries another server is claiming
+        sqlx::query(
+            "
+            WITH claimed AS (
+                UPDATE webhook_deliveries
+                SET next_attempt = $2
+                WHERE id IN (
+                    SELECT id FROM webhook_deliveries
+                    WHERE next_attempt <= $1
+                    ORDER BY next_attempt, id
+                    LIMIT $3
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING id, webhook_id, event_type, change, payload, attempts, created_on
+            )
+            SELECT c.id, w.url, w.secret, c.event_type, c.change, c.payload, c.attempts, c.created_on
+            FROM claimed c
+            JOIN webhooks w ON w.id = c.webhook_id
+            ORDER BY c.id
+            ",
+        )
+        .bind(Utc::now())
+        .bind(until)
+        .bind(limit)
+        .try_map(|row: PgRow| {
+            Ok(DueDelivery {
+                id: WebhookDeliveryId(row.get("id")),
+                url: row.get("url"),
+                secret: row.get("secret"),
+                event_type: parse_webhook_event(row.get("event_type"))?,
+                change: row.try_get::<Json<UserNotification>, _>("change")?.0,
+                payload: row.get("payload"),
+                attempts: row.get("attempts"),
+                created_on: row.get("created_on"),
+            })
+        })
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn record_webhook_attempt(
+        &self,
+        delivery_id: WebhookDeliveryId,
+        payload: String,
+        attempt: WebhookAttempt,
+        status: DeliveryStatus,
+        next_attempt: Option<DateTime<Utc>>,
+    ) -> Result<(), Error> {
+        let mut tx = self.connection.begin().await?;
+        let updated = sqlx::query(
+            "
+            UPDATE webhook_deliveries
+            SET payload = $2, status = $3, attempts = attempts + 1, next_attempt = $4
+            WHERE id = $1
+            ",
+        )
+        .bind(delivery_id.0)
+        .bind(payload)
+        .bind(status.as_str())
+        .bind(next_attempt)
+        .execute(&mut *tx)
+        .await?;
+        // the webhook may have been deleted while the delivery was on its way
+        if updated.rows_affected() == 0 {
+            return Ok(());
+        }
+        sqlx::query(
+            "
+            INSERT INTO webhook_attempts
+                (delivery_id, attempted_on, status_code, error, duration_ms)
+            VALUES ($1, $2, $3, $4, $5)
+            ",
+        )
+        .bind(delivery_id.0)
+        .bind(attempt.attempted_on)
+        .bind(attempt.status_code)
+        .bind(attempt.error)
+        .bind(attempt.duration_ms)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    async fn get_tasks_by_user(&self, user_id: UserId) -> Result<Vec<Task>, Error> {
+        match sqlx::query(
+            "
+            SELECT id, uuid, user_id, name, description, client_id, tags, created_on
+            FROM tasks
+            WHERE user_id = $1
+            ",
+        )
+        .bind(user_id.0)
+        .map(|row: PgRow| Task {
+            id: TaskId(row.get("id")),
+            uuid: row.get("uuid"),
+            user_id: UserId(row.get("user_id")),
+            name: row.get("name"),
+            description: row.get("description"),
+            client_id: row.get::<Option<i32>, _>("client_id").map(ClientId),
+            tags: row.get("tags"),
+            created_on: row.get("created_on"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(tasks) => Ok(tasks),
+            Err(err) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", err);
+                Err(err)
+            }
+        }
+    }
+
+    async fn get_event(
+        &self,
+        event_id: TaskEventId,
+        user_id: UserId,
+    ) -> Result<ListedEvent, Error> {
+        sqlx::query(&format!(
+            "{} WHERE e.id = $1 AND e.user_id = $2",
+            LISTED_EVENTS_QUERY
+        ))
+        .bind(event_id.0)
+        .bind(user_id.0)
+        .map(listed_event_from_row)
+        .fetch_one(&self.connection)
+        .await
+    }
+
+    async fn list_events(
+        &self,
+        user_id: UserId,
+        query: EventListQuery,
+        after: Option<EventCursor>,
+        limit: i64,
+    ) -> Result<Vec<ListedEvent>, Error> {
+        let (from, to) = date_range(query.from, query.to, parse_timezone(&query.tz)?);
+        // No event reaching into the range began longer before it than the
+        // user's longest event lasts, which bounds the scan of the
+        // (user_id, date_began) index on that side too
+        let sql = format!(
+            "
+            {}
+            WHERE e.user_id = $1
+                AND ($2::TIMESTAMPTZ IS NULL OR (
+                    e.date_began >= $2 - (
+                        SELECT COALESCE(MAX(duration), 0) FROM events WHERE user_id = $1
+                    ) * INTERVAL '1 second'
+                    AND (
+                        e.date_began >= $2
+                        OR e.date_began + e.duration * INTERVAL '1 second' > $2
+                    )
+                ))
+                AND ($3::TIMESTAMPTZ IS NULL OR e.date_began < $3)
+                AND ($4::INT IS NULL OR e.task_id = $4)
+                AND ($5::TEXT IS NULL OR $5 = ANY(t.tags))
+                AND {}
+            ORDER BY e.date_began, e.id
+            LIMIT $6
+            ",
+            LISTED_EVENTS_QUERY,
+            if after.is_some() {
+                "(e.date_began, e.id) > ($7, $8)"
+            } else {
+                "TRUE"
+            },
+        );
+        let page = sqlx::query(&sql)
+            .bind(user_id.0)
+            .bind(from)
+            .bind(to)
+            .bind(query.task_id.map(|task_id| task_id.0))
+            .bind(query.tag)
+            .bind(limit);
+        let page = match after {
+            None => page,
+            Some(EventCursor(began, id)) => page.bind(began).bind(id.0),
+        };
+        let mut events = page
+            .map(listed_event_from_row)
+            .fetch_all(&self.connection)
+            .await?;
+        if query.clip {
+            clip_events(&mut events, from, to);
+        }
+        Ok(events)
+    }
+
+    async fn search(
+        &self,
+        user_id: UserId,
+        query: SearchQuery,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, Error> {
+        let (from, to) = date_range(query.from, query.to, parse_timezone(&query.tz)?);
+        // Snippets are only worked out for the page of hits, as they need the
+        // text parsed again. It is escaped first: ts_headline passes entities
+        // through but would drop whatever looks like a tag.
+        sqlx::query(
+            r#"
+            WITH search AS (
+                SELECT websearch_to_tsquery('english', $2) AS query
+            ),
+            hits AS (
+                SELECT
+                    'task' AS kind,
+                    t.id AS task_id,
+                    t.name AS task_name,
+                    NULL::INT AS event_id,
+                    NULL::TIMESTAMPTZ AS date_began,
+                    ts_rank(t.search_vector, search.query)::DOUBLE PRECISION AS rank,
+                    concat_ws(E'
+', t.name, t.description) AS text
+                FROM tasks t
+                CROSS JOIN search
+                WHERE t.user_id = $1
+                    AND t.search_vector @@ search.query
+                    AND ($5::INT IS NULL OR t.id = $5)
+                    AND (($3::TIMESTAMPTZ IS NULL AND $4::TIMESTAMPTZ IS NULL) OR EXISTS (
+                        SELECT 1 FROM events e
+                        WHERE e.task_id = t.id
+                            AND ($3 IS NULL OR e.date_began >= $3)
+                            AND ($4 IS NULL OR e.date_began < $4)
+                    ))
+                UNION ALL
+                SELECT
+                    'event',
+                    e.task_id,
+                    t.name,
+                    e.id,
+                    e.date_began,
+                    ts_rank(e.search_vector, search.query)::DOUBLE PRECISION,
+                    e.notes
+                FROM events e
+                JOIN tasks t ON t.id = e.task_id
+                CROSS JOIN search
+                WHERE e.user_id = $1
+                    AND e.search_vector @@ search.query
+                    AND ($5::INT IS NULL OR e.task_id = $5)
+                    AND ($3::TIMESTAMPTZ IS NULL OR e.date_began >= $3)
+                    AND ($4::TIMESTAMPTZ IS NULL OR e.date_began < $4)
+                ORDER BY rank DESC, date_began DESC NULLS FIRST, task_id, event_id
+                LIMIT $6
+            )
+            SELECT
+                hits.*,
+                ts_headline(
+                    'english',
+                    replace(replace(replace(replace(replace(
+                        hits.text, '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '"', '&quot;'),
+                        '''', '&#39;'),
+                    search.query,
+                    'StartSel=<mark>, StopSel=</mark>, MinWords=10, MaxWords=30'
+                ) AS snippet
+            FROM hits
+            CROSS JOIN search
+            ORDER BY rank DESC, date_began DESC NULLS FIRST, task_id, event_id
+            "#,
+        )
+        .bind(user_id.0)
+        .bind(query.q)
+        .bind(from)
+        .bind(to)
+        .bind(query.task_id.map(|task_id| task_id.0))
+        .bind(limit)
+        .map(|row: PgRow| SearchHit {
+            kind: match row.get::<&str, _>("kind") {
+                "task" => SearchHitKind::Task,
+                _ => SearchHitKind::Event,
+            },
+            task_id: TaskId(row.get("task_id")),
+            task_name: row.get("task_name"),
+            event_id: row.get::<Option<i32>, _>("event_id").map(TaskEventId),
+            date_began: row.get("date_began"),
+            rank: row.get("rank"),
+            snippet: row.get("snippet"),
+        })
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn get_task_by_id(&self, task_id: TaskId) -> Result<Task, Error> {
+        match sqlx::query(
+            "
+            SELECT id, uuid, user_id, name, description, client_id, tags, created_on
+            FROM tasks
+            WHERE id = $1
+            ",
+        )
+        .bind(task_id.0)
+        .map(|row: PgRow| Task {
+            id: TaskId(row.get("id")),
+            uuid: row.get("uuid"),
+            user_id: UserId(row.get("user_id")),
+            name: row.get("name"),
+            description: row.get("description"),
+            client_id: row.get::<Option<i32>, _>("client_id").map(ClientId),
+            tags: row.get("tags"),
+            created_on: row.get("created_on"),
+        })
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(task) => Ok(task),
+            Err(err) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", err);
+                Err(err)
+            }
+        }
+    }
+
+    async fn get_task_with_events_by_task_id(
+        &self,
+        task_id: TaskId,
+        user_id: UserId,
+    ) -> Result<TaskWithTaskEvents, Error> {
+        match sqlx::query(
+            r#"
+        SELECT
+            t.id AS task_id,
+            t.uuid AS task_uuid,
+            t.user_id AS task_user_id,
+            t.name AS task_name,
+            t.description AS task_description,
+            t.client_id AS task_client_id,
+            t.tags AS task_tags,
+            t.created_on AS task_created_on,
+
+          COALESCE(jsonb_agg(json_build_object(
+                'id', e.id,
+                'uuid', e.uuid,
+                'user_id', e.user_id,
+                'task_id', e.task_id,
+                'notes', e.notes,
+                'date_began', e.date_began,
+                'duration', e.duration
+            )), '[]'::jsonb) AS events,
+
+            CAST(COALESCE(SUM(e.duration), 0) AS BIGINT) AS total_duration,
+
+            COALESCE(MAX(e.date_began), t.created_on) AS updated_on
+
+        FROM
+            tasks t
+        LEFT JOIN
+            events e ON t.id = e.task_id
+        WHERE
+            t.id = $1 AND t.user_id = $2
+        GROUP BY
+            t.id, t.uuid, t.user_id, t.name, t.description, t.client_id, t.tags, t.created_on
+    "#,
+        )
+        .bind(task_id.0)
+        .bind(user_id.0)
+        .map(|row: PgRow| {
+            let events: Json<Vec<TaskEvent>> = row.try_get("events").unwrap_or_default();
+            TaskWithTaskEvents {
+                task: Task {
+                    id: TaskId(row.get("task_id")),
+                    uuid: row.get("task_uuid"),
+                    user_id: UserId(row.get("task_user_id")),
+                    name: row.get("task_name"),
+                    description: row.get("task_description"),
+                    client_id: row.get::<Option<i32>, _>("task_client_id").map(ClientId),
+                    tags: row.get("task_tags"),
+                    created_on: row.get("task_created_on"),
+                },
+                events: events.0,
+                total_duration: row.get("total_duration"),
+                updated_on: row.get("updated_on"),
+            }
+        })
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(tasks_with_events) => Ok(tasks_with_events),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+    async fn get_user_tasks_with_events(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<TaskWithTaskEvents>, Error> {
+        let query = sqlx::query(
+            r#"
+        SELECT
+            t.id AS task_id,
+            t.uuid AS task_uuid,
+            t.user_id AS task_user_id,
+            t.name AS task_name,
+            t.description AS task_description,
+            t.client_id AS task_client_id,
+            t.tags AS task_tags,
+            t.created_on AS task_created_on,
+
+          COALESCE(jsonb_agg(json_build_object(
+                'id', e.id,
+                'uuid', e.uuid,
+                'user_id', e.user_id,
+                'task_id', e.task_id,
+                'notes', e.notes,
+                'date_began', e.date_began,
+                'duration', e.duration
+            )), '[]'::jsonb) AS events,
+
+            CAST(COALESCE(SUM(e.duration), 0) AS BIGINT) AS total_duration,
+
+            COALESCE(MAX(e.date_began), t.created_on) AS updated_on
+
+        FROM
+            tasks t
+        LEFT JOIN
+            events e ON t.id = e.task_id
+        WHERE
+            t.user_id = $1
+        GROUP BY
+            t.id, t.uuid, t.user_id, t.name, t.description, t.client_id, t.tags, t.created_on
+    "#,
+        );
+
+        let result = query.bind(user_id.0).fetch_all(&self.connection).await?;
+
+        let tasks_with_events: Result<Vec<TaskWithTaskEvents>, Error> = result
+            .into_iter()
+            .map(|row: PgRow| {
+                let events: Json<Vec<TaskEvent>> = row.try_get("events").unwrap_or_default();
+                Ok(TaskWithTaskEvents {
+                    task: Task {
+                        id: TaskId(row.get("task_id")),
+                        uuid: row.get("task_uuid"),
+                        user_id: UserId(row.get("task_user_id")),
+                        name: row.get("task_name"),
+                        description: row.get("task_description"),
+                        client_id: row.get::<Option<i32>, _>("task_client_id").map(ClientId),
+                        tags: row.get("task_tags"),
+                        created_on: row.get("task_created_on"),
+                    },
+                    events: events.0,
+                    total_duration: row.get("total_duration"),
+                    updated_on: row.get("updated_on"),
+                })
+            })
+            .collect();
+        tasks_with_events
+    }
+
+    async fn list_tasks(
+        &self,
+        user_id: UserId,
+        query: TaskListQuery,
+        after: Option<TaskCursor>,
+        limit: i64,
+    ) -> Result<Vec<TaskWithTaskEvents>, Error> {
+        let (updated_from, updated_to) = date_range(
+            query.updated_from,
+            query.updated_to,
+            parse_timezone(&query.tz)?,
+        );
+        let (order_by, after_cursor) = task_order(query.sort, query.sort.column(), "$6");
+        let sql = format!(
+            "
+            WITH summaries AS (
+                SELECT
+                    t.id, t.uuid, t.user_id, t.name, t.description, t.client_id, t.tags,
+                    t.created_on,
+                    CAST(COALESCE(SUM(e.duration), 0) AS BIGINT) AS total_duration,
+                    COALESCE(MAX(e.date_began), t.created_on) AS updated_on
+                FROM tasks t
+                LEFT JOIN events e ON e.task_id = t.id
+                WHERE t.user_id = $1
+                    AND ($2::TEXT IS NULL OR strpos(lower(t.name), lower($2)) > 0)
+                GROUP BY t.id
+            )
+            SELECT * FROM summaries
+            WHERE ($3::TIMESTAMPTZ IS NULL OR updated_on >= $3)
+                AND ($4::TIMESTAMPTZ IS NULL OR updated_on < $4)
+                AND {}
+            ORDER BY {}
+            LIMIT $5
+            ",
+            if after.is_some() {
+                &after_cursor
+            } else {
+                "TRUE"
+            },
+            order_by,
+        );
+        let page = sqlx::query(&sql)
+            .bind(user_id.0)
+            .bind(query.q)
+            .bind(updated_from)
+            .bind(updated_to)
+            .bind(limit);
+        let page = match after {
+            None => page,
+            Some(TaskCursor::UpdatedOn(at, id)) | Some(TaskCursor::CreatedOn(at, id)) => {
+                page.bind(at).bind(id.0)
+            }
+            Some(TaskCursor::Name(name, id)) => page.bind(name).bind(id.0),
+            Some(TaskCursor::TotalDuration(total, id)) => page.bind(total).bind(id.0),
+        };
+        let mut tasks: Vec<TaskWithTaskEvents> = page
+            .map(task_summary_from_row)
+            .fetch_all(&self.connection)
+            .await?;
+
+        let per_task = match query.include_events {
+            IncludeEvents::None => return Ok(tasks),
+            IncludeEvents::All => None,
+            IncludeEvents::Recent(count) => Some(i64::from(count)),
+        };
+        let task_ids = tasks.iter().map(|task| task.task.id.clone()).collect();
+        let events = self
+            .get_events_of_tasks(user_id, task_ids, per_task)
+            .await?;
+        attach_events(&mut tasks, events);
+        Ok(tasks)
+    }
+
+    async fn get_tasks_by_ids(
+        &self,
+        user_id: UserId,
+        task_ids: Vec<TaskId>,
+    ) -> Result<Vec<TaskWithTaskEvents>, Error> {
+        let task_ids: Vec<i32> = task_ids.into_iter().map(|task_id| task_id.0).collect();
+        sqlx::query(
+            "
+            SELECT
+                t.id, t.uuid, t.user_id, t.name, t.description, t.client_id, t.tags,
+                t.created_on,
+                CAST(COALESCE(SUM(e.duration), 0) AS BIGINT) AS total_duration,
+                COALESCE(MAX(e.date_began), t.created_on) AS updated_on
+            FROM tasks t
+            LEFT JOIN events e ON e.task_id = t.id
+            WHERE t.user_id = $1 AND t.id = ANY($2)
+            GROUP BY t.id
+            ORDER BY t.id
+            ",
+        )
+        .bind(user_id.0)
+        .bind(task_ids)
+        .map(task_summary_from_row)
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn get_events_of_tasks(
+        &self,
+        user_id: UserId,
+        task_ids: Vec<TaskId>,
+        per_task: Option<i64>,
+    ) -> Result<Vec<TaskEvent>, Error> {
+        let task_ids: Vec<i32> = task_ids.into_iter().map(|task_id| task_id.0).collect();
+        sqlx::query(
+            "
+            SELECT id, uuid, user_id, task_id, notes, date_began, duration
+            FROM (
+                SELECT e.*, row_number() OVER (
+                    PARTITION BY e.task_id ORDER BY e.date_began DESC, e.id DESC
+                ) AS position
+                FROM events e
+                WHERE e.task_id = ANY($1) AND e.user_id = $3
+            ) e
+            WHERE $2::BIGINT IS NULL OR position <= $2
+            ORDER BY task_id, position
+            ",
+        )
+        .bind(task_ids)
+        .bind(per_task)
+        .bind(user_id.0)
+        .map(|row: PgRow| TaskEvent {
+            id: TaskEventId(row.get("id")),
+            uuid: row.get("uuid"),
+            user_id: UserId(row.get("user_id")),
+            task_id: TaskId(row.get("task_id")),
+            date_began: row.get("date_began"),
+            duration: row.get("duration"),
+            notes: row.get("notes"),
+        })
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn add_client(&self, new_client: NewClient) -> Result<Client, Error> {
+        match sqlx::query(
+            "INSERT INTO clients (user_id, name, email, phone, address, default_rate)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, uuid, user_id, name, email, phone, address, default_rate, created_on",
+        )
+        .bind(new_client.user_id.0)
+        .bind(new_client.name)
+        .bind(new_client.email)
+        .bind(new_client.phone)
+        .bind(new_client.address)
+        .bind(new_client.default_rate)
+        .map(client_from_row)
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(client) => Ok(client),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_clients_by_user(&self, user_id: UserId) -> Result<Vec<Client>, Error> {
+        match sqlx::query(
+            "
+            SELECT id, uuid, user_id, name, email, phone, address, default_rate, created_on
+            FROM clients
+            WHERE user_id = $1
+            ORDER BY name
+            ",
+        )
+        .bind(user_id.0)
+        .map(client_from_row)
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(clients) => Ok(clients),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_client(&self, client_id: ClientId, user_id: UserId) -> Result<Client, Error> {
+        match sqlx::query(
+            "
+            SELECT id, uuid, user_id, name, email, phone, address, default_rate, created_on
+            FROM clients
+            WHERE id = $1 AND user_id = $2
+            ",
+        )
+        .bind(client_id.0)
+        .bind(user_id.0)
+        .map(client_from_row)
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(client) => Ok(client),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn update_client(
+        &self,
+        new_client: NewClient,
+        client_id: ClientId,
+    ) -> Result<Client, Error> {
+        match sqlx::query(
+            "UPDATE clients
+            SET name = $1, email = $2, phone = $3, address = $4, default_rate = $5
+            WHERE id = $6 AND user_id = $7
+            RETURNING id, uuid, user_id, name, email, phone, address, default_rate, created_on",
+        )
+        .bind(new_client.name)
+        .bind(new_client.email)
+        .bind(new_client.phone)
+        .bind(new_client.address)
+        .bind(new_client.default_rate)
+        .bind(client_id.0)
+        .bind(new_client.user_id.0)
+        .map(client_from_row)
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(client) => Ok(client),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn delete_client(&self, client_id: ClientId, user_id: UserId) -> Result<ClientId, Error> {
+        match sqlx::query(
+            "DELETE FROM clients
+            WHERE id = $1 AND user_id = $2
+            RETURNING id",
+        )
+        .bind(client_id.0)
+        .bind(user_id.0)
+        .map(|row: PgRow| ClientId(row.get("id")))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(client_id) => Ok(client_id),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_client_summary(
+        &self,
+        client_id: ClientId,
+        user_id: UserId,
+    ) -> Result<ClientSummary, Error> {
+        let client = self.get_client(client_id, user_id).await?;
+
+        let tasks = sqlx::query(
+            r#"
+        SELECT
+            t.id AS task_id,
+            t.name AS task_name,
+            CAST(COALESCE(SUM(e.duration), 0) AS BIGINT) AS total_duration
+        FROM
+            tasks t
+        LEFT JOIN
+            events e ON t.id = e.task_id
+        WHERE
+            t.client_id = $1 AND t.user_id = $2
+        GROUP BY
+            t.id, t.name
+        ORDER BY
+            total_duration DESC, t.name
+    "#,
+        )
+        .bind(client.id.0)
+        .bind(client.user_id.0)
+        .map(|row: PgRow| ClientTaskSummary {
+            task_id: TaskId(row.get("task_id")),
+            name: row.get("task_name"),
+            total_duration: row.get("total_duration"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        .map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            e
+        })?;
+
+        let months = sqlx::query(
+            r#"
+        SELECT
+            date_trunc('month', e.date_began AT TIME ZONE 'UTC') AT TIME ZONE 'UTC' AS month,
+            CAST(SUM(e.duration) AS BIGINT) AS total_duration
+        FROM
+            events e
+        JOIN
+            tasks t ON t.id = e.task_id
+        WHERE
+            t.client_id = $1 AND t.user_id = $2
+        GROUP BY
+            month
+        ORDER BY
+            month
+    "#,
+        )
+        .bind(client.id.0)
+        .bind(client.user_id.0)
+        .map(|row: PgRow| ClientMonthSummary {
+            month: row.get("month"),
+            total_duration: row.get("total_duration"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        .map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            e
+        })?;
+
+        let total_duration = tasks.iter().map(|task| task.total_duration).sum();
+        // durations are in seconds and the rate is per hour
+        let billable_amount = client.default_rate.map(|rate| total_duration * rate / 3600);
+
+        Ok(ClientSummary {
+            client,
+            tasks,
+            months,
+            total_duration,
+            billable_amount,
+        })
+    }
+
+    async fn timezone_exists(&self, tz: &str) -> Result<bool, Error> {
+        sqlx::query("SELECT EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1) AS valid")
+            .bind(tz)
+            .map(|row: PgRow| row.get("valid"))
+            .fetch_one(&self.connection)
+            .await
+    }
+
+    async fn get_report_summary(
+        &self,
+        user_id: UserId,
+        report: ReportQuery,
+    ) -> Result<ReportSummary, Error> {
+        let query = report_summary_query(report.group_by);
+
+        let buckets = sqlx::query(&query)
+            .bind(user_id.0)
+            .bind(report.from)
+            .bind(report.to)
+            .bind(&report.tz)
+            .bind(report.group_by.date_trunc_unit())
+            .map(report_bucket_from_row)
+            .fetch_all(&self.connection)
+            .await
+            .map_err(|e| {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                e
+            })?;
+
+        let total_duration = match report.group_by {
+            // a task can carry several tags, so the buckets may overlap
+            ReportGroupBy::Tag => {
+                sqlx::query(
+                    r#"
+        SELECT CAST(COALESCE(SUM(e.duration), 0) AS BIGINT) AS total_duration
+        FROM events e
+        WHERE e.user_id = $1
+            AND e.date_began >= ($2::date)::timestamp AT TIME ZONE $3
+            AND e.date_began < ($4::date + 1)::timestamp AT TIME ZONE $3
+    "#,
+                )
+                .bind(user_id.0)
+                .bind(report.from)
+                .bind(&report.tz)
+                .bind(report.to)
+                .map(|row: PgRow| row.get("total_duration"))
+                .fetch_one(&self.connection)
+                .await?
+            }
+            _ => buckets.iter().map(|bucket| bucket.total_duration).sum(),
+        };
+
+        Ok(ReportSummary {
+            from: report.from,
+            to: report.to,
+            group_by: report.group_by,
+            tz: report.tz,
+            buckets,
+            total_duration,
+        })
+    }
+
+    fn stream_events_for_export(
+        &self,
+        user_id: UserId,
+        export: EventExportQuery,
+    ) -> ReceiverStream<Result<EventExportRow, Error>> {
+        let (sender, receiver) = mpsc::channel(EXPORT_CHANNEL_SIZE);
+        let connection = self.connection.clone();
+        tokio::spawn(async move {
+            let mut rows = sqlx::query(
+                r#"
+        SELECT
+            e.id,
+            e.uuid,
+            e.task_id,
+            t.name AS task_name,
+            c.name AS client_name,
+            t.tags,
+            to_char(e.date_began AT TIME ZONE $4, 'YYYY-MM-DD') AS date,
+            to_char(e.date_began AT TIME ZONE $4, 'HH24:MI:SS') AS start_time,
+            to_char(
+                (e.date_began + e.duration * INTERVAL '1 second') AT TIME ZONE $4,
+                'HH24:MI:SS'
+            ) AS end_time,
+            e.duration,
+            e.notes
+        FROM
+            events e
+        JOIN
+            tasks t ON t.id = e.task_id
+        LEFT JOIN
+            clients c ON c.id = t.client_id
+        WHERE
+            e.user_id = $1
+            AND ($2::date IS NULL OR e.date_began >= ($2::date)::timestamp AT TIME ZONE $4)
+            AND ($3::date IS NULL OR e.date_began < ($3::date + 1)::timestamp AT TIME ZONE $4)
+        ORDER BY
+            e.date_began
+    "#,
+            )
+            .bind(user_id.0)
+            .bind(export.from)
+            .bind(export.to)
+            .bind(export.tz)
+            .map(|row: PgRow| EventExportRow {
+                id: TaskEventId(row.get("id")),
+                uuid: row.get("uuid"),
+                task_id: TaskId(row.get("task_id")),
+                task_name: row.get("task_name"),
+                client_name: row.get("client_name"),
+                tags: row.get("tags"),
+                date: row.get("date"),
+                start_time: row.get("start_time"),
+                end_time: row.get("end_time"),
+                duration: row.get("duration"),
+                notes: row.get("notes"),
+            })
+            .fetch(&connection);
+            forward_rows(&mut rows, sender).await;
+        });
+        ReceiverStream::new(receiver)
+    }
+
+    fn stream_report_summary(
+        &self,
+        user_id: UserId,
+        report: ReportQuery,
+    ) -> ReceiverStream<Result<ReportBucket, Error>> {
+        let (sender, receiver) = mpsc::channel(EXPORT_CHANNEL_SIZE);
+        let connection = self.connection.clone();
+        tokio::spawn(async move {
+            let query = report_summary_query(report.group_by);
+            let mut rows = sqlx::query(&query)
+                .bind(user_id.0)
+                .bind(report.from)
+                .bind(report.to)
+                .bind(&report.tz)
+                .bind(report.group_by.date_trunc_unit())
+                .map(report_bucket_from_row)
+                .fetch(&connection);
+            forward_rows(&mut rows, sender).await;
+        });
+        ReceiverStream::new(receiver)
+    }
+
+    async fn import_events(
+        &self,
+        user_id: UserId,
+        rows: Vec<ImportRow>,
+        dedupe: ImportDedupe,
+        dry_run: bool,
+    ) -> Result<ImportReport, Error> {
+        let mut tx = self.connection.begin().await?;
+        let policy = lock_overlap_policy(&mut tx, &user_id).await?;
+
+        let existing_tasks = sqlx::query("SELECT id, name, tags FROM tasks WHERE user_id = $1")
+            .bind(user_id.0)
+            .map(|row: PgRow| {
+                let tags: Vec<String> = row.get("tags");
+                (row.get("name"), TaskId(row.get("id")), tags)
+            })
+            .fetch_all(&mut *tx)
+            .await?;
+        let mut tasks: HashMap<String, TaskId> = HashMap::new();
+        let mut task_tags: HashMap<TaskId, Vec<String>> = HashMap::new();
+        for (name, task_id, tags) in existing_tasks {
+            tasks.insert(name, task_id.clone());
+            task_tags.insert(task_id, tags);
+        }
+        let mut retagged_tasks: HashSet<TaskId> = HashSet::new();
+
+        let mut clients: HashMap<String, ClientId> =
+            sqlx::query("SELECT id, name FROM clients WHERE user_id = $1")
+                .bind(user_id.0)
+                .map(|row: PgRow| (row.get("name"), ClientId(row.get("id"))))
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .collect();
+
+        let mut seen_uuids: HashSet<Uuid> = HashSet::new();
+        let mut seen_events: HashSet<(TaskId, DateTime<Utc>, i64)> = HashSet::new();
+        match dedupe {
+            ImportDedupe::Uuid => {
+                let uuids: Vec<Uuid> = rows.iter().filter_map(|row| row.uuid).collect();
+                seen_uuids = sqlx::query("SELECT uuid FROM events WHERE uuid = ANY($1)")
+                    .bind(uuids)
+                    .map(|row: PgRow| row.get("uuid"))
+                    .fetch_all(&mut *tx)
+                    .await?
+                    .into_iter()
+                    .collect();
+            }
+            ImportDedupe::TaskDateDuration => {
+                seen_events = sqlx::query(
+                    "SELECT task_id, date_began, duration FROM events WHERE user_id = $1",
+                )
+                .bind(user_id.0)
+                .map(|row: PgRow| {
+                    (
+                        TaskId(row.get("task_id")),
+                        row.get("date_began"),
+                        row.get("duration"),
+                    )
+                })
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .collect();
+            }
+            ImportDedupe::None => {}
+        }
+
+        let ical_uids: Vec<&str> = rows
+            .iter()
+            .filter_map(|row| row.ical_uid.as_deref())
+            .collect();
+        let mut seen_ical_uids: HashSet<String> =
+            sqlx::query("SELECT ical_uid FROM events WHERE user_id = $1 AND ical_uid = ANY($2)")
+                .bind(user_id.0)
+                .bind(ical_uids)
+                .map(|row: PgRow| row.get("ical_uid"))
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .collect();
+
+        let mut report = ImportReport {
+            dry_run,
+            total_rows: rows.len(),
+            ..ImportReport::default()
+        };
+        for row in rows {
+            let task_id = match tasks.get(&row.task_name) {
+                Some(task_id) => task_id.clone(),
+                None => {
+                    let client_id = match &row.client_name {
+                        Some(client_name) => match clients.get(client_name) {
+                            Some(client_id) => Some(client_id.clone()),
+                            None => {
+                                let client_id: ClientId = sqlx::query(
+                                    "INSERT INTO clients (user_id, name) VALUES ($1, $2) RETURNING id",
+                                )
+                                .bind(user_id.0)
+                                .bind(client_name)
+                                .map(|row: PgRow| ClientId(row.get("id")))
+                                .fetch_one(&mut *tx)
+                                .await?;
+                                report.created_clients.push(client_name.clone());
+                                clients.insert(client_name.clone(), client_id.clone());
+                                Some(client_id)
+                            }
+                        },
+                        None => None,
+                    };
+                    let task = insert_task(
+                        &mut *tx,
+                        NewTask {
+                            user_id: user_id.clone(),
+                            name: row.task_name.clone(),
+                            description: None,
+                            client_id,
+                            tags: vec![],
+                        },
+                    )
+                    .await?;
+                    report.created_tasks.push(task.name.clone());
+                    tasks.insert(task.name, task.id.clone());
+                    task_tags.insert(task.id.clone(), vec![]);
+                    task.id
+                }
+            };
+
+            let tags = task_tags.entry(task_id.clone()).or_default();
+            for tag in &row.tags {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                    retagged_tasks.insert(task_id.clone());
+                }
+            }
+
+            let duplicate = row
+                .ical_uid
+                .as_ref()
+                .is_some_and(|ical_uid| !seen_ical_uids.insert(ical_uid.clone()))
+                || match dedupe {
+                    ImportDedupe::Uuid => row.uuid.is_some_and(|uuid| !seen_uuids.insert(uuid)),
+                    ImportDedupe::TaskDateDuration => {
+                        !seen_events.insert((task_id.clone(), row.date_began, row.duration))
+                    }
+                    ImportDedupe::None => false,
+                };
+            if duplicate {
+                report.skipped_duplicates += 1;
+                continue;
+            }
+
+            let new_event = NewTaskEvent {
+                user_id: user_id.clone(),
+                task_id,
+                date_began: row.date_began,
+                duration: row.duration,
+                notes: row.notes,
+            };
+            let Ok(new_event) = fit_event(&mut tx, policy, new_event).await? else {
+                report.errors.push(ImportRowError {
+                    row: row.row,
+                    field: "date_began".to_string(),
+                    message: OVERLAPS_OTHERS.to_string(),
+                });
+                continue;
+            };
+            insert_event(&mut *tx, new_event, row.uuid, row.ical_uid).await?;
+            report.imported += 1;
+        }
+
+        for task_id in retagged_tasks {
+            sqlx::query("UPDATE tasks SET tags = $1 WHERE id = $2")
+                .bind(&task_tags[&task_id])
+                .bind(task_id.0)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+        Ok(report)
+    }
+
+    async fn regenerate_calendar_token(&self, user_id: UserId) -> Result<String, Error> {
+        let token = new_calendar_token();
+        match sqlx::query(
+            "UPDATE users SET calendar_token = $1 WHERE id = $2
+            RETURNING calendar_token",
+        )
+        .bind(&token)
+        .bind(user_id.0)
+        .map(|row: PgRow| row.get("calendar_token"))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(token) => Ok(token),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_user_by_calendar_token(&self, token: &str) -> Result<UserId, Error> {
+        sqlx::query("SELECT id FROM users WHERE calendar_token = $1 AND disabled_at IS NULL")
+            .bind(token)
+            .map(|row: PgRow| UserId(row.get("id")))
+            .fetch_one(&self.connection)
+            .await
+    }
+
+    async fn get_calendar_events(
+        &self,
+        user_id: UserId,
+        filter: CalendarQuery,
+    ) -> Result<Vec<CalendarEvent>, Error> {
+        match sqlx::query(
+            r#"
+        SELECT
+            e.uuid,
+            t.name AS task_name,
+            e.date_began,
+            e.duration,
+            e.notes
+        FROM
+            events e
+        JOIN
+            tasks t ON t.id = e.task_id
+        WHERE
+            e.user_id = $1
+            AND ($2::int IS NULL OR e.task_id = $2)
+            AND ($3::text IS NULL OR $3 = ANY(t.tags))
+        ORDER BY
+            e.date_began
+    "#,
+        )
+        .bind(user_id.0)
+        .bind(filter.task_id.map(|task_id| task_id.0))
+        .bind(filter.tag)
+        .map(|row: PgRow| CalendarEvent {
+            uuid: row.get("uuid"),
+            task_name: row.get("task_name"),
+            date_began: row.get("date_began"),
+            duration: row.get("duration"),
+            notes: row.get("notes"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(events) => Ok(events),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Reads from one snapshot so the returned cursor covers all of it
+    async fn get_sync_changes(&self, user_id: UserId, since: i64) -> Result<SyncChanges, Error> {
+        let mut tx = self.connection.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .execute(&mut *tx)
+            .await?;
+
+        let tasks = sqlx::query(
+            "SELECT uuid, name, COALESCE(description, '') AS description, tags, field_clock, change_seq
+            FROM tasks
+            WHERE user_id = $1 AND change_seq > $2
+            ORDER BY change_seq",
+        )
+        .bind(user_id.0)
+        .bind(since)
+        .map(|row: PgRow| {
+            let task = SyncTask {
+                uuid: row.get("uuid"),
+                name: row.get("name"),
+                description: row.get("description"),
+                tags: row.get("tags"),
+                modified_at: row.get::<Json<FieldClock>, _>("field_clock").0,
+            };
+            (row.get::<i64, _>("change_seq"), task)
+        })
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let events = sqlx::query(
+            "SELECT e.uuid, t.uuid AS task_uuid, e.date_began, e.duration, e.notes,
+                e.field_clock, e.change_seq
+            FROM events e
+            JOIN tasks t ON t.id = e.task_id
+            WHERE e.user_id = $1 AND e.change_seq > $2
+            ORDER BY e.change_seq",
+        )
+        .bind(user_id.0)
+        .bind(since)
+        .map(|row: PgRow| {
+            let mut modified_at = row.get::<Json<FieldClock>, _>("field_clock").0;
+            // clients know the task by its uuid, not by the task_id column
+            if let Some(task_modified_at) = modified_at.remove("task_id") {
+                modified_at.insert("task_uuid".to_string(), task_modified_at);
+            }
+            let event = SyncEvent {
+                uuid: row.get("uuid"),
+                task_uuid: row.get("task_uuid"),
+                date_began: row.get("date_began"),
+                duration: row.get("duration"),
+                notes: row.get("notes"),
+                modified_at,
+            };
+            (row.get::<i64, _>("change_seq"), event)
+        })
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let tombstones = sqlx::query(
+            "SELECT entity, uuid, deleted_at, change_seq
+            FROM tombstones
+            WHERE user_id = $1 AND change_seq > $2
+            ORDER BY change_seq",
+        )
+        .bind(user_id.0)
+        .bind(since)
+        .map(|row: PgRow| {
+            let entity = match row.get::<&str, _>("entity") {
+                "task" => SyncEntity::Task,
+                _ => SyncEntity::Event,
+            };
+            let tombstone = Tombstone {
+                entity,
+                uuid: row.get("uuid"),
+                deleted_at: row.get("deleted_at"),
+            };
+            (row.get::<i64, _>("change_seq"), tombstone)
+        })
+        .fetch_all(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        let cursor = tasks
+            .iter()
+            .map(|(change_seq, _)| *change_seq)
+            .chain(events.iter().map(|(change_seq, _)| *change_seq))
+            .chain(tombstones.iter().map(|(change_seq, _)| *change_seq))
+            .fold(since, i64::max);
+        Ok(SyncChanges {
+            cursor,
+            tasks: tasks.into_iter().map(|(_, task)| task).collect(),
+            events: events.into_iter().map(|(_, event)| event).collect(),
+            tombstones: tombstones
+                .into_iter()
+                .map(|(_, tombstone)| tombstone)
+                .collect(),
+        })
+    }
+
+    async fn apply_sync(&self, user_id: UserId, push: SyncPush) -> Result<SyncReport, Error> {
+        let mut tx = self.connection.begin().await?;
+        let policy = lock_overlap_policy(&mut tx, &user_id).await?;
+        let mut report = SyncReport::default();
+
+        for change in push.tasks {
+            let error = |message: &str| SyncError {
+                entity: SyncEntity::Task,
+                uuid: change.uuid,
+                message: message.to_string(),
+            };
+            let existing = sqlx::query(
+                "SELECT id, user_id, name, COALESCE(description, '') AS description, tags, field_clock
+                FROM tasks
+                WHERE uuid = $1
+                FOR UPDATE",
+            )
+            .bind(change.uuid)
+            .fetch_optional(&mut *tx)
+            .await?;
+            match existing {
+                Some(row) if row.get::<i32, _>("user_id") != user_id.0 => {
+                    report.errors.push(error(UUID_TAKEN));
+                }
+                Some(row) => {
+                    let clock = row.get::<Json<FieldClock>, _>("field_clock").0;
+                    let mut merge =
+                        FieldMerge::new(SyncEntity::Task, change.uuid, change.modified_at, clock);
+                    let name = merge.field("name", "name", row.get("name"), change.name);
+                    let description = merge.field(
+                        "description",
+                        "description",
+                        row.get("description"),
+                        change.description,
+                    );
+                    let tags = merge.field("tags", "tags", row.get("tags"), change.tags);
+                    report.conflicts.append(&mut merge.conflicts);
+                    if merge.written.is_empty() {
+                        continue;
+                    }
+                    if let Err(message) = check_task(&name, &description, &tags) {
+                        report.errors.push(error(&message));
+                        continue;
+                    }
+                    sqlx::query(
+                        "UPDATE tasks
+                        SET name = $1, description = $2, tags = $3, field_clock = field_clock || $4
+                        WHERE id = $5",
+                    )
+                    .bind(name)
+                    .bind(description)
+                    .bind(tags)
+                    .bind(Json(&merge.written))
+                    .bind(row.get::<i32, _>("id"))
+                    .execute(&mut *tx)
+                    .await?;
+                    report.applied += 1;
+                }
+                None => {
+                    if let Some(deleted_at) = deleted_since(
+                        &mut tx,
+                        &user_id,
+                        SyncEntity::Task,
+                        change.uuid,
+                        change.modified_at,
+                    )
+                    .await?
+                    {
+                        report.conflicts.push(deleted_conflict(
+                            SyncEntity::Task,
+                            change.uuid,
+                            deleted_at,
+                            change.modified_at,
+                        ));
+                        continue;
+                    }
+                    let Some(name) = change.name else {
+                        report.errors.push(error("a new task needs a name"));
+                        continue;
+                    };
+                    let description = change.description.unwrap_or_default();
+                    let tags = change.tags.unwrap_or_default();
+                    if let Err(message) = check_task(&name, &description, &tags) {
+                        report.errors.push(error(&message));
+                        continue;
+                    }
+                    sqlx::query(
+                        "INSERT INTO tasks (uuid, user_id, name, description, tags, field_clock)
+                        VALUES ($1, $2, $3, $4, $5, $6)",
+                    )
+                    .bind(change.uuid)
+                    .bind(user_id.0)
+                    .bind(name)
+                    .bind(description)
+                    .bind(tags)
+                    .bind(Json(new_clock(&TASK_SYNC_COLUMNS, change.modified_at)))
+                    .execute(&mut *tx)
+                    .await?;
+                    report.applied += 1;
+                }
+            }
+        }
+
+        for change in push.events {
+            let error = |message: &str| SyncError {
+                entity: SyncEntity::Event,
+                uuid: change.uuid,
+                message: message.to_string(),
+            };
+            let task_id: Option<i32> = match change.task_uuid {
+                Some(task_uuid) => {
+                    match sqlx::query("SELECT id FROM tasks WHERE uuid = $1 AND user_id = $2")
+                        .bind(task_uuid)
+                        .bind(user_id.0)
+                        .map(|row: PgRow| row.get("id"))
+                        .fetch_optional(&mut *tx)
+                        .await?
+                    {
+                        Some(task_id) => Some(task_id),
+                        None => {
+                            report.errors.push(error("unknown task_uuid"));
+                            continue;
+                        }
+                    }
+                }
+                None => None,
+            };
+            let existing = sqlx::query(
+                "SELECT e.id, e.user_id, e.task_id, t.uuid AS task_uuid, e.date_began, e.duration,
+                    e.notes, e.field_clock
+                FROM events e
+                LEFT JOIN tasks t ON t.id = e.task_id
+                WHERE e.uuid = $1
+                FOR UPDATE OF e",
+            )
+            .bind(change.uuid)
+            .fetch_optional(&mut *tx)
+            .await?;
+            match existing {
+                Some(row) if row.get::<i32, _>("user_id") != user_id.0 => {
+                    report.errors.push(error(UUID_TAKEN));
+                }
+                Some(row) => {
+                    let clock = row.get::<Json<FieldClock>, _>("field_clock").0;
+                    let mut merge =
+                        FieldMerge::new(SyncEntity::Event, change.uuid, change.modified_at, clock);
+                    merge.field(
+                        "task_uuid",
+                        "task_id",
+                        row.get::<Option<Uuid>, _>("task_uuid"),
+                        change.task_uuid.map(Some),
+                    );
+                    let task_id = match task_id {
+                        Some(task_id) if merge.wrote("task_id") => task_id,
+                        _ => row.get("task_id"),
+                    };
+                    let date_began = merge.field(
+                        "date_began",
+                        "date_began",
+                        row.get("date_began"),
+                        change.date_began,
+                    );
+                    let duration =
+                        merge.field("duration", "duration", row.get("duration"), change.duration);
+                    let notes = merge.field("notes", "notes", row.get("notes"), change.notes);
+                    report.conflicts.append(&mut merge.conflicts);
+                    if merge.written.is_empty() {
+                        continue;
+                    }
+                    if let Err(message) = check_event(date_began, duration, notes.as_deref()) {
+                        report.errors.push(error(&message));
+                        continue;
+                    }
+                    sqlx::query(
+                        "UPDATE events
+                        SET task_id = $1, date_began = $2, duration = $3, notes = $4,
+                            field_clock = field_clock || $5
+                        WHERE id = $6",
+                    )
+                    .bind(task_id)
+                    .bind(date_began)
+                    .bind(duration)
+                    .bind(notes)
+                    .bind(Json(&merge.written))
+                    .bind(row.get::<i32, _>("id"))
+                    .execute(&mut *tx)
+                    .await?;
+                    report.applied += 1;
+                }
+                None => {
+                    if let Some(deleted_at) = deleted_since(
+                        &mut tx,
+                        &user_id,
+                        SyncEntity::Event,
+                        change.uuid,
+                        change.modified_at,
+                    )
+                    .await?
+                    {
+                        report.conflicts.push(deleted_conflict(
+                            SyncEntity::Event,
+                            change.uuid,
+                            deleted_at,
+                            change.modified_at,
+                        ));
+                        continue;
+                    }
+                    let (Some(task_id), Some(date_began), Some(duration)) =
+                        (task_id, change.date_began, change.duration)
+                    else {
+                        report.errors.push(error(
+                            "a new event needs a task_uuid, date_began and duration",
+                        ));
+                        continue;
+                    };
+                    let notes = change.notes.flatten();
+                    if let Err(message) = check_event(date_began, duration, notes.as_deref()) {
+                        report.errors.push(error(&message));
+                        continue;
+                    }
+                    let new_event = NewTaskEvent {
+                        user_id: user_id.clone(),
+                        task_id: TaskId(task_id),
+                        date_began,
+                        duration,
+                        notes,
+                    };
+                    let Ok(new_event) = fit_event(&mut tx, policy, new_event).await? else {
+                        report.errors.push(error(OVERLAPS_OTHERS));
+                        continue;
+                    };
+                    sqlx::query(
+                        "INSERT INTO events (uuid, user_id, task_id, date_began, duration, notes, field_clock)
+                        VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                    )
+                    .bind(change.uuid)
+                    .bind(user_id.0)
+                    .bind(new_event.task_id.0)
+                    .bind(new_event.date_began)
+                    .bind(new_event.duration)
+                    .bind(new_event.notes)
+                    .bind(Json(new_clock(&EVENT_SYNC_COLUMNS, change.modified_at)))
+                    .execute(&mut *tx)
+                    .await?;
+                    report.applied += 1;
+                }
+            }
+        }
+
+        for deletion in push.deletions {
+            let table = match deletion.entity {
+                SyncEntity::Task => "tasks",
+                SyncEntity::Event => "events",
+            };
+            let select = format!(
+                "SELECT id, user_id, field_clock FROM {} WHERE uuid = $1 FOR UPDATE",
+                table
+            );
+            // deleting twice is fine
+            let Some(row) = sqlx::query(&select)
+                .bind(deletion.uuid)
+                .fetch_optional(&mut *tx)
+                .await?
+            else {
+                continue;
+            };
+            if row.get::<i32, _>("user_id") != user_id.0 {
+                report.errors.push(SyncError {
+                    entity: deletion.entity,
+                    uuid: deletion.uuid,
+                    message: UUID_TAKEN.to_string(),
+                });
+                continue;
+            }
+            // an edit made after the deletion keeps the record alive
+            let clock = row.get::<Json<FieldClock>, _>("field_clock").0;
+            if let Some(&modified_at) = clock
+                .values()
+                .max()
+                .filter(|modified_at| **modified_at > deletion.deleted_at)
+            {
+                report.conflicts.push(SyncConflict {
+                    entity: deletion.entity,
+                    uuid: deletion.uuid,
+                    field: "deleted".to_string(),
+                    server_value: false.into(),
+                    client_value: true.into(),
+                    server_modified_at: modified_at,
+                    client_modified_at: deletion.deleted_at,
+                });
+                continue;
+            }
+            let id: i32 = row.get("id");
+            if deletion.entity == SyncEntity::Task {
+                sqlx::query("DELETE FROM events WHERE task_id = $1")
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query(&format!("DELETE FROM {} WHERE id = $1", table))
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            // the trigger stamped the tombstone with now(), the start of this
+            // transaction, but the deletion happened on the client
+            sqlx::query(
+                "UPDATE tombstones SET deleted_at = $1
+                WHERE entity = $2 AND uuid = $3 AND deleted_at = now()",
+            )
+            .bind(deletion.deleted_at)
+            .bind(deletion.entity.as_str())
+            .bind(deletion.uuid)
+            .execute(&mut *tx)
+            .await?;
+            report.applied += 1;
+        }
+
+        tx.commit().await?;
+        Ok(report)
+    }
+}
+
+async fn insert_task<'e, E>(executor: E, new_task: NewTask) -> Result<Task, Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query(
+        // @todo: authenticate and authorize the user id from a session?
+        "INSERT INTO tasks (user_id, name, description, client_id, tags)
+        Values ($1, $2, $3, $4, $5)
+        RETURNING id, uuid, user_id, name, description, client_id, tags, created_on",
+    )
+    .bind(new_task.user_id.0)
+    .bind(new_task.name)
+    .bind(new_task.description.unwrap_or_default())
+    .bind(new_task.client_id.map(|client_id| client_id.0))
+    .bind(new_task.tags)
+    .map(|row: PgRow| Task {
+        id: TaskId(row.get("id")),
+        uuid: row.get("uuid"),
+        user_id: UserId(row.get("user_id")),
+        name: row.get("name"),
+        description: row.get("description"),
+        client_id: row.get::<Option<i32>, _>("client_id").map(ClientId),
+        tags: row.get("tags"),
+        created_on: row.get("created_on"),
+    })
+    .fetch_one(executor)
+    .await
+}
+
+/// Inserts an event, keeping the given uuid if the event was created elsewhere
+async fn insert_event<'e, E>(
+    executor: E,
+    new_event: NewTaskEvent,
+    uuid: Option<Uuid>,
+    ical_uid: Option<String>,
+) -> Result<TaskEvent, Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query(
+        "INSERT INTO events (user_id, task_id, date_began, duration, notes, uuid, ical_uid)
+        VALUES ($1, $2, $3, $4, $5, COALESCE($6, gen_random_uuid()), $7)
+        RETURNING id, uuid, user_id, task_id, date_began, duration, notes",
+    )
+    .bind(new_event.user_id.0)
+    .bind(new_event.task_id.0)
+    .bind(new_event.date_began)
+    .bind(new_event.duration)
+    .bind(new_event.notes)
+    .bind(uuid)
+    .bind(ical_uid)
+    .map(|row: PgRow| TaskEvent {
+        id: TaskEventId(row.get("id")),
+        uuid: row.get("uuid"),
+        user_id: UserId(row.get("user_id")),
+        task_id: TaskId(row.get("task_id")),
+        date_began: row.get("date_began"),
+        duration: row.get("duration"),
+        notes: row.get("notes"),
+    })
+    .fetch_one(executor)
+    .await
+}
+
+/// Adds an event under its user's overlap policy
+async fn add_event_under_policy(
+    connection: &mut PgConnection,
+    new_event: NewTaskEvent,
+) -> Result<EventAdded, Error> {
+    let policy = lock_overlap_policy(connection, &new_event.user_id).await?;
+    // the event can only be of one of the user's own tasks
+    sqlx::query("SELECT id FROM tasks WHERE id = $1 AND user_id = $2")
+        .bind(new_event.task_id.0)
+        .bind(new_event.user_id.0)
+        .fetch_one(&mut *connection)
+        .await?;
+    match fit_event(connection, policy, new_event).await? {
+        Ok(new_event) => {
+            let event = insert_event(connection, new_event, None, None)
+                .await
+                .inspect_err(|e| tracing::event!(tracing::Level::ERROR, "{:?}", e))?;
+            Ok(EventAdded::Added(event))
+        }
+        Err(overlapping) => Ok(EventAdded::Overlapping(overlapping)),
+    }
+}
+
+/// The user's overlap policy. The lock on the user's row holds off their
+/// other events until the transaction ends.
+async fn lock_overlap_policy(
+    connection: &mut PgConnection,
+    user_id: &UserId,
+) -> Result<OverlapPolicy, Error> {
+    let policy = sqlx::query("SELECT overlap_policy FROM users WHERE id = $1 FOR UPDATE")
+        .bind(user_id.0)
+        .map(|row: PgRow| row.get::<String, _>("overlap_policy"))
+        .fetch_one(connection)
+        .await?;
+    parse_overlap_policy(&policy)
+}
+
+/// Applies the overlap policy to a new event: the event to add, trimmed
+/// under `trim`, or the events that leave no room for it
+async fn fit_event(
+    connection: &mut PgConnection,
+    policy: OverlapPolicy,
+    new_event: NewTaskEvent,
+) -> Result<Result<NewTaskEvent, Vec<ListedEvent>>, Error> {
+    let overlapping = match policy {
+        OverlapPolicy::Allow => vec![],
+        OverlapPolicy::Reject | OverlapPolicy::Trim => {
+            overlapping_events(connection, &new_event).await?
+        }
+    };
+    Ok(apply_overlap_policy(policy, new_event, &overlapping).ok_or(overlapping))
+}
+
+/// Passes on what Postgres sends on the channel until the store is gone.
+/// The listener reconnects after losing its connection, but what was sent
+/// in the meantime is lost.
+async fn forward_notifications(mut listener: PgListener, notifier: Weak<Notifier>) {
+    loop {
+        let received = listener.try_recv().await;
+        let Some(notifier) = notifier.upgrade() else {
+            return;
+        };
+        match received {
+            Ok(Some(notification)) => notifier.send_payload(notification.payload()),
+            Ok(None) => {
+                tracing::event!(tracing::Level::WARN, "Reconnecting to listen for changes");
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "Listening for changes: {:?}", e);
+                tokio::time::sleep(LISTEN_RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+fn webhook_from_row(row: PgRow) -> Result<Webhook, Error> {
+    Ok(Webhook {
+        id: WebhookId(row.get("id")),
+        url: row.get("url"),
+        event_types: row
+            .get::<Vec<String>, _>("event_types")
+            .iter()
+            .map(|event| parse_webhook_event(event))
+            .collect::<Result<_, _>>()?,
+        secret: row.get("secret"),
+        created_on: row.get("created_on"),
+    })
+}
+
+fn delivery_from_row(row: PgRow) -> Result<WebhookDelivery, Error> {
+    Ok(WebhookDelivery {
+        id: WebhookDeliveryId(row.get("id")),
+        webhook_id: WebhookId(row.get("webhook_id")),
+        event_type: parse_webhook_event(row.get("event_type"))?,
+        payload: parse_payload(row.get("payload"))?,
+        status: parse_delivery_status(row.get("status"))?,
+        attempts: row.get("attempts"),
+        next_attempt: row.get("next_attempt"),
+        created_on: row.get("created_on"),
+        log: vec![],
+    })
+}
+
+/// Fills in the attempts at each delivery, the latest first
+async fn attach_delivery_log(
+    connection: &PgPool,
+    deliveries: &mut [WebhookDelivery],
+) -> Result<(), Error> {
+    let ids: Vec<i64> = deliveries.iter().map(|delivery| delivery.id.0).collect();
+    let attempts = sqlx::query(
+        "
+        SELECT delivery_id, attempted_on, status_code, error, duration_ms
+        FROM webhook_attempts
+        WHERE delivery_id = ANY($1)
+        ORDER BY id DESC
+        ",
+    )
+    .bind(ids)
+    .map(|row: PgRow| {
+        (
+            WebhookDeliveryId(row.get("delivery_id")),
+            WebhookAttempt {
+                attempted_on: row.get("attempted_on"),
+                status_code: row.get("status_code"),
+                error: row.get("error"),
+                duration_ms: row.get("duration_ms"),
+            },
+        )
+    })
+    .fetch_all(connection)
+    .await?;
+    attach_attempts(deliveries, attempts);
+    Ok(())
+}
+
+fn timer_from_row(row: PgRow) -> Timer {
+    Timer {
+        task_id: TaskId(row.get("task_id")),
+        started: row.get("started"),
+        notes: row.get("notes"),
+    }
+}
+
+/// The user's events covering some of the time of a new one
+async fn overlapping_events(
+    connection: &mut PgConnection,
+    new_event: &NewTaskEvent,
+) -> Result<Vec<ListedEvent>, Error> {
+    if new_event.duration <= 0 {
+        return Ok(vec![]);
+    }
+    sqlx::query(&format!(
+        "
+        {}
+        WHERE e.user_id = $1
+            AND e.duration > 0
+            AND e.date_began >= $2 - (
+                SELECT COALESCE(MAX(duration), 0) FROM events WHERE user_id = $1
+            ) * INTERVAL '1 second'
+            AND e.date_began < $2 + $3 * INTERVAL '1 second'
+            AND e.date_began + e.duration * INTERVAL '1 second' > $2
+        ORDER BY e.date_began, e.id
+        ",
+        LISTED_EVENTS_QUERY
+    ))
+    .bind(new_event.user_id.0)
+    .bind(new_event.date_began)
+    .bind(new_event.duration)
+    .map(listed_event_from_row)
+    .fetch_all(connection)
+    .await
+}
+
+fn listed_event_from_row(row: PgRow) -> ListedEvent {
+    prefixed_listed_event(&row, "")
+}
+
+/// An event of a row that holds the columns of two, told apart by `prefix`
+fn prefixed_listed_event(row: &PgRow, prefix: &str) -> ListedEvent {
+    let column = |name: &str| format!("{}{}", prefix, name);
+    ListedEvent {
+        event: TaskEvent {
+            id: TaskEventId(row.get(column("id").as_str())),
+            uuid: row.get(column("uuid").as_str()),
+            user_id: UserId(row.get(column("user_id").as_str())),
+            task_id: TaskId(row.get(column("task_id").as_str())),
+            date_began: row.get(column("date_began").as_str()),
+            duration: row.get(column("duration").as_str()),
+            notes: row.get(column("notes").as_str()),
+        },
+        task_name: row.get(column("task_name").as_str()),
+        duration_in_range: None,
+    }
+}
+
+/// A task with its totals but not its events
+fn task_summary_from_row(row: PgRow) -> TaskWithTaskEvents {
+    TaskWithTaskEvents {
+        task: Task {
+            id: TaskId(row.get("id")),
+            uuid: row.get("uuid"),
+            user_id: UserId(row.get("user_id")),
+            name: row.get("name"),
+            description: row.get("description"),
+            client_id: row.get::<Option<i32>, _>("client_id").map(ClientId),
+            tags: row.get("tags"),
+            created_on: row.get("created_on"),
+        },
+        events: vec![],
+        total_duration: row.get("total_duration"),
+        updated_on: row.get("updated_on"),
+    }
+}
+
+fn client_from_row(row: PgRow) -> Client {
+    Client {
+        id: ClientId(row.get("id")),
+        uuid: row.get("uuid"),
+        user_id: UserId(row.get("user_id")),
+        name: row.get("name"),
+        email: row.get("email"),
+        phone: row.get("phone"),
+        address: row.get("address"),
+        default_rate: row.get("default_rate"),
+        created_on: row.get("created_on"),
+    }
+}
+
+fn report_bucket_from_row(row: PgRow) -> ReportBucket {
+    ReportBucket {
+        key: row.get("key"),
+        id: row.get("id"),
+        start: row.get("start"),
+        total_duration: row.get("total_duration"),
+    }
+}
+
+/// Builds the query behind `get_report_summary`, with the parameters
+/// $1 user id, $2 from date, $3 to date, $4 time zone and $5 `date_trunc` unit
+fn report_summary_query(group_by: ReportGroupBy) -> String {
+    // the range ends at the start of the day after `to`
+    let bounds = r#"
+    WITH bounds AS (
+        SELECT
+            ($2::date)::timestamp AT TIME ZONE $4 AS range_start,
+            ($3::date + 1)::timestamp AT TIME ZONE $4 AS range_end
+    )"#;
+    match group_by.date_trunc_unit() {
+        Some(_) => format!(
+            r#"{bounds},
+    buckets AS (
+        SELECT generate_series(
+            date_trunc($5, $2::date::timestamp),
+            $3::date::timestamp,
+            ('1 ' || $5)::interval
+        ) AS bucket
+    ),
+    totals AS (
+        SELECT
+            date_trunc($5, e.date_began AT TIME ZONE $4) AS bucket,
+            SUM(e.duration) AS total_duration
+        FROM
+            events e, bounds b
+        WHERE
+            e.user_id = $1
+            AND e.date_began >= b.range_start
+            AND e.date_began < b.range_end
+        GROUP BY
+            1
+    )
+    SELECT
+        to_char(b.bucket, 'YYYY-MM-DD') AS key,
+        NULL::int AS id,
+        b.bucket AT TIME ZONE $4 AS start,
+        CAST(COALESCE(t.total_duration, 0) AS BIGINT) AS total_duration
+    FROM
+        buckets b
+    LEFT JOIN
+        totals t ON t.bucket = b.bucket
+    ORDER BY
+        b.bucket
+"#
+        ),
+        None => {
+            let (key, id, source, group) = match group_by {
+                ReportGroupBy::Task => ("t.name", "t.id", "tasks t", "t.id, t.name"),
+                ReportGroupBy::Tag => (
+                    "tag",
+                    "NULL::int",
+                    "tasks t CROSS JOIN LATERAL unnest(t.tags) AS tag",
+                    "tag",
+                ),
+                _ => (
+                    "COALESCE(c.name, 'No client')",
+                    "c.id",
+                    "tasks t LEFT JOIN clients c ON c.id = t.client_id",
+                    "c.id, c.name",
+                ),
+            };
+            format!(
+                r#"{bounds}
+    SELECT
+        {key} AS key,
+        {id} AS id,
+        NULL::timestamptz AS start,
+        CAST(COALESCE(SUM(e.duration), 0) AS BIGINT) AS total_duration
+    FROM
+        {source}
+    CROSS JOIN
+        bounds b
+    LEFT JOIN
+        events e ON e.task_id = t.id
+        AND e.user_id = $1
+        AND e.date_began >= b.range_start
+        AND e.date_began < b.range_end
+    WHERE
+        t.user_id = $1
+    GROUP BY
+        {group}
+    ORDER BY
+        total_duration DESC, key
+"#
+            )
+        }
+    }
+}
+
+/// When the user's record was deleted, if that was after `modified_at`
+async fn deleted_since(
+    connection: &mut PgConnection,
+    user_id: &UserId,
+    entity: SyncEntity,
+    uuid: Uuid,
+    modified_at: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, Error> {
+    sqlx::query(
+        "SELECT max(deleted_at) AS deleted_at
+        FROM tombstones
+        WHERE user_id = $1 AND entity = $2 AND uuid = $3",
+    )
+    .bind(user_id.0)
+    .bind(entity.as_str())
+    .bind(uuid)
+    .map(|row: PgRow| row.get::<Option<DateTime<Utc>>, _>("deleted_at"))
+    .fetch_one(connection)
+    .await
+    .map(|deleted_at| deleted_at.filter(|deleted_at| *deleted_at > modified_at))
+}
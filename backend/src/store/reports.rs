@@ -0,0 +1,187 @@
+//! Time zone arithmetic for backends whose database has no time zones.
+//!
+//! Events are selected by the UTC instants their local dates start at and
+//! bucketed here, which is what the Postgres queries do with `AT TIME ZONE`
+//! and `date_trunc`.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use sqlx::Error;
+
+use super::event_end;
+use crate::models::{ReportBucket, ReportGroupBy};
+
+/// Routes check the time zone before asking for a report, so an unknown one
+/// is not the client's fault here
+pub fn parse_timezone(tz: &str) -> Result<Tz, Error> {
+    tz.parse()
+        .map_err(|_| Error::Decode(format!("unknown time zone {}", tz).into()))
+}
+
+/// The instant a local date starts at. Where a clock change skips midnight
+/// the day starts at the first local time that exists.
+pub fn local_midnight(date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+    (0..24)
+        .find_map(|hours| {
+            tz.from_local_datetime(&(midnight + Duration::hours(hours)))
+                .earliest()
+        })
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+/// The instants the inclusive local dates `from` and `to` span, the end
+/// being the start of the day after `to`
+pub fn date_range(
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    tz: Tz,
+) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    (
+        from.map(|from| local_midnight(from, tz)),
+        to.map(|to| {
+            to.succ_opt()
+                .map_or(DateTime::<Utc>::MAX_UTC, |next| local_midnight(next, tz))
+        }),
+    )
+}
+
+/// The local date, start and end time of an event, as written in exports
+pub fn export_times(date_began: DateTime<Utc>, duration: i64, tz: Tz) -> (String, String, String) {
+    let start = date_began.with_timezone(&tz);
+    let end = event_end(date_began, duration).with_timezone(&tz);
+    (
+        start.format("%Y-%m-%d").to_string(),
+        start.format("%H:%M:%S").to_string(),
+        end.format("%H:%M:%S").to_string(),
+    )
+}
+
+/// Sums the durations of events, given as start and duration, into the day,
+/// week or month buckets from `from` to `to`. The events must already be
+/// limited to that range.
+pub fn time_buckets(
+    group_by: ReportGroupBy,
+    from: NaiveDate,
+    to: NaiveDate,
+    tz: Tz,
+    events: impl IntoIterator<Item = (DateTime<Utc>, i64)>,
+) -> Vec<ReportBucket> {
+    let mut totals: BTreeMap<NaiveDate, i64> = BTreeMap::new();
+    for (date_began, duration) in events {
+        let date = date_began.with_timezone(&tz).date_naive();
+        *totals.entry(bucket_start(group_by, date)).or_default() += duration;
+    }
+    let mut buckets = Vec::new();
+    let mut bucket = bucket_start(group_by, from);
+    while bucket <= to {
+        buckets.push(ReportBucket {
+            key: bucket.format("%Y-%m-%d").to_string(),
+            id: None,
+            start: Some(local_midnight(bucket, tz)),
+            total_duration: totals.get(&bucket).copied().unwrap_or_default(),
+        });
+        bucket = match group_by {
+            ReportGroupBy::Week => bucket + Duration::days(7),
+            ReportGroupBy::Month => bucket + Months::new(1),
+            _ => bucket + Duration::days(1),
+        };
+    }
+    buckets
+}
+
+/// Like `date_trunc`, weeks start on Monday
+fn bucket_start(group_by: ReportGroupBy, date: NaiveDate) -> NaiveDate {
+    match group_by {
+        ReportGroupBy::Week => date - Duration::days(date.weekday().num_days_from_monday().into()),
+        ReportGroupBy::Month => date.with_day(1).unwrap(),
+        _ => date,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    fn instant(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn finds_local_midnight() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        assert_eq!(
+            local_midnight(date("2023-07-01"), berlin),
+            instant("2023-06-30T22:00:00Z")
+        );
+        // Santiago skips from midnight to one o'clock in September
+        let santiago: Tz = "America/Santiago".parse().unwrap();
+        assert_eq!(
+            local_midnight(date("2023-09-03"), santiago),
+            instant("2023-09-03T04:00:00Z")
+        );
+    }
+
+    #[test]
+    fn ends_the_range_at_the_last_date() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        assert_eq!(
+            date_range(Some(date("2023-12-01")), Some(date("2023-12-31")), berlin),
+            (
+                Some(instant("2023-11-30T23:00:00Z")),
+                Some(instant("2023-12-31T23:00:00Z"))
+            )
+        );
+        // there is no day after the last one
+        assert_eq!(
+            date_range(None, Some(NaiveDate::MAX), berlin),
+            (None, Some(DateTime::<Utc>::MAX_UTC))
+        );
+    }
+
+    #[test]
+    fn buckets_weeks_from_monday() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let events = [
+            // still Sunday in UTC, but Monday in Berlin
+            (instant("2023-12-10T23:30:00Z"), 600),
+            (instant("2023-12-11T08:00:00Z"), 3600),
+        ];
+        let buckets = time_buckets(
+            ReportGroupBy::Week,
+            date("2023-12-06"),
+            date("2023-12-12"),
+            berlin,
+            events,
+        );
+        let totals: Vec<(&str, i64)> = buckets
+            .iter()
+            .map(|bucket| (bucket.key.as_str(), bucket.total_duration))
+            .collect();
+        assert_eq!(totals, vec![("2023-12-04", 0), ("2023-12-11", 4200)]);
+        assert_eq!(buckets[1].start, Some(instant("2023-12-10T23:00:00Z")));
+    }
+
+    #[test]
+    fn formats_export_times() {
+        let tokyo: Tz = "Asia/Tokyo".parse().unwrap();
+        assert_eq!(
+            export_times(instant("2023-12-31T14:30:00Z"), 5400, tokyo),
+            (
+                "2023-12-31".to_string(),
+                "23:30:00".to_string(),
+                "01:00:00".to_string()
+            )
+        );
+        // stored before durations had a maximum
+        let (_, _, end) = export_times(instant("2023-12-31T14:30:00Z"), i64::MAX, tokyo);
+        assert!(!end.is_empty());
+    }
+}
@@ -0,0 +1,2614 @@
+//! The SQLite backend, for running Time Bandit without a database server.
+//!
+//! The queries follow the Postgres ones. Tags are a JSON array, the event
+//! lists of tasks are built with `json_group_array` and whatever needs time
+//! zones is worked out in `reports`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{
+    migrate::{MigrateError, Migrator},
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteRow},
+    types::Json,
+    Error, Executor, Row, Sqlite, SqliteConnection, Transaction,
+};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::info;
+use uuid::Uuid;
+
+use super::{
+    applied_versions, apply_overlap_policy, attach_attempts, attach_events, check_password,
+    clip_events, forward_rows, hash_password, highlight, new_calendar_token, new_session_id,
+    new_webhook_secret,
+    notifications::Notifier,
+    overlap_duration, parse_delivery_status, parse_overlap_policy, parse_payload,
+    parse_webhook_event, pool_status,
+    reports::{date_range, export_times, parse_timezone, time_buckets},
+    task_order, timer_event, Activity, DueDelivery, EventAdded, EventCursor, PoolStatus, Store,
+    TaskCursor, UserNotification, EVENT_SYNC_COLUMNS, EXPORT_CHANNEL_SIZE, MATCH_END, MATCH_START,
+    OVERLAPS_OTHERS, TASK_SYNC_COLUMNS, UUID_TAKEN,
+};
+use crate::{
+    config::PoolConfig,
+    models::{
+        CalendarEvent, CalendarQuery, Client, ClientId, ClientMonthSummary, ClientSummary,
+        ClientTaskSummary, DeliveryStatus, EventExportQuery, EventExportRow, EventListQuery,
+        EventOverlap, EventOverlapQuery, FieldClock, ImportDedupe, ImportReport, ImportRow,
+        ImportRowError, IncludeEvents, ListedEvent, NewClient, NewTask, NewTaskEvent, NewWebhook,
+        OverlapPolicy, ReportBucket, ReportGroupBy, ReportQuery, ReportSummary, SearchHit,
+        SearchHitKind, SearchQuery, SessionId, StartTimer, StopTimer, SyncChanges, SyncConflict,
+        SyncEntity, SyncError, SyncEvent, SyncPush, SyncReport, SyncTask, Task, TaskEvent,
+        TaskEventId, TaskId, TaskListQuery, TaskSort, TaskWithTaskEvents, Timer, Tombstone, User,
+        UserEmail, UserId, UserSettings, Webhook, WebhookAttempt, WebhookDelivery,
+        WebhookDeliveryId, WebhookEvent, WebhookId,
+    },
+    sync::{check_event, check_task, deleted_conflict, new_clock, FieldMerge},
+    LoginDetails,
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// The columns of a webhook delivery, but for its log
+const WEBHOOK_DELIVERY_COLUMNS: &str =
+    "id, webhook_id, event_type, payload, status, attempts, next_attempt, created_on";
+
+/// How long a write waits for another connection's write to finish
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the notifications the triggers write are looked for
+const NOTIFICATION_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Events with the names of their tasks, for `get_event` and `list_events`
+const LISTED_EVENTS_QUERY: &str = "
+    SELECT
+        e.id, e.uuid, e.user_id, e.task_id, e.notes, e.date_began, e.duration,
+        t.name AS task_name
+    FROM events e
+    JOIN tasks t ON t.id = e.task_id";
+
+/// `hex(uuid)` in JSON, which cannot hold blobs; it parses as a uuid
+const TASK_WITH_EVENTS_QUERY: &str = r#"
+        SELECT
+            t.id AS task_id,
+            t.uuid AS task_uuid,
+            t.user_id AS task_user_id,
+            t.name AS task_name,
+            t.description AS task_description,
+            t.client_id AS task_client_id,
+            t.tags AS task_tags,
+            t.created_on AS task_created_on,
+
+            json_group_array(json_object(
+                'id', e.id,
+                'uuid', hex(e.uuid),
+                'user_id', e.user_id,
+                'task_id', e.task_id,
+                'notes', e.notes,
+                'date_began', e.date_began,
+                'duration', e.duration
+            )) FILTER (WHERE e.id IS NOT NULL) AS events,
+
+            COALESCE(SUM(e.duration), 0) AS total_duration,
+
+            COALESCE(MAX(e.date_began), t.created_on) AS updated_on
+
+        FROM
+            tasks t
+        LEFT JOIN
+            events e ON t.id = e.task_id
+    "#;
+
+#[derive(Clone, Debug)]
+pub struct SqliteStore {
+    pub connection: SqlitePool,
+    notifier: Arc<Notifier>,
+}
+
+impl SqliteStore {
+    pub async fn new(db_url: &str, pool: &PoolConfig) -> Result<Self, Error> {
+        let options = SqliteConnectOptions::from_str(db_url)?
+            .create_if_missing(true)
+            .foreign_keys(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(BUSY_TIMEOUT);
+        let pool_options = SqlitePoolOptions::new().acquire_timeout(pool.acquire_timeout);
+        // every connection to `sqlite::memory:` would open a database of its
+        // own, and the database is gone once its last connection closes
+        let in_memory = db_url.contains(":memory:") || db_url.contains("mode=memory");
+        let pool_options = if in_memory {
+            pool_options
+                .max_connections(1)
+                .min_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+        } else {
+            pool_options
+                .max_connections(pool.max_connections)
+                .min_connections(pool.min_connections)
+                .idle_timeout(pool.idle_timeout)
+        };
+        Ok(SqliteStore {
+            connection: pool_options.connect_with(options).await?,
+            notifier: Arc::default(),
+        })
+    }
+
+    /// Starts a transaction that holds the write lock from its first
+    /// statement. A transaction that reads before it writes fails instead of
+    /// waiting if another connection wrote in between.
+    async fn begin_write(&self) -> Result<Transaction<'static, Sqlite>, Error> {
+        let mut tx = self.connection.begin().await?;
+        sqlx::query("UPDATE sync_change_seq SET value = value")
+            .execute(&mut *tx)
+            .await?;
+        Ok(tx)
+    }
+}
+
+#[async_trait]
+impl Store for SqliteStore {
+    fn migrator(&self) -> &'static Migrator {
+        &MIGRATOR
+    }
+
+    async fn run_migrations(&self) -> Result<(), MigrateError> {
+        MIGRATOR.run(&self.connection).await
+    }
+
+    async fn undo_migrations(&self, target: i64) -> Result<(), MigrateError> {
+        MIGRATOR.undo(&self.connection, target).await
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<i64>, MigrateError> {
+        applied_versions(&mut *self.connection.acquire().await?).await
+    }
+
+    async fn probe_migrations(&self) -> Result<Vec<i64>, Error> {
+        let migrated = sqlx::query(
+            "SELECT EXISTS (
+                SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'
+            ) AS migrated",
+        )
+        .map(|row: SqliteRow| row.get::<bool, _>("migrated"))
+        .fetch_one(&self.connection)
+        .await?;
+        if !migrated {
+            return Ok(vec![]);
+        }
+        sqlx::query("SELECT version FROM _sqlx_migrations WHERE success ORDER BY version")
+            .map(|row: SqliteRow| row.get("version"))
+            .fetch_all(&self.connection)
+            .await
+    }
+
+    fn pool_status(&self) -> PoolStatus {
+        pool_status(&self.connection)
+    }
+
+    async fn register_account(&self, new_user: LoginDetails) -> Result<String, Error> {
+        match sqlx::query("INSERT INTO users (uuid, email, password) VALUES ($1, $2, $3)")
+            .bind(Uuid::new_v4())
+            .bind(new_user.email.0)
+            .bind(hash_password(new_user.password))
+            .execute(&self.connection)
+            .await
+        {
+            Ok(_) => Ok("Created account!".to_string()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_account(&self, email: UserEmail) -> Result<User, Error> {
+        match sqlx::query(
+            "SELECT id, uuid, email, password FROM users WHERE email = $1 AND disabled_at IS NULL",
+        )
+        .bind(email.0)
+        .map(|row: SqliteRow| User {
+            id: UserId(row.get("id")),
+            uuid: row.get("uuid"),
+            email: UserEmail(row.get("email")),
+            password: row.get("password"),
+        })
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(user) => Ok(user),
+            Err(e) => {
+                info!("{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_user_email(&self, user_id: UserId) -> Result<UserEmail, Error> {
+        match sqlx::query("SELECT email FROM users WHERE id = $1")
+            .bind(user_id.0)
+            .map(|row: SqliteRow| UserEmail(row.get("email")))
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(email) => Ok(email),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_user_settings(&self, user_id: UserId) -> Result<UserSettings, Error> {
+        let policy = sqlx::query("SELECT overlap_policy FROM users WHERE id = $1")
+            .bind(user_id.0)
+            .map(|row: SqliteRow| row.get::<String, _>("overlap_policy"))
+            .fetch_one(&self.connection)
+            .await?;
+        Ok(UserSettings {
+            overlap_policy: parse_overlap_policy(&policy)?,
+        })
+    }
+
+    async fn update_user_settings(
+        &self,
+        user_id: UserId,
+        settings: UserSettings,
+    ) -> Result<UserSettings, Error> {
+        sqlx::query("UPDATE users SET overlap_policy = $2 WHERE id = $1")
+            .bind(user_id.0)
+            .bind(settings.overlap_policy.as_str())
+            .execute(&self.connection)
+            .await?;
+        self.get_user_settings(user_id).await
+    }
+
+    async fn get_user_id(&self, email: UserEmail) -> Result<UserId, Error> {
+        sqlx::query("SELECT id FROM users WHERE email = $1")
+            .bind(email.0)
+            .map(|row: SqliteRow| UserId(row.get("id")))
+            .fetch_one(&self.connection)
+            .await
+    }
+
+    async fn set_password(&self, email: UserEmail, password: String) -> Result<UserId, Error> {
+        let mut tx = self.begin_write().await?;
+        let user_id = sqlx::query("UPDATE users SET password = $1 WHERE email = $2 RETURNING id")
+            .bind(hash_password(password))
+            .bind(email.0)
+            .map(|row: SqliteRow| UserId(row.get("id")))
+            .fetch_one(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+            .bind(user_id.0)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(user_id)
+    }
+
+    async fn disable_user(&self, email: UserEmail) -> Result<UserId, Error> {
+        let mut tx = self.begin_write().await?;
+        let user_id = sqlx::query(
+            "UPDATE users SET disabled_at = COALESCE(disabled_at, $1) WHERE email = $2
+            RETURNING id",
+        )
+        .bind(Utc::now())
+        .bind(email.0)
+        .map(|row: SqliteRow| UserId(row.get("id")))
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+            .bind(user_id.0)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(user_id)
+    }
+
+    async fn purge_sessions(&self) -> Result<u64, Error> {
+        match sqlx::query("DELETE FROM sessions")
+            .execute(&self.connection)
+            .await
+        {
+            Ok(res) => Ok(res.rows_affected()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn delete_user_session(&self, user_id: UserId) -> Result<UserId, Error> {
+        match sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+            .bind(user_id.0)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(_) => Ok(user_id),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn create_session(&self, user: User, password: String) -> Result<SessionId, Error> {
+        check_password(&user, password)?;
+        let session_id = new_session_id();
+
+        match sqlx::query(
+            "
+            INSERT INTO sessions (session_id, user_id)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id)
+            DO UPDATE SET session_id = excluded.session_id
+            RETURNING session_id
+            ",
+        )
+        .bind(&session_id)
+        .bind(user.id.0)
+        .map(|row: SqliteRow| SessionId(row.get("session_id")))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(session_id) => Ok(session_id),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_session_user(&self, session_id: String) -> Result<Option<UserId>, Error> {
+        sqlx::query("SELECT user_id FROM sessions WHERE session_id = $1")
+            .bind(session_id)
+            .map(|row: SqliteRow| UserId(row.get("user_id")))
+            .fetch_optional(&self.connection)
+            .await
+    }
+
+    async fn get_activity(&self) -> Result<Activity, Error> {
+        sqlx::query(
+            "SELECT (SELECT COUNT(*) FROM sessions) AS sessions,
+                (SELECT COUNT(*) FROM timers) AS running_timers",
+        )
+        .map(|row: SqliteRow| Activity {
+            sessions: row.get("sessions"),
+            running_timers: row.get("running_timers"),
+        })
+        .fetch_one(&self.connection)
+        .await
+    }
+
+    async fn add_task(&self, new_task: NewTask) -> Result<Task, Error> {
+        match insert_task(&self.connection, new_task).await {
+            Ok(task) => Ok(task),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn update_task(&self, new_task: NewTask, task_id: TaskId) -> Result<Task, Error> {
+        match sqlx::query(
+            "UPDATE tasks SET name = $1, description = $2, client_id = $5, tags = $6
+            WHERE id = $3 AND user_id = $4
+            RETURNING id, uuid, user_id, name, description, client_id, tags, created_on",
+        )
+        .bind(new_task.name)
+        .bind(new_task.description)
+        .bind(task_id.0)
+        .bind(new_task.user_id.0)
+        .bind(new_task.client_id.map(|client_id| client_id.0))
+        .bind(Json(new_task.tags))
+        .map(task_from_row)
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(task) => Ok(task),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn add_event(&self, new_event: NewTaskEvent) -> Result<EventAdded, Error> {
+        let mut tx = self.begin_write().await?;
+        let added = add_event_under_policy(&mut tx, new_event).await?;
+        if let EventAdded::Added(_) = added {
+            tx.commit().await?;
+        }
+        Ok(added)
+    }
+
+    async fn find_overlaps(
+        &self,
+        user_id: UserId,
+        query: EventOverlapQuery,
+    ) -> Result<Vec<EventOverlap>, Error> {
+        let (from, to) = date_range(query.from, query.to, parse_timezone(&query.tz)?);
+        // b begins no earlier than a, so they overlap if b begins before a
+        // ends; the overlap is from the start of b to whichever ends first
+        sqlx::query(
+            "
+            SELECT
+                a.id AS a_id, a.uuid AS a_uuid, a.user_id AS a_user_id, a.task_id AS a_task_id,
+                a.notes AS a_notes, a.date_began AS a_date_began, a.duration AS a_duration,
+                ta.name AS a_task_name,
+                b.id AS b_id, b.uuid AS b_uuid, b.user_id AS b_user_id, b.task_id AS b_task_id,
+                b.notes AS b_notes, b.date_began AS b_date_began, b.duration AS b_duration,
+                tb.name AS b_task_name
+            FROM events a
+            JOIN tasks ta ON ta.id = a.task_id
+            JOIN events b ON b.user_id = a.user_id
+                AND (strftime('%Y-%m-%dT%H:%M:%f', b.date_began), b.id)
+                    > (strftime('%Y-%m-%dT%H:%M:%f', a.date_began), a.id)
+                AND strftime('%Y-%m-%dT%H:%M:%f', b.date_began)
+                    < strftime('%Y-%m-%dT%H:%M:%f', a.date_began, a.duration || ' seconds')
+            JOIN tasks tb ON tb.id = b.task_id
+            WHERE a.user_id = $1
+                AND a.duration > 0
+                AND b.duration > 0
+                AND ($2 IS NULL OR min(
+                    strftime('%Y-%m-%dT%H:%M:%f', a.date_began, a.duration || ' seconds'),
+                    strftime('%Y-%m-%dT%H:%M:%f', b.date_began, b.duration || ' seconds')
+                ) > strftime('%Y-%m-%dT%H:%M:%f', $2))
+                AND ($3 IS NULL OR strftime('%Y-%m-%dT%H:%M:%f', b.date_began)
+                    < strftime('%Y-%m-%dT%H:%M:%f', $3))
+            ORDER BY
+                strftime('%Y-%m-%dT%H:%M:%f', b.date_began), b.id,
+                strftime('%Y-%m-%dT%H:%M:%f', a.date_began), a.id
+            ",
+        )
+        .bind(user_id.0)
+        .bind(from)
+        .bind(to)
+        .map(|row: SqliteRow| {
+            let first = prefixed_listed_event(&row, "a_");
+            let second = prefixed_listed_event(&row, "b_");
+            EventOverlap {
+                duration: overlap_duration(&first.event, &second.event),
+                first,
+                second,
+            }
+        })
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn get_timer(&self, user_id: UserId) -> Result<Option<Timer>, Error> {
+        sqlx::query("SELECT task_id, started, notes FROM timers WHERE user_id = $1")
+            .bind(user_id.0)
+            .map(timer_from_row)
+            .fetch_optional(&self.connection)
+            .await
+    }
+
+    async fn start_timer(&self, user_id: UserId, start: StartTimer) -> Result<Timer, Error> {
+        sqlx::query(
+            "INSERT INTO timers (user_id, task_id, started, notes)
+            SELECT user_id, id, $3, $4 FROM tasks WHERE id = $2 AND user_id = $1
+            RETURNING task_id, started, notes",
+        )
+        .bind(user_id.0)
+        .bind(start.task_id.0)
+        .bind(Utc::now())
+        .bind(start.notes)
+        .map(timer_from_row)
+        .fetch_one(&self.connection)
+        .await
+    }
+
+    async fn stop_timer(&self, user_id: UserId, stop: StopTimer) -> Result<EventAdded, Error> {
+        let mut tx = self.begin_write().await?;
+        let timer =
+            sqlx::query("DELETE FROM timers WHERE user_id = $1 RETURNING task_id, started, notes")
+                .bind(user_id.0)
+                .map(timer_from_row)
+                .fetch_one(&mut *tx)
+                .await?;
+        let new_event = timer_event(user_id, timer, stop);
+        let added = add_event_under_policy(&mut tx, new_event).await?;
+        if let EventAdded::Added(_) = added {
+            tx.commit().await?;
+        }
+        Ok(added)
+    }
+
+    async fn subscribe(&self) -> Result<broadcast::Receiver<UserNotification>, Error> {
+        self.notifier
+            .subscribe(|notifier| async {
+                let last_id = sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM notifications")
+                    .fetch_one(&self.connection)
+                    .await?;
+                tokio::spawn(poll_notifications(
+                    self.connection.clone(),
+                    notifier,
+                    last_id,
+                ));
+                Ok(())
+            })
+            .await
+    }
+
+    async fn add_webhook(
+        &self,
+        user_id: UserId,
+        new_webhook: NewWebhook,
+    ) -> Result<Webhook, Error> {
+        sqlx::query(
+            "INSERT INTO webhooks (user_id, url, event_types, secret)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, url, event_types, secret, created_on",
+        )
+        .bind(user_id.0)
+        .bind(new_webhook.url)
+        .bind(Json(new_webhook.event_types))
+        .bind(new_webhook_secret())
+        .map(webhook_from_row)
+        .fetch_one(&self.connection)
+        .await
+    }
+
+    async fn get_webhooks(&self, user_id: UserId) -> Result<Vec<Webhook>, Error> {
+        sqlx::query(
+            "SELECT id, url, event_types, secret, created_on
+            FROM webhooks
+            WHERE user_id = $1
+            ORDER BY id",
+        )
+        .bind(user_id.0)
+        .map(webhook_from_row)
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn delete_webhook(
+        &self,
+        webhook_id: WebhookId,
+        user_id: UserId,
+    ) -> Result<WebhookId, Error> {
+        sqlx::query("DELETE FROM webhooks WHERE id = $1 AND user_id = $2 RETURNING id")
+            .bind(webhook_id.0)
+            .bind(user_id.0)
+            .map(|row: SqliteRow| WebhookId(row.get("id")))
+            .fetch_one(&self.connection)
+            .await
+    }
+
+    async fn get_webhook_deliveries(
+        &self,
+        webhook_id: WebhookId,
+        user_id: UserId,
+        before: Option<WebhookDeliveryId>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        sqlx::query("SELECT id FROM webhooks WHERE id = $1 AND user_id = $2")
+            .bind(webhook_id.0)
+            .bind(user_id.0)
+            .fetch_one(&self.connection)
+            .await?;
+        let mut deliveries = sqlx::query(&format!(
+            "SELECT {}
+            FROM webhook_deliveries
+            WHERE webhook_id = $1 AND ($2 IS NULL OR id < $2)
+            ORDER BY id DESC
+            LIMIT $3",
+            WEBHOOK_DELIVERY_COLUMNS
+        ))
+        .bind(webhook_id.0)
+        .bind(before.map(|before| before.0))
+        .bind(limit)
+        .try_map(delivery_from_row)
+        .fetch_all(&self.connection)
+        .await?;
+        attach_delivery_log(&self.connection, &mut deliveries).await?;
+        Ok(deliveries)
+    }
+
+    async fn redeliver_webhook(
+        &self,
+        delivery_id: WebhookDeliveryId,
+        webhook_id: WebhookId,
+        user_id: UserId,
+    ) -> Result<WebhookDelivery, Error> {
+        let delivery = sqlx::query(&format!(
+            "UPDATE webhook_deliveries
+            SET status = 'pending', attempts = 0, next_attempt = $4
+            WHERE id = $1 AND webhook_id = $2
+                AND webhook_id IN (SELECT id FROM webhooks WHERE user_id = $3)
+            RETURNING {}",
+            WEBHOOK_DELIVERY_COLUMNS
+        ))
+        .bind(delivery_id.0)
+        .bind(webhook_id.0)
+        .bind(user_id.0)
+        .bind(Utc::now())
+        .try_map(delivery_from_row)
+        .fetch_one(&self.connection)
+        .await?;
+        let mut deliveries = [delivery];
+        attach_delivery_log(&self.connection, &mut deliveries).await?;
+        let [delivery] = deliveries;
+        Ok(delivery)
+    }
+
+    async fn claim_webhook_deliveries(
+        &self,
+        limit: i64,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<DueDelivery>, Error> {
+        let mut tx = self.begin_write().await?;
+        // times compared in one format, the trigger's default has fewer digits
+        let due = sqlx::query(
+            "SELECT d.id, w.url, w.secret, d.event_type, d.change, d.payload, d.attempts,
+                d.created_on
+            FROM webhook_deliveries d
+            JOIN webhooks w ON w.id = d.webhook_id
+            WHERE d.next_attempt IS NOT NULL
+                AND strftime('%Y-%m-%dT%H:%M:%f', d.next_attempt)
+                    <= strftime('%Y-%m-%dT%H:%M:%f', $1)
+            ORDER BY strftime('%Y-%m-%dT%H:%M:%f', d.next_attempt), d.id
+            LIMIT $2",
+        )
+        .bind(Utc::now())
+        .bind(limit)
+        .try_map(|row: SqliteRow| {
+            Ok(DueDelivery {
+                id: WebhookDeliveryId(row.get("id")),
+                url: row.get("url"),
+                secret: row.get("secret"),
+                event_type: parse_webhook_event(row.get("event_type"))?,
+                change: row.try_get::<Json<UserNotification>, _>("change")?.0,
+                payload: row.get("payload"),
+                attempts: row.get("attempts"),
+                created_on: row.get("created_on"),
+            })
+        })
+        .fetch_all(&mut *tx)
+        .await?;
+        let ids: Vec<i64> = due.iter().map(|delivery| delivery.id.0).collect();
+        sqlx::query(
+            "UPDATE webhook_deliveries SET next_attempt = $1
+            WHERE id IN (SELECT value FROM json_each($2))",
+        )
+        .bind(until)
+        .bind(Json(ids))
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(due)
+    }
+
+    async fn record_webhook_attempt(
+        &self,
+        delivery_id: WebhookDeliveryId,
+        payload: String,
+        attempt: WebhookAttempt,
+        status: DeliveryStatus,
+        next_attempt: Option<DateTime<Utc>>,
+    ) -> Result<(), Error> {
+        let mut tx = self.begin_write().await?;
+        let updated = sqlx::query(
+            "UPDATE webhook_deliveries
+            SET payload = $2, status = $3, attempts = attempts + 1, next_attempt = $4
+            WHERE id = $1",
+        )
+        .bind(delivery_id.0)
+        .bind(payload)
+        .bind(status.as_str())
+        .bind(next_attempt)
+        .execute(&mut *tx)
+        .await?;
+        // the webhook may have been deleted while the delivery was on its way
+        if updated.rows_affected() == 0 {
+            return Ok(());
+        }
+        sqlx::query(
+            "INSERT INTO webhook_attempts
+                (delivery_id, attempted_on, status_code, error, duration_ms)
+            VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(delivery_id.0)
+        .bind(attempt.attempted_on)
+        .bind(attempt.status_code)
+        .bind(attempt.error)
+        .bind(attempt.duration_ms)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    async fn get_tasks_by_user(&self, user_id: UserId) -> Result<Vec<Task>, Error> {
+        sqlx::query(
+            "SELECT id, uuid, user_id, name, description, client_id, tags, created_on
+            FROM tasks
+            WHERE user_id = $1",
+        )
+        .bind(user_id.0)
+        .map(task_from_row)
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn get_event(
+        &self,
+        event_id: TaskEventId,
+        user_id: UserId,
+    ) -> Result<ListedEvent, Error> {
+        sqlx::query(&format!(
+            "{} WHERE e.id = $1 AND e.user_id = $2",
+            LISTED_EVENTS_QUERY
+        ))
+        .bind(event_id.0)
+        .bind(user_id.0)
+        .map(listed_event_from_row)
+        .fetch_one(&self.connection)
+        .await
+    }
+
+    async fn list_events(
+        &self,
+        user_id: UserId,
+        query: EventListQuery,
+        after: Option<EventCursor>,
+        limit: i64,
+    ) -> Result<Vec<ListedEvent>, Error> {
+        let (from, to) = date_range(query.from, query.to, parse_timezone(&query.tz)?);
+        // Times are compared in one format, as the same instant may be
+        // written with more or fewer fractional digits. The raw comparison
+        // before that, a second short of the start less the user's longest
+        // event, holds for every event reaching into the range and is one
+        // the (user_id, date_began) index can serve.
+        let sql = format!(
+            "
+            {}
+            WHERE e.user_id = $1
+                AND ($2 IS NULL OR (
+                    e.date_began >= strftime(
+                        '%Y-%m-%dT%H:%M:%S',
+                        $2,
+                        (-1 - (
+                            SELECT COALESCE(MAX(duration), 0) FROM events WHERE user_id = $1
+                        )) || ' seconds'
+                    )
+                    AND (
+                        strftime('%Y-%m-%dT%H:%M:%f', e.date_began)
+                            >= strftime('%Y-%m-%dT%H:%M:%f', $2)
+                        OR strftime('%Y-%m-%dT%H:%M:%f', e.date_began, e.duration || ' seconds')
+                            > strftime('%Y-%m-%dT%H:%M:%f', $2)
+                    )
+                ))
+                AND ($3 IS NULL OR strftime('%Y-%m-%dT%H:%M:%f', e.date_began)
+                    < strftime('%Y-%m-%dT%H:%M:%f', $3))
+                AND ($4 IS NULL OR e.task_id = $4)
+                AND ($5 IS NULL OR EXISTS (SELECT 1 FROM json_each(t.tags) WHERE value = $5))
+                AND {}
+            ORDER BY strftime('%Y-%m-%dT%H:%M:%f', e.date_began), e.id
+            LIMIT $6
+            ",
+            LISTED_EVENTS_QUERY,
+            if after.is_some() {
+                "(strftime('%Y-%m-%dT%H:%M:%f', e.date_began), e.id)
+                    > (strftime('%Y-%m-%dT%H:%M:%f', $7), $8)"
+            } else {
+                "TRUE"
+            },
+        );
+        let page = sqlx::query(&sql)
+            .bind(user_id.0)
+            .bind(from)
+            .bind(to)
+            .bind(query.task_id.map(|task_id| task_id.0))
+            .bind(query.tag)
+            .bind(limit);
+        let page = match after {
+            None => page,
+            Some(EventCursor(began, id)) => page.bind(began).bind(id.0),
+        };
+        let mut events = page
+            .map(listed_event_from_row)
+            .fetch_all(&self.connection)
+            .await?;
+        if query.clip {
+            clip_events(&mut events, from, to);
+        }
+        Ok(events)
+    }
+
+    async fn search(
+        &self,
+        user_id: UserId,
+        query: SearchQuery,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, Error> {
+        let Some(matching) = fts5_query(&query.q) else {
+            return Ok(vec![]);
+        };
+        let (from, to) = date_range(query.from, query.to, parse_timezone(&query.tz)?);
+        // bm25 is lower for better matches
+        sqlx::query(
+            "
+            SELECT * FROM (
+                SELECT
+                    'task' AS kind,
+                    t.id AS task_id,
+                    t.name AS task_name,
+                    NULL AS event_id,
+                    NULL AS date_began,
+                    -bm25(tasks_search, 2.0, 1.0) AS rank,
+                    snippet(tasks_search, -1, $6, $7, '…', 16) AS snippet
+                FROM tasks_search
+                JOIN tasks t ON t.id = tasks_search.rowid
+                WHERE tasks_search MATCH $2
+                    AND t.user_id = $1
+                    AND ($5 IS NULL OR t.id = $5)
+                    AND (($3 IS NULL AND $4 IS NULL) OR EXISTS (
+                        SELECT 1 FROM events e
+                        WHERE e.task_id = t.id
+                            AND ($3 IS NULL OR strftime('%Y-%m-%dT%H:%M:%f', e.date_began)
+                                >= strftime('%Y-%m-%dT%H:%M:%f', $3))
+                            AND ($4 IS NULL OR strftime('%Y-%m-%dT%H:%M:%f', e.date_began)
+                                < strftime('%Y-%m-%dT%H:%M:%f', $4))
+                    ))
+                UNION ALL
+                SELECT
+                    'event',
+                    e.task_id,
+                    t.name,
+                    e.id,
+                    e.date_began,
+                    -bm25(events_search),
+                    snippet(events_search, 0, $6, $7, '…', 16)
+                FROM events_search
+                JOIN events e ON e.id = events_search.rowid
+                JOIN tasks t ON t.id = e.task_id
+                WHERE events_search MATCH $2
+                    AND e.user_id = $1
+                    AND ($5 IS NULL OR e.task_id = $5)
+                    AND ($3 IS NULL OR strftime('%Y-%m-%dT%H:%M:%f', e.date_began)
+                        >= strftime('%Y-%m-%dT%H:%M:%f', $3))
+                    AND ($4 IS NULL OR strftime('%Y-%m-%dT%H:%M:%f', e.date_began)
+                        < strftime('%Y-%m-%dT%H:%M:%f', $4))
+            )
+            ORDER BY rank DESC, date_began IS NOT NULL, date_began DESC, task_id, event_id
+            LIMIT $8
+            ",
+        )
+        .bind(user_id.0)
+        .bind(matching)
+        .bind(from)
+        .bind(to)
+        .bind(query.task_id.map(|task_id| task_id.0))
+        .bind(MATCH_START.to_string())
+        .bind(MATCH_END.to_string())
+        .bind(limit)
+        .map(|row: SqliteRow| SearchHit {
+            kind: match row.get::<&str, _>("kind") {
+                "task" => SearchHitKind::Task,
+                _ => SearchHitKind::Event,
+            },
+            task_id: TaskId(row.get("task_id")),
+            task_name: row.get("task_name"),
+            event_id: row.get::<Option<i32>, _>("event_id").map(TaskEventId),
+            date_began: row.get("date_began"),
+            rank: row.get("rank"),
+            snippet: highlight(row.get("snippet")),
+        })
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn get_task_by_id(&self, task_id: TaskId) -> Result<Task, Error> {
+        sqlx::query(
+            "SELECT id, uuid, user_id, name, description, client_id, tags, created_on
+            FROM tasks
+            WHERE id = $1",
+        )
+        .bind(task_id.0)
+        .map(task_from_row)
+        .fetch_one(&self.connection)
+        .await
+    }
+
+    async fn get_task_with_events_by_task_id(
+        &self,
+        task_id: TaskId,
+        user_id: UserId,
+    ) -> Result<TaskWithTaskEvents, Error> {
+        match sqlx::query(&format!(
+            "{} WHERE t.id = $1 AND t.user_id = $2 GROUP BY t.id",
+            TASK_WITH_EVENTS_QUERY
+        ))
+        .bind(task_id.0)
+        .bind(user_id.0)
+        .map(task_with_events_from_row)
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(task_with_events) => Ok(task_with_events),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_user_tasks_with_events(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<TaskWithTaskEvents>, Error> {
+        sqlx::query(&format!(
+            "{} WHERE t.user_id = $1 GROUP BY t.id",
+            TASK_WITH_EVENTS_QUERY
+        ))
+        .bind(user_id.0)
+        .map(task_with_events_from_row)
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn list_tasks(
+        &self,
+        user_id: UserId,
+        query: TaskListQuery,
+        after: Option<TaskCursor>,
+        limit: i64,
+    ) -> Result<Vec<TaskWithTaskEvents>, Error> {
+        let (updated_from, updated_to) = date_range(
+            query.updated_from,
+            query.updated_to,
+            parse_timezone(&query.tz)?,
+        );
+        // times are compared in one format, as the same instant may be
+        // written with more or fewer fractional digits
+        let (key, cursor_key) = match query.sort {
+            TaskSort::UpdatedOn | TaskSort::CreatedOn => (
+                format!("strftime('%Y-%m-%dT%H:%M:%f', {})", query.sort.column()),
+                "strftime('%Y-%m-%dT%H:%M:%f', $6)".to_string(),
+            ),
+            TaskSort::Name | TaskSort::TotalDuration => {
+                (query.sort.column().to_string(), "$6".to_string())
+            }
+        };
+        let (order_by, after_cursor) = task_order(query.sort, &key, &cursor_key);
+        let sql = format!(
+            "
+            WITH summaries AS (
+                SELECT
+                    t.id, t.uuid, t.user_id, t.name, t.description, t.client_id, t.tags,
+                    t.created_on,
+                    COALESCE(SUM(e.duration), 0) AS total_duration,
+                    COALESCE(MAX(e.date_began), t.created_on) AS updated_on
+                FROM tasks t
+                LEFT JOIN events e ON e.task_id = t.id
+                WHERE t.user_id = $1
+                    AND ($2 IS NULL OR instr(lower(t.name), lower($2)) > 0)
+                GROUP BY t.id
+            )
+            SELECT * FROM summaries
+            WHERE ($3 IS NULL OR updated_on >= $3)
+                AND ($4 IS NULL OR updated_on < $4)
+                AND {}
+            ORDER BY {}
+            LIMIT $5
+            ",
+            if after.is_some() {
+                &after_cursor
+            } else {
+                "TRUE"
+            },
+            order_by,
+        );
+        let page = sqlx::query(&sql)
+            .bind(user_id.0)
+            .bind(query.q)
+            .bind(updated_from)
+            .bind(updated_to)
+            .bind(limit);
+        let page = match after {
+            None => page,
+            Some(TaskCursor::UpdatedOn(at, id)) | Some(TaskCursor::CreatedOn(at, id)) => {
+                page.bind(at).bind(id.0)
+            }
+            Some(TaskCursor::Name(name, id)) => page.bind(name).bind(id.0),
+            Some(TaskCursor::TotalDuration(total, id)) => page.bind(total).bind(id.0),
+        };
+        let mut tasks: Vec<TaskWithTaskEvents> = page
+            .map(task_summary_from_row)
+            .fetch_all(&self.connection)
+            .await?;
+
+        let per_task = match query.include_events {
+            IncludeEvents::None => return Ok(tasks),
+            IncludeEvents::All => None,
+            IncludeEvents::Recent(count) => Some(i64::from(count)),
+        };
+        let task_ids = tasks.iter().map(|task| task.task.id.clone()).collect();
+        let events = self
+            .get_events_of_tasks(user_id, task_ids, per_task)
+            .await?;
+        attach_events(&mut tasks, events);
+        Ok(tasks)
+    }
+
+    async fn get_tasks_by_ids(
+        &self,
+        user_id: UserId,
+        task_ids: Vec<TaskId>,
+    ) -> Result<Vec<TaskWithTaskEvents>, Error> {
+        let task_ids: Vec<i32> = task_ids.into_iter().map(|task_id| task_id.0).collect();
+        sqlx::query(
+            "
+            SELECT
+                t.id, t.uuid, t.user_id, t.name, t.description, t.client_id, t.tags,
+                t.created_on,
+                COALESCE(SUM(e.duration), 0) AS total_duration,
+                COALESCE(MAX(e.date_began), t.created_on) AS updated_on
+            FROM tasks t
+            LEFT JOIN events e ON e.task_id = t.id
+            WHERE t.user_id = $1 AND t.id IN (SELECT value FROM json_each($2))
+            GROUP BY t.id
+            ORDER BY t.id
+            ",
+        )
+        .bind(user_id.0)
+        .bind(Json(task_ids))
+        .map(task_summary_from_row)
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn get_events_of_tasks(
+        &self,
+        user_id: UserId,
+        task_ids: Vec<TaskId>,
+        per_task: Option<i64>,
+    ) -> Result<Vec<TaskEvent>, Error> {
+        let task_ids: Vec<i32> = task_ids.into_iter().map(|task_id| task_id.0).collect();
+        sqlx::query(
+            "
+            SELECT id, uuid, user_id, task_id, notes, date_began, duration
+            FROM (
+                SELECT e.*, row_number() OVER (
+                    PARTITION BY e.task_id ORDER BY e.date_began DESC, e.id DESC
+                ) AS position
+                FROM events e
+                WHERE e.task_id IN (SELECT value FROM json_each($1)) AND e.user_id = $3
+            )
+            WHERE $2 IS NULL OR position <= $2
+            ORDER BY task_id, position
+            ",
+        )
+        .bind(Json(task_ids))
+        .bind(per_task)
+        .bind(user_id.0)
+        .map(event_from_row)
+        .fetch_all(&self.connection)
+        .await
+    }
+
+    async fn add_client(&self, new_client: NewClient) -> Result<Client, Error> {
+        match sqlx::query(
+            "INSERT INTO clients (uuid, user_id, name, email, phone, address, default_rate)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, uuid, user_id, name, email, phone, address, default_rate, created_on",
+        )
+        .bind(Uuid::new_v4())
+        .bind(new_client.user_id.0)
+        .bind(new_client.name)
+        .bind(new_client.email)
+        .bind(new_client.phone)
+        .bind(new_client.address)
+        .bind(new_client.default_rate)
+        .map(client_from_row)
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(client) => Ok(client),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_clients_by_user(&self, user_id: UserId) -> Result<Vec<Client>, Error> {
+        match sqlx::query(
+            "SELECT id, uuid, user_id, name, email, phone, address, default_rate, created_on
+            FROM clients
+            WHERE user_id = $1
+            ORDER BY name",
+        )
+        .bind(user_id.0)
+        .map(client_from_row)
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(clients) => Ok(clients),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_client(&self, client_id: ClientId, user_id: UserId) -> Result<Client, Error> {
+        match sqlx::query(
+            "SELECT id, uuid, user_id, name, email, phone, address, default_rate, created_on
+            FROM clients
+            WHERE id = $1 AND user_id = $2",
+        )
+        .bind(client_id.0)
+        .bind(user_id.0)
+        .map(client_from_row)
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(client) => Ok(client),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn update_client(
+        &self,
+        new_client: NewClient,
+        client_id: ClientId,
+    ) -> Result<Client, Error> {
+        match sqlx::query(
+            "UPDATE clients
+            SET name = $1, email = $2, phone = $3, address = $4, default_rate = $5
+            WHERE id = $6 AND user_id = $7
+            RETURNING id, uuid, user_id, name, email, phone, address, default_rate, created_on",
+        )
+        .bind(new_client.name)
+        .bind(new_client.email)
+        .bind(new_client.phone)
+        .bind(new_client.address)
+        .bind(new_client.default_rate)
+        .bind(client_id.0)
+        .bind(new_client.user_id.0)
+        .map(client_from_row)
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(client) => Ok(client),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn delete_client(&self, client_id: ClientId, user_id: UserId) -> Result<ClientId, Error> {
+        match sqlx::query("DELETE FROM clients WHERE id = $1 AND user_id = $2 RETURNING id")
+            .bind(client_id.0)
+            .bind(user_id.0)
+            .map(|row: SqliteRow| ClientId(row.get("id")))
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(client_id) => Ok(client_id),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_client_summary(
+        &self,
+        client_id: ClientId,
+        user_id: UserId,
+    ) -> Result<ClientSummary, Error> {
+        let client = self.get_client(client_id, user_id).await?;
+
+        let tasks = sqlx::query(
+            r#"
+        SELECT
+            t.id AS task_id,
+            t.name AS task_name,
+            COALESCE(SUM(e.duration), 0) AS total_duration
+        FROM
+            tasks t
+        LEFT JOIN
+            events e ON t.id = e.task_id
+        WHERE
+            t.client_id = $1 AND t.user_id = $2
+        GROUP BY
+            t.id, t.name
+        ORDER BY
+            total_duration DESC, t.name
+    "#,
+        )
+        .bind(client.id.0)
+        .bind(client.user_id.0)
+        .map(|row: SqliteRow| ClientTaskSummary {
+            task_id: TaskId(row.get("task_id")),
+            name: row.get("task_name"),
+            total_duration: row.get("total_duration"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        .map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            e
+        })?;
+
+        let months = sqlx::query(
+            r#"
+        SELECT
+            strftime('%Y-%m-01T00:00:00+00:00', e.date_began) AS month,
+            SUM(e.duration) AS total_duration
+        FROM
+            events e
+        JOIN
+            tasks t ON t.id = e.task_id
+        WHERE
+            t.client_id = $1 AND t.user_id = $2
+        GROUP BY
+            month
+        ORDER BY
+            month
+    "#,
+        )
+        .bind(client.id.0)
+        .bind(client.user_id.0)
+        .map(|row: SqliteRow| ClientMonthSummary {
+            month: row.get("month"),
+            total_duration: row.get("total_duration"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        .map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            e
+        })?;
+
+        let total_duration = tasks.iter().map(|task| task.total_duration).sum();
+        // durations are in seconds and the rate is per hour
+        let billable_amount = client.default_rate.map(|rate| total_duration * rate / 3600);
+
+        Ok(ClientSummary {
+            client,
+            tasks,
+            months,
+            total_duration,
+            billable_amount,
+        })
+    }
+
+    async fn timezone_exists(&self, tz: &str) -> Result<bool, Error> {
+        Ok(parse_timezone(tz).is_ok())
+    }
+
+    async fn get_report_summary(
+        &self,
+        user_id: UserId,
+        report: ReportQuery,
+    ) -> Result<ReportSummary, Error> {
+        let tz = parse_timezone(&report.tz)?;
+        let (Some(range_start), Some(range_end)) =
+            date_range(Some(report.from), Some(report.to), tz)
+        else {
+            unreachable!("both dates are given");
+        };
+
+        let buckets = match report.group_by.date_trunc_unit() {
+            Some(_) => {
+                let events = sqlx::query(
+                    "SELECT date_began, duration
+                    FROM events
+                    WHERE user_id = $1 AND date_began >= $2 AND date_began < $3",
+                )
+                .bind(user_id.0)
+                .bind(range_start)
+                .bind(range_end)
+                .map(|row: SqliteRow| (row.get("date_began"), row.get("duration")))
+                .fetch_all(&self.connection)
+                .await?;
+                time_buckets(report.group_by, report.from, report.to, tz, events)
+            }
+            None => sqlx::query(&report_summary_query(report.group_by))
+                .bind(user_id.0)
+                .bind(range_start)
+                .bind(range_end)
+                .map(|row: SqliteRow| ReportBucket {
+                    key: row.get("key"),
+                    id: row.get("id"),
+                    start: None,
+                    total_duration: row.get("total_duration"),
+                })
+                .fetch_all(&self.connection)
+                .await
+                .map_err(|e| {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    e
+                })?,
+        };
+
+        let total_duration = match report.group_by {
+            // a task can carry several tags, so the buckets may overlap
+            ReportGroupBy::Tag => {
+                sqlx::query(
+                    "SELECT COALESCE(SUM(duration), 0) AS total_duration
+                    FROM events
+                    WHERE user_id = $1 AND date_began >= $2 AND date_began < $3",
+                )
+                .bind(user_id.0)
+                .bind(range_start)
+                .bind(range_end)
+                .map(|row: SqliteRow| row.get("total_duration"))
+                .fetch_one(&self.connection)
+                .await?
+            }
+            _ => buckets.iter().map(|bucket| bucket.total_duration).sum(),
+        };
+
+        Ok(ReportSummary {
+            from: report.from,
+            to: report.to,
+            group_by: report.group_by,
+            tz: report.tz,
+            buckets,
+            total_duration,
+        })
+    }
+
+    fn stream_events_for_export(
+        &self,
+        user_id: UserId,
+        export: EventExportQuery,
+    ) -> ReceiverStream<Result<EventExportRow, Error>> {
+        let (sender, receiver) = mpsc::channel(EXPORT_CHANNEL_SIZE);
+        let connection = self.connection.clone();
+        tokio::spawn(async move {
+            let tz = match parse_timezone(&export.tz) {
+                Ok(tz) => tz,
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                    return;
+                }
+            };
+            let (range_start, range_end) = date_range(export.from, export.to, tz);
+            let mut rows = sqlx::query(
+                r#"
+        SELECT
+            e.id,
+            e.uuid,
+            e.task_id,
+            t.name AS task_name,
+            c.name AS client_name,
+            t.tags,
+            e.date_began,
+            e.duration,
+            e.notes
+        FROM
+            events e
+        JOIN
+            tasks t ON t.id = e.task_id
+        LEFT JOIN
+            clients c ON c.id = t.client_id
+        WHERE
+            e.user_id = $1
+            AND ($2 IS NULL OR e.date_began >= $2)
+            AND ($3 IS NULL OR e.date_began < $3)
+        ORDER BY
+            e.date_began
+    "#,
+            )
+            .bind(user_id.0)
+            .bind(range_start)
+            .bind(range_end)
+            .map(move |row: SqliteRow| {
+                let duration = row.get("duration");
+                let (date, start_time, end_time) =
+                    export_times(row.get("date_began"), duration, tz);
+                EventExportRow {
+                    id: TaskEventId(row.get("id")),
+                    uuid: row.get("uuid"),
+                    task_id: TaskId(row.get("task_id")),
+                    task_name: row.get("task_name"),
+                    client_name: row.get("client_name"),
+                    tags: row.get::<Json<Vec<String>>, _>("tags").0,
+                    date,
+                    start_time,
+                    end_time,
+                    duration,
+                    notes: row.get("notes"),
+                }
+            })
+            .fetch(&connection);
+            forward_rows(&mut rows, sender).await;
+        });
+        ReceiverStream::new(receiver)
+    }
+
+    /// The buckets are computed as a whole; there are only as many as the
+    /// report has days, tasks or tags
+    fn stream_report_summary(
+        &self,
+        user_id: UserId,
+        report: ReportQuery,
+    ) -> ReceiverStream<Result<ReportBucket, Error>> {
+        let (sender, receiver) = mpsc::channel(EXPORT_CHANNEL_SIZE);
+        let store = self.clone();
+        tokio::spawn(async move {
+            match store.get_report_summary(user_id, report).await {
+                Ok(summary) => {
+                    for bucket in summary.buckets {
+                        if sender.send(Ok(bucket)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                }
+            }
+        });
+        ReceiverStream::new(receiver)
+    }
+
+    async fn import_events(
+        &self,
+        user_id: UserId,
+        rows: Vec<ImportRow>,
+        dedupe: ImportDedupe,
+        dry_run: bool,
+    ) -> Result<ImportReport, Error> {
+        let mut tx = self.begin_write().await?;
+        let policy = overlap_policy(&mut tx, &user_id).await?;
+
+        let existing_tasks = sqlx::query("SELECT id, name, tags FROM tasks WHERE user_id = $1")
+            .bind(user_id.0)
+            .map(|row: SqliteRow| {
+                let tags: Json<Vec<String>> = row.get("tags");
+                (row.get("name"), TaskId(row.get("id")), tags.0)
+            })
+            .fetch_all(&mut *tx)
+            .await?;
+        let mut tasks: HashMap<String, TaskId> = HashMap::new();
+        let mut task_tags: HashMap<TaskId, Vec<String>> = HashMap::new();
+        for (name, task_id, tags) in existing_tasks {
+            tasks.insert(name, task_id.clone());
+            task_tags.insert(task_id, tags);
+        }
+        let mut retagged_tasks: HashSet<TaskId> = HashSet::new();
+
+        let mut clients: HashMap<String, ClientId> =
+            sqlx::query("SELECT id, name FROM clients WHERE user_id = $1")
+                .bind(user_id.0)
+                .map(|row: SqliteRow| (row.get("name"), ClientId(row.get("id"))))
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .collect();
+
+        let mut seen_uuids: HashSet<Uuid> = HashSet::new();
+        let mut seen_events: HashSet<(TaskId, DateTime<Utc>, i64)> = HashSet::new();
+        match dedupe {
+            ImportDedupe::Uuid => {
+                // there is no array to bind, but lookups by key are cheap here
+                for uuid in rows.iter().filter_map(|row| row.uuid) {
+                    let taken = sqlx::query("SELECT 1 FROM events WHERE uuid = $1")
+                        .bind(uuid)
+                        .fetch_optional(&mut *tx)
+                        .await?
+                        .is_some();
+                    if taken {
+                        seen_uuids.insert(uuid);
+                    }
+                }
+            }
+            ImportDedupe::TaskDateDuration => {
+                seen_events = sqlx::query(
+                    "SELECT task_id, date_began, duration FROM events WHERE user_id = $1",
+                )
+                .bind(user_id.0)
+                .map(|row: SqliteRow| {
+                    (
+                        TaskId(row.get("task_id")),
+                        row.get("date_began"),
+                        row.get("duration"),
+                    )
+                })
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .collect();
+            }
+            ImportDedupe::None => {}
+        }
+
+        let mut seen_ical_uids: HashSet<String> =
+            sqlx::query("SELECT ical_uid FROM events WHERE user_id = $1 AND ical_uid IS NOT NULL")
+                .bind(user_id.0)
+                .map(|row: SqliteRow| row.get("ical_uid"))
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .collect();
+
+        let mut report = ImportReport {
+            dry_run,
+            total_rows: rows.len(),
+            ..ImportReport::default()
+        };
+        for row in rows {
+            let task_id = match tasks.get(&row.task_name) {
+                Some(task_id) => task_id.clone(),
+                None => {
+                    let client_id = match &row.client_name {
+                        Some(client_name) => match clients.get(client_name) {
+                            Some(client_id) => Some(client_id.clone()),
+                            None => {
+                                let client_id: ClientId = sqlx::query(
+                                    "INSERT INTO clients (uuid, user_id, name) VALUES ($1, $2, $3)
+                                    RETURNING id",
+                                )
+                                .bind(Uuid::new_v4())
+                                .bind(user_id.0)
+                                .bind(client_name)
+                                .map(|row: SqliteRow| ClientId(row.get("id")))
+                                .fetch_one(&mut *tx)
+                                .await?;
+                                report.created_clients.push(client_name.clone());
+                                clients.insert(client_name.clone(), client_id.clone());
+                                Some(client_id)
+                            }
+                        },
+                        None => None,
+                    };
+                    let task = insert_task(
+                        &mut *tx,
+                        NewTask {
+                            user_id: user_id.clone(),
+                            name: row.task_name.clone(),
+                            description: None,
+                            client_id,
+                            tags: vec![],
+                        },
+                    )
+                    .await?;
+                    report.created_tasks.push(task.name.clone());
+                    tasks.insert(task.name, task.id.clone());
+                    task_tags.insert(task.id.clone(), vec![]);
+                    task.id
+                }
+            };
+
+            let tags = task_tags.entry(task_id.clone()).or_default();
+            for tag in &row.tags {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                    retagged_tasks.insert(task_id.clone());
+                }
+            }
+
+            let duplicate = row
+                .ical_uid
+                .as_ref()
+                .is_some_and(|ical_uid| !seen_ical_uids.insert(ical_uid.clone()))
+                || match dedupe {
+                    ImportDedupe::Uuid => row.uuid.is_some_and(|uuid| !seen_uuids.insert(uuid)),
+                    ImportDedupe::TaskDateDuration => {
+                        !seen_events.insert((task_id.clone(), row.date_began, row.duration))
+                    }
+                    ImportDedupe::None => false,
+                };
+            if duplicate {
+                report.skipped_duplicates += 1;
+                continue;
+            }
+
+            let new_event = NewTaskEvent {
+                user_id: user_id.clone(),
+                task_id,
+                date_began: row.date_began,
+                duration: row.duration,
+                notes: row.notes,
+            };
+            let Ok(new_event) = fit_event(&mut tx, policy, new_event).await? else {
+                report.errors.push(ImportRowError {
+                    row: row.row,
+                    field: "date_began".to_string(),
+                    message: OVERLAPS_OTHERS.to_string(),
+                });
+                continue;
+            };
+            insert_event(&mut *tx, new_event, row.uuid, row.ical_uid).await?;
+            report.imported += 1;
+        }
+
+        for task_id in retagged_tasks {
+            sqlx::query("UPDATE tasks SET tags = $1 WHERE id = $2")
+                .bind(Json(&task_tags[&task_id]))
+                .bind(task_id.0)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+        Ok(report)
+    }
+
+    async fn regenerate_calendar_token(&self, user_id: UserId) -> Result<String, Error> {
+        match sqlx::query(
+            "UPDATE users SET calendar_token = $1 WHERE id = $2
+            RETURNING calendar_token",
+        )
+        .bind(new_calendar_token())
+        .bind(user_id.0)
+        .map(|row: SqliteRow| row.get("calendar_token"))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(token) => Ok(token),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn get_user_by_calendar_token(&self, token: &str) -> Result<UserId, Error> {
+        sqlx::query("SELECT id FROM users WHERE calendar_token = $1 AND disabled_at IS NULL")
+            .bind(token)
+            .map(|row: SqliteRow| UserId(row.get("id")))
+            .fetch_one(&self.connection)
+            .await
+    }
+
+    async fn get_calendar_events(
+        &self,
+        user_id: UserId,
+        filter: CalendarQuery,
+    ) -> Result<Vec<CalendarEvent>, Error> {
+        match sqlx::query(
+            r#"
+        SELECT
+            e.uuid,
+            t.name AS task_name,
+            e.date_began,
+            e.duration,
+            e.notes
+        FROM
+            events e
+        JOIN
+            tasks t ON t.id = e.task_id
+        WHERE
+            e.user_id = $1
+            AND ($2 IS NULL OR e.task_id = $2)
+            AND ($3 IS NULL OR EXISTS (SELECT 1 FROM json_each(t.tags) WHERE value = $3))
+        ORDER BY
+            e.date_began
+    "#,
+        )
+        .bind(user_id.0)
+        .bind(filter.task_id.map(|task_id| task_id.0))
+        .bind(filter.tag)
+        .map(|row: SqliteRow| CalendarEvent {
+            uuid: row.get("uuid"),
+            task_name: row.get("task_name"),
+            date_began: row.get("date_began"),
+            duration: row.get("duration"),
+            notes: row.get("notes"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(events) => Ok(events),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// The reads share one transaction, which sees a single snapshot of the
+    /// database, so the returned cursor covers all of them
+    async fn get_sync_changes(&self, user_id: UserId, since: i64) -> Result<SyncChanges, Error> {
+        let mut tx = self.connection.begin().await?;
+
+        let tasks = sqlx::query(
+            "SELECT uuid, name, COALESCE(description, '') AS description, tags, field_clock, change_seq
+            FROM tasks
+            WHERE user_id = $1 AND change_seq > $2
+            ORDER BY change_seq",
+        )
+        .bind(user_id.0)
+        .bind(since)
+        .map(|row: SqliteRow| {
+            let task = SyncTask {
+                uuid: row.get("uuid"),
+                name: row.get("name"),
+                description: row.get("description"),
+                tags: row.get::<Json<Vec<String>>, _>("tags").0,
+                modified_at: row.get::<Json<FieldClock>, _>("field_clock").0,
+            };
+            (row.get::<i64, _>("change_seq"), task)
+        })
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let events = sqlx::query(
+            "SELECT e.uuid, t.uuid AS task_uuid, e.date_began, e.duration, e.notes,
+                e.field_clock, e.change_seq
+            FROM events e
+            JOIN tasks t ON t.id = e.task_id
+            WHERE e.user_id = $1 AND e.change_seq > $2
+            ORDER BY e.change_seq",
+        )
+        .bind(user_id.0)
+        .bind(since)
+        .map(|row: SqliteRow| {
+            let mut modified_at = row.get::<Json<FieldClock>, _>("field_clock").0;
+            // clients know the task by its uuid, not by the task_id column
+            if let Some(task_modified_at) = modified_at.remove("task_id") {
+                modified_at.insert("task_uuid".to_string(), task_modified_at);
+            }
+            let event = SyncEvent {
+                uuid: row.get("uuid"),
+                task_uuid: row.get("task_uuid"),
+                date_began: row.get("date_began"),
+                duration: row.get("duration"),
+                notes: row.get("notes"),
+                modified_at,
+            };
+            (row.get::<i64, _>("change_seq"), event)
+        })
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let tombstones = sqlx::query(
+            "SELECT entity, uuid, deleted_at, change_seq
+            FROM tombstones
+            WHERE user_id = $1 AND change_seq > $2
+            ORDER BY change_seq",
+        )
+        .bind(user_id.0)
+        .bind(since)
+        .map(|row: SqliteRow| {
+            let entity = match row.get::<&str, _>("entity") {
+                "task" => SyncEntity::Task,
+                _ => SyncEntity::Event,
+            };
+            let tombstone = Tombstone {
+                entity,
+                uuid: row.get("uuid"),
+                deleted_at: row.get("deleted_at"),
+            };
+            (row.get::<i64, _>("change_seq"), tombstone)
+        })
+        .fetch_all(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        let cursor = tasks
+            .iter()
+            .map(|(change_seq, _)| *change_seq)
+            .chain(events.iter().map(|(change_seq, _)| *change_seq))
+            .chain(tombstones.iter().map(|(change_seq, _)| *change_seq))
+            .fold(since, i64::max);
+        Ok(SyncChanges {
+            cursor,
+            tasks: tasks.into_iter().map(|(_, task)| task).collect(),
+            events: events.into_iter().map(|(_, event)| event).collect(),
+            tombstones: tombstones
+                .into_iter()
+                .map(|(_, tombstone)| tombstone)
+                .collect(),
+        })
+    }
+
+    /// The write lock is held throughout, which stands in for the row locks
+    /// of Postgres
+    async fn apply_sync(&self, user_id: UserId, push: SyncPush) -> Result<SyncReport, Error> {
+        let mut tx = self.begin_write().await?;
+        let policy = overlap_policy(&mut tx, &user_id).await?;
+        let mut report = SyncReport::default();
+
+        for change in push.tasks {
+            let error = |message: &str| SyncError {
+                entity: SyncEntity::Task,
+                uuid: change.uuid,
+                message: message.to_string(),
+            };
+            let existing = sqlx::query(
+                "SELECT id, user_id, name, COALESCE(description, '') AS description, tags, field_clock
+                FROM tasks
+                WHERE uuid = $1",
+            )
+            .bind(change.uuid)
+            .fetch_optional(&mut *tx)
+            .await?;
+            match existing {
+                Some(row) if row.get::<i32, _>("user_id") != user_id.0 => {
+                    report.errors.push(error(UUID_TAKEN));
+                }
+                Some(row) => {
+                    let clock = row.get::<Json<FieldClock>, _>("field_clock").0;
+                    let mut merge =
+                        FieldMerge::new(SyncEntity::Task, change.uuid, change.modified_at, clock);
+                    let name = merge.field("name", "name", row.get("name"), change.name);
+                    let description = merge.field(
+                        "description",
+                        "description",
+                        row.get("description"),
+                        change.description,
+                    );
+                    let tags = merge.field(
+                        "tags",
+                        "tags",
+                        row.get::<Json<Vec<String>>, _>("tags").0,
+                        change.tags,
+                    );
+                    report.conflicts.append(&mut merge.conflicts);
+                    if merge.written.is_empty() {
+                        continue;
+                    }
+                    if let Err(message) = check_task(&name, &description, &tags) {
+                        report.errors.push(error(&message));
+                        continue;
+                    }
+                    sqlx::query(
+                        "UPDATE tasks
+                        SET name = $1, description = $2, tags = $3,
+                            field_clock = json_patch(field_clock, $4)
+                        WHERE id = $5",
+                    )
+                    .bind(name)
+                    .bind(description)
+                    .bind(Json(tags))
+                    .bind(Json(&merge.written))
+                    .bind(row.get::<i32, _>("id"))
+                    .execute(&mut *tx)
+                    .await?;
+                    report.applied += 1;
+                }
+                None => {
+                    if let Some(deleted_at) = deleted_since(
+                        &mut tx,
+                        &user_id,
+                        SyncEntity::Task,
+                        change.uuid,
+                        change.modified_at,
+                    )
+                    .await?
+                    {
+                        report.conflicts.push(deleted_conflict(
+                            SyncEntity::Task,
+                            change.uuid,
+                            deleted_at,
+                            change.modified_at,
+                        ));
+                        continue;
+                    }
+                    let Some(name) = change.name else {
+                        report.errors.push(error("a new task needs a name"));
+                        continue;
+                    };
+                    let description = change.description.unwrap_or_default();
+                    let tags = change.tags.unwrap_or_default();
+                    if let Err(message) = check_task(&name, &description, &tags) {
+                        report.errors.push(error(&message));
+                        continue;
+                    }
+                    sqlx::query(
+                        "INSERT INTO tasks (uuid, user_id, name, description, tags, field_clock)
+                        VALUES ($1, $2, $3, $4, $5, $6)",
+                    )
+                    .bind(change.uuid)
+                    .bind(user_id.0)
+                    .bind(name)
+                    .bind(description)
+                    .bind(Json(tags))
+                    .bind(Json(new_clock(&TASK_SYNC_COLUMNS, change.modified_at)))
+                    .execute(&mut *tx)
+                    .await?;
+                    report.applied += 1;
+                }
+            }
+        }
+
+        for change in push.events {
+            let error = |message: &str| SyncError {
+                entity: SyncEntity::Event,
+                uuid: change.uuid,
+                message: message.to_string(),
+            };
+            let task_id: Option<i32> = match change.task_uuid {
+                Some(task_uuid) => {
+                    match sqlx::query("SELECT id FROM tasks WHERE uuid = $1 AND user_id = $2")
+                        .bind(task_uuid)
+                        .bind(user_id.0)
+                        .map(|row: SqliteRow| row.get("id"))
+                        .fetch_optional(&mut *tx)
+                        .await?
+                    {
+                        Some(task_id) => Some(task_id),
+                        None => {
+                            report.errors.push(error("unknown task_uuid"));
+                            continue;
+                        }
+                    }
+                }
+                None => None,
+            };
+            let existing = sqlx::query(
+                "SELECT e.id, e.user_id, e.task_id, t.uuid AS task_uuid, e.date_began, e.duration,
+                    e.notes, e.field_clock
+                FROM events e
+                LEFT JOIN tasks t ON t.id = e.task_id
+                WHERE e.uuid = $1",
+            )
+            .bind(change.uuid)
+            .fetch_optional(&mut *tx)
+            .await?;
+            match existing {
+                Some(row) if row.get::<i32, _>("user_id") != user_id.0 => {
+                    report.errors.push(error(UUID_TAKEN));
+                }
+                Some(row) => {
+                    let clock = row.get::<Json<FieldClock>, _>("field_clock").0;
+                    let mut merge =
+                        FieldMerge::new(SyncEntity::Event, change.uuid, change.modified_at, clock);
+                    merge.field(
+                        "task_uuid",
+                        "task_id",
+                        row.get::<Option<Uuid>, _>("task_uuid"),
+                        change.task_uuid.map(Some),
+                    );
+                    let task_id = match task_id {
+                        Some(task_id) if merge.wrote("task_id") => task_id,
+                        _ => row.get("task_id"),
+                    };
+                    let date_began = merge.field(
+                        "date_began",
+                        "date_began",
+                        row.get("date_began"),
+                        change.date_began,
+                    );
+                    let duration =
+                        merge.field("duration", "duration", row.get("duration"), change.duration);
+                    let notes = merge.field("notes", "notes", row.get("notes"), change.notes);
+                    report.conflicts.append(&mut merge.conflicts);
+                    if merge.written.is_empty() {
+                        continue;
+                    }
+                    if let Err(message) = check_event(date_began, duration, notes.as_deref()) {
+                        report.errors.push(error(&message));
+                        continue;
+                    }
+                    sqlx::query(
+                        "UPDATE events
+                        SET task_id = $1, date_began = $2, duration = $3, notes = $4,
+                            field_clock = json_patch(field_clock, $5)
+                        WHERE id = $6",
+                    )
+                    .bind(task_id)
+                    .bind(date_began)
+                    .bind(duration)
+                    .bind(notes)
+                    .bind(Json(&merge.written))
+                    .bind(row.get::<i32, _>("id"))
+                    .execute(&mut *tx)
+                    .await?;
+                    report.applied += 1;
+                }
+                None => {
+                    if let Some(deleted_at) = deleted_since(
+                        &mut tx,
+                        &user_id,
+                        SyncEntity::Event,
+                        change.uuid,
+                        change.modified_at,
+                    )
+                    .await?
+                    {
+                        report.conflicts.push(deleted_conflict(
+                            SyncEntity::Event,
+                            change.uuid,
+                            deleted_at,
+                            change.modified_at,
+                        ));
+                        continue;
+                    }
+                    let (Some(task_id), Some(date_began), Some(duration)) =
+                        (task_id, change.date_began, change.duration)
+                    else {
+                        report.errors.push(error(
+                            "a new event needs a task_uuid, date_began and duration",
+                        ));
+                        continue;
+                    };
+                    let notes = change.notes.flatten();
+                    if let Err(message) = check_event(date_began, duration, notes.as_deref()) {
+                        report.errors.push(error(&message));
+                        continue;
+                    }
+                    let new_event = NewTaskEvent {
+                        user_id: user_id.clone(),
+                        task_id: TaskId(task_id),
+                        date_began,
+                        duration,
+                        notes,
+                    };
+                    let Ok(new_event) = fit_event(&mut tx, policy, new_event).await? else {
+                        report.errors.push(error(OVERLAPS_OTHERS));
+                        continue;
+                    };
+                    sqlx::query(
+                        "INSERT INTO events (uuid, user_id, task_id, date_began, duration, notes, field_clock)
+                        VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                    )
+                    .bind(change.uuid)
+                    .bind(user_id.0)
+                    .bind(new_event.task_id.0)
+                    .bind(new_event.date_began)
+                    .bind(new_event.duration)
+                    .bind(new_event.notes)
+                    .bind(Json(new_clock(&EVENT_SYNC_COLUMNS, change.modified_at)))
+                    .execute(&mut *tx)
+                    .await?;
+                    report.applied += 1;
+                }
+            }
+        }
+
+        for deletion in push.deletions {
+            let table = match deletion.entity {
+                SyncEntity::Task => "tasks",
+                SyncEntity::Event => "events",
+            };
+            let select = format!(
+                "SELECT id, user_id, field_clock FROM {} WHERE uuid = $1",
+                table
+            );
+            // deleting twice is fine
+            let Some(row) = sqlx::query(&select)
+                .bind(deletion.uuid)
+                .fetch_optional(&mut *tx)
+                .await?
+            else {
+                continue;
+            };
+            if row.get::<i32, _>("user_id") != user_id.0 {
+                report.errors.push(SyncError {
+                    entity: deletion.entity,
+                    uuid: deletion.uuid,
+                    message: UUID_TAKEN.to_string(),
+                });
+                continue;
+            }
+            // an edit made after the deletion keeps the record alive
+            let clock = row.get::<Json<FieldClock>, _>("field_clock").0;
+            if let Some(&modified_at) = clock
+                .values()
+                .max()
+                .filter(|modified_at| **modified_at > deletion.deleted_at)
+            {
+                report.conflicts.push(SyncConflict {
+                    entity: deletion.entity,
+                    uuid: deletion.uuid,
+                    field: "deleted".to_string(),
+                    server_value: false.into(),
+                    client_value: true.into(),
+                    server_modified_at: modified_at,
+                    client_modified_at: deletion.deleted_at,
+                });
+                continue;
+            }
+            let id: i32 = row.get("id");
+            if deletion.entity == SyncEntity::Task {
+                sqlx::query("DELETE FROM events WHERE task_id = $1")
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query(&format!("DELETE FROM {} WHERE id = $1", table))
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            // the trigger stamped the tombstone with the current time, but
+            // the deletion happened on the client
+            sqlx::query(
+                "UPDATE tombstones SET deleted_at = $1
+                WHERE id = (SELECT max(id) FROM tombstones WHERE entity = $2 AND uuid = $3)",
+            )
+            .bind(deletion.deleted_at)
+            .bind(deletion.entity.as_str())
+            .bind(deletion.uuid)
+            .execute(&mut *tx)
+            .await?;
+            report.applied += 1;
+        }
+
+        tx.commit().await?;
+        Ok(report)
+    }
+}
+
+async fn insert_task<'e, E>(executor: E, new_task: NewTask) -> Result<Task, Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query(
+        "INSERT INTO tasks (uuid, user_id, name, description, client_id, tags)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, uuid, user_id, name, description, client_id, tags, created_on",
+    )
+    .bind(Uuid::new_v4())
+    .bind(new_task.user_id.0)
+    .bind(new_task.name)
+    .bind(new_task.description.unwrap_or_default())
+    .bind(new_task.client_id.map(|client_id| client_id.0))
+    .bind(Json(new_task.tags))
+    .map(task_from_row)
+    .fetch_one(executor)
+    .await
+}
+
+/// Inserts an event, keeping the given uuid if the event was created elsewhere
+async fn insert_event<'e, E>(
+    executor: E,
+    new_event: NewTaskEvent,
+    uuid: Option<Uuid>,
+    ical_uid: Option<String>,
+) -> Result<TaskEvent, Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query(
+        "INSERT INTO events (user_id, task_id, date_began, duration, notes, uuid, ical_uid)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, uuid, user_id, task_id, date_began, duration, notes",
+    )
+    .bind(new_event.user_id.0)
+    .bind(new_event.task_id.0)
+    .bind(new_event.date_began)
+    .bind(new_event.duration)
+    .bind(new_event.notes)
+    .bind(uuid.unwrap_or_else(Uuid::new_v4))
+    .bind(ical_uid)
+    .map(event_from_row)
+    .fetch_one(executor)
+    .await
+}
+
+/// The FTS5 query of a search written like a web search, as Postgres reads
+/// it: words and "quoted phrases" that must all appear, `or` between
+/// alternatives and `-` before words that must not. Every word is quoted, so
+/// nothing typed is taken for FTS5 syntax. `None` when nothing is left to
+/// look for.
+fn fts5_query(q: &str) -> Option<String> {
+    let mut wanted = String::new();
+    let mut unwanted = vec![];
+    let mut either = false;
+    let mut characters = q.chars().peekable();
+    while let Some(&character) = characters.peek() {
+        if character.is_whitespace() {
+            characters.next();
+            continue;
+        }
+        let negated = character == '-';
+        if negated {
+            characters.next();
+        }
+        let term: String = if characters.peek() == Some(&'"') {
+            characters.next();
+            let phrase = characters.by_ref().take_while(|&c| c != '"').collect();
+            phrase
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = characters.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                word.push(c);
+                characters.next();
+            }
+            word
+        };
+        if term.trim().is_empty() {
+            continue;
+        }
+        if !negated && term.eq_ignore_ascii_case("or") {
+            either = !wanted.is_empty();
+            continue;
+        }
+        let quoted = format!("\"{}\"", term.replace('"', "\"\""));
+        if negated {
+            unwanted.push(quoted);
+        } else {
+            if !wanted.is_empty() {
+                wanted.push_str(if either { " OR " } else { " AND " });
+            }
+            wanted.push_str(&quoted);
+            either = false;
+        }
+    }
+    if wanted.is_empty() {
+        return None;
+    }
+    for quoted in unwanted {
+        wanted = format!("({}) NOT {}", wanted, quoted);
+    }
+    Some(wanted)
+}
+
+/// A task with its totals but not its events
+fn task_summary_from_row(row: SqliteRow) -> TaskWithTaskEvents {
+    TaskWithTaskEvents {
+        total_duration: row.get("total_duration"),
+        updated_on: row.get("updated_on"),
+        events: vec![],
+        task: task_from_row(row),
+    }
+}
+
+fn task_from_row(row: SqliteRow) -> Task {
+    Task {
+        id: TaskId(row.get("id")),
+        uuid: row.get("uuid"),
+        user_id: UserId(row.get("user_id")),
+        name: row.get("name"),
+        description: row.get("description"),
+        client_id: row.get::<Option<i32>, _>("client_id").map(ClientId),
+        tags: row.get::<Json<Vec<String>>, _>("tags").0,
+        created_on: row.get("created_on"),
+    }
+}
+
+fn event_from_row(row: SqliteRow) -> TaskEvent {
+    TaskEvent {
+        id: TaskEventId(row.get("id")),
+        uuid: row.get("uuid"),
+        user_id: UserId(row.get("user_id")),
+        task_id: TaskId(row.get("task_id")),
+        date_began: row.get("date_began"),
+        duration: row.get("duration"),
+        notes: row.get("notes"),
+    }
+}
+
+/// Adds an event under its user's overlap policy, in a transaction from
+/// `begin_write` so that the user's other events wait until it ends
+async fn add_event_under_policy(
+    connection: &mut SqliteConnection,
+    new_event: NewTaskEvent,
+) -> Result<EventAdded, Error> {
+    let policy = overlap_policy(connection, &new_event.user_id).await?;
+    // the event can only be of one of the user's own tasks
+    sqlx::query("SELECT id FROM tasks WHERE id = $1 AND user_id = $2")
+        .bind(new_event.task_id.0)
+        .bind(new_event.user_id.0)
+        .fetch_one(&mut *connection)
+        .await?;
+    match fit_event(connection, policy, new_event).await? {
+        Ok(new_event) => {
+            let event = insert_event(connection, new_event, None, None)
+                .await
+                .inspect_err(|e| tracing::event!(tracing::Level::ERROR, "{:?}", e))?;
+            Ok(EventAdded::Added(event))
+        }
+        Err(overlapping) => Ok(EventAdded::Overlapping(overlapping)),
+    }
+}
+
+/// The user's overlap policy. Callers hold the write lock of `begin_write`,
+/// which stands in for the row lock of Postgres.
+async fn overlap_policy(
+    connection: &mut SqliteConnection,
+    user_id: &UserId,
+) -> Result<OverlapPolicy, Error> {
+    let policy = sqlx::query("SELECT overlap_policy FROM users WHERE id = $1")
+        .bind(user_id.0)
+        .map(|row: SqliteRow| row.get::<String, _>("overlap_policy"))
+        .fetch_one(connection)
+        .await?;
+    parse_overlap_policy(&policy)
+}
+
+/// Applies the overlap policy to a new event: the event to add, trimmed
+/// under `trim`, or the events that leave no room for it
+async fn fit_event(
+    connection: &mut SqliteConnection,
+    policy: OverlapPolicy,
+    new_event: NewTaskEvent,
+) -> Result<Result<NewTaskEvent, Vec<ListedEvent>>, Error> {
+    let overlapping = match policy {
+        OverlapPolicy::Allow => vec![],
+        OverlapPolicy::Reject | OverlapPolicy::Trim => {
+            overlapping_events(connection, &new_event).await?
+        }
+    };
+    Ok(apply_overlap_policy(policy, new_event, &overlapping).ok_or(overlapping))
+}
+
+/// Passes on what the triggers write to `notifications` after `last_id`,
+/// until the store is gone
+async fn poll_notifications(connection: SqlitePool, notifier: Weak<Notifier>, mut last_id: i64) {
+    let mut interval = tokio::time::interval(NOTIFICATION_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(notifier) = notifier.upgrade() else {
+            return;
+        };
+        let rows = sqlx::query("SELECT id, payload FROM notifications WHERE id > $1 ORDER BY id")
+            .bind(last_id)
+            .map(|row: SqliteRow| (row.get::<i64, _>("id"), row.get::<String, _>("payload")))
+            .fetch_all(&connection)
+            .await;
+        match rows {
+            Ok(rows) => {
+                for (id, payload) in rows {
+                    notifier.send_payload(&payload);
+                    last_id = id;
+                }
+            }
+            Err(e) => tracing::event!(tracing::Level::ERROR, "Polling for changes: {:?}", e),
+        }
+    }
+}
+
+fn webhook_from_row(row: SqliteRow) -> Webhook {
+    Webhook {
+        id: WebhookId(row.get("id")),
+        url: row.get("url"),
+        event_types: row.get::<Json<Vec<WebhookEvent>>, _>("event_types").0,
+        secret: row.get("secret"),
+        created_on: row.get("created_on"),
+    }
+}
+
+fn delivery_from_row(row: SqliteRow) -> Result<WebhookDelivery, Error> {
+    Ok(WebhookDelivery {
+        id: WebhookDeliveryId(row.get("id")),
+        webhook_id: WebhookId(row.get("webhook_id")),
+        event_type: parse_webhook_event(row.get("event_type"))?,
+        payload: parse_payload(row.get("payload"))?,
+        status: parse_delivery_status(row.get("status"))?,
+        attempts: row.get("attempts"),
+        next_attempt: row.get("next_attempt"),
+        created_on: row.get("created_on"),
+        log: vec![],
+    })
+}
+
+/// Fills in the attempts at each delivery, the latest first
+async fn attach_delivery_log(
+    connection: &SqlitePool,
+    deliveries: &mut [WebhookDelivery],
+) -> Result<(), Error> {
+    let ids: Vec<i64> = deliveries.iter().map(|delivery| delivery.id.0).collect();
+    let attempts = sqlx::query(
+        "SELECT delivery_id, attempted_on, status_code, error, duration_ms
+        FROM webhook_attempts
+        WHERE delivery_id IN (SELECT value FROM json_each($1))
+        ORDER BY id DESC",
+    )
+    .bind(Json(ids))
+    .map(|row: SqliteRow| {
+        (
+            WebhookDeliveryId(row.get("delivery_id")),
+            WebhookAttempt {
+                attempted_on: row.get("attempted_on"),
+                status_code: row.get("status_code"),
+                error: row.get("error"),
+                duration_ms: row.get("duration_ms"),
+            },
+        )
+    })
+    .fetch_all(connection)
+    .await?;
+    attach_attempts(deliveries, attempts);
+    Ok(())
+}
+
+fn timer_from_row(row: SqliteRow) -> Timer {
+    Timer {
+        task_id: TaskId(row.get("task_id")),
+        started: row.get("started"),
+        notes: row.get("notes"),
+    }
+}
+
+/// The user's events covering some of the time of a new one. Times are
+/// compared in one format, the raw comparison first being one the
+/// (user_id, date_began) index can serve, as in `list_events`.
+async fn overlapping_events(
+    connection: &mut SqliteConnection,
+    new_event: &NewTaskEvent,
+) -> Result<Vec<ListedEvent>, Error> {
+    if new_event.duration <= 0 {
+        return Ok(vec![]);
+    }
+    sqlx::query(&format!(
+        "
+        {}
+        WHERE e.user_id = $1
+            AND e.duration > 0
+            AND e.date_began >= strftime(
+                '%Y-%m-%dT%H:%M:%S',
+                $2,
+                (-1 - (
+                    SELECT COALESCE(MAX(duration), 0) FROM events WHERE user_id = $1
+                )) || ' seconds'
+            )
+            AND strftime('%Y-%m-%dT%H:%M:%f', e.date_began)
+                < strftime('%Y-%m-%dT%H:%M:%f', $2, $3 || ' seconds')
+            AND strftime('%Y-%m-%dT%H:%M:%f', e.date_began, e.duration || ' seconds')
+                > strftime('%Y-%m-%dT%H:%M:%f', $2)
+        ORDER BY strftime('%Y-%m-%dT%H:%M:%f', e.date_began), e.id
+        ",
+        LISTED_EVENTS_QUERY
+    ))
+    .bind(new_event.user_id.0)
+    .bind(new_event.date_began)
+    .bind(new_event.duration)
+    .map(listed_event_from_row)
+    .fetch_all(connection)
+    .await
+}
+
+fn listed_event_from_row(row: SqliteRow) -> ListedEvent {
+    ListedEvent {
+        task_name: row.get("task_name"),
+        duration_in_range: None,
+        event: event_from_row(row),
+    }
+}
+
+/// An event of a row that holds the columns of two, told apart by `prefix`
+fn prefixed_listed_event(row: &SqliteRow, prefix: &str) -> ListedEvent {
+    let column = |name: &str| format!("{}{}", prefix, name);
+    ListedEvent {
+        event: TaskEvent {
+            id: TaskEventId(row.get(column("id").as_str())),
+            uuid: row.get(column("uuid").as_str()),
+            user_id: UserId(row.get(column("user_id").as_str())),
+            task_id: TaskId(row.get(column("task_id").as_str())),
+            date_began: row.get(column("date_began").as_str()),
+            duration: row.get(column("duration").as_str()),
+            notes: row.get(column("notes").as_str()),
+        },
+        task_name: row.get(column("task_name").as_str()),
+        duration_in_range: None,
+    }
+}
+
+fn task_with_events_from_row(row: SqliteRow) -> TaskWithTaskEvents {
+    TaskWithTaskEvents {
+        task: Task {
+            id: TaskId(row.get("task_id")),
+            uuid: row.get("task_uuid"),
+            user_id: UserId(row.get("task_user_id")),
+            name: row.get("task_name"),
+            description: row.get("task_description"),
+            client_id: row.get::<Option<i32>, _>("task_client_id").map(ClientId),
+            tags: row.get::<Json<Vec<String>>, _>("task_tags").0,
+            created_on: row.get("task_created_on"),
+        },
+        events: row.get::<Json<Vec<TaskEvent>>, _>("events").0,
+        total_duration: row.get("total_duration"),
+        updated_on: row.get("updated_on"),
+    }
+}
+
+fn client_from_row(row: SqliteRow) -> Client {
+    Client {
+        id: ClientId(row.get("id")),
+        uuid: row.get("uuid"),
+        user_id: UserId(row.get("user_id")),
+        name: row.get("name"),
+        email: row.get("email"),
+        phone: row.get("phone"),
+        address: row.get("address"),
+        default_rate: row.get("default_rate"),
+        created_on: row.get("created_on"),
+    }
+}
+
+/// Builds the query behind `get_report_summary` for the groupings that are
+/// not by time, with the parameters $1 user id, $2 start and $3 end of the
+/// range
+fn report_summary_query(group_by: ReportGroupBy) -> String {
+    let (key, id, source, group) = match group_by {
+        ReportGroupBy::Task => ("t.name", "t.id", "tasks t", "t.id, t.name"),
+        ReportGroupBy::Tag => (
+            "tag.value",
+            "NULL",
+            "tasks t JOIN json_each(t.tags) AS tag",
+            "tag.value",
+        ),
+        _ => (
+            "COALESCE(c.name, 'No client')",
+            "c.id",
+            "tasks t LEFT JOIN clients c ON c.id = t.client_id",
+            "c.id, c.name",
+        ),
+    };
+    format!(
+        r#"
+    SELECT
+        {key} AS key,
+        {id} AS id,
+        COALESCE(SUM(e.duration), 0) AS total_duration
+    FROM
+        {source}
+    LEFT JOIN
+        events e ON e.task_id = t.id
+        AND e.user_id = $1
+        AND e.date_began >= $2
+        AND e.date_began < $3
+    WHERE
+        t.user_id = $1
+    GROUP BY
+        {group}
+    ORDER BY
+        total_duration DESC, key
+"#
+    )
+}
+
+/// When the user's record was deleted, if that was after `modified_at`
+async fn deleted_since(
+    connection: &mut SqliteConnection,
+    user_id: &UserId,
+    entity: SyncEntity,
+    uuid: Uuid,
+    modified_at: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, Error> {
+    sqlx::query(
+        "SELECT max(deleted_at) AS deleted_at
+        FROM tombstones
+        WHERE user_id = $1 AND entity = $2 AND uuid = $3",
+    )
+    .bind(user_id.0)
+    .bind(entity.as_str())
+    .bind(uuid)
+    .map(|row: SqliteRow| row.get::<Option<DateTime<Utc>>, _>("deleted_at"))
+    .fetch_one(connection)
+    .await
+    .map(|deleted_at| deleted_at.filter(|deleted_at| *deleted_at > modified_at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_searches_for_fts5() {
+        assert_eq!(
+            fts5_query("invoice acme").as_deref(),
+            Some(r#""invoice" AND "acme""#)
+        );
+        assert_eq!(
+            fts5_query(r#""monthly invoice" or receipt -draft"#).as_deref(),
+            Some(r#"("monthly invoice" OR "receipt") NOT "draft""#)
+        );
+        // nothing typed is FTS5 syntax
+        assert_eq!(
+            fts5_query(r#"NEAR(a b) "unclosed"#).as_deref(),
+            Some(r#""NEAR(a" AND "b)" AND "unclosed""#)
+        );
+        assert_eq!(fts5_query("-draft or"), None);
+        assert_eq!(fts5_query("  "), None);
+    }
+}
@@ -0,0 +1,1426 @@
+//! The same checks against every backend. The memory store and SQLite always
+//! run; Postgres only when `TEST_DATABASE_URL` is set, since it needs a server.
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::StreamExt;
+use serde_json::json;
+use uuid::Uuid;
+
+use super::{connect, EventAdded, EventCursor, MemoryStore, Store, TaskCursor};
+use crate::{
+    config::PoolConfig,
+    models::{
+        Deletion, DeliveryStatus, EventChange, EventListQuery, EventOverlapQuery, ImportDedupe,
+        ImportRow, IncludeEvents, NewClient, NewTask, NewTaskEvent, NewWebhook, Notification,
+        OverlapPolicy, ReportGroupBy, ReportQuery, SearchHitKind, SearchQuery, StartTimer,
+        StopTimer, SyncEntity, SyncPush, Task, TaskChange, TaskEvent, TaskListQuery, TaskSort,
+        UserEmail, UserId, UserSettings, WebhookAttempt, WebhookEvent,
+    },
+    LoginDetails,
+};
+
+async fn backends() -> Vec<Arc<dyn Store>> {
+    let pool = PoolConfig {
+        max_connections: 2,
+        min_connections: 0,
+        acquire_timeout: Duration::from_secs(10),
+        idle_timeout: None,
+    };
+    let mut urls = vec!["sqlite::memory:".to_string()];
+    urls.extend(std::env::var("TEST_DATABASE_URL").ok());
+    let mut stores: Vec<Arc<dyn Store>> = vec![Arc::new(MemoryStore::default())];
+    for url in urls {
+        let store = connect(&url, &pool).await.unwrap();
+        store.run_migrations().await.unwrap();
+        stores.push(store);
+    }
+    stores
+}
+
+fn instant(value: &str) -> DateTime<Utc> {
+    value.parse().unwrap()
+}
+
+/// Registers an account with an address no other test run uses, since the
+/// Postgres database outlives the tests
+async fn user(store: &dyn Store) -> UserId {
+    let email = UserEmail(format!("{}@example.com", Uuid::new_v4()));
+    store
+        .register_account(LoginDetails {
+            email: email.clone(),
+            password: "password1".to_string(),
+        })
+        .await
+        .unwrap();
+    store.get_user_id(email).await.unwrap()
+}
+
+fn task(user_id: &UserId, name: &str, tags: &[&str]) -> NewTask {
+    NewTask {
+        user_id: user_id.clone(),
+        name: name.to_string(),
+        description: None,
+        client_id: None,
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+    }
+}
+
+fn event(user_id: &UserId, task: &Task, date_began: &str, duration: i64) -> NewTaskEvent {
+    NewTaskEvent {
+        user_id: user_id.clone(),
+        task_id: task.id.clone(),
+        date_began: instant(date_began),
+        duration,
+        notes: Some("notes".to_string()),
+    }
+}
+
+/// The event added, when no overlap policy stood in the way
+fn added_event(added: EventAdded) -> TaskEvent {
+    match added {
+        EventAdded::Added(event) => event,
+        EventAdded::Overlapping(events) => panic!("overlaps {:?}", events),
+    }
+}
+
+#[tokio::test]
+async fn logs_in_with_the_right_password() {
+    for store in backends().await {
+        let store = &*store;
+        let email = UserEmail(format!("{}@example.com", Uuid::new_v4()));
+        let details = LoginDetails {
+            email: email.clone(),
+            password: "password1".to_string(),
+        };
+        store.register_account(details.clone()).await.unwrap();
+        // emails are unique
+        let e = store.register_account(details).await.unwrap_err();
+        assert!(e.as_database_error().unwrap().is_unique_violation());
+
+        let user = store.get_account(email.clone()).await.unwrap();
+        assert!(store
+            .create_session(user.clone(), "wrong".to_string())
+            .await
+            .is_err());
+        let session = store
+            .create_session(user.clone(), "password1".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_session_user(session.0.clone()).await.unwrap(),
+            Some(user.id.clone())
+        );
+
+        store.disable_user(email.clone()).await.unwrap();
+        assert_eq!(store.get_session_user(session.0).await.unwrap(), None);
+        assert!(store.get_account(email).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn probes_the_applied_migrations() {
+    for store in backends().await {
+        let store = &*store;
+        assert_eq!(
+            store.probe_migrations().await.unwrap(),
+            store.applied_migrations().await.unwrap()
+        );
+    }
+}
+
+/// Other tests share the Postgres database, so its counts are only known
+/// to be at least what this test adds
+#[tokio::test]
+async fn counts_sessions_timers_and_connections() {
+    for store in backends().await {
+        let store = &*store;
+        let email = UserEmail(format!("{}@example.com", Uuid::new_v4()));
+        store
+            .register_account(LoginDetails {
+                email: email.clone(),
+                password: "password1".to_string(),
+            })
+            .await
+            .unwrap();
+        let user = store.get_account(email).await.unwrap();
+        let user_id = user.id.clone();
+        store
+            .create_session(user, "password1".to_string())
+            .await
+            .unwrap();
+        let writing = store
+            .add_task(task(&user_id, "Writing", &[]))
+            .await
+            .unwrap();
+        let start = StartTimer {
+            task_id: writing.id,
+            notes: None,
+        };
+        store.start_timer(user_id, start).await.unwrap();
+
+        let activity = store.get_activity().await.unwrap();
+        assert!(activity.sessions >= 1, "{:?}", activity);
+        assert!(activity.running_timers >= 1, "{:?}", activity);
+        let pool = store.pool_status();
+        assert!(pool.idle as u32 <= pool.size, "{:?}", pool);
+        assert!(pool.size <= pool.max_size, "{:?}", pool);
+    }
+}
+
+#[tokio::test]
+async fn aggregates_the_events_of_tasks() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let writing = store
+            .add_task(task(&user_id, "Writing", &["work"]))
+            .await
+            .unwrap();
+        let idle = store.add_task(task(&user_id, "Idle", &[])).await.unwrap();
+        store
+            .add_event(event(&user_id, &writing, "2023-12-01T10:00:00Z", 600))
+            .await
+            .unwrap();
+        store
+            .add_event(event(&user_id, &writing, "2023-12-02T10:00:00Z", 1200))
+            .await
+            .unwrap();
+
+        let with_events = store
+            .get_task_with_events_by_task_id(writing.id.clone(), user_id.clone())
+            .await
+            .unwrap();
+        assert_eq!(with_events.task.tags, vec!["work"]);
+        assert_eq!(with_events.events.len(), 2);
+        assert_eq!(with_events.total_duration, 1800);
+        assert_eq!(with_events.updated_on, instant("2023-12-02T10:00:00Z"));
+        assert_eq!(with_events.events[0].notes.as_deref(), Some("notes"));
+
+        let mut tasks = store.get_user_tasks_with_events(user_id).await.unwrap();
+        tasks.sort_by_key(|task| task.task.id.0);
+        assert_eq!(tasks.len(), 2);
+        assert!(tasks[1].events.is_empty());
+        assert_eq!(tasks[1].total_duration, 0);
+        assert_eq!(tasks[1].updated_on, idle.created_on);
+    }
+}
+
+#[tokio::test]
+async fn lists_tasks_in_pages() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let mut ids = vec![];
+        for name in ["Writing", "Reading", "Editing"] {
+            let task = store.add_task(task(&user_id, name, &[])).await.unwrap();
+            store
+                .add_event(event(&user_id, &task, "2023-12-01T10:00:00Z", 60))
+                .await
+                .unwrap();
+            ids.push(task.id);
+        }
+        let query = TaskListQuery {
+            sort: TaskSort::UpdatedOn,
+            q: None,
+            updated_from: None,
+            updated_to: None,
+            tz: "UTC".to_string(),
+            include_events: IncludeEvents::None,
+            cursor: None,
+            limit: None,
+        };
+
+        // all were updated at the same time, so the latest task comes first
+        let mut listed = vec![];
+        let mut after = None;
+        loop {
+            let page = store
+                .list_tasks(user_id.clone(), query.clone(), after, 1)
+                .await
+                .unwrap();
+            let Some(task) = page.first() else {
+                break;
+            };
+            assert!(task.events.is_empty());
+            assert_eq!(task.total_duration, 60);
+            listed.push(task.task.id.clone());
+            after = Some(TaskCursor::after(TaskSort::UpdatedOn, task));
+        }
+        ids.reverse();
+        assert_eq!(listed, ids);
+    }
+}
+
+#[tokio::test]
+async fn gets_tasks_and_events_by_task_ids() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let other_id = user(store).await;
+        let writing = store
+            .add_task(task(&user_id, "Writing", &[]))
+            .await
+            .unwrap();
+        let idle = store.add_task(task(&user_id, "Idle", &[])).await.unwrap();
+        let others = store
+            .add_task(task(&other_id, "Others", &[]))
+            .await
+            .unwrap();
+        for date_began in [
+            "2023-12-01T10:00:00Z",
+            "2023-12-03T10:00:00Z",
+            "2023-12-02T10:00:00Z",
+        ] {
+            store
+                .add_event(event(&user_id, &writing, date_began, 600))
+                .await
+                .unwrap();
+        }
+        store
+            .add_event(event(&other_id, &others, "2023-12-01T10:00:00Z", 60))
+            .await
+            .unwrap();
+        let task_ids = vec![idle.id.clone(), writing.id.clone(), others.id.clone()];
+
+        // only the user's own, without their events
+        let tasks = store
+            .get_tasks_by_ids(user_id.clone(), task_ids.clone())
+            .await
+            .unwrap();
+        let found: Vec<_> = tasks.iter().map(|task| task.task.id.clone()).collect();
+        assert_eq!(found, vec![writing.id.clone(), idle.id.clone()]);
+        assert!(tasks[0].events.is_empty());
+        assert_eq!(tasks[0].total_duration, 1800);
+        assert_eq!(tasks[0].updated_on, instant("2023-12-03T10:00:00Z"));
+        assert_eq!(tasks[1].total_duration, 0);
+
+        let events = store
+            .get_events_of_tasks(user_id.clone(), task_ids.clone(), None)
+            .await
+            .unwrap();
+        let began: Vec<_> = events.iter().map(|event| event.date_began).collect();
+        assert_eq!(
+            began,
+            vec![
+                instant("2023-12-03T10:00:00Z"),
+                instant("2023-12-02T10:00:00Z"),
+                instant("2023-12-01T10:00:00Z"),
+            ]
+        );
+        let latest = store
+            .get_events_of_tasks(user_id, task_ids, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].date_began, instant("2023-12-03T10:00:00Z"));
+    }
+}
+
+#[tokio::test]
+async fn lists_events_overlapping_a_range() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let writing = store
+            .add_task(task(&user_id, "Writing", &["work"]))
+            .await
+            .unwrap();
+        let reading = store
+            .add_task(task(&user_id, "Reading", &[]))
+            .await
+            .unwrap();
+        let mut ids = vec![];
+        for (task, date_began, duration) in [
+            // before the day, the second one ending right as it starts
+            (&writing, "2023-11-30T20:00:00Z", 600),
+            (&writing, "2023-11-30T23:00:00Z", 3600),
+            // running into the day, within it and out of it
+            (&writing, "2023-11-30T23:30:00Z", 3600),
+            (&reading, "2023-12-01T10:00:00.250Z", 60),
+            (&writing, "2023-12-01T23:30:00Z", 3600),
+            // starting right as the day ends
+            (&reading, "2023-12-02T00:00:00Z", 60),
+        ] {
+            let added = store
+                .add_event(event(&user_id, task, date_began, duration))
+                .await
+                .unwrap();
+            ids.push(added_event(added).id);
+        }
+        let query = EventListQuery {
+            from: NaiveDate::from_ymd_opt(2023, 12, 1),
+            to: NaiveDate::from_ymd_opt(2023, 12, 1),
+            tz: "UTC".to_string(),
+            task_id: None,
+            tag: None,
+            clip: true,
+            cursor: None,
+            limit: None,
+        };
+
+        let mut listed = vec![];
+        let mut after = None;
+        loop {
+            let page = store
+                .list_events(user_id.clone(), query.clone(), after, 1)
+                .await
+                .unwrap();
+            let Some(event) = page.first() else {
+                break;
+            };
+            after = Some(EventCursor::after(event));
+            listed.push((
+                event.event.id.clone(),
+                event.task_name.clone(),
+                event.duration_in_range,
+            ));
+        }
+        assert_eq!(
+            listed,
+            [
+                (ids[2].clone(), "Writing".to_string(), Some(1800)),
+                (ids[3].clone(), "Reading".to_string(), Some(60)),
+                (ids[4].clone(), "Writing".to_string(), Some(1800)),
+            ]
+        );
+
+        let tagged = store
+            .list_events(
+                user_id.clone(),
+                EventListQuery {
+                    tag: Some("work".to_string()),
+                    clip: false,
+                    ..query.clone()
+                },
+                None,
+                10,
+            )
+            .await
+            .unwrap();
+        let tagged: Vec<_> = tagged.iter().map(|event| event.event.id.clone()).collect();
+        assert_eq!(tagged, [ids[2].clone(), ids[4].clone()]);
+
+        let event = store.get_event(ids[3].clone(), user_id).await.unwrap();
+        assert_eq!(event.task_name, "Reading");
+        assert_eq!(event.duration_in_range, None);
+        let other = user(store).await;
+        let e = store.get_event(ids[3].clone(), other).await.unwrap_err();
+        assert!(matches!(e, sqlx::Error::RowNotFound));
+    }
+}
+
+#[tokio::test]
+async fn applies_the_overlap_policy() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let writing = store
+            .add_task(task(&user_id, "Writing", &[]))
+            .await
+            .unwrap();
+        let settings = store.get_user_settings(user_id.clone()).await.unwrap();
+        assert_eq!(settings.overlap_policy, OverlapPolicy::Allow);
+
+        // 10:00-11:00 and 11:30-12:00
+        let morning = added_event(
+            store
+                .add_event(event(&user_id, &writing, "2023-12-01T10:00:00Z", 3600))
+                .await
+                .unwrap(),
+        );
+        store
+            .add_event(event(&user_id, &writing, "2023-12-01T11:30:00Z", 1800))
+            .await
+            .unwrap();
+        // allowed while the policy is the default
+        let allowed = added_event(
+            store
+                .add_event(event(&user_id, &writing, "2023-12-01T10:30:00Z", 600))
+                .await
+                .unwrap(),
+        );
+
+        let settings = UserSettings {
+            overlap_policy: OverlapPolicy::Reject,
+        };
+        store
+            .update_user_settings(user_id.clone(), settings)
+            .await
+            .unwrap();
+        let rejected = store
+            .add_event(event(&user_id, &writing, "2023-12-01T10:50:00Z", 3600))
+            .await
+            .unwrap();
+        let EventAdded::Overlapping(overlapping) = rejected else {
+            panic!("rejected: {:?}", rejected);
+        };
+        let overlapping: Vec<_> = overlapping.iter().map(|e| e.event.date_began).collect();
+        assert_eq!(
+            overlapping,
+            [
+                instant("2023-12-01T10:00:00Z"),
+                instant("2023-12-01T11:30:00Z")
+            ]
+        );
+        // touching is not overlapping
+        let touching = store
+            .add_event(event(&user_id, &writing, "2023-12-01T12:00:00Z", 60))
+            .await
+            .unwrap();
+        assert!(matches!(touching, EventAdded::Added(_)));
+
+        let settings = UserSettings {
+            overlap_policy: OverlapPolicy::Trim,
+        };
+        store
+            .update_user_settings(user_id.clone(), settings)
+            .await
+            .unwrap();
+        // 9:50-12:10 is left 11:00-11:30 as the longest stretch that is free
+        let trimmed = added_event(
+            store
+                .add_event(event(&user_id, &writing, "2023-12-01T09:50:00Z", 8400))
+                .await
+                .unwrap(),
+        );
+        assert_eq!(trimmed.date_began, instant("2023-12-01T11:00:00Z"));
+        assert_eq!(trimmed.duration, 1800);
+        // nothing is left of one covered entirely
+        let covered = store
+            .add_event(event(&user_id, &writing, "2023-12-01T10:15:00Z", 60))
+            .await
+            .unwrap();
+        assert!(matches!(covered, EventAdded::Overlapping(events) if events.len() == 1));
+
+        let query = EventOverlapQuery {
+            from: None,
+            to: None,
+            tz: "UTC".to_string(),
+        };
+        let overlaps = store
+            .find_overlaps(user_id.clone(), query.clone())
+            .await
+            .unwrap();
+        let overlaps: Vec<_> = overlaps
+            .iter()
+            .map(|o| {
+                (
+                    o.first.event.id.clone(),
+                    o.second.event.id.clone(),
+                    o.duration,
+                )
+            })
+            .collect();
+        assert_eq!(overlaps, [(morning.id.clone(), allowed.id.clone(), 600)]);
+        let later = store
+            .find_overlaps(
+                user_id.clone(),
+                EventOverlapQuery {
+                    from: NaiveDate::from_ymd_opt(2023, 12, 2),
+                    ..query.clone()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(later.is_empty());
+        let other = user(store).await;
+        assert!(store.find_overlaps(other, query).await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn runs_timers_and_notifies_changes() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let mut notifications = store.subscribe().await.unwrap();
+        let writing = store
+            .add_task(task(&user_id, "Writing", &[]))
+            .await
+            .unwrap();
+        let start = StartTimer {
+            task_id: writing.id.clone(),
+            notes: Some("notes".to_string()),
+        };
+
+        let timer = store
+            .start_timer(user_id.clone(), start.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_timer(user_id.clone()).await.unwrap(),
+            Some(timer.clone())
+        );
+        let e = store
+            .start_timer(user_id.clone(), start.clone())
+            .await
+            .unwrap_err();
+        assert!(e.as_database_error().unwrap().is_unique_violation());
+        let other = user(store).await;
+        let e = store.start_timer(other, start.clone()).await.unwrap_err();
+        assert!(matches!(e, sqlx::Error::RowNotFound));
+
+        // an event covering the time the timer ran is in the way
+        let settings = UserSettings {
+            overlap_policy: OverlapPolicy::Reject,
+        };
+        store
+            .update_user_settings(user_id.clone(), settings)
+            .await
+            .unwrap();
+        let mut covering = event(&user_id, &writing, "2023-12-01T10:00:00Z", 7200);
+        covering.date_began = Utc::now() - chrono::Duration::hours(1);
+        let covering = added_event(store.add_event(covering).await.unwrap());
+        // events shorter than a second overlap nothing
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let stopped = store
+            .stop_timer(user_id.clone(), StopTimer::default())
+            .await
+            .unwrap();
+        assert!(matches!(stopped, EventAdded::Overlapping(events) if events.len() == 1));
+        assert!(store.get_timer(user_id.clone()).await.unwrap().is_some());
+
+        let settings = UserSettings {
+            overlap_policy: OverlapPolicy::Allow,
+        };
+        store
+            .update_user_settings(user_id.clone(), settings)
+            .await
+            .unwrap();
+        let stop = StopTimer {
+            notes: Some("more notes".to_string()),
+        };
+        let stopped = added_event(store.stop_timer(user_id.clone(), stop).await.unwrap());
+        assert_eq!(stopped.task_id, writing.id);
+        assert_eq!(stopped.notes.as_deref(), Some("more notes"));
+        assert_eq!(store.get_timer(user_id.clone()).await.unwrap(), None);
+        let e = store
+            .stop_timer(user_id.clone(), StopTimer::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(e, sqlx::Error::RowNotFound));
+
+        let mut renamed = task(&user_id, "Drafting", &[]);
+        renamed.description = Some(writing.description.clone());
+        store
+            .update_task(renamed, writing.id.clone())
+            .await
+            .unwrap();
+
+        let mut received = vec![];
+        while received.len() < 6 {
+            let notification = tokio::time::timeout(Duration::from_secs(5), notifications.recv())
+                .await
+                .expect("no notification within 5s")
+                .unwrap();
+            if notification.user_id == user_id {
+                received.push(notification.notification);
+            }
+        }
+        // the backends may tell of the stopped timer and its event in either
+        // order
+        received[3..5].sort_by_key(|notification| notification.kind());
+        assert_eq!(
+            received,
+            [
+                Notification::TaskCreated {
+                    id: writing.id.clone()
+                },
+                Notification::TimerStarted {
+                    task_id: writing.id.clone(),
+                    started: timer.started,
+                },
+                Notification::EventCreated {
+                    id: covering.id,
+                    task_id: writing.id.clone(),
+                },
+                Notification::EventCreated {
+                    id: stopped.id,
+                    task_id: writing.id.clone(),
+                },
+                Notification::TimerStopped {
+                    task_id: writing.id.clone()
+                },
+                Notification::TaskUpdated { id: writing.id },
+            ]
+        );
+    }
+}
+
+#[tokio::test]
+async fn queues_and_logs_webhook_deliveries() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let other = user(store).await;
+        let hook = |path: &str| NewWebhook {
+            url: format!("http://localhost/{}/{}", Uuid::new_v4(), path),
+            event_types: vec![WebhookEvent::TaskCreated, WebhookEvent::EventCreated],
+        };
+        let webhook = store
+            .add_webhook(user_id.clone(), hook("mine"))
+            .await
+            .unwrap();
+        store
+            .add_webhook(other.clone(), hook("theirs"))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_webhooks(user_id.clone()).await.unwrap(),
+            std::slice::from_ref(&webhook)
+        );
+
+        let writing = store
+            .add_task(task(&user_id, "Writing", &[]))
+            .await
+            .unwrap();
+        // not an event type the webhook wants
+        let mut renamed = task(&user_id, "Drafting", &[]);
+        renamed.description = Some(writing.description.clone());
+        store
+            .update_task(renamed, writing.id.clone())
+            .await
+            .unwrap();
+        let drafting = added_event(
+            store
+                .add_event(event(&user_id, &writing, "2023-12-01T10:00:00Z", 3600))
+                .await
+                .unwrap(),
+        );
+
+        // other tests on the same database may have deliveries due
+        let claim = || async {
+            let until = Utc::now() + chrono::Duration::minutes(1);
+            let mut claimed = store.claim_webhook_deliveries(1000, until).await.unwrap();
+            claimed.retain(|delivery| delivery.url == webhook.url);
+            claimed
+        };
+        let claimed = claim().await;
+        let changes: Vec<_> = claimed
+            .iter()
+            .map(|delivery| (delivery.event_type, delivery.change.notification.clone()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (
+                    WebhookEvent::TaskCreated,
+                    Notification::TaskCreated {
+                        id: writing.id.clone()
+                    }
+                ),
+                (
+                    WebhookEvent::EventCreated,
+                    Notification::EventCreated {
+                        id: drafting.id.clone(),
+                        task_id: writing.id.clone()
+                    }
+                ),
+            ]
+        );
+        assert!(claimed
+            .iter()
+            .all(|delivery| delivery.secret == webhook.secret
+                && delivery.payload.is_none()
+                && delivery.attempts == 0));
+        // held by the claim
+        assert!(claim().await.is_empty());
+
+        let created = claimed[0].id.clone();
+        let attempt = |status_code| WebhookAttempt {
+            attempted_on: instant("2023-12-01T10:00:00Z"),
+            status_code,
+            error: None,
+            duration_ms: 12,
+        };
+        store
+            .record_webhook_attempt(
+                created.clone(),
+                r#"{"sent":1}"#.to_string(),
+                attempt(None),
+                DeliveryStatus::Pending,
+                Some(Utc::now() - chrono::Duration::seconds(1)),
+            )
+            .await
+            .unwrap();
+        let claimed = claim().await;
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].id, created);
+        assert_eq!(claimed[0].payload.as_deref(), Some(r#"{"sent":1}"#));
+        assert_eq!(claimed[0].attempts, 1);
+        store
+            .record_webhook_attempt(
+                created.clone(),
+                r#"{"sent":1}"#.to_string(),
+                attempt(Some(204)),
+                DeliveryStatus::Delivered,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let deliveries = store
+            .get_webhook_deliveries(webhook.id.clone(), user_id.clone(), None, 10)
+            .await
+            .unwrap();
+        assert_eq!(deliveries.len(), 2);
+        let delivered = &deliveries[1];
+        assert_eq!(delivered.id, created);
+        assert_eq!(delivered.status, DeliveryStatus::Delivered);
+        assert_eq!(delivered.attempts, 2);
+        assert_eq!(delivered.next_attempt, None);
+        assert_eq!(delivered.payload, Some(json!({"sent": 1})));
+        assert_eq!(delivered.log, [attempt(Some(204)), attempt(None)]);
+        assert_eq!(deliveries[0].status, DeliveryStatus::Pending);
+        assert!(deliveries[0].log.is_empty());
+        let older = store
+            .get_webhook_deliveries(
+                webhook.id.clone(),
+                user_id.clone(),
+                Some(deliveries[0].id.clone()),
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(older.len(), 1);
+        assert_eq!(older[0].id, created);
+
+        let e = store
+            .get_webhook_deliveries(webhook.id.clone(), other.clone(), None, 10)
+            .await
+            .unwrap_err();
+        assert!(matches!(e, sqlx::Error::RowNotFound));
+        let e = store
+            .redeliver_webhook(created.clone(), webhook.id.clone(), other.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(e, sqlx::Error::RowNotFound));
+        let redelivered = store
+            .redeliver_webhook(created.clone(), webhook.id.clone(), user_id.clone())
+            .await
+            .unwrap();
+        assert_eq!(redelivered.status, DeliveryStatus::Pending);
+        assert_eq!(redelivered.attempts, 0);
+        assert_eq!(redelivered.log.len(), 2);
+        let claimed = claim().await;
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].id, created);
+
+        store
+            .delete_webhook(webhook.id.clone(), user_id.clone())
+            .await
+            .unwrap();
+        assert!(store
+            .get_webhooks(user_id.clone())
+            .await
+            .unwrap()
+            .is_empty());
+        store
+            .add_task(task(&user_id, "Editing", &[]))
+            .await
+            .unwrap();
+        let e = store
+            .get_webhook_deliveries(webhook.id, user_id, None, 10)
+            .await
+            .unwrap_err();
+        assert!(matches!(e, sqlx::Error::RowNotFound));
+    }
+}
+
+#[tokio::test]
+async fn searches_tasks_and_notes() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let billing = store
+            .add_task(NewTask {
+                description: Some("Monthly invoice for <Acme>".to_string()),
+                ..task(&user_id, "Billing", &[])
+            })
+            .await
+            .unwrap();
+        let reading = store
+            .add_task(task(&user_id, "Reading", &[]))
+            .await
+            .unwrap();
+        let mut read = event(&user_id, &reading, "2023-12-01T10:00:00Z", 600);
+        read.notes = Some("Read up on the invoice format".to_string());
+        let read = added_event(store.add_event(read).await.unwrap());
+        let mut sent = event(&user_id, &billing, "2023-12-05T10:00:00Z", 600);
+        sent.notes = Some("Sent the invoice & receipt".to_string());
+        let sent = added_event(store.add_event(sent).await.unwrap());
+        let other = user(store).await;
+        store
+            .add_task(task(&other, "Someone else's invoice", &[]))
+            .await
+            .unwrap();
+        let query = SearchQuery {
+            q: "invoice".to_string(),
+            from: None,
+            to: None,
+            tz: "UTC".to_string(),
+            task_id: None,
+            limit: None,
+        };
+
+        let hits = store
+            .search(user_id.clone(), query.clone(), 10)
+            .await
+            .unwrap();
+        let mut found: Vec<_> = hits
+            .iter()
+            .map(|hit| (hit.kind, hit.task_id.clone(), hit.event_id.clone()))
+            .collect();
+        found.sort_by_key(|(_, task_id, event_id)| (task_id.0, event_id.clone().map(|id| id.0)));
+        assert_eq!(
+            found,
+            [
+                (SearchHitKind::Task, billing.id.clone(), None),
+                (
+                    SearchHitKind::Event,
+                    billing.id.clone(),
+                    Some(sent.id.clone())
+                ),
+                (
+                    SearchHitKind::Event,
+                    reading.id.clone(),
+                    Some(read.id.clone())
+                ),
+            ]
+        );
+        assert!(hits.iter().all(|hit| hit.rank > 0.0));
+        let snippet = |kind| &hits.iter().find(|hit| hit.kind == kind).unwrap().snippet;
+        assert!(snippet(SearchHitKind::Task).contains("<mark>invoice</mark> for &lt;Acme&gt;"));
+
+        let in_range = store
+            .search(
+                user_id.clone(),
+                SearchQuery {
+                    from: NaiveDate::from_ymd_opt(2023, 12, 5),
+                    to: NaiveDate::from_ymd_opt(2023, 12, 5),
+                    ..query.clone()
+                },
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(in_range.len(), 2);
+        assert!(in_range.iter().all(|hit| hit.task_id == billing.id));
+        let sent_hit = in_range
+            .iter()
+            .find(|hit| hit.kind == SearchHitKind::Event)
+            .unwrap();
+        assert_eq!(sent_hit.task_name, "Billing");
+        assert!(sent_hit
+            .snippet
+            .contains("<mark>invoice</mark> &amp; receipt"));
+
+        let of_task = store
+            .search(
+                user_id.clone(),
+                SearchQuery {
+                    task_id: Some(reading.id.clone()),
+                    ..query.clone()
+                },
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(of_task.len(), 1);
+        assert_eq!(of_task[0].event_id, Some(read.id.clone()));
+
+        let without = store
+            .search(
+                user_id.clone(),
+                SearchQuery {
+                    q: "invoice -receipt".to_string(),
+                    ..query.clone()
+                },
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(without.len(), 2);
+        assert!(without
+            .iter()
+            .all(|hit| hit.event_id != Some(sent.id.clone())));
+    }
+}
+
+#[tokio::test]
+async fn rejects_events_of_missing_and_foreign_tasks() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let mut missing = store.add_task(task(&user_id, "Gone", &[])).await.unwrap();
+        missing.id.0 = -1;
+        let e = store
+            .add_event(event(&user_id, &missing, "2023-12-01T10:00:00Z", 600))
+            .await
+            .unwrap_err();
+        assert!(matches!(e, sqlx::Error::RowNotFound));
+
+        // nor can events be added to the tasks of other users
+        let other = user(store).await;
+        let theirs = store.add_task(task(&other, "Theirs", &[])).await.unwrap();
+        let e = store
+            .add_event(event(&user_id, &theirs, "2023-12-01T10:00:00Z", 600))
+            .await
+            .unwrap_err();
+        assert!(matches!(e, sqlx::Error::RowNotFound));
+        let e = store
+            .get_task_with_events_by_task_id(theirs.id, user_id)
+            .await
+            .unwrap_err();
+        assert!(matches!(e, sqlx::Error::RowNotFound));
+    }
+}
+
+#[tokio::test]
+async fn summarizes_clients() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let client = store
+            .add_client(NewClient {
+                user_id: user_id.clone(),
+                name: "Acme".to_string(),
+                email: None,
+                phone: None,
+                address: None,
+                default_rate: Some(60),
+            })
+            .await
+            .unwrap();
+        let design = store
+            .add_task(NewTask {
+                client_id: Some(client.id.clone()),
+                ..task(&user_id, "Design", &[])
+            })
+            .await
+            .unwrap();
+        store
+            .add_event(event(&user_id, &design, "2023-11-30T10:00:00Z", 3600))
+            .await
+            .unwrap();
+        store
+            .add_event(event(&user_id, &design, "2023-12-01T10:00:00Z", 3600))
+            .await
+            .unwrap();
+
+        let summary = store
+            .get_client_summary(client.id.clone(), user_id.clone())
+            .await
+            .unwrap();
+        assert_eq!(summary.total_duration, 7200);
+        assert_eq!(summary.billable_amount, Some(120));
+        let months: Vec<_> = summary
+            .months
+            .iter()
+            .map(|month| (month.month, month.total_duration))
+            .collect();
+        assert_eq!(
+            months,
+            vec![
+                (instant("2023-11-01T00:00:00Z"), 3600),
+                (instant("2023-12-01T00:00:00Z"), 3600)
+            ]
+        );
+
+        // the task stays, without its client
+        store
+            .delete_client(client.id, user_id.clone())
+            .await
+            .unwrap();
+        let design = store
+            .get_task_with_events_by_task_id(design.id, user_id)
+            .await
+            .unwrap();
+        assert_eq!(design.task.client_id, None);
+    }
+}
+
+#[tokio::test]
+async fn reports_in_the_users_time_zone() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let writing = store
+            .add_task(task(&user_id, "Writing", &["work", "words"]))
+            .await
+            .unwrap();
+        let chores = store
+            .add_task(task(&user_id, "Chores", &["home"]))
+            .await
+            .unwrap();
+        // the 2nd in Berlin
+        store
+            .add_event(event(&user_id, &writing, "2023-12-01T23:30:00Z", 600))
+            .await
+            .unwrap();
+        store
+            .add_event(event(&user_id, &chores, "2023-12-03T08:00:00Z", 300))
+            .await
+            .unwrap();
+        // after the range
+        store
+            .add_event(event(&user_id, &chores, "2023-12-03T23:30:00Z", 60))
+            .await
+            .unwrap();
+
+        let query = |group_by| ReportQuery {
+            from: NaiveDate::from_ymd_opt(2023, 12, 1).unwrap(),
+            to: NaiveDate::from_ymd_opt(2023, 12, 3).unwrap(),
+            group_by,
+            tz: "Europe/Berlin".to_string(),
+        };
+        assert!(store.timezone_exists("Europe/Berlin").await.unwrap());
+        assert!(!store.timezone_exists("Mars/Olympus").await.unwrap());
+
+        let days = store
+            .get_report_summary(user_id.clone(), query(ReportGroupBy::Day))
+            .await
+            .unwrap();
+        let totals: Vec<_> = days
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.key.as_str(), bucket.total_duration))
+            .collect();
+        assert_eq!(
+            totals,
+            vec![("2023-12-01", 0), ("2023-12-02", 600), ("2023-12-03", 300)]
+        );
+        assert_eq!(days.buckets[0].start, Some(instant("2023-11-30T23:00:00Z")));
+        assert_eq!(days.total_duration, 900);
+
+        let tags = store
+            .get_report_summary(user_id.clone(), query(ReportGroupBy::Tag))
+            .await
+            .unwrap();
+        let totals: Vec<_> = tags
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.key.as_str(), bucket.total_duration))
+            .collect();
+        assert_eq!(totals, vec![("words", 600), ("work", 600), ("home", 300)]);
+        assert_eq!(tags.total_duration, 900);
+
+        let streamed: Vec<_> = store
+            .stream_report_summary(user_id, query(ReportGroupBy::Task))
+            .map(|bucket| bucket.unwrap().key)
+            .collect()
+            .await;
+        assert_eq!(streamed, vec!["Writing", "Chores"]);
+    }
+}
+
+#[tokio::test]
+async fn imports_without_duplicates() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let uuid = Uuid::new_v4();
+        let row = |uuid, tags: &[&str]| ImportRow {
+            row: 1,
+            uuid,
+            task_name: "Writing".to_string(),
+            client_name: Some("Acme".to_string()),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            date_began: instant("2023-12-01T10:00:00Z"),
+            duration: 600,
+            notes: None,
+            ical_uid: None,
+        };
+        let rows = vec![row(Some(uuid), &["work"]), row(Some(uuid), &["words"])];
+
+        let report = store
+            .import_events(user_id.clone(), rows.clone(), ImportDedupe::Uuid, true)
+            .await
+            .unwrap();
+        assert_eq!((report.imported, report.skipped_duplicates), (1, 1));
+        assert!(store
+            .get_user_tasks_with_events(user_id.clone())
+            .await
+            .unwrap()
+            .is_empty());
+
+        let report = store
+            .import_events(user_id.clone(), rows.clone(), ImportDedupe::Uuid, false)
+            .await
+            .unwrap();
+        assert_eq!(report.created_tasks, vec!["Writing"]);
+        assert_eq!(report.created_clients, vec!["Acme"]);
+        let report = store
+            .import_events(user_id.clone(), rows, ImportDedupe::Uuid, false)
+            .await
+            .unwrap();
+        assert_eq!((report.imported, report.skipped_duplicates), (0, 2));
+
+        let report = store
+            .import_events(
+                user_id.clone(),
+                vec![row(None, &[])],
+                ImportDedupe::TaskDateDuration,
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.skipped_duplicates, 1);
+
+        let tasks = store.get_user_tasks_with_events(user_id).await.unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].task.tags, vec!["work", "words"]);
+        assert_eq!(tasks[0].events[0].uuid, uuid);
+    }
+}
+
+#[tokio::test]
+async fn syncs_changes_and_deletions() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let task_uuid = Uuid::new_v4();
+        let event_uuid = Uuid::new_v4();
+        let push = SyncPush {
+            tasks: vec![TaskChange {
+                uuid: task_uuid,
+                modified_at: instant("2023-12-01T10:00:00Z"),
+                name: Some("Writing".to_string()),
+                description: None,
+                tags: Some(vec!["work".to_string()]),
+            }],
+            events: vec![EventChange {
+                uuid: event_uuid,
+                modified_at: instant("2023-12-01T10:00:00Z"),
+                task_uuid: Some(task_uuid),
+                date_began: Some(instant("2023-12-01T09:00:00Z")),
+                duration: Some(3600),
+                notes: None,
+            }],
+            deletions: vec![],
+        };
+        let report = store.apply_sync(user_id.clone(), push).await.unwrap();
+        assert_eq!(report.applied, 2);
+        assert!(report.errors.is_empty());
+
+        let changes = store.get_sync_changes(user_id.clone(), 0).await.unwrap();
+        assert_eq!(changes.tasks.len(), 1);
+        assert_eq!(changes.tasks[0].tags, vec!["work"]);
+        assert_eq!(changes.events[0].task_uuid, task_uuid);
+        assert!(changes.events[0].modified_at.contains_key("task_uuid"));
+        let cursor = changes.cursor;
+        assert!(cursor > 0);
+
+        // the rules of the API hold for new and merged values alike
+        let push = SyncPush {
+            tasks: vec![
+                TaskChange {
+                    uuid: Uuid::new_v4(),
+                    modified_at: instant("2023-12-01T11:00:00Z"),
+                    name: Some(" ".to_string()),
+                    description: None,
+                    tags: None,
+                },
+                TaskChange {
+                    uuid: task_uuid,
+                    modified_at: instant("2023-12-01T11:00:00Z"),
+                    name: Some(String::new()),
+                    description: None,
+                    tags: None,
+                },
+            ],
+            events: vec![EventChange {
+                uuid: event_uuid,
+                modified_at: instant("2023-12-01T11:00:00Z"),
+                task_uuid: None,
+                date_began: Some(instant("2099-01-01T00:00:00Z")),
+                duration: Some(-500),
+                notes: None,
+            }],
+            deletions: vec![],
+        };
+        let report = store.apply_sync(user_id.clone(), push).await.unwrap();
+        assert_eq!(report.applied, 0);
+        assert_eq!(report.errors.len(), 3, "{:?}", report.errors);
+        assert_eq!(
+            report.errors[2].message,
+            "date_began must not be in the future, duration must not be negative"
+        );
+        let unchanged = store.get_sync_changes(user_id.clone(), 0).await.unwrap();
+        assert_eq!(unchanged.tasks.len(), 1);
+        assert_eq!(unchanged.tasks[0].name, "Writing");
+        assert_eq!(unchanged.events[0].duration, 3600);
+
+        // an older edit loses against the server's
+        let push = SyncPush {
+            tasks: vec![TaskChange {
+                uuid: task_uuid,
+                modified_at: instant("2023-11-30T10:00:00Z"),
+                name: Some("Reading".to_string()),
+                description: None,
+                tags: None,
+            }],
+            ..SyncPush::default()
+        };
+        let report = store.apply_sync(user_id.clone(), push).await.unwrap();
+        assert_eq!(report.applied, 0);
+        assert_eq!(report.conflicts[0].field, "name");
+
+        let push = SyncPush {
+            deletions: vec![Deletion {
+                entity: SyncEntity::Task,
+                uuid: task_uuid,
+                deleted_at: instant("2023-12-02T10:00:00Z"),
+            }],
+            ..SyncPush::default()
+        };
+        store.apply_sync(user_id.clone(), push).await.unwrap();
+        let changes = store
+            .get_sync_changes(user_id.clone(), cursor)
+            .await
+            .unwrap();
+        assert!(changes.tasks.is_empty());
+        assert_eq!(changes.tombstones.len(), 2);
+        let task_tombstone = changes
+            .tombstones
+            .iter()
+            .find(|tombstone| tombstone.uuid == task_uuid)
+            .unwrap();
+        // stamped with the time of the deletion on the client
+        assert_eq!(task_tombstone.deleted_at, instant("2023-12-02T10:00:00Z"));
+
+        // recreating the task from an edit older than its deletion
+        let push = SyncPush {
+            tasks: vec![TaskChange {
+                uuid: task_uuid,
+                modified_at: instant("2023-12-01T12:00:00Z"),
+                name: Some("Writing".to_string()),
+                description: None,
+                tags: None,
+            }],
+            ..SyncPush::default()
+        };
+        let report = store.apply_sync(user_id, push).await.unwrap();
+        assert_eq!(report.conflicts[0].field, "deleted");
+    }
+}
+
+#[tokio::test]
+async fn imports_and_syncs_under_the_overlap_policy() {
+    for store in backends().await {
+        let store = &*store;
+        let user_id = user(store).await;
+        let writing = store
+            .add_task(task(&user_id, "Writing", &[]))
+            .await
+            .unwrap();
+        store
+            .add_event(event(&user_id, &writing, "2023-12-01T10:00:00Z", 3600))
+            .await
+            .unwrap();
+        let settings = UserSettings {
+            overlap_policy: OverlapPolicy::Reject,
+        };
+        store
+            .update_user_settings(user_id.clone(), settings)
+            .await
+            .unwrap();
+
+        let row = |row, date_began: &str| ImportRow {
+            row,
+            uuid: None,
+            task_name: "Writing".to_string(),
+            client_name: None,
+            tags: vec![],
+            date_began: instant(date_began),
+            duration: 600,
+            notes: None,
+            ical_uid: None,
+        };
+        // the third row overlaps the second, which went in before it
+        let rows = vec![
+            row(1, "2023-12-01T10:30:00Z"),
+            row(2, "2023-12-01T12:00:00Z"),
+            row(3, "2023-12-01T12:05:00Z"),
+        ];
+        let report = store
+            .import_events(user_id.clone(), rows, ImportDedupe::None, false)
+            .await
+            .unwrap();
+        assert_eq!(report.imported, 1);
+        let rejected: Vec<_> = report
+            .errors
+            .iter()
+            .map(|error| (error.row, error.field.as_str(), error.message.as_str()))
+            .collect();
+        assert_eq!(
+            rejected,
+            [
+                (1, "date_began", "the event overlaps others"),
+                (3, "date_began", "the event overlaps others")
+            ]
+        );
+
+        let change = |date_began: &str| EventChange {
+            uuid: Uuid::new_v4(),
+            modified_at: instant("2023-12-02T10:00:00Z"),
+            task_uuid: Some(writing.uuid),
+            date_began: Some(instant(date_began)),
+            duration: Some(1800),
+            notes: None,
+        };
+        let overlapping = change("2023-12-01T10:45:00Z");
+        let push = SyncPush {
+            events: vec![overlapping.clone()],
+            ..SyncPush::default()
+        };
+        let report = store.apply_sync(user_id.clone(), push).await.unwrap();
+        assert_eq!(report.applied, 0);
+        assert_eq!(report.errors[0].uuid, overlapping.uuid);
+        assert_eq!(report.errors[0].message, "the event overlaps others");
+
+        // each trimmed to what the events before it left free
+        let settings = UserSettings {
+            overlap_policy: OverlapPolicy::Trim,
+        };
+        store
+            .update_user_settings(user_id.clone(), settings)
+            .await
+            .unwrap();
+        let push = SyncPush {
+            events: vec![overlapping],
+            ..SyncPush::default()
+        };
+        let report = store.apply_sync(user_id.clone(), push).await.unwrap();
+        assert_eq!(report.applied, 1);
+        let report = store
+            .import_events(
+                user_id.clone(),
+                vec![row(1, "2023-12-01T11:10:00Z")],
+                ImportDedupe::None,
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.imported, 1);
+
+        let tasks = store.get_user_tasks_with_events(user_id).await.unwrap();
+        let mut events: Vec<_> = tasks[0]
+            .events
+            .iter()
+            .map(|event| (event.date_began, event.duration))
+            .collect();
+        events.sort();
+        assert_eq!(
+            events,
+            [
+                (instant("2023-12-01T10:00:00Z"), 3600),
+                (instant("2023-12-01T11:00:00Z"), 900),
+                (instant("2023-12-01T11:15:00Z"), 300),
+                (instant("2023-12-01T12:00:00Z"), 600),
+            ]
+        );
+    }
+}
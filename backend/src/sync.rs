@@ -0,0 +1,208 @@
+//! Per-field last-writer-wins merging for `POST /sync`
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    models::{FieldClock, NewTask, NewTaskEvent, SyncConflict, SyncEntity, TaskId, UserId},
+    validation::FieldErrors,
+};
+
+/// Merges a client change into a stored record, one field at a time. A field
+/// takes the client's value unless the server wrote it after the client did.
+pub struct FieldMerge {
+    entity: SyncEntity,
+    uuid: Uuid,
+    modified_at: DateTime<Utc>,
+    clock: FieldClock,
+    /// new clocks of the fields taken from the client, keyed by column
+    pub written: FieldClock,
+    pub conflicts: Vec<SyncConflict>,
+}
+
+impl FieldMerge {
+    pub fn new(
+        entity: SyncEntity,
+        uuid: Uuid,
+        modified_at: DateTime<Utc>,
+        clock: FieldClock,
+    ) -> Self {
+        FieldMerge {
+            entity,
+            uuid,
+            modified_at,
+            clock,
+            written: FieldClock::new(),
+            conflicts: vec![],
+        }
+    }
+
+    /// The value to store for a field, which is named as in the API but
+    /// clocked by its `column`. Unchanged fields are `None`.
+    pub fn field<T>(&mut self, field: &str, column: &str, server: T, client: Option<T>) -> T
+    where
+        T: Serialize + PartialEq,
+    {
+        let Some(client) = client else {
+            return server;
+        };
+        match self.clock.get(column) {
+            Some(&server_modified_at) if server_modified_at > self.modified_at => {
+                if client != server {
+                    self.conflicts.push(SyncConflict {
+                        entity: self.entity,
+                        uuid: self.uuid,
+                        field: field.to_string(),
+                        server_value: serde_json::json!(server),
+                        client_value: serde_json::json!(client),
+                        server_modified_at,
+                        client_modified_at: self.modified_at,
+                    });
+                }
+                server
+            }
+            _ => {
+                self.written.insert(column.to_string(), self.modified_at);
+                client
+            }
+        }
+    }
+
+    pub fn wrote(&self, column: &str) -> bool {
+        self.written.contains_key(column)
+    }
+}
+
+/// Checks a task as a sync leaves it by the rules of `POST /tasks`,
+/// returning what is wrong with it
+pub fn check_task(name: &str, description: &str, tags: &[String]) -> Result<(), String> {
+    let task = NewTask {
+        user_id: UserId(0),
+        name: name.to_string(),
+        description: Some(description.to_string()),
+        client_id: None,
+        tags: tags.to_vec(),
+    };
+    problems(FieldErrors::of(&task))
+}
+
+/// Checks an event as a sync leaves it by the rules of `POST /events`
+pub fn check_event(
+    date_began: DateTime<Utc>,
+    duration: i64,
+    notes: Option<&str>,
+) -> Result<(), String> {
+    let event = NewTaskEvent {
+        user_id: UserId(0),
+        task_id: TaskId(0),
+        date_began,
+        duration,
+        notes: notes.map(str::to_string),
+    };
+    problems(FieldErrors::of(&event))
+}
+
+fn problems(errors: FieldErrors) -> Result<(), String> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.to_string())
+    }
+}
+
+/// The clocks of a record created by a client
+pub fn new_clock(columns: &[&str], modified_at: DateTime<Utc>) -> FieldClock {
+    columns
+        .iter()
+        .map(|column| (column.to_string(), modified_at))
+        .collect()
+}
+
+/// A client write of a record the server deleted later
+pub fn deleted_conflict(
+    entity: SyncEntity,
+    uuid: Uuid,
+    deleted_at: DateTime<Utc>,
+    modified_at: DateTime<Utc>,
+) -> SyncConflict {
+    SyncConflict {
+        entity,
+        uuid,
+        field: "deleted".to_string(),
+        server_value: true.into(),
+        client_value: false.into(),
+        server_modified_at: deleted_at,
+        client_modified_at: modified_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 12, 1, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn newer_writes_win_per_field() {
+        let mut clock = new_clock(&["name"], at(9));
+        clock.insert("description".to_string(), at(11));
+        let mut merge = FieldMerge::new(SyncEntity::Task, Uuid::nil(), at(10), clock);
+
+        let name = merge.field(
+            "name",
+            "name",
+            "server".to_string(),
+            Some("client".to_string()),
+        );
+        let description = merge.field(
+            "description",
+            "description",
+            "server".to_string(),
+            Some("client".to_string()),
+        );
+        let tags = merge.field("tags", "tags", vec!["a".to_string()], None);
+
+        assert_eq!(name, "client");
+        assert_eq!(description, "server");
+        assert_eq!(tags, vec!["a".to_string()]);
+        assert!(merge.wrote("name"));
+        assert!(!merge.wrote("description") && !merge.wrote("tags"));
+        assert_eq!(merge.conflicts.len(), 1);
+        assert_eq!(merge.conflicts[0].field, "description");
+        assert_eq!(merge.conflicts[0].client_value, "client");
+        assert_eq!(merge.conflicts[0].server_modified_at, at(11));
+    }
+
+    #[test]
+    fn checks_values_by_the_rules_of_the_api() {
+        assert_eq!(check_task("Writing", "", &[]), Ok(()));
+        assert_eq!(
+            check_task(" ", "", &["".to_string()]),
+            Err("name must not be empty, tags must not contain empty tags".to_string())
+        );
+        assert_eq!(check_event(at(9), 600, None), Ok(()));
+        let future = Utc.with_ymd_and_hms(2099, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            check_event(future, -500, None),
+            Err("date_began must not be in the future, duration must not be negative".to_string())
+        );
+    }
+
+    #[test]
+    fn equal_values_are_not_conflicts() {
+        let mut merge = FieldMerge::new(
+            SyncEntity::Event,
+            Uuid::nil(),
+            at(9),
+            new_clock(&["duration"], at(10)),
+        );
+        assert_eq!(merge.field("duration", "duration", 60, Some(60)), 60);
+        assert!(merge.conflicts.is_empty());
+        assert!(merge.written.is_empty());
+    }
+}
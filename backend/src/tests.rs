@@ -0,0 +1,1373 @@
+//! Tests of the HTTP API, driving the real router over the memory store.
+//!
+//! `TestApp` sends requests through `oneshot` and keeps the session cookie
+//! from the last login, like a browser would.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    response::Response,
+    routing::post,
+    Router,
+};
+use futures::{Stream, StreamExt};
+use http::{
+    header::{CONTENT_TYPE, COOKIE, SET_COOKIE},
+    HeaderMap, Method, Request, StatusCode,
+};
+use serde_json::{json, Value};
+use tokio::{net::TcpListener, sync::mpsc, time::timeout};
+use tower::ServiceExt;
+
+use crate::{
+    config::Config,
+    router, routes,
+    store::{self, MemoryStore},
+    webhooks,
+};
+
+const PASSWORD: &str = "password1";
+
+struct TestApp {
+    router: Router,
+    cookie: Option<String>,
+}
+
+impl TestApp {
+    async fn new() -> TestApp {
+        TestApp::with_store(Arc::new(MemoryStore::default())).await
+    }
+
+    async fn with_store(store: Arc<MemoryStore>) -> TestApp {
+        TestApp {
+            router: router(store, &Config::for_tests()).await,
+            cookie: None,
+        }
+    }
+
+    /// A new app with an account that is logged in
+    async fn logged_in(email: &str) -> TestApp {
+        let mut app = TestApp::new().await;
+        assert_eq!(app.register(email).await.0, StatusCode::OK);
+        assert_eq!(app.login(email, PASSWORD).await.0, StatusCode::OK);
+        app
+    }
+
+    /// Sends a request with the session cookie
+    async fn send(&self, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        read(self.respond(method, uri, body).await).await
+    }
+
+    /// The response to a request with the session cookie, for bodies that
+    /// are not read whole
+    async fn respond(&self, method: Method, uri: &str, body: Option<Value>) -> Response {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(cookie) = &self.cookie {
+            request = request.header(COOKIE, cookie);
+        }
+        let request = match body {
+            Some(body) => request
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+        self.router.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn get(&self, uri: &str) -> (StatusCode, Value) {
+        self.send(Method::GET, uri, None).await
+    }
+
+    async fn post(&self, uri: &str, body: Value) -> (StatusCode, Value) {
+        self.send(Method::POST, uri, Some(body)).await
+    }
+
+    async fn put(&self, uri: &str, body: Value) -> (StatusCode, Value) {
+        self.send(Method::PUT, uri, Some(body)).await
+    }
+
+    async fn register(&self, email: &str) -> (StatusCode, Value) {
+        self.post(
+            "/users/register",
+            json!({"email": email, "password": PASSWORD}),
+        )
+        .await
+    }
+
+    /// Logs in, keeping the session cookie if it worked
+    async fn login(&mut self, email: &str, password: &str) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/users/login")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({"email": email, "password": password}).to_string(),
+            ))
+            .unwrap();
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        if let Some(set_cookie) = response.headers().get(SET_COOKIE) {
+            let cookie = set_cookie.to_str().unwrap();
+            // only `name=value` goes back to the server
+            self.cookie = cookie.split(';').next().map(str::to_string);
+        }
+        read(response).await
+    }
+
+    async fn user_id(&self) -> i64 {
+        let (status, user_id) = self.get("/auth").await;
+        assert_eq!(status, StatusCode::OK);
+        user_id.as_i64().unwrap()
+    }
+
+    async fn add_task(&self, name: &str) -> Value {
+        let (status, task) = self
+            .post(
+                "/tasks",
+                json!({"user_id": 0, "name": name, "description": null, "tags": ["work"]}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        task
+    }
+
+    async fn add_event(&self, task_id: &Value, date_began: &str, duration: i64) -> Value {
+        let (status, event) = self
+            .post(
+                "/events",
+                json!({
+                    "user_id": 0,
+                    "task_id": task_id,
+                    "date_began": date_began,
+                    "duration": duration,
+                    "notes": null,
+                }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        event
+    }
+
+    /// The response to a GraphQL request, whose errors are in the body
+    async fn graphql(&self, query: &str, variables: Value) -> Value {
+        let (status, response) = self
+            .post("/graphql", json!({"query": query, "variables": variables}))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        response
+    }
+}
+
+/// The names of the next `count` server-sent events of a stream
+async fn next_events<S>(body: &mut S, count: usize) -> Vec<String>
+where
+    S: Stream<Item = Result<Bytes, axum::Error>> + Unpin,
+{
+    let mut names = vec![];
+    while names.len() < count {
+        let chunk = timeout(Duration::from_secs(5), body.next())
+            .await
+            .expect("no event within 5s")
+            .unwrap()
+            .unwrap();
+        for line in String::from_utf8(chunk.to_vec()).unwrap().lines() {
+            if let Some(name) = line.strip_prefix("event: ") {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// What a webhook receiver was sent
+struct Received {
+    headers: HeaderMap,
+    body: String,
+}
+
+/// Receives webhooks on a local port, answering with `statuses` in turn and
+/// then with 204. Returns the URL to post to.
+async fn webhook_receiver(
+    statuses: Vec<StatusCode>,
+) -> (String, mpsc::UnboundedReceiver<Received>) {
+    let (sender, received) = mpsc::unbounded_channel();
+    let statuses = Arc::new(Mutex::new(VecDeque::from(statuses)));
+    let receiver = Router::new().route(
+        "/hook",
+        post(move |headers: HeaderMap, body: String| async move {
+            sender.send(Received { headers, body }).unwrap();
+            let status = statuses.lock().unwrap().pop_front();
+            status.unwrap_or(StatusCode::NO_CONTENT)
+        }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, receiver).await.unwrap() });
+    (url, received)
+}
+
+async fn next_received(received: &mut mpsc::UnboundedReceiver<Received>) -> Received {
+    timeout(Duration::from_secs(5), received.recv())
+        .await
+        .expect("no webhook within 5s")
+        .unwrap()
+}
+
+/// The status and the body of a response as text
+async fn read_text(response: Response) -> (StatusCode, String) {
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+/// The status and the body of a response, which is `null` if it is not JSON
+async fn read(response: Response) -> (StatusCode, Value) {
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+#[tokio::test]
+async fn registers_and_logs_in() {
+    let mut app = TestApp::new().await;
+    let (status, body) = app.register("someone@example.com").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Created account!");
+
+    let (status, body) = app.register("someone@example.com").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "conflict");
+
+    let (status, body) = app.login("someone@example.com", "password2").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["message"], "Wrong email or password");
+    assert!(app.cookie.is_none());
+    let (status, _) = app.login("nobody@example.com", PASSWORD).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = app.login("someone@example.com", PASSWORD).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(app.cookie.is_some());
+    assert!(app.user_id().await > 0);
+}
+
+#[tokio::test]
+async fn rejects_requests_without_a_session() {
+    let mut app = TestApp::new().await;
+    let (status, body) = app.get("/tasks").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "unauthorized");
+
+    // a cookie the server did not encrypt
+    app.cookie = Some("time_bandit_auth_token_v1=1234".to_string());
+    assert_eq!(app.get("/tasks").await.0, StatusCode::UNAUTHORIZED);
+
+    let mut app = TestApp::logged_in("someone@example.com").await;
+    let first_session = app.cookie.clone();
+    assert_eq!(app.get("/tasks").await.0, StatusCode::OK);
+    // logging in again ends the earlier session
+    app.login("someone@example.com", PASSWORD).await;
+    assert_eq!(app.get("/tasks").await.0, StatusCode::OK);
+    app.cookie = first_session;
+    assert_eq!(app.get("/tasks").await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(app.get("/auth").await.0, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn creates_and_updates_tasks() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let user_id = app.user_id().await;
+
+    let task = app.add_task("Writing").await;
+    assert_eq!(task["name"], "Writing");
+    assert_eq!(task["user_id"], user_id);
+    assert_eq!(task["description"], "");
+    let uri = format!("/tasks/{}", task["id"]);
+
+    let (status, found) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(found["task"], task);
+    assert_eq!(found["events"], json!([]));
+
+    let (status, updated) = app
+        .put(
+            &uri,
+            json!({
+                "user_id": 0,
+                "name": "Editing",
+                "description": "second draft",
+                "tags": ["work", "words"],
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    // the owner is the user logged in, whoever the body names
+    assert_eq!(updated["user_id"], user_id);
+    assert_eq!(updated["name"], "Editing");
+    assert_eq!(updated["tags"], json!(["work", "words"]));
+    assert_eq!(updated["uuid"], task["uuid"]);
+
+    let (status, page) = app.get("/tasks").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["tasks"].as_array().unwrap().len(), 1);
+    assert_eq!(page["tasks"][0]["task"]["description"], "second draft");
+
+    let (status, body) = app
+        .post(
+            "/tasks",
+            json!({"user_id": 0, "name": " ", "description": null}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"]["name"], json!(["must not be empty"]));
+    assert_eq!(app.get("/tasks/999").await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn rejects_unreadable_requests_with_api_errors() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let mut request = Request::builder()
+        .method(Method::POST)
+        .uri("/tasks")
+        .header(CONTENT_TYPE, "application/json");
+    if let Some(cookie) = &app.cookie {
+        request = request.header(COOKIE, cookie);
+    }
+    let request = request.body(Body::from("{\"name\": ")).unwrap();
+    let (status, body) = read(app.router.clone().oneshot(request).await.unwrap()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "bad_request");
+
+    let (status, body) = app.post("/tasks", json!({"name": 1})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "unprocessable");
+    let (status, body) = app.get("/tasks?limit=many").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "bad_request");
+    let (status, body) = app.get("/tasks/first").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "bad_request");
+
+    let (status, body) = app.get("/nowhere").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["message"], routes::UNKNOWN_PATH);
+}
+
+#[tokio::test]
+async fn lists_only_the_users_own_tasks() {
+    let mut app = TestApp::logged_in("someone@example.com").await;
+    app.add_task("Writing").await;
+    app.register("other@example.com").await;
+    app.login("other@example.com", PASSWORD).await;
+    app.add_task("Reading").await;
+
+    let (_, page) = app.get("/tasks").await;
+    let names: Vec<&Value> = page["tasks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|task| &task["task"]["name"])
+        .collect();
+    assert_eq!(names, vec!["Reading"]);
+}
+
+#[tokio::test]
+async fn keeps_tasks_to_their_owner() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let task = app.add_task("Writing").await;
+    let uri = format!("/tasks/{}", task["id"]);
+    let mut other = TestApp {
+        router: app.router.clone(),
+        cookie: None,
+    };
+    other.register("other@example.com").await;
+    other.login("other@example.com", PASSWORD).await;
+    let owner = app.user_id().await;
+
+    assert_eq!(other.get(&uri).await.0, StatusCode::NOT_FOUND);
+    let (status, _) = other
+        .put(
+            &uri,
+            json!({"user_id": owner, "name": "Mine now", "description": ""}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = other
+        .post(
+            "/events",
+            json!({
+                "user_id": owner,
+                "task_id": task["id"],
+                "date_began": "2023-12-01T10:00:00Z",
+                "duration": 60,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // the task is as it was
+    let (_, found) = app.get(&uri).await;
+    assert_eq!(found["task"]["name"], "Writing");
+    assert_eq!(found["events"], json!([]));
+}
+
+#[tokio::test]
+async fn aggregates_the_events_of_tasks() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let writing = app.add_task("Writing").await;
+    let idle = app.add_task("Idle").await;
+    app.add_event(&writing["id"], "2023-12-01T10:00:00Z", 600)
+        .await;
+    let last = app
+        .add_event(&writing["id"], "2023-12-02T10:00:00Z", 1200)
+        .await;
+    assert_eq!(last["task_id"], writing["id"]);
+
+    let (status, page) = app.get("/tasks").await;
+    assert_eq!(status, StatusCode::OK);
+    let tasks = page["tasks"].as_array().unwrap();
+    let writing = tasks
+        .iter()
+        .find(|task| task["task"]["id"] == writing["id"])
+        .unwrap();
+    assert_eq!(writing["total_duration"], 1800);
+    assert_eq!(writing["updated_on"], "2023-12-02T10:00:00Z");
+    assert_eq!(writing["events"].as_array().unwrap().len(), 2);
+    assert!(writing["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|event| event["uuid"] == last["uuid"]));
+
+    // a task without events was last updated when it was created
+    let idle_summary = tasks
+        .iter()
+        .find(|task| task["task"]["id"] == idle["id"])
+        .unwrap();
+    assert_eq!(idle_summary["total_duration"], 0);
+    assert_eq!(idle_summary["updated_on"], idle["created_on"]);
+
+    let (status, body) = app
+        .post(
+            "/events",
+            json!({
+                "user_id": 0,
+                "task_id": idle["id"],
+                "date_began": "2023-12-01T10:00:00Z",
+                "duration": -1,
+                "notes": null,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["details"]["duration"], json!(["must not be negative"]));
+
+    let (status, _) = app
+        .post(
+            "/events",
+            json!({
+                "user_id": 0,
+                "task_id": 999,
+                "date_began": "2023-12-01T10:00:00Z",
+                "duration": 60,
+                "notes": null,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn lists_events_by_date() {
+    let mut app = TestApp::logged_in("someone@example.com").await;
+    let writing = app.add_task("Writing").await;
+    let reading = app.add_task("Reading").await;
+    // 1 December in Berlin runs from 23:00 UTC the day before
+    let straddling = app
+        .add_event(&writing["id"], "2023-11-30T22:30:00Z", 3600)
+        .await;
+    let within = app
+        .add_event(&reading["id"], "2023-12-01T10:00:00Z", 600)
+        .await;
+    app.add_event(&writing["id"], "2023-12-01T23:00:00Z", 60)
+        .await;
+
+    let range = "from=2023-12-01&to=2023-12-01&tz=Europe/Berlin&clip=true";
+    let (status, page) = app.get(&format!("/events?{}&limit=1", range)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["events"][0]["id"], straddling["id"]);
+    assert_eq!(page["events"][0]["task_name"], "Writing");
+    assert_eq!(page["events"][0]["date_began"], "2023-11-30T22:30:00Z");
+    assert_eq!(page["events"][0]["duration"], 3600);
+    assert_eq!(page["events"][0]["duration_in_range"], 1800);
+
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let (_, page) = app
+        .get(&format!("/events?{}&limit=1&cursor={}", range, cursor))
+        .await;
+    assert_eq!(page["events"][0]["id"], within["id"]);
+    assert_eq!(page["events"][0]["duration_in_range"], 600);
+    assert_eq!(page["next_cursor"], Value::Null);
+
+    let (_, page) = app
+        .get(&format!(
+            "/events?from=2023-12-01&tz=Europe/Berlin&task_id={}",
+            writing["id"]
+        ))
+        .await;
+    assert_eq!(page["events"].as_array().unwrap().len(), 2);
+    assert_eq!(page["events"][0]["duration_in_range"], Value::Null);
+
+    let (status, event) = app.get(&format!("/events/{}", within["id"])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(event["task_name"], "Reading");
+    assert_eq!(
+        app.get("/events?from=2023-12-02&to=2023-12-01").await.0,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        app.get("/events?cursor=nonsense").await.0,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(app.get("/events/export.csv").await.0, StatusCode::OK);
+
+    app.register("other@example.com").await;
+    app.login("other@example.com", PASSWORD).await;
+    let (status, _) = app.get(&format!("/events/{}", within["id"])).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, page) = app.get("/events").await;
+    assert_eq!(page["events"], json!([]));
+}
+
+#[tokio::test]
+async fn rejects_overlapping_events() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let writing = app.add_task("Writing").await;
+    let first = app
+        .add_event(&writing["id"], "2023-12-01T10:00:00Z", 3600)
+        .await;
+    let second = app
+        .add_event(&writing["id"], "2023-12-01T10:45:00Z", 600)
+        .await;
+
+    let (status, overlaps) = app.get("/events/overlaps?from=2023-12-01").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(overlaps["overlaps"].as_array().unwrap().len(), 1);
+    assert_eq!(overlaps["overlaps"][0]["first"]["id"], first["id"]);
+    assert_eq!(overlaps["overlaps"][0]["second"]["id"], second["id"]);
+    assert_eq!(overlaps["overlaps"][0]["second"]["task_name"], "Writing");
+    assert_eq!(overlaps["overlaps"][0]["duration"], 600);
+
+    let (status, settings) = app.get("/users/settings").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(settings, json!({"overlap_policy": "allow"}));
+    let (status, settings) = app
+        .put("/users/settings", json!({"overlap_policy": "reject"}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(settings["overlap_policy"], "reject");
+    let (status, _) = app
+        .put("/users/settings", json!({"overlap_policy": "sometimes"}))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, error) = app
+        .post(
+            "/events",
+            json!({
+                "user_id": 0,
+                "task_id": writing["id"],
+                "date_began": "2023-12-01T10:50:00Z",
+                "duration": 60,
+                "notes": null,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(error["code"], "conflict");
+    let overlapping = error["details"]["overlapping"].as_array().unwrap();
+    assert_eq!(overlapping.len(), 2);
+    assert_eq!(overlapping[0]["id"], first["id"]);
+    assert_eq!(overlapping[1]["id"], second["id"]);
+
+    // imports leave out the rows the policy rejects and write the others
+    let data = "task,date_began,duration\n\
+        Writing,2023-12-01T10:30:00Z,600\n\
+        Writing,2023-12-01T14:00:00Z,600\n";
+    let (status, report) = app
+        .post("/import", json!({"format": "csv", "data": data}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["errors"].as_array().unwrap().len(), 1);
+    assert_eq!(report["errors"][0]["row"], 1);
+    assert_eq!(report["errors"][0]["message"], "the event overlaps others");
+    // and a dry run lists them with the invalid rows
+    let data = "task,date_began,duration\n\
+        Writing,2023-12-01T14:00:00Z,600\n\
+        Writing,2023-12-01T15:00:00Z,soon\n";
+    let (status, report) = app
+        .post(
+            "/import",
+            json!({"format": "csv", "data": data, "dry_run": true}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let rows: Vec<_> = report["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["row"].clone())
+        .collect();
+    assert_eq!(rows, [json!(1), json!(2)]);
+}
+
+#[tokio::test]
+async fn streams_timers_and_changes() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let response = app.respond(Method::GET, "/stream", None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/event-stream");
+    let mut events = response.into_body().into_data_stream();
+
+    let writing = app.add_task("Writing").await;
+    let (status, timer) = app.get("/timer").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(timer, Value::Null);
+    let start = json!({"task_id": writing["id"], "notes": "chapter two"});
+    let (status, timer) = app.post("/timer/start", start.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(timer["task_id"], writing["id"]);
+    assert_eq!(app.get("/timer").await.1, timer);
+    assert_eq!(
+        app.post("/timer/start", start).await.0,
+        StatusCode::CONFLICT
+    );
+    assert_eq!(
+        app.post("/timer/start", json!({"task_id": -1})).await.0,
+        StatusCode::NOT_FOUND
+    );
+
+    let (status, event) = app.post("/timer/stop", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(event["task_id"], writing["id"]);
+    assert_eq!(event["date_began"], timer["started"]);
+    assert_eq!(event["notes"], "chapter two");
+    assert_eq!(app.get("/timer").await.1, Value::Null);
+    let (status, body) = app.post("/timer/stop", json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["message"], "No timer is running");
+
+    assert_eq!(
+        next_events(&mut events, 4).await,
+        [
+            "task_created",
+            "timer_started",
+            "event_created",
+            "timer_stopped"
+        ]
+    );
+    // others' changes are not sent
+    let mut other = TestApp {
+        router: app.router.clone(),
+        cookie: None,
+    };
+    other.register("other@example.com").await;
+    other.login("other@example.com", PASSWORD).await;
+    other.add_task("Reading").await;
+    app.add_task("Reading").await;
+    assert_eq!(next_events(&mut events, 1).await, ["task_created"]);
+}
+
+#[tokio::test]
+async fn searches_tasks_and_notes() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let writing = app.add_task("Writing").await;
+    let reading = app.add_task("Reading").await;
+    let (status, _) = app
+        .post(
+            "/events",
+            json!({
+                "user_id": 0,
+                "task_id": writing["id"],
+                "date_began": "2023-12-01T10:00:00Z",
+                "duration": 600,
+                "notes": "Drafted the <intro> chapter",
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    app.add_event(&reading["id"], "2023-12-02T10:00:00Z", 600)
+        .await;
+
+    let (status, results) = app.get("/search?q=intro").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(results["hits"].as_array().unwrap().len(), 1);
+    let hit = &results["hits"][0];
+    assert_eq!(hit["kind"], "event");
+    assert_eq!(hit["task_name"], "Writing");
+    assert_eq!(hit["date_began"], "2023-12-01T10:00:00Z");
+    assert_eq!(
+        hit["snippet"],
+        "Drafted the &lt;<mark>intro</mark>&gt; chapter"
+    );
+
+    let (_, results) = app
+        .get("/search?q=intro&from=2023-12-02&to=2023-12-02")
+        .await;
+    assert_eq!(results["hits"], json!([]));
+    let (_, results) = app
+        .get(&format!("/search?q=writing&task_id={}", writing["id"]))
+        .await;
+    assert_eq!(results["hits"][0]["kind"], "task");
+    assert_eq!(app.get("/search?q=%20").await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(app.get("/search").await.0, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn answers_graphql_queries() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let (_, client) = app
+        .post(
+            "/clients",
+            json!({"user_id": 0, "name": "Acme", "email": null, "phone": null,
+                   "address": null, "default_rate": null}),
+        )
+        .await;
+    let (_, writing) = app
+        .post(
+            "/tasks",
+            json!({"user_id": 0, "name": "Writing", "description": null,
+                   "client_id": client["id"]}),
+        )
+        .await;
+    let reading = app.add_task("Reading").await;
+    app.add_event(&writing["id"], "2023-12-04T10:00:00Z", 600)
+        .await;
+    app.add_event(&writing["id"], "2023-12-05T10:00:00Z", 1200)
+        .await;
+    app.add_event(&reading["id"], "2023-12-11T10:00:00Z", 300)
+        .await;
+
+    let response = app
+        .graphql(
+            r#"{
+                me { email settings { overlapPolicy } }
+                tasks(sort: NAME) {
+                    tasks {
+                        name
+                        totalDuration
+                        client { name }
+                        events(last: 1) { duration task { name } }
+                    }
+                    nextCursor
+                }
+                report(from: "2023-12-01", to: "2023-12-31", groupBy: WEEK) {
+                    totalDuration
+                    buckets { key totalDuration }
+                }
+            }"#,
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.get("errors"), None);
+    let data = &response["data"];
+    assert_eq!(data["me"]["email"], "someone@example.com");
+    assert_eq!(data["me"]["settings"]["overlapPolicy"], "ALLOW");
+    assert_eq!(
+        data["tasks"],
+        json!({
+            "tasks": [
+                {
+                    "name": "Reading",
+                    "totalDuration": 300,
+                    "client": null,
+                    "events": [{"duration": 300, "task": {"name": "Reading"}}],
+                },
+                {
+                    "name": "Writing",
+                    "totalDuration": 1800,
+                    "client": {"name": "Acme"},
+                    "events": [{"duration": 1200, "task": {"name": "Writing"}}],
+                },
+            ],
+            "nextCursor": null,
+        })
+    );
+    assert_eq!(data["report"]["totalDuration"], 2100);
+    assert_eq!(
+        data["report"]["buckets"],
+        json!([
+            {"key": "2023-11-27", "totalDuration": 0},
+            {"key": "2023-12-04", "totalDuration": 1800},
+            {"key": "2023-12-11", "totalDuration": 300},
+            {"key": "2023-12-18", "totalDuration": 0},
+            {"key": "2023-12-25", "totalDuration": 0},
+        ])
+    );
+
+    // someone else's task is as good as missing
+    let mut other = TestApp {
+        router: app.router.clone(),
+        cookie: None,
+    };
+    other.register("other@example.com").await;
+    other.login("other@example.com", PASSWORD).await;
+    let response = other
+        .graphql(
+            "query ($id: Int!) { task(id: $id) { name } }",
+            json!({"id": writing["id"]}),
+        )
+        .await;
+    assert_eq!(response["data"]["task"], Value::Null);
+}
+
+#[tokio::test]
+async fn runs_graphql_mutations() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let response = app
+        .graphql(
+            r#"mutation { addTask(input: {name: "Writing", tags: ["work"]}) { id name tags } }"#,
+            json!({}),
+        )
+        .await;
+    let task = &response["data"]["addTask"];
+    assert_eq!(task["name"], "Writing");
+    assert_eq!(task["tags"], json!(["work"]));
+
+    // the same rules as the REST API, with its codes and details
+    let response = app
+        .graphql(
+            r#"mutation { addTask(input: {name: " "}) { id } }"#,
+            json!({}),
+        )
+        .await;
+    let error = &response["errors"][0];
+    assert_eq!(error["extensions"]["code"], "unprocessable");
+    assert_eq!(
+        error["extensions"]["details"]["name"],
+        json!(["must not be empty"])
+    );
+
+    let response = app
+        .graphql(
+            r#"mutation ($id: Int!) {
+                updateTask(id: $id, input: {name: "Editing"}) { name }
+                addEvent(input: {taskId: $id, dateBegan: "2023-12-01T10:00:00Z", duration: 600}) {
+                    duration
+                    task { name totalDuration }
+                }
+            }"#,
+            json!({"id": task["id"]}),
+        )
+        .await;
+    assert_eq!(response.get("errors"), None);
+    assert_eq!(response["data"]["updateTask"]["name"], "Editing");
+    assert_eq!(
+        response["data"]["addEvent"]["task"],
+        json!({"name": "Editing", "totalDuration": 600})
+    );
+
+    let response = app
+        .graphql(
+            r#"mutation ($id: Int!) {
+                updateSettings(overlapPolicy: REJECT) { overlapPolicy }
+                startTimer(taskId: $id, notes: "drafting") { notes task { name } }
+            }"#,
+            json!({"id": task["id"]}),
+        )
+        .await;
+    assert_eq!(
+        response["data"]["startTimer"],
+        json!({"notes": "drafting", "task": {"name": "Editing"}})
+    );
+    let response = app
+        .graphql("mutation { stopTimer { notes } }", json!({}))
+        .await;
+    assert_eq!(response["data"]["stopTimer"]["notes"], "drafting");
+    let (_, timer) = app.get("/timer").await;
+    assert_eq!(timer, Value::Null);
+}
+
+#[tokio::test]
+async fn limits_graphql_queries() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let response = app
+        .graphql(
+            "{ tasks { tasks { events(last: 1) { task { events(last: 1) { task { \
+             events(last: 1) { task { events(last: 1) { task { name } } } } } } } } } } }",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response["data"], Value::Null);
+    assert_eq!(
+        response["errors"][0]["message"],
+        "Query is nested too deep."
+    );
+
+    // every task's events as well as a page of tasks
+    let response = app
+        .graphql(
+            "{ tasks(limit: 100) { tasks { events { id } } } }",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response["errors"][0]["message"], "Query is too complex.");
+    let response = app
+        .graphql(
+            "{ tasks(limit: 100) { tasks { events(last: 5) { id } } } }",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.get("errors"), None);
+    // no more events are loaded than were counted
+    app.add_task("Writing").await;
+    let response = app
+        .graphql(
+            "{ tasks(limit: 1) { tasks { events(last: 100000) { id } } } }",
+            json!({}),
+        )
+        .await;
+    assert_eq!(
+        response["errors"][0]["message"],
+        "`last` must be between 1 and 200"
+    );
+
+    let anonymous = TestApp::new().await;
+    let (status, _) = anonymous
+        .post("/graphql", json!({"query": "{ me { email } }"}))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn limits_the_range_of_reports() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let writing = app.add_task("Writing").await;
+    app.add_event(&writing["id"], "2023-12-01T10:00:00Z", 600)
+        .await;
+
+    let (status, summary) = app
+        .get("/reports/summary?from=2023-12-01&to=2023-12-07")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["buckets"].as_array().unwrap().len(), 7);
+
+    // a thousand days, then a day too many
+    let (status, _) = app
+        .get("/reports/summary?from=2021-03-07&to=2023-12-01")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = app
+        .get("/reports/summary?from=2021-03-06&to=2023-12-01")
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["message"],
+        "A report has at most 1000 days, weeks or months, group it by a longer period"
+    );
+    let csv = app
+        .respond(
+            Method::GET,
+            "/reports/summary.csv?from=2000-01-01&to=2023-12-31",
+            None,
+        )
+        .await;
+    assert_eq!(csv.status(), StatusCode::BAD_REQUEST);
+    let response = app
+        .graphql(
+            r#"{ report(from: "2000-01-01", to: "2023-12-31") { totalDuration } }"#,
+            json!({}),
+        )
+        .await;
+    assert_eq!(
+        response["errors"][0]["message"],
+        "A report has at most 1000 days, weeks or months, group it by a longer period"
+    );
+
+    // by month, or by task, the years are fine
+    for group_by in ["month", "task"] {
+        let uri = format!(
+            "/reports/summary?from=2000-01-01&to=2023-12-31&group_by={}",
+            group_by
+        );
+        assert_eq!(app.get(&uri).await.0, StatusCode::OK);
+    }
+    let (status, body) = app
+        .get("/reports/summary?from=2023-12-01&to=%2B10000-01-01&group_by=task")
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["message"],
+        "Dates must be between 0001-01-01 and 9999-12-31"
+    );
+}
+
+#[tokio::test]
+async fn escapes_formulas_in_csv_exports() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let task = app.add_task("=HYPERLINK(\"http://example.com\")").await;
+    app.add_event(&task["id"], "2023-12-01T10:00:00Z", 600)
+        .await;
+
+    let export = app
+        .respond(Method::GET, "/events/export.csv?columns=task,tags", None)
+        .await;
+    let (status, body) = read_text(export).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        "task,tags\n\"'=HYPERLINK(\"\"http://example.com\"\")\",work\n"
+    );
+
+    let summary = app
+        .respond(
+            Method::GET,
+            "/reports/summary.csv?from=2023-12-01&to=2023-12-01&group_by=task&columns=key",
+            None,
+        )
+        .await;
+    let (status, body) = read_text(summary).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "key\n\"'=HYPERLINK(\"\"http://example.com\"\")\"\n");
+}
+
+#[tokio::test]
+async fn serves_health_and_metrics() {
+    let app = TestApp::new().await;
+    let health = app.respond(Method::GET, "/healthz", None).await;
+    assert_eq!(read_text(health).await, (StatusCode::OK, "OK".to_string()));
+    let readiness = app.respond(Method::GET, "/readyz", None).await;
+    assert_eq!(
+        read_text(readiness).await,
+        (StatusCode::OK, "Ready".to_string())
+    );
+
+    let app = TestApp::logged_in("someone@example.com").await;
+    let writing = app.add_task("Writing").await;
+    let task = format!("/tasks/{}", writing["id"]);
+    app.get(&task).await;
+    app.get(&task).await;
+    app.get("/nowhere").await;
+    app.post("/timer/start", json!({"task_id": writing["id"]}))
+        .await;
+
+    let response = app.respond(Method::GET, "/metrics", None).await;
+    assert_eq!(
+        response.headers()[CONTENT_TYPE],
+        "application/openmetrics-text; version=1.0.0; charset=utf-8"
+    );
+    let (status, text) = read_text(response).await;
+    assert_eq!(status, StatusCode::OK);
+    let lines: Vec<&str> = text.lines().collect();
+    for expected in [
+        // by route, not by path
+        r#"bandit_http_requests_total{method="GET",route="/tasks/:task_id",status="200"} 2"#,
+        r#"bandit_http_requests_total{method="GET",route="unmatched",status="404"} 1"#,
+        r#"bandit_http_request_duration_seconds_count{method="GET",route="/tasks/:task_id"} 2"#,
+        "bandit_sessions 1",
+        "bandit_running_timers 1",
+        r#"bandit_db_pool_connections{state="idle"} 0"#,
+        "bandit_db_pool_max_connections 0",
+    ] {
+        assert!(lines.contains(&expected), "no {} in\n{}", expected, text);
+    }
+    assert_eq!(lines.last(), Some(&"# EOF"));
+}
+
+#[tokio::test]
+async fn is_ready_once_migrated() {
+    let config = Config::for_tests();
+    let store = store::connect("sqlite::memory:", &config.pool)
+        .await
+        .unwrap();
+    let router = router(store.clone(), &config).await;
+    let readiness = || async {
+        let request = Request::get("/readyz").body(Body::empty()).unwrap();
+        read_text(router.clone().oneshot(request).await.unwrap()).await
+    };
+    let (status, text) = readiness().await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(text.ends_with("migrations are pending"), "{}", text);
+
+    store.run_migrations().await.unwrap();
+    assert_eq!(readiness().await, (StatusCode::OK, "Ready".to_string()));
+}
+
+#[tokio::test]
+async fn checks_readiness_without_writing() {
+    let config = Config::for_tests();
+    let path = std::env::temp_dir().join(format!("bandit-{}.db", uuid::Uuid::new_v4()));
+    let url = format!("sqlite://{}", path.display());
+    let writable = store::connect(&url, &config.pool).await.unwrap();
+    // a database that can't be written to, so creating the table of
+    // migrations would fail
+    let read_only = store::connect(&format!("{}?mode=ro", url), &config.pool)
+        .await
+        .unwrap();
+    let router = router(read_only, &config).await;
+    let readiness = || async {
+        let request = Request::get("/readyz").body(Body::empty()).unwrap();
+        read_text(router.clone().oneshot(request).await.unwrap()).await
+    };
+    let (status, text) = readiness().await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(text.ends_with("migrations are pending"), "{}", text);
+
+    writable.run_migrations().await.unwrap();
+    assert_eq!(readiness().await, (StatusCode::OK, "Ready".to_string()));
+    std::fs::remove_file(&path).ok();
+}
+
+#[tokio::test]
+async fn tells_unknown_calendars_from_database_failures() {
+    let app = TestApp::new().await;
+    let (status, _) = app.get("/calendar/nonsense.ics").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // without its tables every query fails, which must not read as a
+    // revoked token to calendar apps
+    let config = Config::for_tests();
+    let store = store::connect("sqlite::memory:", &config.pool)
+        .await
+        .unwrap();
+    let app = TestApp {
+        router: router(store, &config).await,
+        cookie: None,
+    };
+    let (status, _) = app.get("/calendar/nonsense.ics").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn delivers_signed_webhooks_with_retries() {
+    let store = Arc::new(MemoryStore::default());
+    let mut app = TestApp::with_store(store.clone()).await;
+    app.register("someone@example.com").await;
+    app.login("someone@example.com", PASSWORD).await;
+    let mut config = Config::for_tests().webhooks;
+    config.retry_delay = Duration::from_millis(50);
+    config.poll_interval = Duration::from_millis(20);
+    // the receiver is on this host
+    config.allow_private_targets = true;
+    let worker = webhooks::spawn(store, config);
+    let (url, mut received) = webhook_receiver(vec![StatusCode::INTERNAL_SERVER_ERROR]).await;
+
+    let (status, _) = app
+        .post(
+            "/webhooks",
+            json!({"url": "file:///etc/passwd", "event_types": ["task.created"]}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, webhook) = app
+        .post(
+            "/webhooks",
+            json!({"url": url, "event_types": ["task.created", "timer.stopped"]}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let secret = webhook["secret"].as_str().unwrap();
+    assert_eq!(app.get("/webhooks").await.1, json!([webhook]));
+
+    let task = app.add_task("Writing").await;
+    // refused first, then tried again with the same body
+    let first = next_received(&mut received).await;
+    let second = next_received(&mut received).await;
+    assert_eq!(first.body, second.body);
+    for attempt in [&first, &second] {
+        let header = |name: &str| attempt.headers[name].to_str().unwrap().to_string();
+        let timestamp: i64 = header(webhooks::TIMESTAMP_HEADER).parse().unwrap();
+        assert_eq!(
+            header(webhooks::SIGNATURE_HEADER),
+            webhooks::sign(secret, timestamp, &attempt.body)
+        );
+        assert_eq!(header(webhooks::EVENT_HEADER), "task.created");
+    }
+    let payload: Value = serde_json::from_str(&first.body).unwrap();
+    assert_eq!(payload["type"], "task.created");
+    assert_eq!(payload["data"], task);
+
+    let deliveries_uri = format!("/webhooks/{}/deliveries", webhook["id"]);
+    let delivery = delivered(&app, &deliveries_uri).await;
+    assert_eq!(delivery["id"], payload["id"]);
+    assert_eq!(delivery["attempts"], 2);
+    assert_eq!(delivery["payload"], payload);
+    let statuses: Vec<&Value> = delivery["log"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|attempt| &attempt["status_code"])
+        .collect();
+    assert_eq!(statuses, [&json!(204), &json!(500)]);
+
+    let (status, redelivered) = app
+        .post(
+            &format!("{}/{}/redeliver", deliveries_uri, delivery["id"]),
+            json!(null),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(redelivered["status"], "pending");
+    assert_eq!(next_received(&mut received).await.body, first.body);
+    assert_eq!(
+        delivered(&app, &deliveries_uri).await["log"][0]["status_code"],
+        204
+    );
+
+    let mut other = TestApp {
+        router: app.router.clone(),
+        cookie: None,
+    };
+    other.register("other@example.com").await;
+    other.login("other@example.com", PASSWORD).await;
+    assert_eq!(other.get(&deliveries_uri).await.0, StatusCode::NOT_FOUND);
+
+    let uri = format!("/webhooks/{}", webhook["id"]);
+    assert_eq!(app.send(Method::DELETE, &uri, None).await.0, StatusCode::OK);
+    assert_eq!(app.get("/webhooks").await.1, json!([]));
+    worker.abort();
+}
+
+#[tokio::test]
+async fn sends_no_webhooks_to_private_addresses() {
+    let store = Arc::new(MemoryStore::default());
+    let mut app = TestApp::with_store(store.clone()).await;
+    app.register("someone@example.com").await;
+    app.login("someone@example.com", PASSWORD).await;
+    let mut config = Config::for_tests().webhooks;
+    config.max_attempts = 1;
+    config.poll_interval = Duration::from_millis(20);
+    let worker = webhooks::spawn(store, config);
+    let (url, mut received) = webhook_receiver(vec![]).await;
+    let named = url.replace("127.0.0.1", "localhost");
+
+    let mut deliveries = vec![];
+    for url in [&url, &named] {
+        let (status, webhook) = app
+            .post(
+                "/webhooks",
+                json!({"url": url, "event_types": ["task.created"]}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        deliveries.push(format!("/webhooks/{}/deliveries", webhook["id"]));
+    }
+    app.add_task("Writing").await;
+
+    for uri in &deliveries {
+        let delivery = timeout(Duration::from_secs(5), async {
+            loop {
+                let (_, page) = app.get(uri).await;
+                if page["deliveries"][0]["status"] == "failed" {
+                    return page["deliveries"][0].clone();
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("not given up within 5s");
+        assert_eq!(delivery["log"][0]["status_code"], Value::Null);
+        let error = delivery["log"][0]["error"].as_str().unwrap();
+        assert!(error.contains("public address"), "{}", error);
+    }
+    assert!(received.try_recv().is_err());
+    worker.abort();
+}
+
+/// The latest delivery of a webhook, once it has been delivered
+async fn delivered(app: &TestApp, deliveries_uri: &str) -> Value {
+    timeout(Duration::from_secs(5), async {
+        loop {
+            let (status, page) = app.get(deliveries_uri).await;
+            assert_eq!(status, StatusCode::OK);
+            if page["deliveries"][0]["status"] == "delivered" {
+                return page["deliveries"][0].clone();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("not delivered within 5s")
+}
+
+#[tokio::test]
+async fn pages_through_tasks() {
+    let app = TestApp::logged_in("someone@example.com").await;
+    let writing = app.add_task("Writing").await;
+    let reading = app.add_task("Reading").await;
+    app.add_task("Rewriting").await;
+    app.add_event(&writing["id"], "2023-12-01T10:00:00Z", 600)
+        .await;
+    app.add_event(&writing["id"], "2023-12-03T10:00:00Z", 60)
+        .await;
+    app.add_event(&reading["id"], "2023-12-02T10:00:00Z", 1200)
+        .await;
+
+    let (status, page) = app
+        .get("/tasks?sort=total_duration&limit=2&include_events=recent:1")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<&Value> = page["tasks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|task| &task["task"]["name"])
+        .collect();
+    assert_eq!(names, vec!["Reading", "Writing"]);
+    // only the latest event, but the total of all of them
+    assert_eq!(page["tasks"][1]["events"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        page["tasks"][1]["events"][0]["date_began"],
+        "2023-12-03T10:00:00Z"
+    );
+    assert_eq!(page["tasks"][1]["total_duration"], 660);
+
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let (status, page) = app
+        .get(&format!(
+            "/tasks?sort=total_duration&limit=2&cursor={}",
+            cursor
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["tasks"].as_array().unwrap().len(), 1);
+    assert_eq!(page["tasks"][0]["task"]["name"], "Rewriting");
+    assert_eq!(page["next_cursor"], Value::Null);
+
+    let (_, page) = app
+        .get("/tasks?sort=name&q=WRIT&include_events=false")
+        .await;
+    assert_eq!(page["tasks"][0]["task"]["name"], "Rewriting");
+    assert_eq!(page["tasks"][1]["task"]["name"], "Writing");
+    assert_eq!(page["tasks"][1]["events"], json!([]));
+    assert_eq!(page["tasks"].as_array().unwrap().len(), 2);
+
+    let (_, page) = app
+        .get("/tasks?updated_from=2023-12-02&updated_to=2023-12-02")
+        .await;
+    assert_eq!(page["tasks"].as_array().unwrap().len(), 1);
+    assert_eq!(page["tasks"][0]["task"]["name"], "Reading");
+
+    let (status, _) = app
+        .get(&format!("/tasks?sort=name&cursor={}", cursor))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        app.get("/tasks?cursor=nonsense").await.0,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(app.get("/tasks?limit=0").await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        app.get("/tasks?include_events=recent:0").await.0,
+        StatusCode::BAD_REQUEST
+    );
+}
@@ -0,0 +1,458 @@
+//! Field-level validation of request bodies.
+//!
+//! Handlers take `Valid<T>` instead of `Json<T>` for bodies with rules. A body
+//! that breaks any of them is rejected with 422 and every failing field in
+//! `details`, so a form can show all of its errors at once.
+
+use std::{collections::BTreeMap, fmt};
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, Request},
+};
+use chrono::{Duration, Utc};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    error::AppError,
+    extract::Json,
+    models::{LoginDetails, NewClient, NewTask, NewTaskEvent, NewWebhook, StartTimer, StopTimer},
+};
+
+/// Clocks of clients may run a little ahead of the server's
+const CLOCK_SKEW_MINUTES: i64 = 5;
+
+const MAX_NAME_LENGTH: usize = 200;
+const MAX_TEXT_LENGTH: usize = 5000;
+const MAX_TAG_LENGTH: usize = 50;
+const MAX_TAGS: usize = 20;
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 128;
+const MAX_URL_LENGTH: usize = 2000;
+
+/// The longest an event may last, a year in seconds, which keeps its end
+/// well within what chrono and the databases can count to
+pub const MAX_DURATION: i64 = 366 * 24 * 3600;
+
+pub trait Validate {
+    fn validate(&self, errors: &mut FieldErrors);
+}
+
+/// What is wrong with each field of a body, by field name
+#[derive(Debug, Default, PartialEq)]
+pub struct FieldErrors(BTreeMap<String, Vec<String>>);
+
+impl FieldErrors {
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0
+            .entry(field.to_string())
+            .or_default()
+            .push(message.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Each problem as its field and message
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().flat_map(|(field, messages)| {
+            messages
+                .iter()
+                .map(move |message| (field.as_str(), message.as_str()))
+        })
+    }
+
+    /// Everything wrong with a value, which came some other way than as a
+    /// body and may be reported without failing the request
+    pub fn of<T: Validate>(value: &T) -> FieldErrors {
+        let mut errors = FieldErrors::default();
+        value.validate(&mut errors);
+        errors
+    }
+
+    fn length(&mut self, field: &str, value: &str, max: usize) {
+        if value.trim().is_empty() {
+            self.add(field, "must not be empty");
+        } else if value.chars().count() > max {
+            self.add(field, format!("must be at most {} characters", max));
+        }
+    }
+
+    fn max_length(&mut self, field: &str, value: Option<&str>, max: usize) {
+        if value.is_some_and(|value| value.chars().count() > max) {
+            self.add(field, format!("must be at most {} characters", max));
+        }
+    }
+
+    fn email(&mut self, field: &str, value: &str) {
+        if !is_email(value) {
+            self.add(field, "must be an email address");
+        }
+    }
+}
+
+impl fmt::Display for FieldErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let problems: Vec<String> = self
+            .0
+            .iter()
+            .flat_map(|(field, messages)| {
+                messages
+                    .iter()
+                    .map(move |message| format!("{} {}", field, message))
+            })
+            .collect();
+        write!(f, "{}", problems.join(", "))
+    }
+}
+
+impl From<FieldErrors> for AppError {
+    fn from(errors: FieldErrors) -> Self {
+        let fields: Vec<&str> = errors.0.keys().map(String::as_str).collect();
+        AppError::Unprocessable {
+            message: format!("Invalid {}", fields.join(", ")),
+            details: Some(serde_json::to_value(&errors.0).unwrap()),
+        }
+    }
+}
+
+/// A JSON body which passed its `Validate` rules
+pub struct Valid<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for Valid<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(request, state).await?;
+        Valid::check(value)
+    }
+}
+
+impl<T: Validate> Valid<T> {
+    /// Checks a value that came some other way than as a JSON body
+    pub fn check(value: T) -> Result<Self, AppError> {
+        let errors = FieldErrors::of(&value);
+        if !errors.is_empty() {
+            return Err(errors.into());
+        }
+        Ok(Valid(value))
+    }
+}
+
+/// Only a plausibility check, whether the address exists shows when mail
+/// bounces
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    value.len() <= 254
+        && !value.chars().any(char::is_whitespace)
+        && !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+}
+
+impl Validate for LoginDetails {
+    fn validate(&self, errors: &mut FieldErrors) {
+        errors.email("email", &self.email.0);
+        let length = self.password.chars().count();
+        if length < MIN_PASSWORD_LENGTH {
+            errors.add(
+                "password",
+                format!("must be at least {} characters", MIN_PASSWORD_LENGTH),
+            );
+        } else if length > MAX_PASSWORD_LENGTH {
+            errors.add(
+                "password",
+                format!("must be at most {} characters", MAX_PASSWORD_LENGTH),
+            );
+        }
+    }
+}
+
+impl Validate for NewTask {
+    fn validate(&self, errors: &mut FieldErrors) {
+        errors.length("name", &self.name, MAX_NAME_LENGTH);
+        errors.max_length("description", self.description.as_deref(), MAX_TEXT_LENGTH);
+        if self.tags.len() > MAX_TAGS {
+            errors.add("tags", format!("must be at most {} tags", MAX_TAGS));
+        }
+        if self.tags.iter().any(|tag| tag.trim().is_empty()) {
+            errors.add("tags", "must not contain empty tags");
+        }
+        if self
+            .tags
+            .iter()
+            .any(|tag| tag.chars().count() > MAX_TAG_LENGTH)
+        {
+            errors.add(
+                "tags",
+                format!("must each be at most {} characters", MAX_TAG_LENGTH),
+            );
+        }
+    }
+}
+
+impl Validate for NewTaskEvent {
+    fn validate(&self, errors: &mut FieldErrors) {
+        if self.duration < 0 {
+            errors.add("duration", "must not be negative");
+        } else if self.duration > MAX_DURATION {
+            errors.add(
+                "duration",
+                format!("must be at most {} seconds", MAX_DURATION),
+            );
+        }
+        if self.date_began > Utc::now() + Duration::minutes(CLOCK_SKEW_MINUTES) {
+            errors.add("date_began", "must not be in the future");
+        }
+        errors.max_length("notes", self.notes.as_deref(), MAX_TEXT_LENGTH);
+    }
+}
+
+impl Validate for StartTimer {
+    fn validate(&self, errors: &mut FieldErrors) {
+        errors.max_length("notes", self.notes.as_deref(), MAX_TEXT_LENGTH);
+    }
+}
+
+impl Validate for StopTimer {
+    fn validate(&self, errors: &mut FieldErrors) {
+        errors.max_length("notes", self.notes.as_deref(), MAX_TEXT_LENGTH);
+    }
+}
+
+impl Validate for NewClient {
+    fn validate(&self, errors: &mut FieldErrors) {
+        errors.length("name", &self.name, MAX_NAME_LENGTH);
+        if let Some(email) = self.email.as_deref().filter(|email| !email.is_empty()) {
+            errors.email("email", email);
+        }
+        errors.max_length("phone", self.phone.as_deref(), MAX_NAME_LENGTH);
+        errors.max_length("address", self.address.as_deref(), MAX_TEXT_LENGTH);
+        if self.default_rate.is_some_and(|rate| rate < 0) {
+            errors.add("default_rate", "must not be negative");
+        }
+    }
+}
+
+impl Validate for NewWebhook {
+    fn validate(&self, errors: &mut FieldErrors) {
+        let is_http = reqwest::Url::parse(&self.url)
+            .is_ok_and(|url| ["http", "https"].contains(&url.scheme()) && url.host_str().is_some());
+        if !is_http {
+            errors.add("url", "must be an http or https URL");
+        } else if self.url.len() > MAX_URL_LENGTH {
+            errors.add(
+                "url",
+                format!("must be at most {} characters", MAX_URL_LENGTH),
+            );
+        }
+        if self.event_types.is_empty() {
+            errors.add("event_types", "must not be empty");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::{header::CONTENT_TYPE, StatusCode};
+    use serde_json::json;
+
+    use super::*;
+    use crate::models::{ApiError, TaskId, UserEmail, UserId, WebhookEvent};
+
+    fn errors<T: Validate>(value: &T) -> Vec<(String, Vec<String>)> {
+        let mut errors = FieldErrors::default();
+        value.validate(&mut errors);
+        errors.0.into_iter().collect()
+    }
+
+    fn field(name: &str, messages: &[&str]) -> (String, Vec<String>) {
+        (
+            name.to_string(),
+            messages.iter().map(|message| message.to_string()).collect(),
+        )
+    }
+
+    fn task(name: &str, tags: &[&str]) -> NewTask {
+        NewTask {
+            user_id: UserId(1),
+            name: name.to_string(),
+            description: None,
+            client_id: None,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn checks_emails() {
+        assert!(is_email("someone@example.com"));
+        assert!(is_email("first.last+tag@mail.example.co.uk"));
+        assert!(!is_email("someone"));
+        assert!(!is_email("@example.com"));
+        assert!(!is_email("someone@localhost"));
+        assert!(!is_email("some one@example.com"));
+        assert!(!is_email("someone@example.com."));
+        assert!(!is_email("a@b@example.com"));
+    }
+
+    #[test]
+    fn validates_login_details() {
+        let details = LoginDetails {
+            email: UserEmail("nope".to_string()),
+            password: "x".to_string(),
+        };
+        assert_eq!(
+            errors(&details),
+            vec![
+                field("email", &["must be an email address"]),
+                field("password", &["must be at least 8 characters"]),
+            ]
+        );
+        let details = LoginDetails {
+            email: UserEmail("someone@example.com".to_string()),
+            password: "correct horse".to_string(),
+        };
+        assert!(errors(&details).is_empty());
+    }
+
+    #[test]
+    fn validates_tasks() {
+        assert!(errors(&task("Writing", &["work"])).is_empty());
+        assert_eq!(
+            errors(&task("  ", &["", &"x".repeat(51)])),
+            vec![
+                field("name", &["must not be empty"]),
+                field(
+                    "tags",
+                    &[
+                        "must not contain empty tags",
+                        "must each be at most 50 characters"
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_events() {
+        let event = NewTaskEvent {
+            user_id: UserId(1),
+            task_id: TaskId(1),
+            date_began: Utc::now() + Duration::days(1),
+            duration: -60,
+            notes: None,
+        };
+        assert_eq!(
+            errors(&event),
+            vec![
+                field("date_began", &["must not be in the future"]),
+                field("duration", &["must not be negative"]),
+            ]
+        );
+        let event = NewTaskEvent {
+            date_began: Utc::now() - Duration::hours(1),
+            duration: i64::MAX,
+            ..event
+        };
+        assert_eq!(
+            errors(&event),
+            vec![field("duration", &["must be at most 31622400 seconds"])]
+        );
+        let event = NewTaskEvent {
+            duration: 0,
+            ..event
+        };
+        assert!(errors(&event).is_empty());
+    }
+
+    #[test]
+    fn validates_clients() {
+        let client = NewClient {
+            user_id: UserId(1),
+            name: "Acme".to_string(),
+            email: Some("billing@acme".to_string()),
+            phone: None,
+            address: None,
+            default_rate: Some(-1),
+        };
+        assert_eq!(
+            errors(&client),
+            vec![
+                field("default_rate", &["must not be negative"]),
+                field("email", &["must be an email address"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_webhooks() {
+        let webhook = NewWebhook {
+            url: "ftp://example.com/hook".to_string(),
+            event_types: vec![],
+        };
+        assert_eq!(
+            errors(&webhook),
+            vec![
+                field("event_types", &["must not be empty"]),
+                field("url", &["must be an http or https URL"]),
+            ]
+        );
+        let webhook = NewWebhook {
+            url: "https://example.com/hook".to_string(),
+            event_types: vec![WebhookEvent::EventCreated],
+        };
+        assert!(errors(&webhook).is_empty());
+    }
+
+    async fn extract(body: serde_json::Value) -> Result<Valid<NewTask>, (StatusCode, ApiError)> {
+        let request = Request::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        match Valid::<NewTask>::from_request(request, &()).await {
+            Ok(valid) => Ok(valid),
+            Err(e) => {
+                let response = axum::response::IntoResponse::into_response(e);
+                let status = response.status();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                Err((status, serde_json::from_slice(&bytes).unwrap()))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_bodies_with_every_failing_field() {
+        let body = json!({"user_id": 0, "name": "", "description": null, "tags": [""]});
+        let (status, error) = extract(body).await.err().unwrap();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.code, "unprocessable");
+        assert_eq!(error.message, "Invalid name, tags");
+        assert_eq!(
+            error.details,
+            Some(json!({
+                "name": ["must not be empty"],
+                "tags": ["must not contain empty tags"],
+            }))
+        );
+
+        let body = json!({"user_id": 0, "name": "Writing", "description": null});
+        let Valid(task) = extract(body).await.ok().unwrap();
+        assert_eq!(task.name, "Writing");
+
+        let (status, _) = extract(json!({"user_id": 0})).await.err().unwrap();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
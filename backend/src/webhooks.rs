@@ -0,0 +1,407 @@
+//! Sending of the deliveries queued for users' webhooks.
+//!
+//! Triggers queue a delivery for each change a webhook wants, in the same
+//! transaction as the change. Every server runs a worker that claims the
+//! deliveries that are due, so no two servers send one at the same time, and
+//! posts them. A delivery that is not answered with a 2xx is tried again
+//! after a delay that doubles each time, until it runs out of attempts.
+//!
+//! The body is a `WebhookPayload`. Receivers check that it came from us with
+//! `X-Bandit-Signature`, which is `sha256=` and the hex HMAC-SHA256 of
+//! `{X-Bandit-Timestamp}.{body}` under the webhook's secret.
+//!
+//! Users choose the URLs, so unless the config allows it, nothing is sent to
+//! loopback, private or link-local addresses. The names are checked as they
+//! are resolved for each delivery, so a name that later points inside the
+//! network is refused too, and redirects are not followed.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::Utc;
+use futures::future::join_all;
+use hmac::{Hmac, Mac};
+use http::header::CONTENT_TYPE;
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect, Url,
+};
+use serde_json::Value;
+use sha2::Sha256;
+use sqlx::Error;
+use tokio::{
+    task::JoinHandle,
+    time::{interval, Instant, MissedTickBehavior},
+};
+
+use crate::{
+    config::WebhookConfig,
+    models::{DeliveryStatus, Notification, WebhookAttempt, WebhookPayload},
+    store::{DueDelivery, Store},
+};
+
+pub const SIGNATURE_HEADER: &str = "x-bandit-signature";
+pub const TIMESTAMP_HEADER: &str = "x-bandit-timestamp";
+pub const EVENT_HEADER: &str = "x-bandit-event";
+pub const DELIVERY_HEADER: &str = "x-bandit-delivery";
+
+/// Deliveries claimed at a time
+const BATCH_SIZE: i64 = 20;
+
+/// The longest wait between two attempts at a delivery
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Runs the worker sending the deliveries that are due until the server
+/// stops
+pub fn spawn(store: Arc<dyn Store>, config: WebhookConfig) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut builder = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .redirect(redirect::Policy::none())
+            // a proxy would resolve the names itself
+            .no_proxy();
+        if !config.allow_private_targets {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        let client = builder.build().expect("Cannot build the webhook client");
+        let mut ticks = interval(config.poll_interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            // a full batch means there may be more that are due
+            loop {
+                match deliver_due(&*store, &client, &config).await {
+                    Ok(claimed) if claimed == BATCH_SIZE as usize => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::event!(tracing::Level::ERROR, "webhooks: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Sends a batch of the deliveries that are due, returning how many there
+/// were
+async fn deliver_due(
+    store: &dyn Store,
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+) -> Result<usize, Error> {
+    // held until they are bound to have timed out, should this server stop
+    // halfway
+    let until = Utc::now() + chrono::Duration::from_std(config.timeout * 2).unwrap();
+    let due = store.claim_webhook_deliveries(BATCH_SIZE, until).await?;
+    let claimed = due.len();
+    let sent = due
+        .into_iter()
+        .map(|delivery| deliver(store, client, config, delivery));
+    for result in join_all(sent).await {
+        if let Err(e) = result {
+            tracing::event!(tracing::Level::ERROR, "webhook delivery: {:?}", e);
+        }
+    }
+    Ok(claimed)
+}
+
+/// Makes one attempt at a delivery and logs it
+async fn deliver(
+    store: &dyn Store,
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+    delivery: DueDelivery,
+) -> Result<(), Error> {
+    let payload = match delivery.payload.clone() {
+        Some(payload) => payload,
+        None => render(store, &delivery).await?,
+    };
+    let timestamp = Utc::now().timestamp();
+    let attempted_on = Utc::now();
+    let started = Instant::now();
+    let response = match check_target(config, &delivery.url) {
+        Ok(()) => client
+            .post(&delivery.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(
+                SIGNATURE_HEADER,
+                sign(&delivery.secret, timestamp, &payload),
+            )
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(EVENT_HEADER, delivery.event_type.as_str())
+            .header(DELIVERY_HEADER, delivery.id.0)
+            .body(payload.clone())
+            .send()
+            .await
+            .map_err(|e| sent_error(&e)),
+        Err(refused) => Err(refused),
+    };
+    // only the status of a response is kept, what the receiver said is none
+    // of the user's business
+    let (delivered, status_code, error) = match response {
+        Ok(response) => (
+            response.status().is_success(),
+            Some(response.status().as_u16().into()),
+            None,
+        ),
+        Err(e) => (false, None, Some(e)),
+    };
+    let attempt = WebhookAttempt {
+        attempted_on,
+        status_code,
+        error,
+        duration_ms: started.elapsed().as_millis() as i64,
+    };
+    let attempts = delivery.attempts + 1;
+    let (status, next_attempt) = if delivered {
+        (DeliveryStatus::Delivered, None)
+    } else if attempts >= config.max_attempts {
+        (DeliveryStatus::Failed, None)
+    } else {
+        let delay = chrono::Duration::from_std(retry_delay(config, attempts)).unwrap();
+        (DeliveryStatus::Pending, Some(Utc::now() + delay))
+    };
+    store
+        .record_webhook_attempt(delivery.id, payload, attempt, status, next_attempt)
+        .await
+}
+
+/// Refuses a URL whose host is an address that is not public, which the
+/// resolver never sees
+fn check_target(config: &WebhookConfig, url: &str) -> Result<(), String> {
+    if config.allow_private_targets {
+        return Ok(());
+    }
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string));
+    let ip = host.and_then(|host| {
+        host.trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .ok()
+    });
+    match ip {
+        Some(ip) if !is_public(ip) => Err(format!("{} is not a public address", ip)),
+        _ => Ok(()),
+    }
+}
+
+/// What went wrong sending a request, with its cause, which `reqwest` keeps
+/// out of its message
+fn sent_error(e: &reqwest::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        message = format!("{}: {}", message, cause);
+        source = cause.source();
+    }
+    message
+}
+
+/// Resolves names to their public addresses only, failing if there are none
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let all: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            let public: Vec<SocketAddr> = all
+                .into_iter()
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if public.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            let addrs: Addrs = Box::new(public.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Whether an address is on the internet, rather than this host, a private
+/// network, a link or one of the blocks reserved for other uses
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // this network, shared address space, IETF protocol assignments,
+        // benchmarking and reserved
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // unique local, link-local, documentation and IPv4-compatible
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8)
+        || ip.to_ipv4().is_some())
+}
+
+/// The body of a delivery, made for its first attempt: what changed as it is
+/// now, or only the ids of it if it is gone
+async fn render(store: &dyn Store, delivery: &DueDelivery) -> Result<String, Error> {
+    let change = &delivery.change;
+    let data = match &change.notification {
+        Notification::TaskCreated { id } | Notification::TaskUpdated { id } => {
+            found(store.get_task_by_id(id.clone()).await)?
+        }
+        Notification::EventCreated { id, .. } | Notification::EventUpdated { id, .. } => found(
+            store
+                .get_event(id.clone(), change.user_id.clone())
+                .await
+                .map(|listed| listed.event),
+        )?,
+        _ => None,
+    };
+    let data = data.unwrap_or_else(|| {
+        let mut ids = serde_json::to_value(&change.notification).unwrap();
+        if let Value::Object(fields) = &mut ids {
+            fields.remove("type");
+        }
+        ids
+    });
+    let payload = WebhookPayload {
+        id: delivery.id.clone(),
+        event_type: delivery.event_type,
+        created_on: delivery.created_on,
+        data,
+    };
+    Ok(serde_json::to_string(&payload).unwrap())
+}
+
+/// The JSON of something that was looked up, `None` if it is gone
+fn found<T: serde::Serialize>(result: Result<T, Error>) -> Result<Option<Value>, Error> {
+    match result {
+        Ok(value) => Ok(Some(serde_json::to_value(value).unwrap())),
+        Err(Error::RowNotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The `X-Bandit-Signature` of a body sent at `timestamp`
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// How long to wait after the given number of failed attempts
+fn retry_delay(config: &WebhookConfig, attempts: i32) -> Duration {
+    let doublings = (attempts - 1).clamp(0, 31) as u32;
+    config
+        .retry_delay
+        .saturating_mul(2u32.saturating_pow(doublings))
+        .min(MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn signs_the_timestamp_and_body() {
+        // the same as `printf '1700000000.{}' | openssl dgst -sha256 -hmac secret`
+        assert_eq!(
+            sign("secret", 1700000000, "{}"),
+            "sha256=b8569b78799ff9e3cbff0fc2d63a33a2b57f3282abd07c37ae5e8e7d79a5f163"
+        );
+    }
+
+    #[test]
+    fn doubles_the_delay_up_to_the_longest() {
+        let config = Config::for_tests().webhooks;
+        assert_eq!(retry_delay(&config, 1), Duration::from_secs(30));
+        assert_eq!(retry_delay(&config, 2), Duration::from_secs(60));
+        assert_eq!(retry_delay(&config, 4), Duration::from_secs(240));
+        assert_eq!(retry_delay(&config, 20), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(&config, 1000), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn tells_public_addresses() {
+        for ip in [
+            "93.184.216.34",
+            "2606:2800:220:1::1",
+            "::ffff:93.184.216.34",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn refuses_private_addresses_in_urls() {
+        let mut config = Config::for_tests().webhooks;
+        assert!(check_target(&config, "https://example.com/hook").is_ok());
+        assert!(check_target(&config, "https://93.184.216.34/hook").is_ok());
+        assert_eq!(
+            check_target(&config, "http://127.0.0.1:8080/hook"),
+            Err("127.0.0.1 is not a public address".to_string())
+        );
+        assert_eq!(
+            check_target(&config, "http://[::1]/hook"),
+            Err("::1 is not a public address".to_string())
+        );
+        config.allow_private_targets = true;
+        assert!(check_target(&config, "http://127.0.0.1:8080/hook").is_ok());
+    }
+
+    #[tokio::test]
+    async fn resolves_names_to_public_addresses_only() {
+        let e = PublicResolver
+            .resolve("localhost".parse().unwrap())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(e.to_string(), "localhost has no public address");
+    }
+}
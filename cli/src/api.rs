@@ -0,0 +1,181 @@
+//! A blocking client for the backend's HTTP API
+
+use reqwest::{
+    blocking::{Client, RequestBuilder},
+    header::{HeaderMap, COOKIE, SET_COOKIE},
+    StatusCode,
+};
+use serde::de::DeserializeOwned;
+use time_bandit_models::{
+    ApiError, IncludeEvents, LoginDetails, NewTask, NewTaskEvent, ReportQuery, ReportSummary,
+    StartTimer, StopTimer, Task, TaskEvent, TaskId, TaskListQuery, TaskPage, TaskSort,
+    TaskWithTaskEvents, Timer, UserId,
+};
+
+use crate::config::Config;
+
+/// The most tasks the backend sends in one page
+const TASK_PAGE_SIZE: i64 = 200;
+
+pub struct Api {
+    client: Client,
+    server: String,
+    session: Option<String>,
+}
+
+impl Api {
+    pub fn new(config: &Config) -> Api {
+        Api {
+            client: Client::new(),
+            server: config.server.trim_end_matches('/').to_string(),
+            session: config.session.clone(),
+        }
+    }
+
+    /// Logs in and returns the session cookie, whatever the server names it
+    pub fn login(&self, details: &LoginDetails) -> Result<String, String> {
+        let response = self
+            .client
+            .post(self.url("/users/login"))
+            .json(details)
+            .send()
+            .map_err(|e| format!("Cannot reach {}: {}", self.server, e))?;
+        if !response.status().is_success() {
+            return Err("Wrong email or password".to_string());
+        }
+        session_cookie(response.headers())
+            .ok_or_else(|| "The server did not start a session".to_string())
+    }
+
+    /// Every task by name, or those whose name contains `name`, without
+    /// their events
+    pub fn tasks(&self, name: Option<&str>) -> Result<Vec<TaskWithTaskEvents>, String> {
+        let mut query = TaskListQuery {
+            sort: TaskSort::Name,
+            q: name.map(str::to_string),
+            updated_from: None,
+            updated_to: None,
+            tz: "UTC".to_string(),
+            include_events: IncludeEvents::None,
+            cursor: None,
+            limit: Some(TASK_PAGE_SIZE),
+        };
+        let mut tasks = vec![];
+        loop {
+            let page: TaskPage = self.send(self.client.get(self.url("/tasks")).query(&query))?;
+            tasks.extend(page.tasks);
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => return Ok(tasks),
+            }
+        }
+    }
+
+    /// The id of the task with this name, which is created if there is none
+    pub fn task_id(&self, name: &str) -> Result<TaskId, String> {
+        if let Some(task) = self
+            .tasks(Some(name))?
+            .into_iter()
+            .find(|task| task.task.name == name)
+        {
+            return Ok(task.task.id);
+        }
+        let new_task = NewTask {
+            // the server fills in the user
+            user_id: UserId(0),
+            name: name.to_string(),
+            description: None,
+            client_id: None,
+            tags: vec![],
+        };
+        let task: Task = self.send(self.client.post(self.url("/tasks")).json(&new_task))?;
+        Ok(task.id)
+    }
+
+    pub fn task(&self, task_id: &TaskId) -> Result<TaskWithTaskEvents, String> {
+        self.send(self.client.get(self.url(&format!("/tasks/{}", task_id.0))))
+    }
+
+    pub fn add_event(&self, new_event: &NewTaskEvent) -> Result<TaskEvent, String> {
+        self.send(self.client.post(self.url("/events")).json(new_event))
+    }
+
+    /// The timer running on the server, which every client of the user shares
+    pub fn timer(&self) -> Result<Option<Timer>, String> {
+        self.send(self.client.get(self.url("/timer")))
+    }
+
+    pub fn start_timer(&self, start: &StartTimer) -> Result<Timer, String> {
+        self.send(self.client.post(self.url("/timer/start")).json(start))
+    }
+
+    /// Stops the timer and returns the event it was saved as
+    pub fn stop_timer(&self, stop: &StopTimer) -> Result<TaskEvent, String> {
+        self.send(self.client.post(self.url("/timer/stop")).json(stop))
+    }
+
+    pub fn report(&self, query: &ReportQuery) -> Result<ReportSummary, String> {
+        self.send(self.client.get(self.url("/reports/summary")).query(query))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.server, path)
+    }
+
+    fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, String> {
+        let Some(session) = &self.session else {
+            return Err("Not logged in, run `time_bandit login` first".to_string());
+        };
+        let response = request
+            .header(COOKIE, session)
+            .send()
+            .map_err(|e| format!("Cannot reach {}: {}", self.server, e))?;
+        match response.status() {
+            StatusCode::UNAUTHORIZED => {
+                Err("The session has expired, run `time_bandit login` again".to_string())
+            }
+            status if status.is_success() => response
+                .json()
+                .map_err(|e| format!("Unexpected response from the server: {}", e)),
+            status => Err(match response.json::<ApiError>() {
+                Ok(error) => error.message,
+                Err(_) => format!("The server answered {}", status),
+            }),
+        }
+    }
+}
+
+/// The cookies set by a login as one `Cookie` header, `name=value; ...`. The
+/// server's `COOKIE_NAME` is not known here, so every cookie is kept.
+fn session_cookie(headers: &HeaderMap) -> Option<String> {
+    let cookies: Vec<&str> = headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .filter_map(|header| header.split(';').next())
+        .map(str::trim)
+        .filter(|cookie| cookie.contains('='))
+        .collect();
+    (!cookies.is_empty()).then(|| cookies.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_session_cookie_whatever_its_name() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(session_cookie(&headers), None);
+        headers.append(
+            SET_COOKIE,
+            "bandit_session=abc123; HttpOnly; SameSite=Lax; Path=/"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(
+            session_cookie(&headers),
+            Some("bandit_session=abc123".to_string())
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! The config file, which holds the server and the session from `login`. It
+//! lives at `$XDG_CONFIG_HOME/time_bandit/config.toml` unless
+//! `TIME_BANDIT_CONFIG` names another file. The running timer is kept by the
+//! server, so other clients see it too.
+
+use std::{env, fs, io::Write, path::PathBuf};
+
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Config {
+    #[serde(default = "default_server")]
+    pub server: String,
+    pub email: Option<String>,
+    /// the session cookie, `name=value`
+    pub session: Option<String>,
+    /// time zone of reports and of the times shown
+    #[serde(default = "default_timezone")]
+    pub tz: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            server: default_server(),
+            email: None,
+            session: None,
+            tz: default_timezone(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Result<PathBuf, String> {
+        if let Some(path) = env::var_os("TIME_BANDIT_CONFIG") {
+            return Ok(PathBuf::from(path));
+        }
+        let config_home = match env::var_os("XDG_CONFIG_HOME") {
+            Some(config_home) => PathBuf::from(config_home),
+            None => env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".config"))
+                .ok_or("Cannot find the config directory, set TIME_BANDIT_CONFIG")?,
+        };
+        Ok(config_home.join("time_bandit").join("config.toml"))
+    }
+
+    /// The saved config, or the defaults before the first `login`
+    pub fn load() -> Result<Config, String> {
+        let path = Config::path()?;
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| format!("Invalid config file {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("Cannot read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Config::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        }
+        let contents = toml::to_string(self).map_err(|e| e.to_string())?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // the file holds the session cookie
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(&path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+    }
+
+    pub fn tz(&self) -> Result<Tz, String> {
+        self.tz
+            .parse()
+            .map_err(|_| format!("Unknown time zone in the config file: {}", self.tz))
+    }
+}
+
+fn default_server() -> String {
+    "http://localhost:8080".to_string()
+}
+
+/// `TZ` if it names a time zone, UTC otherwise
+fn default_timezone() -> String {
+    env::var("TZ")
+        .ok()
+        .filter(|tz| tz.parse::<Tz>().is_ok())
+        .unwrap_or_else(|| "UTC".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_in_missing_settings() {
+        let config: Config = toml::from_str("email = \"a@b.c\"").unwrap();
+        assert_eq!(config.server, "http://localhost:8080");
+        assert!(config.session.is_none());
+    }
+
+    #[test]
+    fn ignores_timers_of_older_versions() {
+        let config: Config = toml::from_str(
+            "email = \"a@b.c\"\n\n[timer]\ntask = \"writing\"\nstarted = \"2023-12-01T09:00:00Z\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.email.as_deref(), Some("a@b.c"));
+        assert!(!toml::to_string(&config).unwrap().contains("timer"));
+    }
+}
@@ -0,0 +1,60 @@
+/// Parses durations such as `1h30m`, `45m`, `90s` or `1.5h` into seconds
+pub fn parse_duration(value: &str) -> Result<i64, String> {
+    let invalid = || format!("`{}` is not a duration like 1h30m", value);
+    let mut seconds = 0.0;
+    let mut number = String::new();
+    for character in value.trim().chars() {
+        let unit = match character.to_ascii_lowercase() {
+            '0'..='9' | '.' => {
+                number.push(character);
+                continue;
+            }
+            'h' => 3600.0,
+            'm' => 60.0,
+            's' => 1.0,
+            _ => return Err(invalid()),
+        };
+        let amount: f64 = number.parse().map_err(|_| invalid())?;
+        seconds += amount * unit;
+        number.clear();
+    }
+    if !number.is_empty() || seconds <= 0.0 {
+        return Err(invalid());
+    }
+    Ok(seconds.round() as i64)
+}
+
+/// Formats seconds like `1h 30m`, dropping the seconds of longer durations
+pub fn format_duration(seconds: i64) -> String {
+    let (hours, minutes) = (seconds / 3600, seconds / 60 % 60);
+    match (hours, minutes) {
+        (0, 0) => format!("{}s", seconds),
+        (0, minutes) => format!("{}m", minutes),
+        (hours, 0) => format!("{}h", hours),
+        (hours, minutes) => format!("{}h {}m", hours, minutes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("1h30m"), Ok(5400));
+        assert_eq!(parse_duration("45m"), Ok(2700));
+        assert_eq!(parse_duration("1.5h"), Ok(5400));
+        assert_eq!(parse_duration("2H5S"), Ok(7205));
+        assert!(parse_duration("90").is_err());
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("1d").is_err());
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(5400), "1h 30m");
+        assert_eq!(format_duration(7200), "2h");
+        assert_eq!(format_duration(2700), "45m");
+        assert_eq!(format_duration(42), "42s");
+    }
+}
@@ -0,0 +1,296 @@
+use std::io::{self, Write};
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use clap::{Parser, Subcommand};
+use serde::{de::IntoDeserializer, Deserialize};
+use time_bandit_models::{
+    LoginDetails, NewTaskEvent, ReportGroupBy, ReportQuery, StartTimer, StopTimer, UserEmail,
+    UserId,
+};
+
+use crate::{
+    api::Api,
+    config::Config,
+    duration::{format_duration, parse_duration},
+};
+
+mod api;
+mod config;
+mod duration;
+
+/// Track time from the terminal with a Time Bandit server
+#[derive(Parser)]
+#[command(name = "time_bandit", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Log in and keep the session in the config file
+    Login {
+        /// e.g. https://bandit.example.com, defaults to the saved server
+        #[arg(long)]
+        server: Option<String>,
+        #[arg(long)]
+        email: Option<String>,
+    },
+    /// Start the timer on the server for a task, which is created if it
+    /// doesn't exist
+    Start {
+        task: String,
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    /// Stop the timer and save the time spent
+    Stop {
+        /// replaces the notes given to `start`
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    /// Show the running timer
+    Status,
+    /// Save time already spent, e.g. `log 1h30m writing -m "chapter two"`
+    Log {
+        duration: String,
+        task: String,
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    /// List the tasks with their tracked time
+    Tasks,
+    /// Sum up the time tracked today, this week or this month
+    Report {
+        #[arg(long, conflicts_with = "month")]
+        week: bool,
+        #[arg(long)]
+        month: bool,
+        /// day, week, month, task, tag or project
+        #[arg(long, value_parser = parse_group_by, default_value = "day")]
+        group_by: ReportGroupBy,
+    },
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli.command) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(command: Command) -> Result<(), String> {
+    let mut config = Config::load()?;
+    match command {
+        Command::Login { server, email } => {
+            if let Some(server) = server {
+                config.server = server;
+            }
+            let email = match email.or(config.email.clone()) {
+                Some(email) => email,
+                None => prompt("Email: ")?,
+            };
+            let password = rpassword::prompt_password("Password: ").map_err(|e| e.to_string())?;
+            let session = Api::new(&config).login(&LoginDetails {
+                email: UserEmail(email.clone()),
+                password,
+            })?;
+            config.email = Some(email);
+            config.session = Some(session);
+            config.save()?;
+            println!("Logged in to {}", config.server);
+        }
+        Command::Start { task, message } => {
+            let api = Api::new(&config);
+            if let Some(timer) = api.timer()? {
+                return Err(format!(
+                    "Already working on {} since {}, run `time_bandit stop` first",
+                    api.task(&timer.task_id)?.task.name,
+                    local_time(&config, timer.started)?
+                ));
+            }
+            api.start_timer(&StartTimer {
+                task_id: api.task_id(&task)?,
+                notes: message,
+            })?;
+            println!("Started working on {}", task);
+        }
+        Command::Stop { message } => {
+            let api = Api::new(&config);
+            let event = api.stop_timer(&StopTimer { notes: message })?;
+            println!(
+                "Saved {} on {}",
+                format_duration(event.duration),
+                api.task(&event.task_id)?.task.name
+            );
+        }
+        Command::Status => {
+            let api = Api::new(&config);
+            match api.timer()? {
+                Some(timer) => println!(
+                    "Working on {} for {} (since {})",
+                    api.task(&timer.task_id)?.task.name,
+                    format_duration((Utc::now() - timer.started).num_seconds()),
+                    local_time(&config, timer.started)?
+                ),
+                None => println!("No timer is running"),
+            }
+        }
+        Command::Log {
+            duration,
+            task,
+            message,
+        } => {
+            let duration = parse_duration(&duration)?;
+            let date_began = Utc::now() - Duration::seconds(duration);
+            save_event(&Api::new(&config), &task, date_began, duration, message)?;
+            println!("Saved {} on {}", format_duration(duration), task);
+        }
+        Command::Tasks => {
+            let tasks = Api::new(&config).tasks(None)?;
+            if tasks.is_empty() {
+                println!("No tasks yet");
+            }
+            let width = tasks
+                .iter()
+                .map(|task| task.task.name.len())
+                .max()
+                .unwrap_or(0);
+            for task in tasks {
+                println!(
+                    "{:width$}  {}",
+                    task.task.name,
+                    format_duration(task.total_duration),
+                );
+            }
+        }
+        Command::Report {
+            week,
+            month,
+            group_by,
+        } => {
+            let today = Utc::now().with_timezone(&config.tz()?).date_naive();
+            let (from, to) = match (week, month) {
+                (true, _) => week_of(today),
+                (_, true) => month_of(today),
+                _ => (today, today),
+            };
+            let summary = Api::new(&config).report(&ReportQuery {
+                from,
+                to,
+                group_by,
+                tz: config.tz.clone(),
+            })?;
+            println!("{} to {}", summary.from, summary.to);
+            let width = summary
+                .buckets
+                .iter()
+                .map(|bucket| bucket.key.len())
+                .max()
+                .unwrap_or(0);
+            for bucket in &summary.buckets {
+                println!(
+                    "{:width$}  {}",
+                    bucket.key,
+                    format_duration(bucket.total_duration)
+                );
+            }
+            println!("Total: {}", format_duration(summary.total_duration));
+        }
+    }
+    Ok(())
+}
+
+fn save_event(
+    api: &Api,
+    task: &str,
+    date_began: DateTime<Utc>,
+    duration: i64,
+    notes: Option<String>,
+) -> Result<(), String> {
+    let task_id = api.task_id(task)?;
+    api.add_event(&NewTaskEvent {
+        // the server fills in the user
+        user_id: UserId(0),
+        task_id,
+        date_began,
+        duration,
+        notes,
+    })?;
+    Ok(())
+}
+
+fn prompt(label: &str) -> Result<String, String> {
+    print!("{}", label);
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let mut line = String::new();
+    io::stdin()
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())?;
+    Ok(line.trim().to_string())
+}
+
+fn local_time(config: &Config, time: DateTime<Utc>) -> Result<String, String> {
+    Ok(time
+        .with_timezone(&config.tz()?)
+        .format("%H:%M")
+        .to_string())
+}
+
+/// Parses the group with the same names the API uses
+fn parse_group_by(value: &str) -> Result<ReportGroupBy, String> {
+    ReportGroupBy::deserialize(value.into_deserializer())
+        .map_err(|e: serde::de::value::Error| e.to_string())
+}
+
+/// Monday to Sunday
+fn week_of(day: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let monday = day - Duration::days(day.weekday().num_days_from_monday().into());
+    (monday, monday + Duration::days(6))
+}
+
+fn month_of(day: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let first = day.with_day(1).unwrap();
+    let next_month = if first.month() == 12 {
+        NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(first.year(), first.month() + 1, 1)
+    };
+    (first, next_month.unwrap() - Duration::days(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn finds_the_week_and_month() {
+        assert_eq!(
+            week_of(date(2023, 12, 14)),
+            (date(2023, 12, 11), date(2023, 12, 17))
+        );
+        assert_eq!(
+            week_of(date(2023, 12, 11)),
+            (date(2023, 12, 11), date(2023, 12, 17))
+        );
+        assert_eq!(
+            month_of(date(2023, 12, 14)),
+            (date(2023, 12, 1), date(2023, 12, 31))
+        );
+        assert_eq!(
+            month_of(date(2024, 2, 10)),
+            (date(2024, 2, 1), date(2024, 2, 29))
+        );
+    }
+
+    #[test]
+    fn parses_groups_like_the_api() {
+        assert_eq!(parse_group_by("task"), Ok(ReportGroupBy::Task));
+        assert!(parse_group_by("year").is_err());
+    }
+}
@@ -0,0 +1,1203 @@
+//! Types of the Time Bandit HTTP API
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+#[cfg(feature = "sqlx")]
+use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LoginDetails {
+    pub email: UserEmail,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+pub struct SessionId(pub String);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+pub struct UserId(pub i32);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+pub struct TaskEventId(pub i32);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+pub struct TaskId(pub i32);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+pub struct ClientId(pub i32);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+pub struct WebhookId(pub i32);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+pub struct WebhookDeliveryId(pub i64);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UserEmail(pub String);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct User {
+    pub id: UserId,
+    pub uuid: Uuid,
+    pub email: UserEmail,
+    pub password: String,
+}
+
+/// What happens to a new event covering time that other events of the user
+/// already cover
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapPolicy {
+    #[default]
+    Allow,
+    /// refuse it with 409, naming the events it overlaps
+    Reject,
+    /// cut it down to the longest stretch of it that no other event covers
+    Trim,
+}
+
+impl OverlapPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OverlapPolicy::Allow => "allow",
+            OverlapPolicy::Reject => "reject",
+            OverlapPolicy::Trim => "trim",
+        }
+    }
+}
+
+impl std::str::FromStr for OverlapPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "allow" => Ok(OverlapPolicy::Allow),
+            "reject" => Ok(OverlapPolicy::Reject),
+            "trim" => Ok(OverlapPolicy::Trim),
+            _ => Err(format!("unknown overlap policy {}", policy)),
+        }
+    }
+}
+
+/// Body of `GET` and `PUT /users/settings`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UserSettings {
+    pub overlap_policy: OverlapPolicy,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Task {
+    pub id: TaskId,
+    pub uuid: Uuid,
+    pub user_id: UserId,
+    pub name: String,
+    pub description: String,
+    pub client_id: Option<ClientId>,
+    pub tags: Vec<String>,
+    pub created_on: DateTime<Utc>,
+    // changed_on field
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NewTask {
+    pub user_id: UserId,
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<ClientId>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+pub struct TaskEvent {
+    pub id: TaskEventId,
+    pub uuid: Uuid,
+    pub user_id: UserId,
+    pub task_id: TaskId,
+    pub date_began: DateTime<Utc>,
+    pub duration: i64,
+    pub notes: Option<String>,
+}
+#[cfg(feature = "sqlx")]
+impl PgHasArrayType for TaskEvent {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_event")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NewTaskEvent {
+    pub user_id: UserId,
+    pub task_id: TaskId,
+    pub date_began: DateTime<Utc>,
+    pub duration: i64,
+    pub notes: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TaskWithTaskEvents {
+    pub task: Task,
+    pub events: Vec<TaskEvent>,
+    pub total_duration: i64,
+    pub updated_on: DateTime<Utc>,
+}
+
+/// The timer a user has running. It is kept on the server so that all of the
+/// user's clients see it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Timer {
+    pub task_id: TaskId,
+    pub started: DateTime<Utc>,
+    pub notes: Option<String>,
+}
+
+/// Body of `POST /timer/start`, the timer starts at the server's time
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StartTimer {
+    pub task_id: TaskId,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+/// Body of `POST /timer/stop`, `notes` replace those given at the start
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StopTimer {
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+/// Query string of `GET /tasks`
+/// `updated_from` and `updated_to` are inclusive calendar dates in the `tz`
+/// time zone
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct TaskListQuery {
+    #[serde(default)]
+    pub sort: TaskSort,
+    /// part of the name, in any case
+    pub q: Option<String>,
+    pub updated_from: Option<NaiveDate>,
+    pub updated_to: Option<NaiveDate>,
+    #[serde(default = "default_timezone")]
+    pub tz: String,
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", param(value_type = String, example = "recent:5"))]
+    pub include_events: IncludeEvents,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Times sort the latest first, durations the longest first and names
+/// alphabetically
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum TaskSort {
+    #[default]
+    UpdatedOn,
+    CreatedOn,
+    Name,
+    TotalDuration,
+}
+
+impl TaskSort {
+    /// The value of a task listing's rows that this sorts by
+    pub fn column(&self) -> &'static str {
+        match self {
+            TaskSort::UpdatedOn => "updated_on",
+            TaskSort::CreatedOn => "created_on",
+            TaskSort::Name => "name",
+            TaskSort::TotalDuration => "total_duration",
+        }
+    }
+}
+
+/// Which events a task listing embeds: `true`, `false` or `recent:N` for the
+/// latest N of each task
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum IncludeEvents {
+    #[default]
+    All,
+    None,
+    Recent(u32),
+}
+
+impl TryFrom<String> for IncludeEvents {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "true" => Ok(IncludeEvents::All),
+            "false" => Ok(IncludeEvents::None),
+            _ => value
+                .strip_prefix("recent:")
+                .and_then(|count| count.parse().ok())
+                .filter(|count| *count > 0)
+                .map(IncludeEvents::Recent)
+                .ok_or_else(|| {
+                    format!(
+                        "expected `true`, `false` or `recent:N` with N above 0, got `{}`",
+                        value
+                    )
+                }),
+        }
+    }
+}
+
+impl From<IncludeEvents> for String {
+    fn from(value: IncludeEvents) -> Self {
+        match value {
+            IncludeEvents::All => "true".to_string(),
+            IncludeEvents::None => "false".to_string(),
+            IncludeEvents::Recent(count) => format!("recent:{}", count),
+        }
+    }
+}
+
+/// Body of `GET /tasks`, `next_cursor` is `null` on the last page
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TaskPage {
+    pub tasks: Vec<TaskWithTaskEvents>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Client {
+    pub id: ClientId,
+    pub uuid: Uuid,
+    pub user_id: UserId,
+    pub name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    /// hourly rate in the smallest currency unit (e.g. cents)
+    pub default_rate: Option<i64>,
+    pub created_on: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NewClient {
+    pub user_id: UserId,
+    pub name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<String>,
+    pub default_rate: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ClientTaskSummary {
+    pub task_id: TaskId,
+    pub name: String,
+    pub total_duration: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ClientMonthSummary {
+    pub month: DateTime<Utc>,
+    pub total_duration: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ClientSummary {
+    pub client: Client,
+    pub tasks: Vec<ClientTaskSummary>,
+    pub months: Vec<ClientMonthSummary>,
+    pub total_duration: i64,
+    /// total_duration billed at the client's default_rate, if one is set
+    pub billable_amount: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ReportGroupBy {
+    #[default]
+    Day,
+    Week,
+    Month,
+    Task,
+    Tag,
+    /// tasks are grouped into projects by their client
+    Project,
+}
+
+impl ReportGroupBy {
+    /// The unit passed to postgres' `date_trunc` for the time based groupings
+    pub fn date_trunc_unit(&self) -> Option<&'static str> {
+        match self {
+            ReportGroupBy::Day => Some("day"),
+            ReportGroupBy::Week => Some("week"),
+            ReportGroupBy::Month => Some("month"),
+            ReportGroupBy::Task | ReportGroupBy::Tag | ReportGroupBy::Project => None,
+        }
+    }
+}
+
+/// Query string of `GET /reports/summary`
+/// `from` and `to` are inclusive calendar dates in the `tz` time zone
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct ReportQuery {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    #[serde(default)]
+    pub group_by: ReportGroupBy,
+    #[serde(default = "default_timezone")]
+    pub tz: String,
+}
+
+pub fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReportBucket {
+    /// the local date the bucket starts on, or the task, tag or client name
+    pub key: String,
+    /// the task or client id when grouping by task or project
+    pub id: Option<i32>,
+    /// the instant a time based bucket starts at
+    pub start: Option<DateTime<Utc>>,
+    pub total_duration: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReportSummary {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub group_by: ReportGroupBy,
+    pub tz: String,
+    pub buckets: Vec<ReportBucket>,
+    pub total_duration: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum DurationFormat {
+    /// whole seconds, e.g. `5400`
+    #[default]
+    Seconds,
+    /// decimal hours, e.g. `1.50`
+    Hours,
+    /// hours and minutes, e.g. `1:30`
+    #[serde(alias = "hh:mm")]
+    Hhmm,
+}
+
+/// Query string of `GET /events/export.csv`
+/// `from` and `to` are inclusive calendar dates in the `tz` time zone
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct EventExportQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    #[serde(default = "default_timezone")]
+    pub tz: String,
+    /// comma separated list of columns, defaults to all of them
+    pub columns: Option<String>,
+    #[serde(default)]
+    pub duration_format: DurationFormat,
+}
+
+/// Query string of `GET /events`
+/// `from` and `to` are inclusive calendar dates in the `tz` time zone. Every
+/// event overlapping them is listed, including those straddling either end.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct EventListQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    #[serde(default = "default_timezone")]
+    pub tz: String,
+    pub task_id: Option<TaskId>,
+    /// only events of tasks with this tag
+    pub tag: Option<String>,
+    /// fill in `duration_in_range` of each event
+    #[serde(default)]
+    pub clip: bool,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// An event in the listing of `GET /events` together with its task's name
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ListedEvent {
+    #[serde(flatten)]
+    pub event: TaskEvent,
+    pub task_name: String,
+    /// with `clip`, the seconds of the event that fall within the range
+    pub duration_in_range: Option<i64>,
+}
+
+/// Body of `GET /events`, `next_cursor` is `null` on the last page
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct EventPage {
+    pub events: Vec<ListedEvent>,
+    pub next_cursor: Option<String>,
+}
+
+/// Query string of `GET /events/overlaps`
+/// `from` and `to` are inclusive calendar dates in the `tz` time zone
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct EventOverlapQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    #[serde(default = "default_timezone")]
+    pub tz: String,
+}
+
+/// Two events covering the same time, `first` beginning no later than
+/// `second`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct EventOverlap {
+    pub first: ListedEvent,
+    pub second: ListedEvent,
+    /// the seconds both of them cover
+    pub duration: i64,
+}
+
+/// Body of `GET /events/overlaps`, in the order the overlaps begin
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct EventOverlaps {
+    pub overlaps: Vec<EventOverlap>,
+}
+
+/// Query string of `GET /search`
+/// `q` is written like a web search: words and "quoted phrases" that must
+/// all appear, `or` between alternatives and `-` before words that must not.
+/// `from` and `to` are inclusive calendar dates in the `tz` time zone; tasks
+/// are in range if any of their events began in it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct SearchQuery {
+    pub q: String,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    #[serde(default = "default_timezone")]
+    pub tz: String,
+    /// only the task and its events
+    pub task_id: Option<TaskId>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SearchHitKind {
+    Task,
+    Event,
+}
+
+/// A task or event matching a search
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SearchHit {
+    pub kind: SearchHitKind,
+    pub task_id: TaskId,
+    pub task_name: String,
+    /// only for events
+    pub event_id: Option<TaskEventId>,
+    pub date_began: Option<DateTime<Utc>>,
+    /// higher for better matches, only comparable within one search
+    pub rank: f64,
+    /// HTML of the matching text around the matches, which are in `<mark>`
+    pub snippet: String,
+}
+
+/// Body of `GET /search`, the best matches first
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SearchResults {
+    pub hits: Vec<SearchHit>,
+}
+
+/// Query string of `GET /reports/summary.csv`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReportExportQuery {
+    #[serde(flatten)]
+    pub report: ReportQuery,
+    /// comma separated list of columns, defaults to all of them
+    pub columns: Option<String>,
+    #[serde(default)]
+    pub duration_format: DurationFormat,
+}
+
+/// An event joined with its task and client, with its times already
+/// converted to the requested time zone
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventExportRow {
+    pub id: TaskEventId,
+    pub uuid: Uuid,
+    pub task_id: TaskId,
+    pub task_name: String,
+    pub client_name: Option<String>,
+    pub tags: Vec<String>,
+    pub date: String,
+    pub start_time: String,
+    pub end_time: String,
+    pub duration: i64,
+    pub notes: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Csv,
+    Json,
+}
+
+/// How already existing events are recognised during an import
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ImportDedupe {
+    /// skip rows whose uuid is already taken, rows without a uuid are always imported
+    #[default]
+    Uuid,
+    /// skip rows with the same task, start and duration as an existing event
+    TaskDateDuration,
+    None,
+}
+
+/// The names of the source columns (or JSON keys) holding each event field
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(default)]
+pub struct ImportMapping {
+    pub task: String,
+    pub date_began: String,
+    pub duration: String,
+    pub notes: Option<String>,
+    pub uuid: Option<String>,
+}
+
+impl Default for ImportMapping {
+    fn default() -> Self {
+        ImportMapping {
+            task: "task".to_string(),
+            date_began: "date_began".to_string(),
+            duration: "duration".to_string(),
+            notes: Some("notes".to_string()),
+            uuid: Some("uuid".to_string()),
+        }
+    }
+}
+
+/// Body of `POST /import`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ImportRequest {
+    pub format: ImportFormat,
+    /// the contents of the CSV file, or a JSON array of objects
+    pub data: String,
+    #[serde(default)]
+    pub mapping: ImportMapping,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub dedupe: ImportDedupe,
+    #[serde(default)]
+    pub duration_format: DurationFormat,
+    /// time zone of start times without an offset
+    #[serde(default = "default_timezone")]
+    pub tz: String,
+}
+
+/// A validated event of an import, with its task still referenced by name
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportRow {
+    pub row: usize,
+    pub uuid: Option<Uuid>,
+    pub task_name: String,
+    /// client of the task, created by name if the task is new
+    pub client_name: Option<String>,
+    /// tags added to the task
+    pub tags: Vec<String>,
+    pub date_began: DateTime<Utc>,
+    pub duration: i64,
+    pub notes: Option<String>,
+    /// UID of the calendar event the row was read from; an event is never
+    /// imported twice, whatever the dedupe mode
+    pub ical_uid: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ImportRowError {
+    /// 1-based position of the record in the file, not counting a CSV header
+    pub row: usize,
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub total_rows: usize,
+    pub imported: usize,
+    pub skipped_duplicates: usize,
+    /// names of the tasks that did not exist yet
+    pub created_tasks: Vec<String>,
+    /// names of the clients that did not exist yet
+    pub created_clients: Vec<String>,
+    pub errors: Vec<ImportRowError>,
+}
+
+/// Body of `POST /import/:source`, for export files of other time trackers
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ExternalImportRequest {
+    /// the contents of the exported file
+    pub data: String,
+    #[serde(default)]
+    pub dry_run: bool,
+    /// exports rarely carry ids, so entries are matched on their content by default
+    #[serde(default = "default_external_dedupe")]
+    pub dedupe: ImportDedupe,
+    /// time zone of start times without an offset
+    #[serde(default = "default_timezone")]
+    pub tz: String,
+}
+
+fn default_external_dedupe() -> ImportDedupe {
+    ImportDedupe::TaskDateDuration
+}
+
+/// Body of `POST /import/ics`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct IcsImportRequest {
+    /// the contents of the .ics file
+    pub data: String,
+    /// tried in order, the first matching rule decides the task of an event
+    pub rules: Vec<IcsRule>,
+    #[serde(default)]
+    pub dry_run: bool,
+    /// whose declined invitations are skipped, defaults to the account's email
+    pub attendee: Option<String>,
+    /// time zone of floating start times
+    #[serde(default = "default_timezone")]
+    pub tz: String,
+}
+
+/// Maps calendar events to a task. An event matches if it satisfies every
+/// condition that is set, so a rule without conditions matches all events.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct IcsRule {
+    /// regular expression searched for in the event's summary
+    pub summary: Option<String>,
+    /// email address or name of the organizer, ignoring case
+    pub organizer: Option<String>,
+    /// name of the calendar (`X-WR-CALNAME`), ignoring case
+    pub calendar: Option<String>,
+    pub task: String,
+    /// tags added to the task
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Calendar events that were left out on purpose
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct IcsSkipped {
+    /// invitations the attendee declined
+    pub declined: usize,
+    pub cancelled: usize,
+    pub all_day: usize,
+    /// events no rule matched
+    pub unmatched: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct IcsImportReport {
+    #[serde(flatten)]
+    pub report: ImportReport,
+    pub skipped: IcsSkipped,
+}
+
+/// Query string of `GET /calendar/:token.ics`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct CalendarQuery {
+    pub task_id: Option<TaskId>,
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CalendarEvent {
+    pub uuid: Uuid,
+    pub task_name: String,
+    pub date_began: DateTime<Utc>,
+    pub duration: i64,
+    pub notes: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CalendarToken {
+    pub token: String,
+    /// path of the feed, relative to the API root
+    pub path: String,
+}
+
+/// When each synced field of a record was last written
+pub type FieldClock = HashMap<String, DateTime<Utc>>;
+
+/// Query string of `GET /sync`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct SyncQuery {
+    /// the `cursor` of the previous sync, 0 fetches everything
+    #[serde(default)]
+    pub since: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum SyncEntity {
+    Task,
+    Event,
+}
+
+impl SyncEntity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncEntity::Task => "task",
+            SyncEntity::Event => "event",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SyncTask {
+    pub uuid: Uuid,
+    pub name: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    #[cfg_attr(feature = "openapi", schema(value_type = HashMap<String, DateTime<Utc>>))]
+    pub modified_at: FieldClock,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SyncEvent {
+    pub uuid: Uuid,
+    pub task_uuid: Uuid,
+    pub date_began: DateTime<Utc>,
+    pub duration: i64,
+    pub notes: Option<String>,
+    #[cfg_attr(feature = "openapi", schema(value_type = HashMap<String, DateTime<Utc>>))]
+    pub modified_at: FieldClock,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Tombstone {
+    pub entity: SyncEntity,
+    pub uuid: Uuid,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// Response of `GET /sync`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SyncChanges {
+    /// pass as `since` to the next sync
+    pub cursor: i64,
+    pub tasks: Vec<SyncTask>,
+    pub events: Vec<SyncEvent>,
+    pub tombstones: Vec<Tombstone>,
+}
+
+/// A task created or edited by a client; only the fields that are set changed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TaskChange {
+    pub uuid: Uuid,
+    pub modified_at: DateTime<Utc>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+/// An event created or edited by a client; only the fields that are set changed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct EventChange {
+    pub uuid: Uuid,
+    pub modified_at: DateTime<Utc>,
+    pub task_uuid: Option<Uuid>,
+    pub date_began: Option<DateTime<Utc>>,
+    pub duration: Option<i64>,
+    /// `null` clears the notes, leaving the field out keeps them
+    #[serde(default, deserialize_with = "present")]
+    pub notes: Option<Option<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Deletion {
+    pub entity: SyncEntity,
+    pub uuid: Uuid,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// Body of `POST /sync`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(default)]
+pub struct SyncPush {
+    pub tasks: Vec<TaskChange>,
+    pub events: Vec<EventChange>,
+    pub deletions: Vec<Deletion>,
+}
+
+/// A client change that lost against a newer write on the server. The
+/// field `deleted` stands for the record as a whole.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SyncConflict {
+    pub entity: SyncEntity,
+    pub uuid: Uuid,
+    pub field: String,
+    pub server_value: serde_json::Value,
+    pub client_value: serde_json::Value,
+    pub server_modified_at: DateTime<Utc>,
+    pub client_modified_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SyncError {
+    pub entity: SyncEntity,
+    pub uuid: Uuid,
+    pub message: String,
+}
+
+/// Response of `POST /sync`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SyncReport {
+    /// changes and deletions that were written, fully or in part
+    pub applied: usize,
+    pub conflicts: Vec<SyncConflict>,
+    pub errors: Vec<SyncError>,
+}
+
+/// A change to a user's data, sent by `GET /stream` as a server-sent event
+/// named after its `type`. Only ids are sent; clients fetch what they show.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Notification {
+    TaskCreated {
+        id: TaskId,
+    },
+    TaskUpdated {
+        id: TaskId,
+    },
+    TaskDeleted {
+        id: TaskId,
+    },
+    EventCreated {
+        id: TaskEventId,
+        task_id: TaskId,
+    },
+    EventUpdated {
+        id: TaskEventId,
+        task_id: TaskId,
+    },
+    EventDeleted {
+        id: TaskEventId,
+        task_id: TaskId,
+    },
+    TimerStarted {
+        task_id: TaskId,
+        started: DateTime<Utc>,
+    },
+    TimerStopped {
+        task_id: TaskId,
+    },
+}
+
+impl Notification {
+    /// The `type` of the notification
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Notification::TaskCreated { .. } => "task_created",
+            Notification::TaskUpdated { .. } => "task_updated",
+            Notification::TaskDeleted { .. } => "task_deleted",
+            Notification::EventCreated { .. } => "event_created",
+            Notification::EventUpdated { .. } => "event_updated",
+            Notification::EventDeleted { .. } => "event_deleted",
+            Notification::TimerStarted { .. } => "timer_started",
+            Notification::TimerStopped { .. } => "timer_stopped",
+        }
+    }
+}
+
+/// What a webhook can be sent for, the types of `Notification` written
+/// with a dot
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum WebhookEvent {
+    #[serde(rename = "task.created")]
+    TaskCreated,
+    #[serde(rename = "task.updated")]
+    TaskUpdated,
+    #[serde(rename = "task.deleted")]
+    TaskDeleted,
+    #[serde(rename = "event.created")]
+    EventCreated,
+    #[serde(rename = "event.updated")]
+    EventUpdated,
+    #[serde(rename = "event.deleted")]
+    EventDeleted,
+    #[serde(rename = "timer.started")]
+    TimerStarted,
+    #[serde(rename = "timer.stopped")]
+    TimerStopped,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::TaskCreated => "task.created",
+            WebhookEvent::TaskUpdated => "task.updated",
+            WebhookEvent::TaskDeleted => "task.deleted",
+            WebhookEvent::EventCreated => "event.created",
+            WebhookEvent::EventUpdated => "event.updated",
+            WebhookEvent::EventDeleted => "event.deleted",
+            WebhookEvent::TimerStarted => "timer.started",
+            WebhookEvent::TimerStopped => "timer.stopped",
+        }
+    }
+}
+
+impl std::str::FromStr for WebhookEvent {
+    type Err = String;
+
+    fn from_str(event: &str) -> Result<Self, Self::Err> {
+        match event {
+            "task.created" => Ok(WebhookEvent::TaskCreated),
+            "task.updated" => Ok(WebhookEvent::TaskUpdated),
+            "task.deleted" => Ok(WebhookEvent::TaskDeleted),
+            "event.created" => Ok(WebhookEvent::EventCreated),
+            "event.updated" => Ok(WebhookEvent::EventUpdated),
+            "event.deleted" => Ok(WebhookEvent::EventDeleted),
+            "timer.started" => Ok(WebhookEvent::TimerStarted),
+            "timer.stopped" => Ok(WebhookEvent::TimerStopped),
+            _ => Err(format!("unknown webhook event {}", event)),
+        }
+    }
+}
+
+impl From<&Notification> for WebhookEvent {
+    fn from(notification: &Notification) -> Self {
+        match notification {
+            Notification::TaskCreated { .. } => WebhookEvent::TaskCreated,
+            Notification::TaskUpdated { .. } => WebhookEvent::TaskUpdated,
+            Notification::TaskDeleted { .. } => WebhookEvent::TaskDeleted,
+            Notification::EventCreated { .. } => WebhookEvent::EventCreated,
+            Notification::EventUpdated { .. } => WebhookEvent::EventUpdated,
+            Notification::EventDeleted { .. } => WebhookEvent::EventDeleted,
+            Notification::TimerStarted { .. } => WebhookEvent::TimerStarted,
+            Notification::TimerStopped { .. } => WebhookEvent::TimerStopped,
+        }
+    }
+}
+
+/// Body of `POST /webhooks`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NewWebhook {
+    /// an `http` or `https` URL the payloads are posted to
+    pub url: String,
+    pub event_types: Vec<WebhookEvent>,
+}
+
+/// A webhook of the user. Payloads are signed with `secret`, see
+/// `X-Bandit-Signature`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Webhook {
+    pub id: WebhookId,
+    pub url: String,
+    pub event_types: Vec<WebhookEvent>,
+    pub secret: String,
+    pub created_on: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// waiting for its next attempt
+    Pending,
+    /// the receiver answered with a 2xx status
+    Delivered,
+    /// every attempt failed; it is only sent again if redelivered
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for DeliveryStatus {
+    type Err = String;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        match status {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "failed" => Ok(DeliveryStatus::Failed),
+            _ => Err(format!("unknown delivery status {}", status)),
+        }
+    }
+}
+
+/// A change queued for a webhook and the log of the attempts to send it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WebhookDelivery {
+    pub id: WebhookDeliveryId,
+    pub webhook_id: WebhookId,
+    pub event_type: WebhookEvent,
+    /// the body that is sent, `null` until the first attempt
+    pub payload: Option<serde_json::Value>,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    /// when it is tried next, while it is pending
+    pub next_attempt: Option<DateTime<Utc>>,
+    pub created_on: DateTime<Utc>,
+    /// the latest attempt first
+    pub log: Vec<WebhookAttempt>,
+}
+
+/// One try at sending a delivery
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WebhookAttempt {
+    pub attempted_on: DateTime<Utc>,
+    /// of the receiver's response
+    pub status_code: Option<i32>,
+    /// why there was no response; what the receiver said is not kept
+    pub error: Option<String>,
+    pub duration_ms: i64,
+}
+
+/// Query string of `GET /webhooks/:webhook_id/deliveries`, which lists the
+/// latest first
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams), into_params(parameter_in = Query))]
+pub struct WebhookDeliveryQuery {
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Body of `GET /webhooks/:webhook_id/deliveries`, `next_cursor` is `null`
+/// on the last page
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WebhookDeliveryPage {
+    pub deliveries: Vec<WebhookDelivery>,
+    pub next_cursor: Option<String>,
+}
+
+/// What is posted to a webhook
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WebhookPayload {
+    /// the delivery, the same for every attempt and redelivery
+    pub id: WebhookDeliveryId,
+    #[serde(rename = "type")]
+    pub event_type: WebhookEvent,
+    /// when the change was made
+    pub created_on: DateTime<Utc>,
+    /// the task, event or timer as it was when first sent, or only the ids
+    /// if it is gone
+    pub data: serde_json::Value,
+}
+
+/// Tells a field set to `null` apart from a missing one
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Body of every error response of the API
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ApiError {
+    /// stable, machine readable, e.g. `not_found` or `conflict`
+    pub code: String,
+    pub message: String,
+    /// more about the error where there is any, e.g. the invalid fields
+    pub details: Option<serde_json::Value>,
+    /// also in the `x-request-id` header and the server log
+    pub request_id: Option<String>,
+}
@@ -21,28 +21,43 @@ export interface TaskWithEvents {
   updated_on: string;
 }
 
+export interface TaskPage {
+  tasks: TaskWithEvents[];
+  next_cursor: string | null;
+}
+
+// every page of the user's tasks, following `next_cursor` to the last one
 const getTasks = async () => {
-  const url = `http://localhost:8080/tasks`;
-  const request = new Request(url);
   const cookieStore = cookies();
   try {
     const cookie = cookieStore.get("time_bandit_auth_token_v1");
-    let res = await fetch(request, {
-      method: "GET",
-      mode: "cors",
-      credentials: "include",
-      headers: {
-        "Access-Control-Allow-Credentials": "true",
-        Cookie: `time_bandit_auth_token_v1=${cookie?.value}`,
-      },
-      cache: "force-cache",
-    });
-    if (res.ok) {
-      return res.json();
-    } else {
-      const error = new Error("UNAUTHORIZED");
-      throw error;
-    }
+    const tasks: TaskWithEvents[] = [];
+    let cursor: string | null = null;
+    do {
+      const url = new URL(`http://localhost:8080/tasks`);
+      if (cursor) {
+        url.searchParams.set("cursor", cursor);
+      }
+      let res = await fetch(new Request(url), {
+        method: "GET",
+        mode: "cors",
+        credentials: "include",
+        headers: {
+          "Access-Control-Allow-Credentials": "true",
+          Cookie: `time_bandit_auth_token_v1=${cookie?.value}`,
+        },
+        // the tasks belong to the user of the cookie
+        cache: "no-store",
+      });
+      if (!res.ok) {
+        const error = new Error("UNAUTHORIZED");
+        throw error;
+      }
+      const page: TaskPage = await res.json();
+      tasks.push(...page.tasks);
+      cursor = page.next_cursor;
+    } while (cursor);
+    return tasks;
   } catch (e) {
     JSON.stringify(e);
     console.error(e);
@@ -50,7 +65,7 @@ const getTasks = async () => {
 };
 
 const Page = async () => {
-  const tasks: TaskWithEvents[] = await getTasks();
+  const tasks = await getTasks();
   if (!tasks) {
     redirect("/");
   }